@@ -0,0 +1,461 @@
+use anyhow::Context;
+use futures::future::BoxFuture;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, GenericImageView, ImageEncoder};
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::config::{ArtworkConfig, Config};
+use crate::sources::models::{EpisodeMetadata, TrackMetadata};
+use crate::sources::musicbrainz::MusicBrainzClient;
+
+/// What we know about a release when looking for its cover art
+#[derive(Debug, Clone, Default)]
+pub struct ArtworkQuery {
+    pub artist: String,
+    pub album: String,
+    /// Image URLs already known from the metadata source (e.g. Spotify album images)
+    pub image_urls: Vec<String>,
+    pub release_mbid: Option<String>,
+    pub release_group_mbid: Option<String>,
+    /// YouTube video the audio was taken from, for the thumbnail fallback
+    pub video_url: Option<String>,
+}
+
+impl ArtworkQuery {
+    pub fn new(artist: &str, album: &str) -> Self {
+        Self {
+            artist: artist.to_string(),
+            album: album.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Build a query from a metadata provider lookup
+    pub fn from_metadata(meta: &TrackMetadata) -> Self {
+        Self {
+            artist: meta.artist.clone(),
+            album: meta.album.clone(),
+            image_urls: meta.cover_url.iter().cloned().collect(),
+            release_mbid: meta.mbids.release.clone(),
+            release_group_mbid: meta.mbids.release_group.clone(),
+            video_url: None,
+        }
+    }
+
+    /// Build a query for a podcast episode's show artwork
+    pub fn from_episode(episode: &EpisodeMetadata) -> Self {
+        Self {
+            artist: episode.publisher.clone(),
+            album: episode.show.clone(),
+            image_urls: episode.cover_url.iter().cloned().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// An image URL offered by a provider
+#[derive(Debug, Clone)]
+pub struct ArtworkCandidate {
+    pub url: String,
+    /// Video thumbnails are 16:9 and get cropped to a square
+    pub is_video_thumbnail: bool,
+}
+
+/// A source of cover art. Each provider returns its candidates best-first;
+/// the chain downloads the first one that works and keeps the largest image.
+pub trait ArtworkProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn candidates<'a>(&'a self, query: &'a ArtworkQuery) -> BoxFuture<'a, anyhow::Result<Vec<ArtworkCandidate>>>;
+}
+
+/// Images already provided by Spotify album metadata (up to 640px)
+pub struct SpotifyArtwork;
+
+impl ArtworkProvider for SpotifyArtwork {
+    fn name(&self) -> &'static str {
+        "spotify"
+    }
+
+    fn candidates<'a>(&'a self, query: &'a ArtworkQuery) -> BoxFuture<'a, anyhow::Result<Vec<ArtworkCandidate>>> {
+        Box::pin(async move {
+            Ok(query
+                .image_urls
+                .iter()
+                .map(|url| ArtworkCandidate {
+                    url: url.clone(),
+                    is_video_thumbnail: false,
+                })
+                .collect())
+        })
+    }
+}
+
+/// Cover Art Archive, looked up by release or release-group MBID. Albums
+/// without an MBID are found through a MusicBrainz release-group search.
+pub struct CoverArtArchive {
+    base_url: String,
+    musicbrainz: MusicBrainzClient,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct CaaListing {
+    #[serde(default)]
+    images: Vec<CaaImage>,
+}
+
+#[derive(Deserialize)]
+struct CaaImage {
+    #[serde(default)]
+    front: bool,
+    image: String,
+}
+
+impl CoverArtArchive {
+    pub fn new(base_url: &str, musicbrainz_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            musicbrainz: MusicBrainzClient::new(musicbrainz_url),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn front_images(&self, kind: &str, mbid: &str) -> anyhow::Result<Vec<ArtworkCandidate>> {
+        let response = self
+            .client
+            .get(format!("{}/{}/{}", self.base_url, kind, mbid))
+            .send()
+            .await
+            .context("requesting Cover Art Archive listing")?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let listing: CaaListing = response
+            .error_for_status()
+            .context("Cover Art Archive request failed")?
+            .json()
+            .await
+            .context("parsing Cover Art Archive listing")?;
+        Ok(listing
+            .images
+            .into_iter()
+            .filter(|img| img.front)
+            .map(|img| ArtworkCandidate {
+                url: img.image,
+                is_video_thumbnail: false,
+            })
+            .collect())
+    }
+}
+
+impl ArtworkProvider for CoverArtArchive {
+    fn name(&self) -> &'static str {
+        "coverartarchive"
+    }
+
+    fn candidates<'a>(&'a self, query: &'a ArtworkQuery) -> BoxFuture<'a, anyhow::Result<Vec<ArtworkCandidate>>> {
+        Box::pin(async move {
+            if let Some(release) = &query.release_mbid {
+                let found = self.front_images("release", release).await?;
+                if !found.is_empty() {
+                    return Ok(found);
+                }
+            }
+            let group = match &query.release_group_mbid {
+                Some(group) => Some(group.clone()),
+                None if !query.album.is_empty() => {
+                    self.musicbrainz
+                        .search_release_group(&query.artist, &query.album)
+                        .await?
+                }
+                None => None,
+            };
+            match group {
+                Some(group) => self.front_images("release-group", &group).await,
+                None => Ok(Vec::new()),
+            }
+        })
+    }
+}
+
+/// iTunes Search API style endpoint (`/search?entity=album`)
+pub struct ITunesArtwork {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct ITunesSearch {
+    #[serde(default)]
+    results: Vec<ITunesAlbum>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ITunesAlbum {
+    #[serde(default)]
+    collection_name: String,
+    #[serde(default)]
+    artwork_url100: Option<String>,
+}
+
+impl ITunesArtwork {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl ArtworkProvider for ITunesArtwork {
+    fn name(&self) -> &'static str {
+        "itunes"
+    }
+
+    fn candidates<'a>(&'a self, query: &'a ArtworkQuery) -> BoxFuture<'a, anyhow::Result<Vec<ArtworkCandidate>>> {
+        Box::pin(async move {
+            if query.album.is_empty() {
+                return Ok(Vec::new());
+            }
+            let term = format!("{} {}", query.artist, query.album);
+            let search: ITunesSearch = self
+                .client
+                .get(format!("{}/search", self.base_url))
+                .query(&[("term", term.as_str()), ("media", "music"), ("entity", "album"), ("limit", "5")])
+                .send()
+                .await
+                .context("searching iTunes")?
+                .error_for_status()
+                .context("iTunes search failed")?
+                .json()
+                .await
+                .context("parsing iTunes response")?;
+
+            // Only trust results whose album name matches, iTunes search is loose
+            let wanted = query.album.to_lowercase();
+            Ok(search
+                .results
+                .into_iter()
+                .filter(|r| {
+                    let name = r.collection_name.to_lowercase();
+                    !name.is_empty() && (name.contains(&wanted) || wanted.contains(&name))
+                })
+                .filter_map(|r| r.artwork_url100)
+                .map(|url| ArtworkCandidate {
+                    url: itunes_high_res(&url),
+                    is_video_thumbnail: false,
+                })
+                .collect())
+        })
+    }
+}
+
+/// YouTube video thumbnail, as used by yt-dlp (`/vi/{id}/maxresdefault.jpg`)
+pub struct YouTubeThumbnail {
+    base_url: String,
+}
+
+impl YouTubeThumbnail {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl ArtworkProvider for YouTubeThumbnail {
+    fn name(&self) -> &'static str {
+        "youtube"
+    }
+
+    fn candidates<'a>(&'a self, query: &'a ArtworkQuery) -> BoxFuture<'a, anyhow::Result<Vec<ArtworkCandidate>>> {
+        Box::pin(async move {
+            let Some(id) = query.video_url.as_deref().and_then(youtube_video_id) else {
+                return Ok(Vec::new());
+            };
+            Ok(["maxresdefault.jpg", "hqdefault.jpg"]
+                .iter()
+                .map(|file| ArtworkCandidate {
+                    url: format!("{}/vi/{}/{}", self.base_url, id, file),
+                    is_video_thumbnail: true,
+                })
+                .collect())
+        })
+    }
+}
+
+/// Build the artwork providers in configured order. Unknown names are skipped.
+pub fn providers_from_config(config: &Config) -> Vec<Box<dyn ArtworkProvider>> {
+    let artwork = &config.artwork;
+    artwork
+        .providers
+        .iter()
+        .filter_map(|name| -> Option<Box<dyn ArtworkProvider>> {
+            match name.trim().to_lowercase().as_str() {
+                "spotify" => Some(Box::new(SpotifyArtwork)),
+                "coverartarchive" | "caa" => Some(Box::new(CoverArtArchive::new(
+                    &artwork.coverartarchive_url,
+                    &config.metadata.musicbrainz_url,
+                ))),
+                "itunes" => Some(Box::new(ITunesArtwork::new(&artwork.itunes_url))),
+                "youtube" => Some(Box::new(YouTubeThumbnail::new(&artwork.youtube_thumbnail_url))),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// The cover chosen by the provider chain
+pub struct Artwork {
+    pub image: DynamicImage,
+    pub provider: &'static str,
+}
+
+/// Ask every provider for artwork and keep the highest resolution image.
+/// Each provider contributes the first of its candidates that downloads and decodes.
+pub async fn fetch_best(
+    providers: &[Box<dyn ArtworkProvider>],
+    query: &ArtworkQuery,
+    config: &ArtworkConfig,
+) -> Option<Artwork> {
+    let client = reqwest::Client::new();
+    let mut best: Option<Artwork> = None;
+
+    for provider in providers {
+        let candidates = match provider.candidates(query).await {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+
+        for candidate in candidates {
+            let Some(mut image) = download_image(&client, &candidate.url).await else {
+                continue;
+            };
+            if candidate.is_video_thumbnail && config.crop_thumbnails {
+                image = crop_to_square(&image);
+            }
+            if best.as_ref().is_none_or(|b| pixel_count(&image) > pixel_count(&b.image)) {
+                best = Some(Artwork {
+                    image,
+                    provider: provider.name(),
+                });
+            }
+            break;
+        }
+    }
+
+    best
+}
+
+async fn download_image(client: &reqwest::Client, url: &str) -> Option<DynamicImage> {
+    let response = client.get(url).send().await.ok()?.error_for_status().ok()?;
+    let bytes = response.bytes().await.ok()?;
+    image::load_from_memory(&bytes).ok()
+}
+
+fn pixel_count(image: &DynamicImage) -> u64 {
+    let (w, h) = image.dimensions();
+    w as u64 * h as u64
+}
+
+/// Save the full-size cover as a high quality JPEG
+pub fn save_cover(image: &DynamicImage, dest: &Path) -> anyhow::Result<()> {
+    let rgb = image.to_rgb8();
+    let mut buf = Vec::new();
+    JpegEncoder::new_with_quality(&mut buf, 95).write_image(
+        rgb.as_raw(),
+        rgb.width(),
+        rgb.height(),
+        image::ExtendedColorType::Rgb8,
+    )?;
+    std::fs::write(dest, buf).with_context(|| format!("writing {}", dest.display()))
+}
+
+/// Where a saved cover came from
+pub struct CoverInfo {
+    pub provider: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Run the configured provider chain and save the best cover to `dest` at full size.
+/// Embedding code downscales from this file, so `dest` doubles as the `cover.jpg` kept
+/// next to the tracks.
+pub async fn fetch_cover(query: &ArtworkQuery, dest: &Path) -> anyhow::Result<Option<CoverInfo>> {
+    let config = Config::load();
+    let providers = providers_from_config(&config);
+    let Some(artwork) = fetch_best(&providers, query, &config.artwork).await else {
+        return Ok(None);
+    };
+    save_cover(&artwork.image, dest)?;
+    let (width, height) = artwork.image.dimensions();
+    Ok(Some(CoverInfo {
+        provider: artwork.provider,
+        width,
+        height,
+    }))
+}
+
+/// Center-crop an image to a square (used for 16:9 video thumbnails)
+pub fn crop_to_square(image: &DynamicImage) -> DynamicImage {
+    let (w, h) = image.dimensions();
+    let side = w.min(h);
+    image.crop_imm((w - side) / 2, (h - side) / 2, side, side)
+}
+
+/// iTunes artwork URLs embed the size (`.../100x100bb.jpg`); ask for the largest
+fn itunes_high_res(url: &str) -> String {
+    url.replace("100x100bb", "3000x3000bb")
+}
+
+/// Extract the video ID from a YouTube watch, short or youtu.be URL
+fn youtube_video_id(url: &str) -> Option<&str> {
+    let id = if let Some(pos) = url.find("v=") {
+        &url[pos + 2..]
+    } else if let Some(pos) = url.find("youtu.be/") {
+        &url[pos + 9..]
+    } else if let Some(pos) = url.find("/shorts/") {
+        &url[pos + 8..]
+    } else {
+        return None;
+    };
+    let end = id.find(['&', '?', '#', '/']).unwrap_or(id.len());
+    let id = &id[..end];
+    if id.is_empty() {
+        None
+    } else {
+        Some(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crop_to_square() {
+        let wide = DynamicImage::new_rgb8(1280, 720);
+        assert_eq!(crop_to_square(&wide).dimensions(), (720, 720));
+        let tall = DynamicImage::new_rgb8(300, 400);
+        assert_eq!(crop_to_square(&tall).dimensions(), (300, 300));
+    }
+
+    #[test]
+    fn test_itunes_high_res() {
+        assert_eq!(
+            itunes_high_res("https://is1.mzstatic.com/image/thumb/x/100x100bb.jpg"),
+            "https://is1.mzstatic.com/image/thumb/x/3000x3000bb.jpg"
+        );
+    }
+
+    #[test]
+    fn test_youtube_video_id() {
+        assert_eq!(youtube_video_id("https://www.youtube.com/watch?v=abc123&list=x"), Some("abc123"));
+        assert_eq!(youtube_video_id("https://youtu.be/abc123?t=5"), Some("abc123"));
+        assert_eq!(youtube_video_id("https://www.youtube.com/shorts/abc123"), Some("abc123"));
+        assert_eq!(youtube_video_id("https://example.com/"), None);
+    }
+}
@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser)]
 #[command(name = "rustwav")]
@@ -26,7 +26,12 @@ pub struct PortableConfig {
 
 impl PortableConfig {
     pub fn from_cli(cli: &Cli) -> Self {
-        if cli.portable {
+        Self::new(cli.portable)
+    }
+
+    /// Settings for portable mode on or off
+    pub fn new(enabled: bool) -> Self {
+        if enabled {
             Self {
                 enabled: true,
                 max_cover_dim: 128,
@@ -47,26 +52,119 @@ impl PortableConfig {
 #[derive(Subcommand)]
 pub enum Commands {
     Album {
+        /// Output format, or "original" to keep the source codec without re-encoding
         #[arg(short, long, default_value = "mp3")]
         format: String,
         #[arg(short, long, default_value = "high")]
         quality: String,
+        /// Fetch and embed lyrics for each downloaded track
+        #[arg(long, default_value_t = false)]
+        lyrics: bool,
+        /// Minimum source bitrate in kbps, checked before each download
+        /// (overrides source_quality.min_bitrate_kbps from the config)
+        #[arg(long, value_name = "KBPS")]
+        require_quality: Option<u32>,
+        /// Write ReplayGain tags after downloading
+        #[arg(long, default_value_t = false)]
+        replaygain: bool,
+        /// Normalize loudness in place (destructive, for players that ignore ReplayGain)
+        #[arg(long, default_value_t = false)]
+        normalize: bool,
+        /// Cut leading and trailing silence after downloading
+        #[arg(long, default_value_t = false)]
+        trim_silence: bool,
+        /// Cut SponsorBlock "music_offtopic" segments (intros, skits, outros) from music videos
+        #[arg(long, default_value_t = false)]
+        sponsorblock: bool,
         link: String,
     },
     Playlist {
+        /// Output format, or "original" to keep the source codec without re-encoding
         #[arg(short, long, default_value = "mp3")]
         format: String,
         #[arg(short, long, default_value = "high")]
         quality: String,
+        /// Fetch and embed lyrics for each downloaded track
+        #[arg(long, default_value_t = false)]
+        lyrics: bool,
+        /// Minimum source bitrate in kbps, checked before each download
+        /// (overrides source_quality.min_bitrate_kbps from the config)
+        #[arg(long, value_name = "KBPS")]
+        require_quality: Option<u32>,
+        /// Write ReplayGain tags after downloading
+        #[arg(long, default_value_t = false)]
+        replaygain: bool,
+        /// Normalize loudness in place (destructive, for players that ignore ReplayGain)
+        #[arg(long, default_value_t = false)]
+        normalize: bool,
+        /// Cut leading and trailing silence after downloading
+        #[arg(long, default_value_t = false)]
+        trim_silence: bool,
+        /// Cut SponsorBlock "music_offtopic" segments (intros, skits, outros) from music videos
+        #[arg(long, default_value_t = false)]
+        sponsorblock: bool,
+        /// Playlist files to write: m3u8, pls, xspf, jspf (comma-separated;
+        /// defaults to playlist.formats from the config)
+        #[arg(long = "playlist-format", value_name = "FORMAT", value_delimiter = ',')]
+        playlist_formats: Vec<String>,
         link: String,
     },
-    /// Convert audio files between formats (mp3, flac, wav, aac)
+    /// Keep Spotify playlists in sync: download new tracks and rewrite the
+    /// playlist in Spotify's order. Without a playlist, syncs every tracked one.
+    Sync {
+        /// Spotify playlist link (starts tracking it), or the name of a tracked playlist
+        playlist: Option<String>,
+        /// Output format for newly tracked playlists (tracked ones keep theirs)
+        #[arg(short, long)]
+        format: Option<String>,
+        #[arg(short, long)]
+        quality: Option<String>,
+        /// Fetch and embed lyrics for new tracks
+        #[arg(long, default_value_t = false)]
+        lyrics: bool,
+        /// Move tracks a sync downloaded that no tracked playlist has any more to the trash
+        #[arg(long, default_value_t = false)]
+        clean: bool,
+        /// List tracked playlists
+        #[arg(long, default_value_t = false)]
+        list: bool,
+        /// Stop tracking the playlist; its files stay in the library
+        #[arg(long, default_value_t = false)]
+        untrack: bool,
+        /// Playlist files to write: m3u8, pls, xspf, jspf (comma-separated;
+        /// defaults to playlist.formats from the config)
+        #[arg(long = "playlist-format", value_name = "FORMAT", value_delimiter = ',')]
+        playlist_formats: Vec<String>,
+    },
+    /// Import M3U/M3U8/PLS playlists from other tools: match entries to the
+    /// library, download missing tracks and write a clean copy to data/playlists
+    Import {
+        /// Playlist files to import
+        #[arg(required = true)]
+        playlists: Vec<String>,
+        /// Format for downloaded tracks
+        #[arg(short, long, default_value = "mp3")]
+        format: String,
+        #[arg(short, long, default_value = "high")]
+        quality: String,
+        /// Fetch and embed lyrics for downloaded tracks
+        #[arg(long, default_value_t = false)]
+        lyrics: bool,
+        /// Only report how entries resolve; don't download or write anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// Playlist files to write: m3u8, pls, xspf, jspf (comma-separated;
+        /// defaults to playlist.formats from the config)
+        #[arg(long = "playlist-format", value_name = "FORMAT", value_delimiter = ',')]
+        playlist_formats: Vec<String>,
+    },
+    /// Convert audio files between formats (mp3, flac, wav, aac, opus, ogg, m4a, alac, aiff)
     Convert {
         /// Input file or directory to convert
         #[arg(short, long)]
         input: String,
 
-        /// Target format (mp3, flac, wav, aac)
+        /// Target format (mp3, flac, wav, aac, opus, ogg, m4a, alac, aiff)
         #[arg(short = 't', long, default_value = "mp3")]
         to: String,
 
@@ -81,6 +179,54 @@ pub enum Commands {
         /// Process directories recursively
         #[arg(short, long, default_value_t = false)]
         recursive: bool,
+
+        /// Write ReplayGain tags to the converted files (album gain per folder)
+        #[arg(long, default_value_t = false)]
+        replaygain: bool,
+
+        /// Normalize loudness of the converted files in place
+        #[arg(long, default_value_t = false)]
+        normalize: bool,
+
+        /// LAME VBR quality for mp3, 0 (best) to 9, instead of a fixed bitrate
+        #[arg(long)]
+        vbr: Option<u8>,
+
+        /// Bitrate in kbit/s for lossy formats, overriding --quality
+        #[arg(long)]
+        bitrate: Option<u32>,
+
+        /// Opus application: audio, voip or lowdelay
+        #[arg(long)]
+        opus_application: Option<String>,
+
+        /// FLAC compression level, 0 (fastest) to 12 (smallest)
+        #[arg(long)]
+        compression_level: Option<u8>,
+
+        /// Output sample rate in Hz (e.g. 44100)
+        #[arg(long)]
+        sample_rate: Option<u32>,
+
+        /// Output bit depth for lossless formats (16, 24, or 32 for wav/aiff)
+        #[arg(long)]
+        bit_depth: Option<u8>,
+
+        /// Downmix to mono
+        #[arg(long, default_value_t = false)]
+        mono: bool,
+
+        /// Dither when reducing the bit depth (needs --bit-depth)
+        #[arg(long, default_value_t = false)]
+        dither: bool,
+
+        /// Write converted files under this folder, recreating the folders below the input
+        #[arg(short, long)]
+        output_dir: Option<String>,
+
+        /// Keep the originals and record converted files as format variants of the same track
+        #[arg(long, default_value_t = false)]
+        keep_both: bool,
     },
     /// Clean up the download database by removing entries for deleted files
     Cleanup {
@@ -92,6 +238,21 @@ pub enum Commands {
         #[arg(short, long, default_value_t = false)]
         verbose: bool,
     },
+    /// Check or regenerate the playlists in data/playlists
+    Playlists {
+        #[command(subcommand)]
+        action: PlaylistsAction,
+    },
+    /// Manage smart playlists built from library rules (genre, year, rating, ...)
+    Smart {
+        #[command(subcommand)]
+        action: SmartAction,
+    },
+    /// List, restore or permanently delete what was removed from the library
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction,
+    },
     /// Retry failed operations from error logs
     Retry {
         /// Type of errors to retry: download, convert, refresh, all
@@ -128,7 +289,7 @@ pub enum Commands {
         #[arg(short, long, default_value_t = false)]
         recursive: bool,
     },
-    /// Re-tag audio files with metadata from Spotify
+    /// Re-tag audio files with metadata from Spotify and/or MusicBrainz
     Retag {
         /// Input file or directory to retag
         #[arg(short, long)]
@@ -138,7 +299,7 @@ pub enum Commands {
         #[arg(short, long, default_value_t = false)]
         recursive: bool,
 
-        /// Override artist name (optional, otherwise uses existing or searches Spotify)
+        /// Override artist name (optional, otherwise uses existing or searches the providers)
         #[arg(long)]
         artist: Option<String>,
 
@@ -154,9 +315,200 @@ pub enum Commands {
         #[arg(long)]
         genre: Option<String>,
 
-        /// Skip Spotify lookup, only apply provided overrides
+        /// Skip metadata lookup, only apply provided overrides
         #[arg(long, default_value_t = false)]
         no_lookup: bool,
+
+        /// Metadata providers to try, in order (e.g. "musicbrainz" or "musicbrainz,spotify").
+        /// Defaults to the `metadata.retag` order in data/config.json
+        #[arg(long)]
+        provider: Option<String>,
+
+        /// Show the tag changes for each file and ask before writing them
+        #[arg(long, default_value_t = false)]
+        preview: bool,
+    },
+    /// Fetch lyrics for audio files and embed them (USLT/SYLT or Vorbis LYRICS)
+    Lyrics {
+        /// Input file or directory
+        #[arg(short, long)]
+        input: String,
+
+        /// Process directories recursively
+        #[arg(short, long, default_value_t = false)]
+        recursive: bool,
+
+        /// Also write synced lyrics to .lrc files next to the tracks
+        #[arg(long, default_value_t = false)]
+        lrc: bool,
+
+        /// Refetch lyrics even for files that already have them
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+    /// Manage embedded cover art: extract, embed, folder, strip or audit
+    Cover {
+        #[command(subcommand)]
+        action: CoverAction,
+    },
+    /// Mirror the library (or part of it) into a separate tree for a device,
+    /// transcoding and renaming per an export profile from data/config.json
+    Export {
+        /// Export profile (e.g. car-usb, portable, phone)
+        #[arg(long, default_value = "car-usb")]
+        profile: String,
+
+        /// Destination folder, e.g. the mount point of a USB stick
+        #[arg(short, long)]
+        dest: String,
+
+        /// Only export tracks by this artist (matched against tags)
+        #[arg(long)]
+        artist: Option<String>,
+
+        /// Only export tracks from this album (matched against tags)
+        #[arg(long)]
+        album: Option<String>,
+
+        /// Only export the tracks of this playlist (name in data/playlists or path to an .m3u8/.m3u)
+        #[arg(long)]
+        playlist: Option<String>,
+
+        /// Space the export may use in MB (overrides the profile's capacity_mb)
+        #[arg(long, value_name = "MB")]
+        capacity: Option<u64>,
+
+        /// Show what would be exported and removed without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PlaylistsAction {
+    /// Report playlist entries whose files are gone and what they most likely were
+    Check {
+        /// Rewrite the playlists: follow converted and moved tracks, replace
+        /// broken entries with their matches and drop the rest
+        #[arg(long, default_value_t = false)]
+        repair: bool,
+    },
+    /// Rewrite the playlist of every album and playlist downloaded so far
+    /// from its Spotify link, matching tracks to the library by name
+    Regenerate {
+        /// Download tracks that aren't in the library yet
+        #[arg(long, default_value_t = false)]
+        download: bool,
     },
 }
 
+#[derive(Subcommand)]
+pub enum SmartAction {
+    /// List smart playlists and their rules
+    List,
+    /// Create or replace a smart playlist and write it
+    Add {
+        name: String,
+        /// Rules as key=value pairs separated by ';', e.g.
+        /// "genre=ambient,chill; year=1990-1999; added=30; format=flac;
+        /// duration=120-600; rating=4; limit=50; sort=-added"
+        #[arg(default_value = "")]
+        rules: String,
+    },
+    /// Delete a smart playlist definition (its playlist files stay)
+    Remove { name: String },
+    /// Regenerate one smart playlist, or all of them
+    Run { name: Option<String> },
+}
+
+/// Files a cover action works on
+#[derive(Args)]
+pub struct CoverTarget {
+    /// Input file or directory
+    #[arg(short, long)]
+    pub input: String,
+
+    /// Process directories recursively
+    #[arg(short, long, default_value_t = false)]
+    pub recursive: bool,
+}
+
+#[derive(Subcommand)]
+pub enum CoverAction {
+    /// Save embedded art to files
+    Extract {
+        #[command(flatten)]
+        target: CoverTarget,
+        /// Directory to extract covers into (defaults to next to each track)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Embed an image into each track
+    Embed {
+        #[command(flatten)]
+        target: CoverTarget,
+        /// Image to embed
+        #[arg(long)]
+        image: String,
+    },
+    /// Re-embed cover.jpg/folder.jpg from each album folder
+    Folder {
+        #[command(flatten)]
+        target: CoverTarget,
+    },
+    /// Remove embedded art
+    Strip {
+        #[command(flatten)]
+        target: CoverTarget,
+    },
+    /// Report missing, tiny or non-square covers
+    Audit {
+        #[command(flatten)]
+        target: CoverTarget,
+        /// Covers smaller than this many pixels on either side are reported as tiny
+        #[arg(long, default_value_t = 300)]
+        min_size: u32,
+    },
+}
+
+impl CoverAction {
+    pub fn target(&self) -> &CoverTarget {
+        match self {
+            CoverAction::Extract { target, .. }
+            | CoverAction::Embed { target, .. }
+            | CoverAction::Folder { target }
+            | CoverAction::Strip { target }
+            | CoverAction::Audit { target, .. } => target,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CoverAction::Extract { .. } => "extract",
+            CoverAction::Embed { .. } => "embed",
+            CoverAction::Folder { .. } => "folder",
+            CoverAction::Strip { .. } => "strip",
+            CoverAction::Audit { .. } => "audit",
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum TrashAction {
+    /// List trashed files and download cache entries
+    List,
+    /// Put trashed items back where they were
+    Restore {
+        /// IDs (or unique ID prefixes) shown by `trash list`
+        ids: Vec<String>,
+        /// Restore everything in the trash
+        #[arg(long, default_value_t = false)]
+        all: bool,
+    },
+    /// Permanently delete trashed items
+    Empty {
+        /// Only delete items trashed more than this many days ago
+        #[arg(long)]
+        older_than: Option<u32>,
+    },
+}
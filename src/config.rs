@@ -0,0 +1,503 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::sources::providers::Operation;
+
+/// Default location of the user configuration file
+pub const CONFIG_PATH: &str = "data/config.json";
+
+/// User configuration loaded from `data/config.json`.
+/// Every section is optional; missing keys fall back to their defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    pub podcast: PodcastConfig,
+    pub lyrics: LyricsConfig,
+    pub metadata: MetadataConfig,
+    pub artwork: ArtworkConfig,
+    pub tagging: TaggingConfig,
+    pub source_quality: SourceQualityConfig,
+    pub loudness: LoudnessConfig,
+    pub trim: TrimConfig,
+    pub export: ExportConfig,
+    pub convert: ConvertConfig,
+    pub playlist: PlaylistConfig,
+}
+
+/// Settings for podcast episodes found in Spotify playlists
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PodcastConfig {
+    /// yt-dlp source used to locate episode audio. `{show}` and `{title}` are
+    /// replaced with the show and episode names, e.g. "ytsearch1:{show} {title}"
+    /// or "scsearch1:{title}" for SoundCloud.
+    pub source: String,
+}
+
+impl Default for PodcastConfig {
+    fn default() -> Self {
+        Self {
+            source: "ytsearch1:{show} {title}".to_string(),
+        }
+    }
+}
+
+/// Settings for lyrics lookup and embedding
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LyricsConfig {
+    /// Base URL of the LRCLIB-compatible lyrics server
+    pub base_url: String,
+    /// Also write synced lyrics to an `.lrc` file next to each track
+    pub write_lrc: bool,
+}
+
+impl Default for LyricsConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://lrclib.net".to_string(),
+            write_lrc: false,
+        }
+    }
+}
+
+/// Metadata provider settings. Each operation lists provider names
+/// ("spotify", "musicbrainz") in the order they are tried.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MetadataConfig {
+    /// Base URL of the MusicBrainz server (or a local mirror)
+    pub musicbrainz_url: String,
+    /// Provider order for refreshing library metadata (TUI, convert, retry)
+    pub refresh: Vec<String>,
+    /// Provider order for the `retag` command
+    pub retag: Vec<String>,
+    /// Provider order for filling in missing album genres after downloads
+    pub genre: Vec<String>,
+}
+
+impl Default for MetadataConfig {
+    fn default() -> Self {
+        let order = vec!["spotify".to_string(), "musicbrainz".to_string()];
+        Self {
+            musicbrainz_url: "https://musicbrainz.org".to_string(),
+            refresh: order.clone(),
+            retag: order.clone(),
+            genre: order,
+        }
+    }
+}
+
+impl MetadataConfig {
+    /// Provider order configured for an operation
+    pub fn order(&self, operation: Operation) -> &[String] {
+        match operation {
+            Operation::Refresh => &self.refresh,
+            Operation::Retag => &self.retag,
+            Operation::Genre => &self.genre,
+        }
+    }
+}
+
+/// Cover art provider settings
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ArtworkConfig {
+    /// Providers to ask for artwork ("spotify", "coverartarchive", "itunes", "youtube");
+    /// the highest resolution result wins
+    pub providers: Vec<String>,
+    pub coverartarchive_url: String,
+    pub itunes_url: String,
+    /// Host serving YouTube thumbnails (`{url}/vi/{id}/maxresdefault.jpg`)
+    pub youtube_thumbnail_url: String,
+    /// Crop 16:9 video thumbnails to a centered square
+    pub crop_thumbnails: bool,
+}
+
+impl Default for ArtworkConfig {
+    fn default() -> Self {
+        Self {
+            providers: vec![
+                "spotify".to_string(),
+                "coverartarchive".to_string(),
+                "itunes".to_string(),
+                "youtube".to_string(),
+            ],
+            coverartarchive_url: "https://coverartarchive.org".to_string(),
+            itunes_url: "https://itunes.apple.com".to_string(),
+            youtube_thumbnail_url: "https://i.ytimg.com".to_string(),
+            crop_thumbnails: true,
+        }
+    }
+}
+
+impl PodcastConfig {
+    /// Build the yt-dlp source for an episode from the configured template
+    pub fn source_for(&self, show: &str, title: &str) -> String {
+        self.source.replace("{show}", show).replace("{title}", title)
+    }
+}
+
+/// How a tag field is written when the file already has a value for it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverwritePolicy {
+    /// Replace the existing value
+    #[default]
+    Always,
+    /// Only fill in fields the file doesn't have yet
+    OnlyIfEmpty,
+    /// Leave the field alone
+    Never,
+}
+
+impl OverwritePolicy {
+    /// Whether a new value may be written over the current one
+    pub fn allows(self, has_existing: bool) -> bool {
+        match self {
+            OverwritePolicy::Always => true,
+            OverwritePolicy::OnlyIfEmpty => !has_existing,
+            OverwritePolicy::Never => false,
+        }
+    }
+}
+
+/// Per-field overwrite policy used when tagging files that already have tags.
+/// Fields not listed here (comments, lyrics, ReplayGain, custom frames, other
+/// pictures) are always kept.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TaggingConfig {
+    pub artist: OverwritePolicy,
+    pub album: OverwritePolicy,
+    pub title: OverwritePolicy,
+    pub track: OverwritePolicy,
+    pub genre: OverwritePolicy,
+    pub cover: OverwritePolicy,
+}
+
+/// What happens to a track whose best source stream is below the required quality
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum BelowQualityPolicy {
+    /// Don't download it
+    Skip,
+    /// Download it anyway and mark the file with a quality warning tag
+    #[default]
+    Warn,
+    /// Don't download it now; add it to the error log so it can be retried later
+    Retry,
+}
+
+/// Minimum quality of the source stream, checked before downloading.
+/// Zero or empty values disable the individual checks.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SourceQualityConfig {
+    /// Minimum average bitrate in kbps
+    pub min_bitrate_kbps: u32,
+    /// Minimum sample rate in Hz
+    pub min_sample_rate: u32,
+    /// Accepted codecs (prefix match, e.g. "opus" or "mp4a")
+    pub codecs: Vec<String>,
+    pub policy: BelowQualityPolicy,
+}
+
+impl SourceQualityConfig {
+    /// Whether any requirement is set
+    pub fn is_enabled(&self) -> bool {
+        self.min_bitrate_kbps > 0 || self.min_sample_rate > 0 || !self.codecs.is_empty()
+    }
+}
+
+/// Loudness analysis (ReplayGain) and normalization settings
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LoudnessConfig {
+    /// Write ReplayGain tags after every download
+    pub replaygain: bool,
+    /// Normalize portable mode downloads in place (two-pass loudnorm), for
+    /// players that ignore ReplayGain
+    pub normalize_portable: bool,
+    /// Integrated loudness target of normalization, in LUFS
+    pub target_lufs: f64,
+    /// Maximum true peak after normalization, in dBTP
+    pub true_peak_db: f64,
+    /// Loudness range target, in LU
+    pub lra: f64,
+}
+
+impl Default for LoudnessConfig {
+    fn default() -> Self {
+        Self {
+            replaygain: false,
+            normalize_portable: false,
+            target_lufs: -16.0,
+            true_peak_db: -1.5,
+            lra: 11.0,
+        }
+    }
+}
+
+/// Post-download trimming of silence and off-topic segments
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TrimConfig {
+    /// Cut leading and trailing silence after every download
+    pub silence: bool,
+    /// Level below which audio counts as silence, in dBFS
+    pub threshold_db: f64,
+    /// Silence kept at each end so tracks don't start abruptly, in seconds
+    pub keep_secs: f64,
+    /// Cut SponsorBlock `music_offtopic` segments (intros, skits, outros)
+    pub sponsorblock: bool,
+}
+
+impl Default for TrimConfig {
+    fn default() -> Self {
+        Self {
+            silence: false,
+            threshold_db: -50.0,
+            keep_secs: 0.2,
+            sponsorblock: false,
+        }
+    }
+}
+
+/// Where `convert` writes its output
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ConvertConfig {
+    /// Folder converted files go to, recreating the folders below the input
+    /// (the library for the TUI); unset writes them beside the originals
+    pub output_dir: Option<String>,
+    /// Keep the originals and record converted files as format variants of
+    /// the same track instead of asking to delete the originals
+    pub keep_both: bool,
+}
+
+/// How track paths are written in playlists
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PathStyle {
+    /// Relative to the playlist file
+    #[default]
+    Relative,
+    /// Absolute paths on this machine
+    Absolute,
+    /// `path_prefix` followed by the path below the folder the playlist and
+    /// its tracks share
+    Prefix,
+}
+
+/// Playlist file formats rustwav can write
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaylistFormat {
+    M3u8,
+    Pls,
+    Xspf,
+    Jspf,
+}
+
+impl PlaylistFormat {
+    pub const ALL: [PlaylistFormat; 4] = [
+        PlaylistFormat::M3u8,
+        PlaylistFormat::Pls,
+        PlaylistFormat::Xspf,
+        PlaylistFormat::Jspf,
+    ];
+
+    /// Parse a format name as used on the command line ("m3u" means M3U8)
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "m3u8" | "m3u" => Some(PlaylistFormat::M3u8),
+            "pls" => Some(PlaylistFormat::Pls),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            "jspf" => Some(PlaylistFormat::Jspf),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u8 => "m3u8",
+            PlaylistFormat::Pls => "pls",
+            PlaylistFormat::Xspf => "xspf",
+            PlaylistFormat::Jspf => "jspf",
+        }
+    }
+}
+
+/// Playlist files written for downloaded playlists and exports
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PlaylistConfig {
+    /// Formats written for every playlist (m3u8, pls, xspf, jspf)
+    pub formats: Vec<PlaylistFormat>,
+    pub path_style: PathStyle,
+    /// Put in front of track paths with the `prefix` style, e.g. "/sdcard/Music/"
+    pub path_prefix: String,
+    /// Separate folders with backslashes, for devices that require them
+    pub backslashes: bool,
+    /// Write a `#PLAYLIST` line with the playlist's name
+    pub playlist_name: bool,
+    /// Write an `#EXTIMG` line pointing at each track's album cover
+    pub extimg: bool,
+}
+
+impl Default for PlaylistConfig {
+    fn default() -> Self {
+        Self {
+            formats: vec![PlaylistFormat::M3u8],
+            path_style: PathStyle::Relative,
+            path_prefix: String::new(),
+            backslashes: false,
+            playlist_name: true,
+            extimg: false,
+        }
+    }
+}
+
+/// Encoder options on top of the quality preset. Unset fields keep FFmpeg's
+/// defaults (or the source's sample rate, bit depth and channels).
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct EncoderSettings {
+    /// LAME VBR quality for MP3, 0 (best) to 9; replaces the CBR bitrate
+    pub vbr: Option<u8>,
+    /// Bitrate in kbit/s for lossy formats, overriding the quality preset
+    pub bitrate_kbps: Option<u32>,
+    /// Opus tuning: audio, voip or lowdelay
+    pub opus_application: Option<String>,
+    /// FLAC compression level, 0 (fastest) to 12 (smallest)
+    pub compression_level: Option<u8>,
+    /// Output sample rate in Hz, e.g. 44100
+    pub sample_rate: Option<u32>,
+    /// Output bit depth for lossless formats: 16, 24 or 32
+    pub bit_depth: Option<u8>,
+    /// Downmix to a single channel
+    pub mono: bool,
+    /// Dither when reducing the bit depth
+    pub dither: bool,
+}
+
+/// How the library is written to one kind of device by `export`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ExportProfile {
+    /// Audio format on the device, or "original" to copy files unchanged.
+    /// Files already in this format are copied, never re-encoded.
+    pub format: String,
+    /// Encoding quality for transcoded files: high, medium or low
+    pub quality: String,
+    /// Encoder options for transcoded files
+    #[serde(flatten)]
+    pub encoder: EncoderSettings,
+    /// Longest side of embedded covers in pixels; 0 removes covers
+    pub max_cover_dim: u32,
+    /// Largest embedded cover in KB (JPEG quality is lowered to fit)
+    pub max_cover_kb: usize,
+    /// Restrict folder and file names to FAT32-safe characters
+    pub fat32: bool,
+    /// Longest folder or file name
+    pub max_filename_len: usize,
+    /// Space the export may use in MB; 0 means no limit
+    pub capacity_mb: u64,
+}
+
+impl Default for ExportProfile {
+    fn default() -> Self {
+        Self {
+            format: "mp3".to_string(),
+            quality: "high".to_string(),
+            encoder: EncoderSettings::default(),
+            max_cover_dim: 500,
+            max_cover_kb: 300,
+            fat32: true,
+            max_filename_len: 64,
+            capacity_mb: 0,
+        }
+    }
+}
+
+/// Device profiles for `export`, by name
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ExportConfig {
+    pub profiles: BTreeMap<String, ExportProfile>,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        let mut profiles = BTreeMap::new();
+        // Older head units only play 44.1 kHz
+        profiles.insert(
+            "car-usb".to_string(),
+            ExportProfile {
+                encoder: EncoderSettings {
+                    sample_rate: Some(44100),
+                    ..EncoderSettings::default()
+                },
+                max_cover_dim: 300,
+                max_cover_kb: 100,
+                ..ExportProfile::default()
+            },
+        );
+        // Same limits as portable mode downloads
+        profiles.insert(
+            "portable".to_string(),
+            ExportProfile {
+                quality: "medium".to_string(),
+                max_cover_dim: 128,
+                max_cover_kb: 64,
+                ..ExportProfile::default()
+            },
+        );
+        profiles.insert(
+            "phone".to_string(),
+            ExportProfile {
+                format: "opus".to_string(),
+                fat32: false,
+                max_filename_len: 100,
+                ..ExportProfile::default()
+            },
+        );
+        Self { profiles }
+    }
+}
+
+impl Config {
+    /// Load the configuration from the default path
+    pub fn load() -> Self {
+        Self::load_from(CONFIG_PATH)
+    }
+
+    /// Load the configuration from a specific path, falling back to defaults
+    /// if the file is missing or cannot be parsed
+    pub fn load_from(path: &str) -> Self {
+        if Path::new(path).exists() {
+            let data = fs::read_to_string(path).unwrap_or_default();
+            serde_json::from_str(&data).unwrap_or_default()
+        } else {
+            Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overwrite_policy_allows() {
+        assert!(OverwritePolicy::Always.allows(false));
+        assert!(OverwritePolicy::Always.allows(true));
+        assert!(OverwritePolicy::OnlyIfEmpty.allows(false));
+        assert!(!OverwritePolicy::OnlyIfEmpty.allows(true));
+        assert!(!OverwritePolicy::Never.allows(false));
+        assert!(!OverwritePolicy::Never.allows(true));
+    }
+}
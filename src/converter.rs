@@ -3,8 +3,11 @@ use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
+use crate::config::EncoderSettings;
+use crate::trash::{self, Trash};
+
 /// Supported audio formats for conversion
-pub const SUPPORTED_FORMATS: [&str; 4] = ["mp3", "flac", "wav", "aac"];
+pub const SUPPORTED_FORMATS: [&str; 9] = ["mp3", "flac", "wav", "aac", "opus", "ogg", "m4a", "alac", "aiff"];
 
 /// Check if FFmpeg is available on the system
 pub fn check_ffmpeg_available() -> bool {
@@ -26,28 +29,260 @@ pub fn quality_to_bitrate(format: &str, quality: &str) -> Option<&'static str> {
             "low" => "128k",
             _ => "320k",
         }),
-        "aac" => Some(match quality {
+        "aac" | "m4a" | "ogg" => Some(match quality {
             "high" => "256k",
             "medium" => "192k",
             "low" => "128k",
             _ => "256k",
         }),
-        // FLAC and WAV are lossless, no bitrate setting
+        // Opus is transparent at much lower bitrates
+        "opus" => Some(match quality {
+            "high" => "160k",
+            "medium" => "128k",
+            "low" => "96k",
+            _ => "160k",
+        }),
+        // FLAC, ALAC, WAV and AIFF are lossless, no bitrate setting
         _ => None,
     }
 }
 
 /// Get the FFmpeg codec for a given format
-fn format_to_codec(format: &str) -> &'static str {
+pub fn format_to_codec(format: &str) -> &'static str {
     match format {
         "mp3" => "libmp3lame",
         "flac" => "flac",
         "wav" => "pcm_s16le",
-        "aac" => "aac",
+        "aac" | "m4a" => "aac",
+        "opus" => "libopus",
+        "ogg" => "libvorbis",
+        "alac" => "alac",
+        "aiff" => "pcm_s16be",
         _ => "libmp3lame",
     }
 }
 
+/// Whether a format stores audio losslessly
+pub fn is_lossless_format(format: &str) -> bool {
+    matches!(format, "flac" | "wav" | "alac" | "aiff")
+}
+
+/// File extension for a format. ALAC is stored in an M4A container.
+pub fn format_extension(format: &str) -> &str {
+    match format {
+        "alac" => "m4a",
+        other => other,
+    }
+}
+
+/// Sample rates accepted for conversion output
+const SAMPLE_RATES: [u32; 13] = [
+    8000, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000, 88200, 96000, 176400, 192000,
+];
+
+/// The only sample rates libopus encodes at
+const OPUS_SAMPLE_RATES: [u32; 5] = [8000, 12000, 16000, 24000, 48000];
+
+/// Opus encoder tunings
+pub const OPUS_APPLICATIONS: [&str; 3] = ["audio", "voip", "lowdelay"];
+
+/// Whether the format has a VBR quality scale (LAME's V0-V9)
+pub fn supports_vbr(format: &str) -> bool {
+    format == "mp3"
+}
+
+/// Whether the output bit depth can be chosen for the format
+pub fn supports_bit_depth(format: &str) -> bool {
+    is_lossless_format(format)
+}
+
+/// Whether the format takes a compression level
+pub fn supports_compression_level(format: &str) -> bool {
+    format == "flac"
+}
+
+/// Whether the format takes an Opus application setting
+pub fn supports_opus_application(format: &str) -> bool {
+    format == "opus"
+}
+
+/// Check that encoder settings are valid for the target format
+pub fn validate_encoder(format: &str, settings: &EncoderSettings) -> anyhow::Result<()> {
+    let format = format.to_lowercase();
+    let format = format.as_str();
+
+    if let Some(vbr) = settings.vbr {
+        if !supports_vbr(format) {
+            anyhow::bail!("VBR quality only applies to mp3, not {}", format);
+        }
+        if vbr > 9 {
+            anyhow::bail!("VBR quality must be between 0 and 9, got {}", vbr);
+        }
+        if settings.bitrate_kbps.is_some() {
+            anyhow::bail!("VBR quality and a fixed bitrate can't be combined");
+        }
+    }
+
+    if let Some(kbps) = settings.bitrate_kbps {
+        if is_lossless_format(format) {
+            anyhow::bail!("{} is lossless and takes no bitrate", format);
+        }
+        let (min, max) = match format {
+            "mp3" => (8, 320),
+            "opus" => (6, 510),
+            _ => (32, 512),
+        };
+        if !(min..=max).contains(&kbps) {
+            anyhow::bail!("{} bitrate must be between {} and {} kbit/s, got {}", format, min, max, kbps);
+        }
+    }
+
+    if let Some(application) = &settings.opus_application {
+        if !supports_opus_application(format) {
+            anyhow::bail!("Opus application only applies to opus, not {}", format);
+        }
+        if !OPUS_APPLICATIONS.contains(&application.as_str()) {
+            anyhow::bail!(
+                "Unknown Opus application: {}. Supported: {:?}",
+                application,
+                OPUS_APPLICATIONS
+            );
+        }
+    }
+
+    if let Some(level) = settings.compression_level {
+        if !supports_compression_level(format) {
+            anyhow::bail!("Compression level only applies to flac, not {}", format);
+        }
+        if level > 12 {
+            anyhow::bail!("FLAC compression level must be between 0 and 12, got {}", level);
+        }
+    }
+
+    if let Some(rate) = settings.sample_rate {
+        if format == "opus" && !OPUS_SAMPLE_RATES.contains(&rate) {
+            anyhow::bail!("Opus only encodes at {:?} Hz, not {}", OPUS_SAMPLE_RATES, rate);
+        }
+        if format == "mp3" && rate > 48000 {
+            anyhow::bail!("MP3 supports sample rates up to 48000 Hz, not {}", rate);
+        }
+        if !SAMPLE_RATES.contains(&rate) {
+            anyhow::bail!("Unsupported sample rate: {}. Supported: {:?}", rate, SAMPLE_RATES);
+        }
+    }
+
+    if let Some(depth) = settings.bit_depth {
+        if !supports_bit_depth(format) {
+            anyhow::bail!("Bit depth only applies to lossless formats, not {}", format);
+        }
+        let depths: &[u8] = if matches!(format, "wav" | "aiff") {
+            &[16, 24, 32]
+        } else {
+            &[16, 24]
+        };
+        if !depths.contains(&depth) {
+            anyhow::bail!("{} supports bit depths {:?}, not {}", format, depths, depth);
+        }
+    } else if settings.dither {
+        anyhow::bail!("Dithering needs a target bit depth");
+    }
+
+    Ok(())
+}
+
+/// Drop the settings that don't apply to `format`, for callers that keep
+/// one set of settings across formats (like the TUI)
+pub fn encoder_for_format(format: &str, settings: &EncoderSettings) -> EncoderSettings {
+    let lossless = is_lossless_format(format);
+    EncoderSettings {
+        vbr: settings.vbr.filter(|_| supports_vbr(format)),
+        bitrate_kbps: settings.bitrate_kbps.filter(|_| !lossless && settings.vbr.is_none()),
+        opus_application: settings
+            .opus_application
+            .clone()
+            .filter(|_| supports_opus_application(format)),
+        compression_level: settings.compression_level.filter(|_| supports_compression_level(format)),
+        sample_rate: settings.sample_rate,
+        bit_depth: settings.bit_depth.filter(|_| supports_bit_depth(format)),
+        mono: settings.mono,
+        dither: settings.dither && settings.bit_depth.is_some() && supports_bit_depth(format),
+    }
+}
+
+/// Sample format FFmpeg should hand the encoder for a bit depth
+fn sample_format(format: &str, bit_depth: u8) -> &'static str {
+    match (format, bit_depth) {
+        // The ALAC encoder only takes planar samples
+        ("alac", 16) => "s16p",
+        ("alac", _) => "s32p",
+        (_, 16) => "s16",
+        _ => "s32",
+    }
+}
+
+/// FFmpeg codec for a format; PCM codecs depend on the bit depth
+fn encoder_codec(format: &str, settings: &EncoderSettings) -> String {
+    match (format, settings.bit_depth) {
+        ("wav", Some(depth)) => format!("pcm_s{}le", depth),
+        ("aiff", Some(depth)) => format!("pcm_s{}be", depth),
+        _ => format_to_codec(format).to_string(),
+    }
+}
+
+/// FFmpeg output options (codec, rate control, sample format) for encoding
+/// to `format`
+fn encoder_args(format: &str, quality: &str, settings: &EncoderSettings) -> Vec<String> {
+    let mut args = vec!["-codec:a".to_string(), encoder_codec(format, settings)];
+
+    // Rate control: VBR scale, explicit bitrate, or the quality preset
+    if let Some(vbr) = settings.vbr {
+        args.extend(["-q:a".to_string(), vbr.to_string()]);
+    } else if let Some(kbps) = settings.bitrate_kbps {
+        args.extend(["-b:a".to_string(), format!("{}k", kbps)]);
+    } else if let Some(bitrate) = quality_to_bitrate(format, quality) {
+        args.extend(["-b:a".to_string(), bitrate.to_string()]);
+    }
+
+    if let Some(application) = &settings.opus_application {
+        args.extend(["-application".to_string(), application.clone()]);
+    }
+    if let Some(level) = settings.compression_level {
+        args.extend(["-compression_level".to_string(), level.to_string()]);
+    }
+
+    if let Some(depth) = settings.bit_depth {
+        let sample_fmt = sample_format(format, depth);
+        if matches!(format, "flac" | "alac") {
+            args.extend(["-sample_fmt".to_string(), sample_fmt.to_string()]);
+            if depth == 24 {
+                args.extend(["-bits_per_raw_sample".to_string(), "24".to_string()]);
+            }
+        }
+        if settings.dither {
+            // Requantize in the filter graph so the dither method is honoured
+            args.extend([
+                "-af".to_string(),
+                format!("aresample=osf={}:dither_method=triangular_hp", sample_fmt),
+            ]);
+        }
+    }
+
+    if let Some(rate) = settings.sample_rate {
+        args.extend(["-ar".to_string(), rate.to_string()]);
+    }
+    if settings.mono {
+        args.extend(["-ac".to_string(), "1".to_string()]);
+    }
+
+    args
+}
+
+/// Whether the output container can't take the cover art stream FFmpeg
+/// copies from MP3/FLAC inputs, so video has to be dropped
+fn drops_video(format: &str) -> bool {
+    matches!(format, "opus" | "ogg" | "m4a" | "alac")
+}
+
 /// Check if a format is supported
 pub fn is_supported_format(format: &str) -> bool {
     SUPPORTED_FORMATS.contains(&format.to_lowercase().as_str())
@@ -60,6 +295,51 @@ pub fn get_format_from_path(path: &Path) -> Option<String> {
         .map(|ext| ext.to_lowercase())
 }
 
+/// How far a running conversion has got
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConvertProgress {
+    /// Audio written so far, in seconds
+    pub out_secs: f64,
+    /// Input duration, if FFmpeg could read it
+    pub duration_secs: Option<f64>,
+}
+
+impl ConvertProgress {
+    /// Completed fraction between 0 and 1, if the input duration is known
+    pub fn fraction(&self) -> Option<f64> {
+        self.duration_secs
+            .filter(|d| *d > 0.0)
+            .map(|d| (self.out_secs / d).clamp(0.0, 1.0))
+    }
+}
+
+/// Number of conversions to run at once: one per CPU core
+pub fn parallel_jobs() -> usize {
+    std::thread::available_parallelism().map_or(2, |n| n.get())
+}
+
+/// Where a converted copy of `input_path` is written: beside it, or under
+/// `output_root` at the same place relative to whichever of `input_roots`
+/// holds it (files outside them go straight into `output_root`)
+pub fn output_path(
+    input_path: &Path,
+    output_format: &str,
+    input_roots: &[PathBuf],
+    output_root: Option<&Path>,
+) -> PathBuf {
+    let output_format = output_format.to_lowercase();
+    let extension = format_extension(&output_format);
+    let Some(output_root) = output_root else {
+        return input_path.with_extension(extension);
+    };
+    let relative = input_roots
+        .iter()
+        .find_map(|root| input_path.strip_prefix(root).ok())
+        .filter(|relative| relative.file_name().is_some())
+        .unwrap_or_else(|| Path::new(input_path.file_name().unwrap_or_default()));
+    output_root.join(relative).with_extension(extension)
+}
+
 /// Convert an audio file to a different format using FFmpeg.
 ///
 /// Returns the path to the newly created file on success.
@@ -72,6 +352,63 @@ pub fn convert_audio<F>(
 ) -> anyhow::Result<PathBuf>
 where
     F: Fn(&str) + Send + Clone + 'static,
+{
+    convert_audio_with_progress(
+        input_path,
+        output_format,
+        quality,
+        &EncoderSettings::default(),
+        on_output,
+        |_| {},
+    )
+}
+
+/// Like `convert_audio`, with encoder settings on top of the quality preset,
+/// also reporting progress to `on_progress` as FFmpeg writes the output
+pub fn convert_audio_with_progress<F, P>(
+    input_path: &Path,
+    output_format: &str,
+    quality: &str,
+    settings: &EncoderSettings,
+    on_output: F,
+    on_progress: P,
+) -> anyhow::Result<PathBuf>
+where
+    F: Fn(&str) + Send + Clone + 'static,
+    P: Fn(ConvertProgress),
+{
+    // Generate output path by changing extension
+    let output_path = input_path.with_extension(format_extension(&output_format.to_lowercase()));
+    convert_audio_to(input_path, &output_path, output_format, quality, settings, on_output, on_progress)?;
+    Ok(output_path)
+}
+
+/// Move a finished in-place conversion over the original, keeping the
+/// original in the trash
+fn replace_original(temp_path: &Path, original: &Path, format: &str, trash: &mut Trash) -> anyhow::Result<()> {
+    let reason = format!("replaced by its {} conversion", format);
+    if let Err(e) = trash.trash_file(original, None, &reason) {
+        let _ = std::fs::remove_file(temp_path);
+        return Err(e.context(format!("moving {} to the trash", original.display())));
+    }
+    std::fs::rename(temp_path, original).with_context(|| format!("replacing {}", original.display()))
+}
+
+/// Convert an audio file with FFmpeg, writing the result to `output_path`
+/// (overwritten if it exists). The `on_output` callback receives progress
+/// lines from FFmpeg, `on_progress` the position in the input.
+pub fn convert_audio_to<F, P>(
+    input_path: &Path,
+    output_path: &Path,
+    output_format: &str,
+    quality: &str,
+    settings: &EncoderSettings,
+    on_output: F,
+    on_progress: P,
+) -> anyhow::Result<()>
+where
+    F: Fn(&str) + Send + Clone + 'static,
+    P: Fn(ConvertProgress),
 {
     let output_format = output_format.to_lowercase();
 
@@ -82,36 +419,42 @@ where
             SUPPORTED_FORMATS
         );
     }
+    validate_encoder(&output_format, settings)?;
 
     if !input_path.exists() {
         anyhow::bail!("Input file does not exist: {}", input_path.display());
     }
 
-    // Generate output path by changing extension
-    let output_path = input_path.with_extension(&output_format);
-
-    // Don't overwrite if same file
+    // Same file name: only a codec change within a container (AAC .m4a to
+    // ALAC) is a conversion, written beside the file and then moved over it.
+    // The original goes to the trash first, so it can be restored.
     if input_path == output_path {
-        anyhow::bail!("Input and output formats are the same");
+        if is_in_format(input_path, &output_format) {
+            anyhow::bail!("Input and output formats are the same");
+        }
+        let temp_path = output_path.with_extension(format!("converting.{}", format_extension(&output_format)));
+        convert_audio_to(input_path, &temp_path, &output_format, quality, settings, on_output, on_progress)?;
+        // Parallel conversions share the trash manifest
+        let _guard = trash::lock();
+        return replace_original(&temp_path, output_path, &output_format, &mut Trash::open());
+    }
+    if let Some(parent) = output_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
     }
 
     // Build FFmpeg arguments
-    let codec = format_to_codec(&output_format);
-    let mut args = vec![
-        "-i".to_string(),
-        input_path.to_string_lossy().to_string(),
-        "-codec:a".to_string(),
-        codec.to_string(),
-    ];
+    let codec = encoder_codec(&output_format, settings);
+    let mut args = vec!["-i".to_string(), input_path.to_string_lossy().to_string()];
+    args.extend(encoder_args(&output_format, quality, settings));
 
-    // Add bitrate for lossy formats
-    if let Some(bitrate) = quality_to_bitrate(&output_format, quality) {
-        args.push("-b:a".to_string());
-        args.push(bitrate.to_string());
+    if drops_video(&output_format) {
+        args.push("-vn".to_string());
     }
 
-    // Overwrite output without asking, show progress
+    // Overwrite output without asking, show progress (as key=value lines on
+    // stdout, instead of the stats line on stderr)
     args.push("-y".to_string());
+    args.push("-nostats".to_string());
     args.push("-progress".to_string());
     args.push("pipe:1".to_string());
     args.push(output_path.to_string_lossy().to_string());
@@ -123,6 +466,9 @@ where
         codec
     ));
 
+    // Known up front so progress can be reported as a percentage
+    let duration_secs = probe_duration(input_path);
+
     let mut child = Command::new("ffmpeg")
         .args(&args)
         .stdout(Stdio::piped())
@@ -140,10 +486,16 @@ where
         for line in reader.lines().map_while(Result::ok) {
             let trimmed = line.trim();
             // FFmpeg progress output includes lines like "out_time=00:01:23.456"
-            if trimmed.starts_with("out_time=") {
-                if let Some(time) = trimmed.strip_prefix("out_time=") {
-                    on_output(&format!("Progress: {}", time));
+            if let Some(out_secs) = trimmed.strip_prefix("out_time=").and_then(parse_timestamp) {
+                let progress = ConvertProgress {
+                    out_secs,
+                    duration_secs,
+                };
+                match progress.fraction() {
+                    Some(fraction) => on_output(&format!("Progress: {:.0}%", fraction * 100.0)),
+                    None => on_output(&format!("Progress: {:.1}s", out_secs)),
                 }
+                on_progress(progress);
             }
         }
     }
@@ -171,7 +523,7 @@ where
 
     if !status.success() {
         // Clean up partial output file if it exists
-        let _ = std::fs::remove_file(&output_path);
+        let _ = std::fs::remove_file(output_path);
         anyhow::bail!(
             "FFmpeg conversion failed for: {}",
             input_path.display()
@@ -186,19 +538,181 @@ where
         );
     }
 
+    // FFmpeg only maps the tags it knows between formats and drops most
+    // artwork, so carry everything over natively
+    if let Err(e) = crate::metadata::transfer_tags(input_path, output_path) {
+        on_output(&format!("Tags not carried over: {:#}", e));
+    }
+
     on_output(&format!("Conversion complete: {}", output_path.display()));
 
-    Ok(output_path)
+    Ok(())
+}
+
+/// Parse an FFmpeg "HH:MM:SS.micros" timestamp into seconds
+fn parse_timestamp(stamp: &str) -> Option<f64> {
+    let mut parts = stamp.trim().split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    // FFmpeg reports a negative time before the first packet is written
+    (hours >= 0.0 && parts.next().is_none()).then_some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Input duration in seconds from FFmpeg's input summary
+pub fn probe_duration(file_path: &Path) -> Option<f64> {
+    probe_stream(file_path)?.duration_secs
 }
 
-/// Delete a file (used after successful conversion when user confirms)
-pub fn delete_file(path: &Path) -> anyhow::Result<()> {
-    std::fs::remove_file(path).context(format!("Failed to delete: {}", path.display()))
+/// Audio stream details of a file from FFmpeg's input summary
+pub fn probe_stream(file_path: &Path) -> Option<StreamInfo> {
+    // With no output file FFmpeg exits with an error after printing the summary
+    let output = Command::new("ffmpeg")
+        .arg("-hide_banner")
+        .arg("-i")
+        .arg(file_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .ok()?;
+    Some(parse_stream_info(&String::from_utf8_lossy(&output.stderr)))
+}
+
+/// Whether a file is already in `format`. The extension decides, except for
+/// M4A files, which hold AAC or ALAC: for those the codec is compared.
+pub fn is_in_format(file_path: &Path, format: &str) -> bool {
+    let format = format.to_lowercase();
+    let extension = format_extension(&format);
+    if get_format_from_path(file_path).as_deref() != Some(extension) {
+        return false;
+    }
+    if extension != "m4a" {
+        return true;
+    }
+    let codec = if format == "alac" { "alac" } else { "aac" };
+    probe_stream(file_path)
+        .and_then(|stream| stream.codec)
+        .is_none_or(|c| c == codec)
+}
+
+/// Audio stream details from FFmpeg's input summary
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamInfo {
+    pub codec: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub duration_secs: Option<f64>,
+    /// Stream bitrate, or the whole file's when FFmpeg reports no stream bitrate
+    pub bitrate_kbps: Option<u32>,
+}
+
+/// Parse "Duration: 00:03:25.12, ..." and the first
+/// "Stream #0:0: Audio: mp3, 44100 Hz, ..." line of an FFmpeg log
+pub fn parse_stream_info(log: &str) -> StreamInfo {
+    let duration_secs = log
+        .find("Duration: ")
+        .and_then(|i| log[i + "Duration: ".len()..].split(',').next())
+        .and_then(parse_timestamp);
+
+    let details = log
+        .lines()
+        .find(|l| l.contains("Stream #") && l.contains("Audio: "))
+        .and_then(|line| line.split_once("Audio: "))
+        .map(|(_, details)| details);
+    let codec = details
+        .and_then(|d| d.split(',').next())
+        .and_then(|c| c.split_whitespace().next())
+        .map(str::to_string);
+    let sample_rate = details
+        .and_then(|d| d.split(',').map(str::trim).find_map(|f| f.strip_suffix(" Hz")))
+        .and_then(|r| r.parse().ok());
+    let bitrate_kbps = details
+        .and_then(|d| d.split(',').map(str::trim).find_map(|f| f.strip_suffix(" kb/s")))
+        .or_else(|| {
+            log.find("bitrate: ")
+                .and_then(|i| log[i + "bitrate: ".len()..].split(" kb/s").next())
+        })
+        .and_then(|b| b.trim().parse().ok());
+
+    StreamInfo {
+        codec,
+        sample_rate,
+        duration_secs,
+        bitrate_kbps,
+    }
+}
+
+/// FFmpeg encoder that re-creates a stream with the given codec
+fn encoder_for(codec: Option<&str>, format: &str) -> String {
+    match codec {
+        Some("mp3") => "libmp3lame".to_string(),
+        Some("vorbis") => "libvorbis".to_string(),
+        Some("opus") => "libopus".to_string(),
+        Some(codec @ ("aac" | "alac" | "flac")) => codec.to_string(),
+        Some(codec) if codec.starts_with("pcm_") => codec.to_string(),
+        _ => format_to_codec(format).to_string(),
+    }
+}
+
+/// Re-encode a file in place through an FFmpeg audio filter. The file keeps
+/// its format, codec, sample rate, bitrate and tags; lossy streams of unknown
+/// bitrate are written at the "high" bitrate.
+pub fn filter_in_place(file_path: &Path, filter: &str, stream: &StreamInfo) -> anyhow::Result<()> {
+    let format = get_format_from_path(file_path).context("File has no extension")?;
+    let temp_path = file_path.with_extension(format!("filtered.{}", format));
+
+    let mut args = vec![
+        "-hide_banner".to_string(),
+        "-i".to_string(),
+        file_path.to_string_lossy().to_string(),
+        "-map".to_string(),
+        "0:a".to_string(),
+        "-af".to_string(),
+        filter.to_string(),
+        "-codec:a".to_string(),
+        encoder_for(stream.codec.as_deref(), &format),
+    ];
+    // Some filters resample internally; write the original rate back out
+    if let Some(rate) = stream.sample_rate {
+        args.push("-ar".to_string());
+        args.push(rate.to_string());
+    }
+    let bitrate = match stream.bitrate_kbps {
+        Some(kbps) if quality_to_bitrate(&format, "high").is_some() => Some(format!("{}k", kbps)),
+        _ => quality_to_bitrate(&format, "high").map(str::to_string),
+    };
+    if let Some(bitrate) = bitrate {
+        args.push("-b:a".to_string());
+        args.push(bitrate);
+    }
+    args.push("-y".to_string());
+    args.push(temp_path.to_string_lossy().to_string());
+
+    let output = Command::new("ffmpeg")
+        .args(&args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to spawn FFmpeg. Is it installed?")?;
+
+    let result = if output.status.success() {
+        crate::metadata::copy_tags(file_path, &temp_path).and_then(|()| {
+            std::fs::rename(&temp_path, file_path)
+                .with_context(|| format!("replacing {}", file_path.display()))
+        })
+    } else {
+        Err(anyhow::anyhow!("FFmpeg filtering failed for: {}", file_path.display()))
+    };
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::DownloadDB;
 
     #[test]
     fn test_quality_to_bitrate() {
@@ -206,8 +720,173 @@ mod tests {
         assert_eq!(quality_to_bitrate("mp3", "medium"), Some("192k"));
         assert_eq!(quality_to_bitrate("mp3", "low"), Some("128k"));
         assert_eq!(quality_to_bitrate("aac", "high"), Some("256k"));
+        assert_eq!(quality_to_bitrate("m4a", "low"), Some("128k"));
+        assert_eq!(quality_to_bitrate("ogg", "medium"), Some("192k"));
+        assert_eq!(quality_to_bitrate("opus", "high"), Some("160k"));
+        assert_eq!(quality_to_bitrate("opus", "low"), Some("96k"));
         assert_eq!(quality_to_bitrate("flac", "high"), None);
+        assert_eq!(quality_to_bitrate("alac", "high"), None);
         assert_eq!(quality_to_bitrate("wav", "high"), None);
+        assert_eq!(quality_to_bitrate("aiff", "high"), None);
+    }
+
+    #[test]
+    fn test_encoder_args() {
+        let defaults = EncoderSettings::default();
+        assert_eq!(
+            encoder_args("mp3", "medium", &defaults),
+            ["-codec:a", "libmp3lame", "-b:a", "192k"]
+        );
+        assert_eq!(encoder_args("flac", "high", &defaults), ["-codec:a", "flac"]);
+
+        let vbr = EncoderSettings {
+            vbr: Some(2),
+            sample_rate: Some(44100),
+            mono: true,
+            ..EncoderSettings::default()
+        };
+        assert_eq!(
+            encoder_args("mp3", "high", &vbr),
+            ["-codec:a", "libmp3lame", "-q:a", "2", "-ar", "44100", "-ac", "1"]
+        );
+
+        let opus = EncoderSettings {
+            bitrate_kbps: Some(96),
+            opus_application: Some("voip".to_string()),
+            ..EncoderSettings::default()
+        };
+        assert_eq!(
+            encoder_args("opus", "high", &opus),
+            ["-codec:a", "libopus", "-b:a", "96k", "-application", "voip"]
+        );
+
+        let cd = EncoderSettings {
+            compression_level: Some(8),
+            bit_depth: Some(16),
+            dither: true,
+            ..EncoderSettings::default()
+        };
+        assert_eq!(
+            encoder_args("flac", "high", &cd),
+            [
+                "-codec:a",
+                "flac",
+                "-compression_level",
+                "8",
+                "-sample_fmt",
+                "s16",
+                "-af",
+                "aresample=osf=s16:dither_method=triangular_hp"
+            ]
+        );
+
+        let hires = EncoderSettings {
+            bit_depth: Some(24),
+            ..EncoderSettings::default()
+        };
+        assert_eq!(encoder_args("wav", "high", &hires), ["-codec:a", "pcm_s24le"]);
+        assert_eq!(
+            encoder_args("alac", "high", &hires),
+            ["-codec:a", "alac", "-sample_fmt", "s32p", "-bits_per_raw_sample", "24"]
+        );
+    }
+
+    #[test]
+    fn test_validate_encoder() {
+        let ok = |format: &str, settings: EncoderSettings| validate_encoder(format, &settings).is_ok();
+
+        assert!(ok("mp3", EncoderSettings::default()));
+        assert!(ok("mp3", EncoderSettings { vbr: Some(0), ..Default::default() }));
+        assert!(!ok("mp3", EncoderSettings { vbr: Some(10), ..Default::default() }));
+        assert!(!ok("aac", EncoderSettings { vbr: Some(2), ..Default::default() }));
+        assert!(!ok(
+            "mp3",
+            EncoderSettings { vbr: Some(2), bitrate_kbps: Some(192), ..Default::default() }
+        ));
+
+        assert!(ok("opus", EncoderSettings { bitrate_kbps: Some(64), ..Default::default() }));
+        assert!(!ok("mp3", EncoderSettings { bitrate_kbps: Some(500), ..Default::default() }));
+        assert!(!ok("flac", EncoderSettings { bitrate_kbps: Some(320), ..Default::default() }));
+
+        let application = |name: &str| EncoderSettings {
+            opus_application: Some(name.to_string()),
+            ..Default::default()
+        };
+        assert!(ok("opus", application("lowdelay")));
+        assert!(!ok("opus", application("music")));
+        assert!(!ok("mp3", application("audio")));
+
+        assert!(ok("flac", EncoderSettings { compression_level: Some(12), ..Default::default() }));
+        assert!(!ok("flac", EncoderSettings { compression_level: Some(13), ..Default::default() }));
+        assert!(!ok("wav", EncoderSettings { compression_level: Some(5), ..Default::default() }));
+
+        assert!(ok("mp3", EncoderSettings { sample_rate: Some(44100), ..Default::default() }));
+        assert!(!ok("mp3", EncoderSettings { sample_rate: Some(96000), ..Default::default() }));
+        assert!(!ok("opus", EncoderSettings { sample_rate: Some(44100), ..Default::default() }));
+        assert!(!ok("flac", EncoderSettings { sample_rate: Some(44000), ..Default::default() }));
+
+        assert!(ok("wav", EncoderSettings { bit_depth: Some(32), ..Default::default() }));
+        assert!(!ok("flac", EncoderSettings { bit_depth: Some(32), ..Default::default() }));
+        assert!(!ok("mp3", EncoderSettings { bit_depth: Some(16), ..Default::default() }));
+        assert!(ok("flac", EncoderSettings { bit_depth: Some(16), dither: true, ..Default::default() }));
+        assert!(!ok("flac", EncoderSettings { dither: true, ..Default::default() }));
+    }
+
+    #[test]
+    fn test_encoder_for_format() {
+        let settings = EncoderSettings {
+            vbr: Some(2),
+            compression_level: Some(8),
+            sample_rate: Some(44100),
+            bit_depth: Some(16),
+            dither: true,
+            ..EncoderSettings::default()
+        };
+        let mp3 = encoder_for_format("mp3", &settings);
+        assert_eq!(mp3.vbr, Some(2));
+        assert_eq!(mp3.compression_level, None);
+        assert_eq!(mp3.bit_depth, None);
+        assert!(!mp3.dither);
+        assert_eq!(mp3.sample_rate, Some(44100));
+        assert!(validate_encoder("mp3", &mp3).is_ok());
+
+        let flac = encoder_for_format("flac", &settings);
+        assert_eq!(flac.vbr, None);
+        assert_eq!(flac.compression_level, Some(8));
+        assert!(flac.dither);
+        assert!(validate_encoder("flac", &flac).is_ok());
+    }
+
+    #[test]
+    fn test_parse_stream_info() {
+        let log = "Input #0, mp3, from 'a.mp3':\n  Duration: 00:03:25.50, start: 0.025057, bitrate: 320 kb/s\n  Stream #0:0: Audio: mp3 (mp3float), 44100 Hz, stereo, fltp, 320 kb/s\n";
+        let info = parse_stream_info(log);
+        assert_eq!(info.codec.as_deref(), Some("mp3"));
+        assert_eq!(info.sample_rate, Some(44100));
+        assert_eq!(info.duration_secs, Some(205.5));
+        assert_eq!(info.bitrate_kbps, Some(320));
+        let opus = "  Duration: 00:01:00.00, start: 0.000000, bitrate: 131 kb/s\n  Stream #0:0(eng): Audio: opus, 48000 Hz, stereo, fltp\n";
+        assert_eq!(parse_stream_info(opus).bitrate_kbps, Some(131));
+        assert_eq!(parse_stream_info("nothing"), StreamInfo::default());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("00:01:23.500000"), Some(83.5));
+        assert_eq!(parse_timestamp("01:00:00.000000"), Some(3600.0));
+        assert_eq!(parse_timestamp("-577014:32:22.775808"), None);
+        assert_eq!(parse_timestamp("N/A"), None);
+
+        let progress = ConvertProgress {
+            out_secs: 50.0,
+            duration_secs: Some(200.0),
+        };
+        assert_eq!(progress.fraction(), Some(0.25));
+        let unknown = ConvertProgress {
+            out_secs: 50.0,
+            duration_secs: None,
+        };
+        assert_eq!(unknown.fraction(), None);
     }
 
     #[test]
@@ -217,16 +896,58 @@ mod tests {
         assert!(is_supported_format("flac"));
         assert!(is_supported_format("wav"));
         assert!(is_supported_format("aac"));
-        assert!(!is_supported_format("ogg"));
+        assert!(is_supported_format("opus"));
+        assert!(is_supported_format("ogg"));
+        assert!(is_supported_format("m4a"));
+        assert!(is_supported_format("alac"));
+        assert!(is_supported_format("aiff"));
         assert!(!is_supported_format("wma"));
     }
 
+    #[test]
+    fn test_is_in_format() {
+        assert!(is_in_format(Path::new("/lib/a.FLAC"), "flac"));
+        assert!(!is_in_format(Path::new("/lib/a.mp3"), "flac"));
+        assert!(!is_in_format(Path::new("/lib/a.m4a"), "opus"));
+    }
+
     #[test]
     fn test_format_to_codec() {
         assert_eq!(format_to_codec("mp3"), "libmp3lame");
         assert_eq!(format_to_codec("flac"), "flac");
         assert_eq!(format_to_codec("wav"), "pcm_s16le");
         assert_eq!(format_to_codec("aac"), "aac");
+        assert_eq!(format_to_codec("m4a"), "aac");
+        assert_eq!(format_to_codec("opus"), "libopus");
+        assert_eq!(format_to_codec("ogg"), "libvorbis");
+        assert_eq!(format_to_codec("alac"), "alac");
+        assert_eq!(format_to_codec("aiff"), "pcm_s16be");
+    }
+
+    #[test]
+    fn test_format_extension() {
+        assert_eq!(format_extension("alac"), "m4a");
+        assert_eq!(format_extension("opus"), "opus");
+        assert_eq!(format_extension("mp3"), "mp3");
+    }
+
+    #[test]
+    fn test_output_path() {
+        let input = Path::new("data/music/Artist/Album/01 - Song.flac");
+        let roots = [PathBuf::from("data/music"), PathBuf::from("data/playlists")];
+        assert_eq!(
+            output_path(input, "mp3", &roots, None),
+            PathBuf::from("data/music/Artist/Album/01 - Song.mp3")
+        );
+        assert_eq!(
+            output_path(input, "ALAC", &roots, Some(Path::new("/mnt/lossless"))),
+            PathBuf::from("/mnt/lossless/Artist/Album/01 - Song.m4a")
+        );
+        // Outside every root only the file name is kept
+        assert_eq!(
+            output_path(Path::new("/tmp/x/Song.wav"), "opus", &roots, Some(Path::new("out"))),
+            PathBuf::from("out/Song.opus")
+        );
     }
 
     #[test]
@@ -234,4 +955,28 @@ mod tests {
         // This test depends on the environment, just ensure it doesn't panic
         let _ = check_ffmpeg_available();
     }
+
+    #[test]
+    fn test_replace_original_keeps_original_in_trash() {
+        let dir = std::env::temp_dir().join(format!("rustwav-convert-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let original = dir.join("song.m4a");
+        let temp = dir.join("song.converting.m4a");
+        std::fs::write(&original, "aac").unwrap();
+        std::fs::write(&temp, "alac").unwrap();
+
+        let mut trash = Trash::new(dir.join("trash").to_str().unwrap());
+        replace_original(&temp, &original, "alac", &mut trash).unwrap();
+        assert_eq!(std::fs::read_to_string(&original).unwrap(), "alac");
+        assert!(!temp.exists());
+
+        // Move the conversion away and the original comes back
+        std::fs::rename(&original, dir.join("song.alac.m4a")).unwrap();
+        let id = trash.items()[0].id.clone();
+        let mut db = DownloadDB::new(dir.join("db.json").to_str().unwrap());
+        trash.restore(&id, &mut db).unwrap();
+        assert_eq!(std::fs::read_to_string(&original).unwrap(), "aac");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -1,15 +1,64 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone)]
+use crate::downloader::SourceStream;
+use crate::trim::Trim;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TrackEntry {
+    /// Stable identifier that survives conversions and moves, so playlists
+    /// can follow the track. Assigned when the entry is added.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
     pub artist: String,
     pub title: String,
     pub path: String,
+    /// Codec and bitrate of the stream the file was downloaded from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceStream>,
+    /// Silence and off-topic segments cut from the download, so its length
+    /// can still be checked against the original track
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trims: Vec<Trim>,
+    /// Converted copies kept alongside the file at `path` (e.g. an MP3 of a
+    /// FLAC master), so exports can pick the one in the format they need
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<String>,
+    /// Length of the file in seconds, probed once for smart playlist rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<u64>,
+}
+
+// Entries are identified by artist, title and path; the ID, source details,
+// trims, variants and duration are only known after downloading
+impl PartialEq for TrackEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.artist == other.artist && self.title == other.title && self.path == other.path
+    }
+}
+
+impl Eq for TrackEntry {}
+
+impl TrackEntry {
+    /// Whether neither the file nor any of its variants exists any more
+    pub fn is_missing(&self) -> bool {
+        !Path::new(&self.path).exists() && !self.variants.iter().any(|v| Path::new(v).exists())
+    }
+}
+
+impl Hash for TrackEntry {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.artist.hash(state);
+        self.title.hash(state);
+        self.path.hash(state);
+    }
 }
 
+#[derive(Clone)]
 pub struct DownloadDB {
     pub tracks: HashSet<TrackEntry>,
     file_path: String,
@@ -17,26 +66,94 @@ pub struct DownloadDB {
 
 impl DownloadDB {
     pub fn new(file_path: &str) -> Self {
-        let tracks = if Path::new(file_path).exists() {
+        let tracks: HashSet<TrackEntry> = if Path::new(file_path).exists() {
             let data = fs::read_to_string(file_path).unwrap_or_default();
             serde_json::from_str(&data).unwrap_or_default()
         } else {
             HashSet::new()
         };
+        // Entries from before IDs existed get one derived from their fields,
+        // so every instance loading the same file agrees on it
+        let mut legacy = false;
+        let tracks = tracks
+            .into_iter()
+            .map(|mut entry| {
+                if entry.id.is_empty() {
+                    entry.id = legacy_id(&entry);
+                    legacy = true;
+                }
+                entry
+            })
+            .collect();
 
-        Self {
+        let db = Self {
             tracks,
             file_path: file_path.to_string(),
+        };
+        if legacy {
+            db.save();
         }
+        db
     }
 
-    pub fn add(&mut self, entry: TrackEntry) {
-        self.tracks.insert(entry);
+    pub fn add(&mut self, mut entry: TrackEntry) {
+        // A re-download of the same file keeps the ID playlists follow and
+        // the converted copies kept alongside it
+        if let Some(existing) = self.tracks.get(&entry) {
+            if entry.id.is_empty() {
+                entry.id = existing.id.clone();
+            }
+            if entry.variants.is_empty() {
+                entry.variants = existing.variants.clone();
+            }
+        }
+        // Restored entries keep their ID unless another track took it since
+        if entry.id.is_empty() || self.find_by_id(&entry.id).is_some_and(|t| *t != entry) {
+            entry.id = Uuid::new_v4().to_string();
+        }
+        // The new entry's source, trims and duration replace the old ones
+        self.tracks.replace(entry);
         self.save();
     }
 
-    pub fn contains(&self, entry: &TrackEntry) -> bool {
-        self.tracks.contains(entry)
+    /// Find an earlier download of a track: the entry itself, or one with
+    /// the same ID or artist and title whose file still exists. Converted
+    /// and original-format downloads count whatever their extension.
+    pub fn find_download(&self, entry: &TrackEntry) -> Option<&TrackEntry> {
+        self.tracks
+            .get(entry)
+            .or_else(|| {
+                self.tracks
+                    .iter()
+                    .find(|t| !entry.id.is_empty() && t.id == entry.id && Path::new(&t.path).exists())
+            })
+            .or_else(|| self.find_track(&entry.artist, &entry.title))
+    }
+
+    /// Find a track by artist and title whose file still exists, in any
+    /// format
+    pub fn find_track(&self, artist: &str, title: &str) -> Option<&TrackEntry> {
+        self.tracks
+            .iter()
+            .find(|t| t.artist == artist && t.title == title && Path::new(&t.path).exists())
+    }
+
+    /// Store probed file lengths, keyed by path
+    pub fn set_durations(&mut self, durations: &HashMap<String, u64>) {
+        if durations.is_empty() {
+            return;
+        }
+        self.tracks = self
+            .tracks
+            .drain()
+            .map(|mut entry| {
+                if let Some(&secs) = durations.get(&entry.path) {
+                    entry.duration_secs = Some(secs);
+                }
+                entry
+            })
+            .collect();
+        self.save();
     }
 
     /// Find a track entry by its file path
@@ -44,6 +161,10 @@ impl DownloadDB {
         self.tracks.iter().find(|t| t.path == path)
     }
 
+    pub fn find_by_id(&self, id: &str) -> Option<&TrackEntry> {
+        self.tracks.iter().find(|t| t.id == id)
+    }
+
     /// Update the path for a track (after format conversion).
     /// Returns true if the entry was found and updated.
     pub fn update_path(&mut self, old_path: &str, new_path: &str) -> bool {
@@ -54,9 +175,8 @@ impl DownloadDB {
             // Remove old entry and insert updated one
             self.tracks.remove(&old_entry);
             let new_entry = TrackEntry {
-                artist: old_entry.artist,
-                title: old_entry.title,
                 path: new_path.to_string(),
+                ..old_entry
             };
             self.tracks.insert(new_entry);
             self.save();
@@ -66,6 +186,31 @@ impl DownloadDB {
         }
     }
 
+    /// Record a converted copy of a track, keeping the original as the
+    /// track's main file. Returns true if the track was found.
+    pub fn add_variant(&mut self, path: &str, variant_path: &str) -> bool {
+        let Some(mut entry) = self.tracks.iter().find(|t| t.path == path).cloned() else {
+            return false;
+        };
+        if !entry.variants.iter().any(|v| v == variant_path) {
+            // The entry's identity doesn't change, so replace it in place
+            self.tracks.remove(&entry);
+            entry.variants.push(variant_path.to_string());
+            self.tracks.insert(entry);
+            self.save();
+        }
+        true
+    }
+
+    /// Format variants of every track that has some, keyed by the main file
+    pub fn variants(&self) -> HashMap<PathBuf, Vec<PathBuf>> {
+        self.tracks
+            .iter()
+            .filter(|t| !t.variants.is_empty())
+            .map(|t| (PathBuf::from(&t.path), t.variants.iter().map(PathBuf::from).collect()))
+            .collect()
+    }
+
     /// Remove a track entry by its file path.
     /// Returns true if the entry was found and removed.
     pub fn remove_by_path(&mut self, path: &str) -> bool {
@@ -81,30 +226,46 @@ impl DownloadDB {
     }
 
     /// Clean up the database by removing entries for files that no longer exist.
-    /// Returns a tuple of (removed_count, total_before_cleanup).
-    pub fn cleanup(&mut self) -> (usize, usize) {
+    /// Deleted variants are forgotten, and a track whose main file is gone
+    /// keeps its first remaining variant as the main file.
+    /// Returns the removed entries and the number of entries before cleanup.
+    pub fn cleanup(&mut self) -> (Vec<TrackEntry>, usize) {
         let total_before = self.tracks.len();
 
-        // Collect entries to remove (files that don't exist)
-        let missing: Vec<TrackEntry> = self
+        // Collect entries to remove or update
+        let stale: Vec<TrackEntry> = self
             .tracks
             .iter()
-            .filter(|entry| !Path::new(&entry.path).exists())
+            .filter(|entry| {
+                !Path::new(&entry.path).exists() || entry.variants.iter().any(|v| !Path::new(v).exists())
+            })
             .cloned()
             .collect();
 
-        let removed_count = missing.len();
-
-        // Remove missing entries
-        for entry in missing {
-            self.tracks.remove(&entry);
+        let mut removed = Vec::new();
+        for entry in &stale {
+            self.tracks.remove(entry);
+            let mut files = std::iter::once(&entry.path)
+                .chain(&entry.variants)
+                .filter(|p| Path::new(p).exists())
+                .cloned();
+            match files.next() {
+                Some(path) => {
+                    self.tracks.insert(TrackEntry {
+                        path,
+                        variants: files.collect(),
+                        ..entry.clone()
+                    });
+                }
+                None => removed.push(entry.clone()),
+            }
         }
 
-        if removed_count > 0 {
+        if !stale.is_empty() {
             self.save();
         }
 
-        (removed_count, total_before)
+        (removed, total_before)
     }
 
     /// Get all track entries (for listing purposes)
@@ -120,3 +281,13 @@ impl DownloadDB {
         fs::write(&self.file_path, data).expect("Failed to save downloaded_songs.json");
     }
 }
+
+/// FNV-1a hash of an entry's artist, title and path
+fn legacy_id(entry: &TrackEntry) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in [&entry.artist, &entry.title, &entry.path].iter().flat_map(|s| s.bytes().chain([0])) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
@@ -1,7 +1,255 @@
+use crate::config::{BelowQualityPolicy, SourceQualityConfig};
+use crate::converter;
+use crate::error_log::{DownloadErrorEntry, DownloadJob, ErrorLogManager};
+use crate::trim::{self, Trim, TrimKind};
 use anyhow::Context;
+use serde::{Deserialize, Serialize};
 use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Download format that keeps the codec the source delivers (usually Opus or
+/// AAC from YouTube), only remuxing it into a taggable container
+pub const ORIGINAL_FORMAT: &str = "original";
+
+/// Marker for the line yt-dlp prints once the file is in place
+const SOURCE_MARKER: &str = "RUSTWAV_SOURCE";
+
+/// Marker for the line listing the SponsorBlock segments of a download
+const SPONSORBLOCK_MARKER: &str = "RUSTWAV_SPONSORBLOCK";
+
+/// SponsorBlock category for non-music parts of music videos
+const SPONSORBLOCK_CATEGORY: &str = "music_offtopic";
+
+/// The audio stream a download was taken from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SourceStream {
+    /// Codec as reported by yt-dlp, e.g. "opus" or "mp4a.40.2"
+    pub codec: String,
+    /// Average bitrate in kbps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate_kbps: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>,
+}
+
+impl SourceStream {
+    pub fn is_lossless(&self) -> bool {
+        let codec = self.codec.to_lowercase();
+        codec == "flac" || codec == "alac" || codec.starts_with("pcm")
+    }
+}
+
+impl std::fmt::Display for SourceStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.codec)?;
+        if let Some(kbps) = self.bitrate_kbps {
+            write!(f, " {} kbps", kbps)?;
+        }
+        if let Some(rate) = self.sample_rate {
+            write!(f, " {} Hz", rate)?;
+        }
+        Ok(())
+    }
+}
+
+/// Stream details from a yt-dlp info JSON (`-j`) for the selected format
+fn parse_source_info(info: &serde_json::Value) -> Option<SourceStream> {
+    let codec = info["acodec"].as_str().filter(|c| *c != "none")?.to_string();
+    let bitrate_kbps = info["abr"]
+        .as_f64()
+        .or_else(|| info["tbr"].as_f64())
+        .map(|b| b.round() as u32);
+    let sample_rate = info["asr"].as_u64().map(|r| r as u32);
+    Some(SourceStream { codec, bitrate_kbps, sample_rate })
+}
+
+/// Look up the stream yt-dlp would download for a query, without downloading it
+pub fn probe_source(query: &str) -> anyhow::Result<SourceStream> {
+    let output = Command::new("yt-dlp")
+        .args([
+            "-j",
+            "--no-playlist",
+            "--no-warnings",
+            "-f",
+            "bestaudio/best", // what `-x` picks
+            &search_query(query),
+        ])
+        .stderr(Stdio::null())
+        .output()
+        .context("failed to spawn yt-dlp")?;
+
+    if !output.status.success() {
+        anyhow::bail!("yt-dlp could not inspect: {}", query);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let info: serde_json::Value = stdout
+        .lines()
+        .next()
+        .map(serde_json::from_str)
+        .transpose()
+        .context("parsing yt-dlp info")?
+        .context("yt-dlp returned no results")?;
+    parse_source_info(&info).context("source has no audio stream")
+}
+
+/// What to do with a track after checking its source against the required quality
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceVerdict {
+    Accept,
+    /// Below the bar, but download it and tag the file with this warning
+    AcceptWithWarning(String),
+    /// Below the bar, don't download
+    Skip(String),
+    /// Below the bar, log it for a later retry
+    Defer(String),
+}
+
+/// Compare a source stream with the required quality. Values the source
+/// doesn't report (e.g. bitrate of lossless streams) are not held against it.
+pub fn judge_source(source: &SourceStream, requirement: &SourceQualityConfig) -> SourceVerdict {
+    let mut problems = Vec::new();
+
+    if let Some(kbps) = source.bitrate_kbps {
+        if kbps < requirement.min_bitrate_kbps {
+            problems.push(format!("{} kbps < {} kbps", kbps, requirement.min_bitrate_kbps));
+        }
+    }
+    if let Some(rate) = source.sample_rate {
+        if rate < requirement.min_sample_rate {
+            problems.push(format!("{} Hz < {} Hz", rate, requirement.min_sample_rate));
+        }
+    }
+    let codec = source.codec.to_lowercase();
+    if !requirement.codecs.is_empty()
+        && !requirement.codecs.iter().any(|c| codec.starts_with(&c.to_lowercase()))
+    {
+        problems.push(format!("codec {} not in {}", source.codec, requirement.codecs.join("/")));
+    }
+
+    if problems.is_empty() {
+        return SourceVerdict::Accept;
+    }
+    let reason = format!("Source below required quality: {}", problems.join(", "));
+    match requirement.policy {
+        BelowQualityPolicy::Skip => SourceVerdict::Skip(reason),
+        BelowQualityPolicy::Warn => SourceVerdict::AcceptWithWarning(reason),
+        BelowQualityPolicy::Retry => SourceVerdict::Defer(reason),
+    }
+}
+
+/// A track about to be downloaded, as the error log records it for a retry
+pub struct TrackOrigin<'a> {
+    pub link: &'a str,
+    pub link_type: &'a str,
+    pub format: &'a str,
+    pub quality: &'a str,
+    pub portable: bool,
+    pub artist: &'a str,
+    pub title: &'a str,
+}
+
+/// Why a screened track isn't downloaded now
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeldBack {
+    /// Below the bar and skipped
+    Skipped(String),
+    /// Below the bar and logged in the error log for a later retry
+    Deferred(String),
+}
+
+/// Check the stream a query would download against the required quality.
+/// Sources that can't be inspected are accepted; deferred tracks go to the
+/// error log. Returns the warning to tag the file with, if any. `log`
+/// receives a line about the source and the warning.
+pub async fn screen_track(
+    query: &str,
+    requirement: &SourceQualityConfig,
+    origin: &TrackOrigin<'_>,
+    error_log: &Mutex<ErrorLogManager>,
+    mut log: impl FnMut(String),
+) -> Result<Option<String>, HeldBack> {
+    if !requirement.is_enabled() {
+        return Ok(None);
+    }
+    let query = query.to_string();
+    let verdict = match tokio::task::spawn_blocking(move || probe_source(&query)).await {
+        Ok(Ok(source)) => {
+            log(format!("Source stream: {}", source));
+            judge_source(&source, requirement)
+        }
+        Ok(Err(e)) => {
+            log(format!("Could not check source quality: {}", e));
+            SourceVerdict::Accept
+        }
+        Err(e) => {
+            log(format!("Could not check source quality: {}", e));
+            SourceVerdict::Accept
+        }
+    };
+    match verdict {
+        SourceVerdict::Accept => Ok(None),
+        SourceVerdict::AcceptWithWarning(warning) => {
+            log(format!("Warning: {}", warning));
+            Ok(Some(warning))
+        }
+        SourceVerdict::Skip(reason) => Err(HeldBack::Skipped(reason)),
+        SourceVerdict::Defer(reason) => {
+            // Logged for a later retry, when a better source may exist
+            let error_log = error_log.lock().unwrap_or_else(|e| e.into_inner());
+            error_log.add_download_error(DownloadErrorEntry::new(
+                &DownloadJob {
+                    link: origin.link,
+                    link_type: origin.link_type,
+                    format: origin.format,
+                    quality: origin.quality,
+                    portable: origin.portable,
+                },
+                Some(origin.artist.to_string()),
+                Some(origin.title.to_string()),
+                reason.clone(),
+            ));
+            Err(HeldBack::Deferred(reason))
+        }
+    }
+}
+
+/// A finished download
+#[derive(Debug, Clone)]
+pub struct DownloadedTrack {
+    /// Final file path. In original format mode the extension is the one
+    /// matching the source codec.
+    pub path: PathBuf,
+    pub source: Option<SourceStream>,
+    /// Cuts yt-dlp made while downloading
+    pub trims: Vec<Trim>,
+}
+
+/// Seconds of `music_offtopic` audio removed, from the `--print` line
+/// carrying yt-dlp's `sponsorblock_chapters` JSON ("NA" when there are none)
+fn parse_sponsorblock_line(line: &str) -> Option<f64> {
+    let json = line.strip_prefix(SPONSORBLOCK_MARKER)?.strip_prefix('\t')?;
+    let chapters: Vec<serde_json::Value> = serde_json::from_str(json).unwrap_or_default();
+    let segments = chapters
+        .iter()
+        .filter(|c| c["category"].as_str() == Some(SPONSORBLOCK_CATEGORY))
+        .filter_map(|c| Some((c["start_time"].as_f64()?, c["end_time"].as_f64()?)))
+        .collect();
+    Some(trim::covered_secs(segments))
+}
+
+/// Parse the `--print` line describing the downloaded stream and file
+fn parse_source_line(line: &str) -> Option<(PathBuf, SourceStream)> {
+    let mut fields = line.strip_prefix(SOURCE_MARKER)?.strip_prefix('\t')?.splitn(4, '\t');
+    let codec = fields.next()?.to_string();
+    let bitrate_kbps = fields.next()?.parse::<f64>().ok().map(|b| b.round() as u32);
+    let sample_rate = fields.next()?.parse::<u32>().ok();
+    let path = PathBuf::from(fields.next()?);
+    let codec = if codec == "NA" || codec == "none" { "unknown".to_string() } else { codec };
+    Some((path, SourceStream { codec, bitrate_kbps, sample_rate }))
+}
 
 /// Convert quality string to yt-dlp audio quality value
 /// yt-dlp uses 0 (best) to 10 (worst)
@@ -14,15 +262,59 @@ fn quality_to_ytdlp(quality: &str) -> &str {
     }
 }
 
+/// yt-dlp `--audio-format` name for a format, or `None` when yt-dlp can't
+/// produce it and the download has to be converted afterwards
+fn format_to_ytdlp(format: &str) -> Option<&str> {
+    match format {
+        ORIGINAL_FORMAT => Some("best"),
+        "ogg" => Some("vorbis"),
+        "aiff" => None,
+        other => Some(other),
+    }
+}
+
+/// Check whether a query is already something yt-dlp can fetch directly:
+/// a URL or a search expression such as "ytsearch1:..." or "scsearch5:..."
+fn is_direct_source(query: &str) -> bool {
+    if query.starts_with("http://") || query.starts_with("https://") {
+        return true;
+    }
+    match query.split_once(':') {
+        Some((prefix, _)) => {
+            let name = prefix.trim_end_matches(|c: char| c.is_ascii_digit());
+            let name = name.strip_suffix("all").unwrap_or(name);
+            name.ends_with("search") && name.chars().all(|c| c.is_ascii_lowercase())
+        }
+        None => false,
+    }
+}
+
+/// Use the ytsearch: prefix to search YouTube for the track, unless the caller
+/// already passed a URL or an explicit yt-dlp search (e.g. "scsearch1:...")
+fn search_query(query: &str) -> String {
+    if is_direct_source(query) {
+        query.to_string()
+    } else {
+        format!("ytsearch1:{}", query)
+    }
+}
+
 /// Download a track using yt-dlp (legacy version without output capture)
-#[allow(dead_code)]
-pub fn download_track(query: &str, output_path: &Path, format: &str) -> anyhow::Result<()> {
-    download_track_with_output(query, output_path, format, "high", |_| {})
+pub fn download_track(
+    query: &str,
+    output_path: &Path,
+    format: &str,
+    sponsorblock: bool,
+) -> anyhow::Result<DownloadedTrack> {
+    download_track_with_output(query, output_path, format, "high", sponsorblock, |_| {})
 }
 
 /// Download a track to a specific file path using yt-dlp with output streaming.
 ///
 /// The `output_file` should be the full path including filename and extension.
+/// Returns where the file ended up and which source stream it came from.
+/// With `sponsorblock` set, SponsorBlock `music_offtopic` segments are cut
+/// out and reported as a trim.
 /// The `on_output` callback is called for each line of output from yt-dlp,
 /// allowing real-time progress updates in the TUI.
 pub fn download_track_with_output<F>(
@@ -30,31 +322,62 @@ pub fn download_track_with_output<F>(
     output_file: &Path,
     format: &str,
     quality: &str,
+    sponsorblock: bool,
     on_output: F,
-) -> anyhow::Result<()>
+) -> anyhow::Result<DownloadedTrack>
 where
     F: Fn(&str) + Send + Clone + 'static,
 {
-    // Use the exact output path provided (strip extension as yt-dlp adds it)
+    // yt-dlp has no AIFF output; fetch lossless FLAC and convert it afterwards
+    let Some(ytdlp_format) = format_to_ytdlp(format) else {
+        let intermediate = output_file.with_extension("flac");
+        let downloaded =
+            download_track_with_output(query, &intermediate, "flac", quality, sponsorblock, on_output.clone())?;
+        let result = converter::convert_audio(&intermediate, format, quality, on_output);
+        let _ = std::fs::remove_file(&intermediate);
+        return result.map(|path| DownloadedTrack { path, ..downloaded });
+    };
+
+    // Use the exact output path provided (strip extension as yt-dlp adds it).
+    // In original format mode yt-dlp picks the extension from the codec.
+    let extension = if format == ORIGINAL_FORMAT {
+        "%(ext)s"
+    } else {
+        converter::format_extension(format)
+    };
     let output_template = output_file
-        .with_extension(format)
+        .with_extension(extension)
         .to_string_lossy()
         .to_string();
+    let source_template = format!(
+        "after_move:{}\t%(acodec)s\t%(abr)s\t%(asr)s\t%(filepath)s",
+        SOURCE_MARKER
+    );
 
-    // Use ytsearch: prefix to search YouTube for the track
-    let search_query = format!("ytsearch1:{}", query);
+    let search_query = search_query(query);
     let audio_quality = quality_to_ytdlp(quality);
 
-    let mut child = Command::new("yt-dlp")
+    let mut command = Command::new("yt-dlp");
+    if sponsorblock {
+        command.args([
+            "--sponsorblock-remove",
+            SPONSORBLOCK_CATEGORY,
+            "--print",
+            &format!("after_move:{}\t%(sponsorblock_chapters)j", SPONSORBLOCK_MARKER),
+        ]);
+    }
+    let mut child = command
         .args([
             "-x",            // extract audio
             "--no-playlist", // don't download playlists
             "--audio-format",
-            format, // mp3, flac, wav, aac
+            ytdlp_format, // mp3, flac, wav, aac, m4a, opus, vorbis, alac
             "--audio-quality",
             audio_quality, // 0=best, 10=worst
             "--newline",   // output progress on new lines (easier to parse)
             "--progress",  // show progress
+            "--print",
+            &source_template, // report the source stream and final path
             "-o",
             &output_template,
             &search_query,
@@ -69,10 +392,21 @@ where
     let stderr = child.stderr.take();
     let on_output_clone = on_output.clone();
 
+    let mut downloaded: Option<(PathBuf, SourceStream)> = None;
+    let mut offtopic_secs: Option<f64> = None;
+
     // Process stdout
     if let Some(stdout) = stdout {
         let reader = BufReader::new(stdout);
         for line in reader.lines().map_while(Result::ok) {
+            if let Some(parsed) = parse_source_line(&line) {
+                downloaded = Some(parsed);
+                continue;
+            }
+            if let Some(secs) = parse_sponsorblock_line(&line) {
+                offtopic_secs = Some(secs);
+                continue;
+            }
             let trimmed = line.trim();
             if !trimmed.is_empty() {
                 on_output(trimmed);
@@ -97,5 +431,123 @@ where
         anyhow::bail!("yt-dlp failed for query: {}", query);
     }
 
-    Ok(())
+    let (path, source) = match downloaded {
+        Some((path, source)) => (path, Some(source)),
+        None => (output_file.with_extension(converter::format_extension(format)), None),
+    };
+
+    if let Some(source) = &source {
+        on_output(&format!("Source stream: {}", source));
+        if converter::is_lossless_format(format) && !source.is_lossless() {
+            on_output(&format!(
+                "Warning: the source is lossy ({}); {} output is an upconversion with no quality gain",
+                source, format
+            ));
+        }
+    }
+
+    let mut trims = Vec::new();
+    if let Some(removed_secs) = offtopic_secs.filter(|secs| *secs > 0.0) {
+        on_output(&format!("Removed {:.1}s of off-topic segments", removed_secs));
+        trims.push(Trim {
+            kind: TrimKind::Sponsorblock,
+            removed_secs,
+        });
+    }
+
+    Ok(DownloadedTrack { path, source, trims })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_to_ytdlp() {
+        assert_eq!(format_to_ytdlp("mp3"), Some("mp3"));
+        assert_eq!(format_to_ytdlp("ogg"), Some("vorbis"));
+        assert_eq!(format_to_ytdlp("alac"), Some("alac"));
+        assert_eq!(format_to_ytdlp("aiff"), None);
+        assert_eq!(format_to_ytdlp(ORIGINAL_FORMAT), Some("best"));
+    }
+
+    #[test]
+    fn test_parse_source_line() {
+        let (path, source) =
+            parse_source_line("RUSTWAV_SOURCE\topus\t129.482\t48000\t/music/A - B.opus").unwrap();
+        assert_eq!(path, PathBuf::from("/music/A - B.opus"));
+        assert_eq!(source.codec, "opus");
+        assert_eq!(source.bitrate_kbps, Some(129));
+        assert_eq!(source.sample_rate, Some(48000));
+        assert!(!source.is_lossless());
+
+        let (_, source) = parse_source_line("RUSTWAV_SOURCE\tmp4a.40.2\tNA\tNA\tx.m4a").unwrap();
+        assert_eq!(source.bitrate_kbps, None);
+        assert_eq!(source.sample_rate, None);
+        assert!(parse_source_line("[download] 50.0%").is_none());
+    }
+
+    #[test]
+    fn test_parse_sponsorblock_line() {
+        let line = r#"RUSTWAV_SPONSORBLOCK	[{"start_time": 0.0, "end_time": 12.5, "category": "music_offtopic"}, {"start_time": 200.0, "end_time": 230.0, "category": "music_offtopic"}, {"start_time": 50.0, "end_time": 60.0, "category": "sponsor"}]"#;
+        assert_eq!(parse_sponsorblock_line(line), Some(42.5));
+        assert_eq!(parse_sponsorblock_line("RUSTWAV_SPONSORBLOCK\tNA"), Some(0.0));
+        assert!(parse_sponsorblock_line("RUSTWAV_SOURCE\topus\tNA\tNA\tx.opus").is_none());
+    }
+
+    #[test]
+    fn test_parse_source_info() {
+        let info = serde_json::json!({"acodec": "opus", "abr": 129.5, "asr": 48000});
+        let source = parse_source_info(&info).unwrap();
+        assert_eq!(source.codec, "opus");
+        assert_eq!(source.bitrate_kbps, Some(130));
+        assert_eq!(source.sample_rate, Some(48000));
+
+        let info = serde_json::json!({"acodec": "none", "tbr": 500.0});
+        assert!(parse_source_info(&info).is_none());
+    }
+
+    #[test]
+    fn test_judge_source() {
+        let source = SourceStream {
+            codec: "mp4a.40.5".to_string(),
+            bitrate_kbps: Some(48),
+            sample_rate: Some(22050),
+        };
+        let mut requirement = SourceQualityConfig::default();
+        assert_eq!(judge_source(&source, &requirement), SourceVerdict::Accept);
+
+        requirement.codecs = vec!["mp4a".to_string()];
+        assert_eq!(judge_source(&source, &requirement), SourceVerdict::Accept);
+
+        requirement.min_bitrate_kbps = 128;
+        requirement.min_sample_rate = 44100;
+        requirement.policy = BelowQualityPolicy::Skip;
+        match judge_source(&source, &requirement) {
+            SourceVerdict::Skip(reason) => {
+                assert!(reason.contains("48 kbps < 128 kbps"));
+                assert!(reason.contains("22050 Hz < 44100 Hz"));
+            }
+            other => panic!("unexpected verdict: {:?}", other),
+        }
+
+        requirement.policy = BelowQualityPolicy::Retry;
+        assert!(matches!(judge_source(&source, &requirement), SourceVerdict::Defer(_)));
+
+        requirement.codecs = vec!["opus".to_string()];
+        requirement.min_bitrate_kbps = 0;
+        requirement.min_sample_rate = 0;
+        requirement.policy = BelowQualityPolicy::Warn;
+        assert!(matches!(judge_source(&source, &requirement), SourceVerdict::AcceptWithWarning(_)));
+    }
+
+    #[test]
+    fn test_is_direct_source() {
+        assert!(is_direct_source("https://www.youtube.com/watch?v=abc"));
+        assert!(is_direct_source("ytsearch1:show episode"));
+        assert!(is_direct_source("scsearch5:episode"));
+        assert!(is_direct_source("ytsearchall:episode"));
+        assert!(!is_direct_source("Artist Title"));
+        assert!(!is_direct_source("Artist: Title"));
+    }
 }
@@ -4,6 +4,8 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+use crate::config::EncoderSettings;
+
 /// Error types for categorizing errors
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ErrorType {
@@ -46,25 +48,26 @@ pub struct DownloadErrorEntry {
     pub retry_count: u32,
 }
 
+/// The download job a failed track belonged to, re-run on retry
+#[derive(Debug, Clone, Copy)]
+pub struct DownloadJob<'a> {
+    pub link: &'a str,
+    pub link_type: &'a str, // "album" or "playlist"
+    pub format: &'a str,
+    pub quality: &'a str,
+    pub portable: bool,
+}
+
 impl DownloadErrorEntry {
-    pub fn new(
-        link: String,
-        link_type: String,
-        format: String,
-        quality: String,
-        portable: bool,
-        artist: Option<String>,
-        title: Option<String>,
-        error: String,
-    ) -> Self {
+    pub fn new(job: &DownloadJob, artist: Option<String>, title: Option<String>, error: String) -> Self {
         Self {
             id: Uuid::new_v4().to_string(),
             timestamp: Utc::now(),
-            link,
-            link_type,
-            format,
-            quality,
-            portable,
+            link: job.link.to_string(),
+            link_type: job.link_type.to_string(),
+            format: job.format.to_string(),
+            quality: job.quality.to_string(),
+            portable: job.portable,
             artist,
             title,
             error,
@@ -81,6 +84,15 @@ pub struct ConvertErrorEntry {
     pub input_path: String,
     pub target_format: String,
     pub quality: String,
+    /// Encoder settings of the failed conversion, reused on retry
+    #[serde(default)]
+    pub encoder: EncoderSettings,
+    /// Folder the converted file was to be written under, if not beside the input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<String>,
+    /// Whether the original was to be kept as a format variant
+    #[serde(default)]
+    pub keep_both: bool,
     pub refresh_metadata: bool,
     pub artist: String,
     pub title: String,
@@ -88,23 +100,29 @@ pub struct ConvertErrorEntry {
     pub retry_count: u32,
 }
 
+/// The settings a failed conversion ran with, reused on retry
+#[derive(Debug, Clone, Copy)]
+pub struct ConvertJob<'a> {
+    pub target_format: &'a str,
+    pub quality: &'a str,
+    pub encoder: &'a EncoderSettings,
+    pub output_dir: Option<&'a str>,
+    pub keep_both: bool,
+    pub refresh_metadata: bool,
+}
+
 impl ConvertErrorEntry {
-    pub fn new(
-        input_path: String,
-        target_format: String,
-        quality: String,
-        refresh_metadata: bool,
-        artist: String,
-        title: String,
-        error: String,
-    ) -> Self {
+    pub fn new(input_path: String, job: &ConvertJob, artist: String, title: String, error: String) -> Self {
         Self {
             id: Uuid::new_v4().to_string(),
             timestamp: Utc::now(),
             input_path,
-            target_format,
-            quality,
-            refresh_metadata,
+            target_format: job.target_format.to_string(),
+            quality: job.quality.to_string(),
+            encoder: job.encoder.clone(),
+            output_dir: job.output_dir.map(str::to_string),
+            keep_both: job.keep_both,
+            refresh_metadata: job.refresh_metadata,
             artist,
             title,
             error,
@@ -357,7 +375,7 @@ impl ErrorLogManager {
             }
         }
         // Sort by timestamp descending (newest first)
-        all.sort_by(|a, b| b.1.timestamp.cmp(&a.1.timestamp));
+        all.sort_by_key(|e| std::cmp::Reverse(e.1.timestamp));
         all
     }
 
@@ -370,7 +388,7 @@ impl ErrorLogManager {
                 all.push((date.clone(), entry));
             }
         }
-        all.sort_by(|a, b| b.1.timestamp.cmp(&a.1.timestamp));
+        all.sort_by_key(|e| std::cmp::Reverse(e.1.timestamp));
         all
     }
 
@@ -383,7 +401,7 @@ impl ErrorLogManager {
                 all.push((date.clone(), entry));
             }
         }
-        all.sort_by(|a, b| b.1.timestamp.cmp(&a.1.timestamp));
+        all.sort_by_key(|e| std::cmp::Reverse(e.1.timestamp));
         all
     }
 
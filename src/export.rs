@@ -0,0 +1,629 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::cli::PortableConfig;
+use crate::config::ExportProfile;
+use crate::converter;
+use crate::downloader::ORIGINAL_FORMAT;
+use crate::file_utils;
+use crate::metadata;
+
+/// Manifest kept at the root of an export, listing each file written and
+/// the library file it came from
+pub const MANIFEST_FILE: &str = ".rustwav-export.json";
+
+/// Part of the library to export. With nothing set everything is exported;
+/// artist and album are matched against tags, ignoring case.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// M3U playlist whose tracks are exported
+    pub playlist: Option<PathBuf>,
+    /// Converted copies of library files, keyed by the file they were
+    /// converted from. A track is exported once, from the copy already in
+    /// the profile's format if there is one.
+    pub variants: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+/// Outcome of an export
+#[derive(Debug, Clone, Default)]
+pub struct ExportSummary {
+    pub exported: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub failed: usize,
+    /// Files left out because the capacity budget was used up
+    pub over_budget: usize,
+    /// Files left out because a file the export didn't write is in their place
+    pub skipped: usize,
+    /// Space the exported files take up
+    pub used_bytes: u64,
+    /// Files present on the device, relative to the export root, in selection order
+    pub files: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct ManifestEntry {
+    source: String,
+    source_size: u64,
+    /// Modification time of the source, in seconds since the Unix epoch
+    source_modified: u64,
+    size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Manifest {
+    /// The profile the files were written with; any change re-exports everything
+    profile: String,
+    files: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    fn load(dest: &Path) -> Self {
+        std::fs::read_to_string(dest.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, dest: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(dest.join(MANIFEST_FILE), json).context("writing export manifest")
+    }
+}
+
+/// A library file and where it goes on the device
+struct Planned {
+    source: PathBuf,
+    /// Path relative to the export root, '/'-separated
+    dest: String,
+    source_size: u64,
+    source_modified: u64,
+}
+
+impl Planned {
+    fn is_exported_as(&self, entry: &ManifestEntry) -> bool {
+        entry.source == self.source.display().to_string()
+            && entry.source_size == self.source_size
+            && entry.source_modified == self.source_modified
+    }
+}
+
+/// Format variants resolved to canonical paths
+#[derive(Default)]
+struct VariantIndex {
+    /// Each variant's main file
+    main_file: HashMap<PathBuf, PathBuf>,
+    /// Each main file's variants
+    variants: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl VariantIndex {
+    fn new(variants: &HashMap<PathBuf, Vec<PathBuf>>) -> Self {
+        let mut index = Self::default();
+        for (main, copies) in variants {
+            // Without their main file, variants are exported on their own
+            let Ok(main) = std::fs::canonicalize(main) else {
+                continue;
+            };
+            let copies: Vec<PathBuf> = copies.iter().filter_map(|c| std::fs::canonicalize(c).ok()).collect();
+            for copy in &copies {
+                index.main_file.insert(copy.clone(), main.clone());
+            }
+            index.variants.insert(main, copies);
+        }
+        index
+    }
+
+    /// The track a library file belongs to, by its main file
+    fn track_of(&self, file: PathBuf) -> PathBuf {
+        self.main_file.get(&file).cloned().unwrap_or(file)
+    }
+
+    /// The file to export for a track: a variant already in the profile's
+    /// format, or else the main file
+    fn source_for(&self, main: &Path, profile: &ExportProfile) -> PathBuf {
+        let wanted = profile.format.to_lowercase();
+        if wanted != ORIGINAL_FORMAT && target_format(main, profile).is_some() {
+            let copy = self
+                .variants
+                .get(main)
+                .and_then(|copies| copies.iter().find(|c| target_format(c, profile).is_none()));
+            if let Some(copy) = copy {
+                return copy.clone();
+            }
+        }
+        main.to_path_buf()
+    }
+}
+
+/// Track paths listed in an M3U/M3U8 playlist, resolved against its folder
+fn read_m3u(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    Ok(text
+        .lines()
+        .map(|line| line.trim_start_matches('\u{feff}').trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        // Playlists written for devices may use backslashes
+        .map(|line| dir.join(line.replace('\\', "/")))
+        .collect())
+}
+
+fn tag_matches(wanted: Option<&str>, actual: Option<&str>) -> bool {
+    match wanted {
+        None => true,
+        Some(wanted) => actual.is_some_and(|a| a.trim().eq_ignore_ascii_case(wanted.trim())),
+    }
+}
+
+/// Selected tracks' main files with their paths relative to the library
+/// root they're in; variants count as their main file
+fn select_sources(
+    roots: &[PathBuf],
+    selection: &Selection,
+    index: &VariantIndex,
+) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    let roots: Vec<PathBuf> = roots.iter().filter_map(|r| std::fs::canonicalize(r).ok()).collect();
+    let files = match &selection.playlist {
+        Some(playlist) => read_m3u(playlist)?,
+        None => {
+            let mut files = Vec::new();
+            for root in &roots {
+                let mut found = file_utils::collect_audio_files(root, true)?;
+                found.sort();
+                files.extend(found);
+            }
+            files
+        }
+    };
+
+    let mut selected = Vec::new();
+    let mut seen = HashSet::new();
+    for file in files {
+        // Playlist entries may point at files that are gone
+        let Ok(file) = std::fs::canonicalize(&file) else {
+            continue;
+        };
+        let file = index.track_of(file);
+        if !seen.insert(file.clone()) {
+            continue;
+        }
+        if selection.artist.is_some() || selection.album.is_some() {
+            let tags = metadata::read_tags(&file).unwrap_or_default();
+            if !tag_matches(selection.artist.as_deref(), tags.artist.as_deref())
+                || !tag_matches(selection.album.as_deref(), tags.album.as_deref())
+            {
+                continue;
+            }
+        }
+        let relative = roots
+            .iter()
+            .find_map(|root| file.strip_prefix(root).ok())
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from(file.file_name().unwrap_or_default()));
+        selected.push((file, relative));
+    }
+    Ok(selected)
+}
+
+/// Format a file is transcoded to, or `None` when it is copied as is
+fn target_format(source: &Path, profile: &ExportProfile) -> Option<String> {
+    let format = profile.format.to_lowercase();
+    if format == ORIGINAL_FORMAT {
+        return None;
+    }
+    let source_format = match converter::get_format_from_path(source)?.as_str() {
+        "aif" => "aiff".to_string(),
+        other => other.to_string(),
+    };
+    (source_format != format).then_some(format)
+}
+
+fn clean_name(name: &str, max_len: usize, fat32: bool) -> String {
+    let cleaned = if fat32 {
+        file_utils::sanitize_filename_portable(name, max_len)
+    } else {
+        file_utils::sanitize_filename_with_len(name, max_len)
+    };
+    if cleaned.is_empty() {
+        "_".to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// Where a library file goes on the device, relative to the export root.
+/// Every folder and file name is cleaned per the profile's naming rules.
+fn dest_path(relative: &Path, extension: &str, profile: &ExportProfile) -> String {
+    let mut parts: Vec<String> = relative
+        .parent()
+        .into_iter()
+        .flat_map(Path::iter)
+        .map(|c| clean_name(&c.to_string_lossy(), profile.max_filename_len, profile.fat32))
+        .collect();
+    let stem = relative.file_stem().unwrap_or_default().to_string_lossy();
+    let stem_len = profile.max_filename_len.saturating_sub(extension.len() + 1).max(1);
+    parts.push(format!("{}.{}", clean_name(&stem, stem_len, profile.fat32), extension));
+    parts.join("/")
+}
+
+/// Make a destination unique among those already taken, ignoring case as FAT32 does
+fn unique_dest(dest: String, taken: &mut HashSet<String>) -> String {
+    if taken.insert(dest.to_lowercase()) {
+        return dest;
+    }
+    let (stem, extension) = dest.rsplit_once('.').unwrap_or((&dest, ""));
+    (2..)
+        .map(|n| format!("{}_{}.{}", stem, n, extension))
+        .find(|candidate| taken.insert(candidate.to_lowercase()))
+        .expect("unbounded range")
+}
+
+fn plan(roots: &[PathBuf], selection: &Selection, profile: &ExportProfile) -> anyhow::Result<Vec<Planned>> {
+    let index = VariantIndex::new(&selection.variants);
+    let mut taken = HashSet::new();
+    let mut planned = Vec::new();
+    for (main, relative) in select_sources(roots, selection, &index)? {
+        let source = index.source_for(&main, profile);
+        let extension = match target_format(&source, profile) {
+            Some(format) => converter::format_extension(&format).to_string(),
+            None => converter::get_format_from_path(&source).unwrap_or_default(),
+        };
+        let meta = std::fs::metadata(&source)?;
+        let source_modified = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        planned.push(Planned {
+            dest: unique_dest(dest_path(&relative, &extension, profile), &mut taken),
+            source,
+            source_size: meta.len(),
+            source_modified,
+        });
+    }
+    Ok(planned)
+}
+
+/// Replace the exported file's cover with one downscaled for the profile
+/// (preferring the full-size folder cover), or remove it
+fn apply_cover(source: &Path, dest: &Path, profile: &ExportProfile) -> anyhow::Result<()> {
+    if profile.max_cover_dim == 0 {
+        metadata::strip_cover(dest)?;
+        return Ok(());
+    }
+    let limits = PortableConfig {
+        enabled: profile.fat32,
+        max_cover_dim: profile.max_cover_dim,
+        max_cover_bytes: profile.max_cover_kb * 1024,
+        max_filename_len: profile.max_filename_len,
+    };
+
+    if let Some(cover) = source.parent().and_then(file_utils::find_folder_cover) {
+        return metadata::embed_cover(dest, &cover, &limits);
+    }
+    let Some(cover) = metadata::read_cover(source)? else {
+        return Ok(());
+    };
+    let temp_path = dest.with_extension(format!("cover.{}", cover.extension()));
+    std::fs::write(&temp_path, &cover.data)?;
+    let result = metadata::embed_cover(dest, &temp_path, &limits);
+    let _ = std::fs::remove_file(&temp_path);
+    result
+}
+
+/// Copy or transcode one file to the device. Returns its size.
+/// The file is written beside `dest` and only moved over it once complete,
+/// so a failed re-export leaves the previous copy in place.
+fn export_file<F>(source: &Path, dest: &Path, profile: &ExportProfile, on_output: &F) -> anyhow::Result<u64>
+where
+    F: Fn(&str),
+{
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let extension = dest.extension().unwrap_or_default().to_string_lossy();
+    let temp_path = dest.with_extension(format!("exporting.{}", extension));
+    let result = write_export(source, &temp_path, profile, on_output).and_then(|size| {
+        std::fs::rename(&temp_path, dest).with_context(|| format!("replacing {}", dest.display()))?;
+        Ok(size)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn write_export<F>(source: &Path, dest: &Path, profile: &ExportProfile, on_output: &F) -> anyhow::Result<u64>
+where
+    F: Fn(&str),
+{
+    match target_format(source, profile) {
+        Some(format) => converter::convert_audio_to(
+            source,
+            dest,
+            &format,
+            &profile.quality,
+            &profile.encoder,
+            |_| {},
+            |_| {},
+        )?,
+        None => {
+            std::fs::copy(source, dest).with_context(|| format!("copying to {}", dest.display()))?;
+        }
+    }
+    if let Err(e) = apply_cover(source, dest, profile) {
+        on_output(&format!("  Cover not updated: {}", e));
+    }
+    Ok(std::fs::metadata(dest)?.len())
+}
+
+/// Delete an exported file and any folders it leaves empty
+fn remove_exported(dest: &Path, relative: &str) {
+    let path = dest.join(relative);
+    let _ = std::fs::remove_file(&path);
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if d == dest || std::fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
+
+/// Mirror a selection of the library (the files under `roots`) into `dest`
+/// for a device profile.
+///
+/// Files unchanged since the last export are skipped using the manifest in
+/// `dest`, exported files that left the selection are deleted, and new files
+/// are left out once the profile's capacity budget is used up. Only files
+/// listed in the manifest are ever deleted or overwritten. With `dry_run`
+/// nothing is written.
+pub fn export<F>(
+    roots: &[PathBuf],
+    selection: &Selection,
+    profile: &ExportProfile,
+    dest: &Path,
+    dry_run: bool,
+    on_output: F,
+) -> anyhow::Result<ExportSummary>
+where
+    F: Fn(&str),
+{
+    if profile.format.to_lowercase() != ORIGINAL_FORMAT {
+        converter::validate_encoder(&profile.format, &profile.encoder).context("Invalid export profile")?;
+    }
+    let plan = plan(roots, selection, profile)?;
+    let budget = profile.capacity_mb * 1024 * 1024;
+    let mut summary = ExportSummary::default();
+
+    let mut manifest = Manifest::load(dest);
+    // The budget only decides what fits, not how files are written
+    let fingerprint = serde_json::to_string(&ExportProfile {
+        capacity_mb: 0,
+        ..profile.clone()
+    })?;
+    let profile_changed = manifest.profile != fingerprint;
+    if profile_changed && !manifest.files.is_empty() {
+        on_output("Profile settings changed; re-exporting everything");
+    }
+    manifest.profile = fingerprint;
+    if !dry_run {
+        std::fs::create_dir_all(dest).with_context(|| format!("creating {}", dest.display()))?;
+    }
+
+    // Remove what left the selection first to free space
+    let planned: HashSet<&str> = plan.iter().map(|p| p.dest.as_str()).collect();
+    let stale: Vec<String> = manifest
+        .files
+        .keys()
+        .filter(|key| !planned.contains(key.as_str()))
+        .cloned()
+        .collect();
+    for key in stale {
+        on_output(&format!("Removing {}", key));
+        if !dry_run {
+            remove_exported(dest, &key);
+            manifest.files.remove(&key);
+        }
+        summary.removed += 1;
+    }
+
+    // Files already up to date count against the budget before new ones
+    let mut pending = Vec::new();
+    for item in &plan {
+        let on_device = std::fs::metadata(dest.join(&item.dest)).map(|m| m.len()).ok();
+        match manifest.files.get(&item.dest) {
+            Some(entry) if !profile_changed && item.is_exported_as(entry) && on_device == Some(entry.size) => {
+                summary.unchanged += 1;
+                summary.used_bytes += entry.size;
+            }
+            _ => pending.push(item),
+        }
+    }
+
+    for item in pending {
+        let target = dest.join(&item.dest);
+        let known = manifest.files.get(&item.dest).cloned();
+        // Never overwrite something put there by hand
+        if known.is_none() && target.exists() {
+            on_output(&format!("Skipping {}: already there and not written by an export", item.dest));
+            summary.skipped += 1;
+            continue;
+        }
+        if budget > 0 && summary.used_bytes >= budget {
+            summary.over_budget += 1;
+            continue;
+        }
+        on_output(&format!("Exporting {}", item.dest));
+        if dry_run {
+            summary.exported += 1;
+            continue;
+        }
+
+        match export_file(&item.source, &target, profile, &on_output) {
+            Ok(size) if budget > 0 && summary.used_bytes + size > budget => {
+                remove_exported(dest, &item.dest);
+                manifest.files.remove(&item.dest);
+                on_output("  Left out: over the capacity budget");
+                summary.over_budget += 1;
+            }
+            Ok(size) => {
+                manifest.files.insert(
+                    item.dest.clone(),
+                    ManifestEntry {
+                        source: item.source.display().to_string(),
+                        source_size: item.source_size,
+                        source_modified: item.source_modified,
+                        size,
+                    },
+                );
+                // Keep the manifest current so an interrupted export resumes
+                manifest.save(dest)?;
+                summary.used_bytes += size;
+                summary.exported += 1;
+            }
+            Err(e) => {
+                on_output(&format!("  Failed: {}", e));
+                summary.failed += 1;
+                match known {
+                    // The previous copy is still there; it's retried next time
+                    Some(entry) if target.exists() => {
+                        on_output("  Kept the previous copy");
+                        summary.used_bytes += entry.size;
+                    }
+                    _ => {
+                        remove_exported(dest, &item.dest);
+                        manifest.files.remove(&item.dest);
+                    }
+                }
+            }
+        }
+    }
+
+    if !dry_run {
+        manifest.save(dest)?;
+    }
+    summary.files = plan
+        .iter()
+        .filter(|p| dry_run || manifest.files.contains_key(&p.dest))
+        .map(|p| p.dest.clone())
+        .collect();
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(format: &str, fat32: bool) -> ExportProfile {
+        ExportProfile {
+            format: format.to_string(),
+            fat32,
+            max_filename_len: 24,
+            ..ExportProfile::default()
+        }
+    }
+
+    #[test]
+    fn test_target_format() {
+        let mp3 = profile("mp3", true);
+        assert_eq!(target_format(Path::new("a/b.flac"), &mp3), Some("mp3".to_string()));
+        assert_eq!(target_format(Path::new("a/b.MP3"), &mp3), None);
+        assert_eq!(target_format(Path::new("a/b.aif"), &profile("aiff", true)), None);
+        assert_eq!(target_format(Path::new("a/b.flac"), &profile(ORIGINAL_FORMAT, true)), None);
+        assert_eq!(target_format(Path::new("a/b.m4a"), &profile("alac", true)), Some("alac".to_string()));
+    }
+
+    #[test]
+    fn test_profile_encoder_settings() {
+        let profile: ExportProfile =
+            serde_json::from_str(r#"{"format": "flac", "bit_depth": 16, "sample_rate": 44100, "dither": true}"#)
+                .unwrap();
+        assert_eq!(profile.encoder.bit_depth, Some(16));
+        assert_eq!(profile.encoder.sample_rate, Some(44100));
+        assert!(profile.encoder.dither);
+        assert_eq!(profile.max_cover_dim, 500);
+        assert!(converter::validate_encoder(&profile.format, &profile.encoder).is_ok());
+    }
+
+    #[test]
+    fn test_variant_source() {
+        let main = PathBuf::from("/music/A/B/song.flac");
+        let index = VariantIndex {
+            main_file: HashMap::from([(PathBuf::from("/music/A/B/song.mp3"), main.clone())]),
+            variants: HashMap::from([(main.clone(), vec![PathBuf::from("/music/A/B/song.mp3")])]),
+        };
+        assert_eq!(index.source_for(&main, &profile("mp3", true)), PathBuf::from("/music/A/B/song.mp3"));
+        assert_eq!(index.source_for(&main, &profile("opus", true)), main);
+        assert_eq!(index.source_for(&main, &profile("flac", true)), main);
+        assert_eq!(index.source_for(&main, &profile(ORIGINAL_FORMAT, true)), main);
+        assert_eq!(index.track_of(PathBuf::from("/music/A/B/song.mp3")), main);
+    }
+
+    #[test]
+    fn test_dest_path() {
+        let relative = Path::new("Sigur Rós/Ágætis byrjun (Deluxe Edition)/Sigur Rós - Svefn-g-englar.flac");
+        assert_eq!(
+            dest_path(relative, "mp3", &profile("mp3", true)),
+            "Sigur_Rs/gtis_byrjun_Deluxe_Editi/Sigur_Rs_-_Svefn-g-e.mp3"
+        );
+        assert_eq!(
+            dest_path(Path::new("Artist/Album/A - B.flac"), "flac", &profile("original", false)),
+            "Artist/Album/A - B.flac"
+        );
+        // Names with nothing FAT32-safe left still get a name
+        assert_eq!(dest_path(Path::new("椎名林檎.mp3"), "mp3", &profile("mp3", true)), "_.mp3");
+    }
+
+    #[test]
+    fn test_unique_dest() {
+        let mut taken = HashSet::new();
+        assert_eq!(unique_dest("A/b.mp3".to_string(), &mut taken), "A/b.mp3");
+        assert_eq!(unique_dest("a/B.mp3".to_string(), &mut taken), "a/B_2.mp3");
+        assert_eq!(unique_dest("A/b.mp3".to_string(), &mut taken), "A/b_3.mp3");
+    }
+
+    #[test]
+    fn test_tag_matches() {
+        assert!(tag_matches(None, None));
+        assert!(tag_matches(Some("radiohead"), Some("Radiohead ")));
+        assert!(!tag_matches(Some("Radiohead"), Some("Portishead")));
+        assert!(!tag_matches(Some("Radiohead"), None));
+    }
+
+    #[test]
+    fn test_export_keeps_foreign_and_previous_files() {
+        let dir = std::env::temp_dir().join(format!("rustwav-export-{}", uuid::Uuid::new_v4()));
+        let library = dir.join("library");
+        let device = dir.join("device");
+        std::fs::create_dir_all(library.join("Artist")).unwrap();
+        std::fs::create_dir_all(device.join("Artist")).unwrap();
+        std::fs::write(library.join("Artist/a.mp3"), "a").unwrap();
+        std::fs::write(library.join("Artist/b.mp3"), "b").unwrap();
+        // Not written by an export
+        std::fs::write(device.join("Artist/b.mp3"), "mine").unwrap();
+
+        let profile = profile("original", false);
+        let roots = vec![library.clone()];
+        let export = || export(&roots, &Selection::default(), &profile, &device, false, |_| {}).unwrap();
+        let summary = export();
+        assert_eq!((summary.exported, summary.skipped), (1, 1));
+        assert_eq!(std::fs::read_to_string(device.join("Artist/b.mp3")).unwrap(), "mine");
+
+        // A failed re-export leaves the previous copy
+        std::fs::write(library.join("Artist/a.mp3"), "a2").unwrap();
+        std::fs::create_dir_all(device.join("Artist/a.exporting.mp3")).unwrap();
+        let summary = export();
+        assert_eq!(summary.failed, 1);
+        assert_eq!(std::fs::read_to_string(device.join("Artist/a.mp3")).unwrap(), "a");
+        assert!(summary.files.contains(&"Artist/a.mp3".to_string()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,8 +1,10 @@
-use std::fs::File;
-use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 
 use crate::cli::PortableConfig;
+use crate::config::{Config, PlaylistFormat};
+use crate::db::DownloadDB;
+use crate::playlist;
+use crate::playlist_index::PlaylistIndex;
 
 /// Sanitize filenames to remove invalid characters
 pub fn sanitize_filename(name: &str) -> String {
@@ -80,6 +82,44 @@ pub fn create_album_folder(base_path: &Path, artist: &str, album: &str) -> PathB
     folder
 }
 
+/// Create folder for a podcast show: `{base}/Podcasts/{Show}/`
+pub fn create_podcast_folder(base_path: &Path, show: &str) -> PathBuf {
+    let folder = base_path.join("Podcasts").join(sanitize_filename(show));
+    std::fs::create_dir_all(&folder).expect("Failed to create podcast folder");
+    folder
+}
+
+/// Find the album cover kept next to the tracks (`cover.jpg`, `folder.jpg`, or `.png` variants)
+pub fn find_folder_cover(dir: &Path) -> Option<PathBuf> {
+    ["cover.jpg", "folder.jpg", "cover.png", "folder.png"]
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+/// Collect audio files from a directory, optionally recursively
+pub fn collect_audio_files(dir: &Path, recursive: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let extensions = ["mp3", "flac", "wav", "aac", "m4a", "ogg", "opus", "aiff", "aif"];
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if path.is_file() {
+            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+                if extensions.contains(&ext.to_lowercase().as_str()) {
+                    files.push(path);
+                }
+            }
+        } else if path.is_dir() && recursive {
+            files.extend(collect_audio_files(&path, recursive)?);
+        }
+    }
+
+    Ok(files)
+}
+
 /// Create folder for portable mode - shallow structure (no artist/album nesting)
 pub fn create_portable_folder(base_path: &Path, config: &PortableConfig) -> PathBuf {
     let folder = if config.enabled {
@@ -107,52 +147,23 @@ pub fn build_filename(artist: &str, title: &str, ext: &str, config: &PortableCon
     }
 }
 
-/// Calculate relative path from one directory to a file
-fn relative_path_from(from_dir: &Path, to_file: &Path) -> PathBuf {
-    // Canonicalize both paths to get absolute paths
-    let from_abs = std::fs::canonicalize(from_dir).unwrap_or_else(|_| from_dir.to_path_buf());
-    let to_abs = std::fs::canonicalize(to_file).unwrap_or_else(|_| to_file.to_path_buf());
-
-    // Find common ancestor
-    let from_parts: Vec<_> = from_abs.components().collect();
-    let to_parts: Vec<_> = to_abs.components().collect();
-
-    let mut common_len = 0;
-    for (a, b) in from_parts.iter().zip(to_parts.iter()) {
-        if a == b {
-            common_len += 1;
-        } else {
-            break;
-        }
-    }
-
-    // Build relative path: go up from 'from_dir' then down to 'to_file'
-    let mut result = PathBuf::new();
-    for _ in common_len..from_parts.len() {
-        result.push("..");
-    }
-    for part in &to_parts[common_len..] {
-        result.push(part);
-    }
-
-    result
-}
-
-pub fn create_m3u(playlist_name: &str, tracks: &[PathBuf], playlist_dir: &Path) -> anyhow::Result<()> {
-    std::fs::create_dir_all(playlist_dir)?;
-    let playlist_file = playlist_dir.join(format!("{}.m3u", sanitize_filename(playlist_name)));
-    let file = File::create(&playlist_file)?;
-    let mut writer = BufWriter::new(file);
-
-    // write header
-    writeln!(writer, "#EXTM3U")?;
-
-    for track in tracks {
-        // Calculate relative path from playlist directory to the track
-        let rel = relative_path_from(playlist_dir, track);
-        writeln!(writer, "{}", rel.display())?;
-    }
-
-    println!("Playlist saved: {}", playlist_file.display());
-    Ok(())
+/// Write the playlist for a list of files in each of the given formats,
+/// using the configured path style, and record it in the playlist index so
+/// it follows its tracks through conversions and cleanups. Returns the
+/// written files.
+pub fn create_playlists(
+    name: &str,
+    tracks: &[PathBuf],
+    playlist_dir: &Path,
+    db: Option<&DownloadDB>,
+    formats: &[PlaylistFormat],
+) -> anyhow::Result<Vec<PathBuf>> {
+    let playlist_tracks = playlist::read_tracks(tracks, db);
+    let config = Config::load().playlist;
+    let files = formats
+        .iter()
+        .map(|&format| playlist::write_playlist(format, name, &playlist_tracks, playlist_dir, &config))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    PlaylistIndex::open().record(name, playlist_dir, formats, tracks, db)?;
+    Ok(files)
 }
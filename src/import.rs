@@ -0,0 +1,263 @@
+use std::path::{Path, PathBuf};
+
+use crate::db::TrackEntry;
+use crate::metadata;
+use crate::playlist::PlaylistEntry;
+
+/// Title similarity needed for a fuzzy match
+const FUZZY_TITLE_THRESHOLD: f64 = 0.8;
+/// Artist similarity needed for a fuzzy match; lower, since credits vary
+/// ("Artist feat. X", "The Artist")
+const FUZZY_ARTIST_THRESHOLD: f64 = 0.6;
+
+/// How an imported entry was matched to a library track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// The file exists, or a library file has the same name and the same
+    /// artist and title or folder
+    Path,
+    /// Artist and title equal a download cache entry
+    Tags,
+    /// Artist and title are close to a download cache entry
+    Fuzzy,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolved {
+    pub path: PathBuf,
+    pub kind: MatchKind,
+}
+
+/// Find the library file for a playlist entry: by path, then by artist and
+/// title, then by a fuzzy artist/title match. `library` is the download
+/// cache's tracks whose files exist.
+pub fn resolve(entry: &PlaylistEntry, playlist_dir: &Path, library: &[TrackEntry]) -> Option<Resolved> {
+    let path = entry.path(playlist_dir);
+    if let Some(path) = path.as_ref().filter(|p| p.is_file()) {
+        return Some(Resolved {
+            path: path.clone(),
+            kind: MatchKind::Path,
+        });
+    }
+
+    // Playlists from another machine point at the same files under another
+    // root. A file name alone is ambiguous ("01 - Intro"), so the artist and
+    // title, or else the folder the file is in, must agree too.
+    if let Some(stem) = path.as_deref().and_then(Path::file_stem).map(|s| s.to_string_lossy().to_lowercase()) {
+        let known = artist_title(entry, playlist_dir);
+        let folder = path
+            .as_deref()
+            .filter(|_| entry.location.contains(['/', '\\']))
+            .and_then(folder_name);
+        let same_name = library.iter().find(|t| {
+            let track_path = Path::new(&t.path);
+            track_path
+                .file_stem()
+                .is_some_and(|s| s.to_string_lossy().to_lowercase() == stem)
+                && match &known {
+                    Some((artist, title)) => find_track(artist, title, std::slice::from_ref(t)).is_some(),
+                    None => folder.is_some() && folder == folder_name(track_path),
+                }
+        });
+        if let Some(track) = same_name {
+            return Some(Resolved {
+                path: PathBuf::from(&track.path),
+                kind: MatchKind::Path,
+            });
+        }
+    }
+
+    let (artist, title) = artist_title(entry, playlist_dir)?;
+    find_track(&artist, &title, library).map(|(track, kind)| Resolved {
+        path: PathBuf::from(&track.path),
+        kind,
+    })
+}
+
+/// Name of the folder a file is in, lowercased
+fn folder_name(path: &Path) -> Option<String> {
+    path.parent()?.file_name().map(|s| s.to_string_lossy().to_lowercase())
+}
+
+/// Find a library track by artist and title: exactly (ignoring case), then
+/// by a fuzzy match of the normalized names
+pub fn find_track<'a>(artist: &str, title: &str, library: &'a [TrackEntry]) -> Option<(&'a TrackEntry, MatchKind)> {
+    let exact = library
+        .iter()
+        .find(|t| t.artist.eq_ignore_ascii_case(artist) && t.title.eq_ignore_ascii_case(title));
+    if let Some(track) = exact {
+        return Some((track, MatchKind::Tags));
+    }
+
+    let (artist, title) = (normalize(artist), normalize(title));
+    library
+        .iter()
+        .filter_map(|t| {
+            let title_score = similarity(&title, &normalize(&t.title));
+            let artist_score = similarity(&artist, &normalize(&t.artist));
+            (title_score >= FUZZY_TITLE_THRESHOLD && artist_score >= FUZZY_ARTIST_THRESHOLD)
+                .then_some((title_score + artist_score, t))
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, track)| (track, MatchKind::Fuzzy))
+}
+
+/// Artist and title of an entry: from its `#EXTINF`/`TitleN` title, the
+/// file's tags, or an "Artist - Title" file name
+pub fn artist_title(entry: &PlaylistEntry, playlist_dir: &Path) -> Option<(String, String)> {
+    if let Some(split) = entry.title.as_deref().and_then(split_artist_title) {
+        return Some(split);
+    }
+    let path = entry.path(playlist_dir)?;
+    if let Ok(tags) = metadata::read_tags(&path) {
+        if let (Some(artist), Some(title)) = (tags.artist, tags.title) {
+            return Some((artist, title));
+        }
+    }
+    let stem = path.file_stem()?.to_string_lossy().to_string();
+    // Drop a leading track number ("01 - ", "01. ") but not "50 Cent"
+    let name = match stem.trim_start_matches(|c: char| c.is_ascii_digit()) {
+        rest if rest.len() < stem.len() && (rest.starts_with('.') || rest.starts_with('-') || rest.starts_with(" - ")) => {
+            rest.trim_start_matches(['.', '-', ' '])
+        }
+        _ => &stem,
+    };
+    split_artist_title(name)
+}
+
+fn split_artist_title(text: &str) -> Option<(String, String)> {
+    let (artist, title) = text.split_once(" - ")?;
+    let (artist, title) = (artist.trim(), title.trim());
+    (!artist.is_empty() && !title.is_empty()).then(|| (artist.to_string(), title.to_string()))
+}
+
+/// Lowercase words without punctuation, bracketed notes ("(Remastered)"),
+/// remaster suffixes ("- Remastered 2009"), featured artists or a leading
+/// "the"
+fn normalize(text: &str) -> String {
+    let mut text = text.to_lowercase();
+    for feat in [" feat.", " feat ", " ft.", " featuring "] {
+        if let Some(i) = text.find(feat) {
+            text.truncate(i);
+        }
+    }
+    if let Some(i) = text.rfind(" - ").filter(|&i| text[i..].contains("remaster")) {
+        text.truncate(i);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut depth = 0usize;
+    for c in text.replace('&', " and ").chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            _ if depth > 0 => {}
+            c if c.is_alphanumeric() => out.push(c),
+            _ => out.push(' '),
+        }
+    }
+    let words: Vec<&str> = out.split_whitespace().collect();
+    let words = match words.split_first() {
+        Some((&"the", rest)) if !rest.is_empty() => rest,
+        _ => &words[..],
+    };
+    words.join(" ")
+}
+
+/// Dice coefficient of the character bigrams of two strings, from 0 to 1
+fn similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    let bigrams = |s: &str| -> Vec<(char, char)> {
+        let chars: Vec<char> = s.chars().collect();
+        chars.windows(2).map(|w| (w[0], w[1])).collect()
+    };
+    let (a, mut b) = (bigrams(a), bigrams(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let total = a.len() + b.len();
+    let mut shared = 0;
+    for pair in &a {
+        if let Some(i) = b.iter().position(|p| p == pair) {
+            b.swap_remove(i);
+            shared += 1;
+        }
+    }
+    2.0 * shared as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(artist: &str, title: &str, path: &str) -> TrackEntry {
+        TrackEntry {
+            id: String::new(),
+            artist: artist.to_string(),
+            title: title.to_string(),
+            path: path.to_string(),
+            source: None,
+            trims: Vec::new(),
+            variants: Vec::new(),
+            duration_secs: None,
+        }
+    }
+
+    fn entry(location: &str, title: Option<&str>) -> PlaylistEntry {
+        PlaylistEntry {
+            location: location.to_string(),
+            title: title.map(str::to_string),
+            duration_secs: None,
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let library = vec![
+            track("Radiohead", "Karma Police", "/lib/Radiohead/OK Computer/Radiohead - Karma Police.mp3"),
+            track("The Beatles", "Let It Be", "/lib/The Beatles/Singles/The Beatles - Let It Be.flac"),
+            track("Daft Punk", "Get Lucky", "/lib/Daft Punk/Singles/Daft Punk - Get Lucky.mp3"),
+            track("Muse", "Intro", "/lib/Muse/Absolution/01 - Intro.mp3"),
+            track("Eminem", "Intro", "/lib/Eminem/Encore/01 - Intro.mp3"),
+        ];
+        let dir = Path::new("/nonexistent");
+        let resolve = |e: PlaylistEntry| resolve(&e, dir, &library).map(|r| (r.path, r.kind));
+
+        // Same file name under another root
+        let found = resolve(entry("D:\\Music\\Radiohead - Karma Police.mp3", None)).unwrap();
+        assert_eq!(found.1, MatchKind::Path);
+
+        // A generic file name needs its folder to agree
+        let found = resolve(entry("D:\\Music\\Eminem\\Encore\\01 - Intro.mp3", None)).unwrap();
+        assert_eq!(found, (PathBuf::from(&library[4].path), MatchKind::Path));
+        assert_eq!(resolve(entry("01 - Intro.mp3", None)), None);
+        assert_eq!(resolve(entry("Other/01 - Intro.mp3", None)), None);
+        let found = resolve(entry("01 - Intro.mp3", Some("Eminem - Intro"))).unwrap();
+        assert_eq!(found, (PathBuf::from(&library[4].path), MatchKind::Path));
+
+        let found = resolve(entry("x.mp3", Some("radiohead - karma police"))).unwrap();
+        assert_eq!(found, (PathBuf::from(&library[0].path), MatchKind::Tags));
+
+        let found = resolve(entry("x.mp3", Some("Beatles - Let It Be (Remastered 2009)"))).unwrap();
+        assert_eq!(found, (PathBuf::from(&library[1].path), MatchKind::Fuzzy));
+
+        let found = resolve(entry("07. Daft Punk - Get Lucky feat. Pharrell Williams.m4a", None)).unwrap();
+        assert_eq!(found, (PathBuf::from(&library[2].path), MatchKind::Fuzzy));
+
+        assert_eq!(resolve(entry("x.mp3", Some("Radiohead - Creep"))), None);
+        assert_eq!(resolve(entry("x.mp3", None)), None);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("The Beatles"), "beatles");
+        assert_eq!(normalize("Get Lucky (Radio Edit) [feat. Pharrell]"), "get lucky");
+        assert_eq!(normalize("Simon & Garfunkel"), "simon and garfunkel");
+        assert_eq!(normalize("The The"), "the");
+        assert_eq!(normalize("Let It Be - Remastered 2009"), "let it be");
+        assert_eq!(normalize("Hey - Ho"), "hey ho");
+        assert!(similarity("karma police", "karma polize") > FUZZY_TITLE_THRESHOLD);
+        assert!(similarity("karma police", "creep") < FUZZY_ARTIST_THRESHOLD);
+    }
+}
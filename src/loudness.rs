@@ -0,0 +1,333 @@
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::LoudnessConfig;
+use crate::converter;
+use crate::metadata;
+
+/// Reference loudness of ReplayGain 2.0
+pub const REPLAYGAIN_REFERENCE_LUFS: f64 = -18.0;
+
+/// Reference loudness of Opus R128 gain tags (RFC 7845)
+const R128_REFERENCE_LUFS: f64 = -23.0;
+
+/// EBU R128 measurement of a file, from the first `loudnorm` pass
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    /// Integrated loudness in LUFS
+    pub integrated: f64,
+    /// True peak in dBTP
+    pub true_peak: f64,
+    /// Loudness range in LU
+    pub lra: f64,
+    pub threshold: f64,
+    pub target_offset: f64,
+    pub duration_secs: f64,
+    pub sample_rate: Option<u32>,
+    /// Decoder name of the audio stream, e.g. "mp3" or "alac"
+    pub codec: Option<String>,
+    pub bitrate_kbps: Option<u32>,
+}
+
+/// ReplayGain values for one track
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: f64,
+    /// Linear sample peak (1.0 = full scale)
+    pub track_peak: f64,
+    pub album_gain: Option<f64>,
+    pub album_peak: Option<f64>,
+}
+
+impl ReplayGain {
+    /// Tag fields in the usual REPLAYGAIN_* text form. Album fields are `None`
+    /// when only track gain was computed.
+    pub fn tag_values(&self) -> [(&'static str, Option<String>); 4] {
+        [
+            ("REPLAYGAIN_TRACK_GAIN", Some(format!("{:.2} dB", self.track_gain))),
+            ("REPLAYGAIN_TRACK_PEAK", Some(format!("{:.6}", self.track_peak))),
+            ("REPLAYGAIN_ALBUM_GAIN", self.album_gain.map(|g| format!("{:.2} dB", g))),
+            ("REPLAYGAIN_ALBUM_PEAK", self.album_peak.map(|p| format!("{:.6}", p))),
+        ]
+    }
+
+    /// Opus R128_* gain tags (Q7.8 fixed point relative to -23 LUFS)
+    pub fn r128_values(&self) -> [(&'static str, Option<String>); 2] {
+        let q78 = |gain: f64| {
+            let db = gain + R128_REFERENCE_LUFS - REPLAYGAIN_REFERENCE_LUFS;
+            ((db * 256.0).round() as i64).clamp(i16::MIN as i64, i16::MAX as i64).to_string()
+        };
+        [
+            ("R128_TRACK_GAIN", Some(q78(self.track_gain))),
+            ("R128_ALBUM_GAIN", self.album_gain.map(q78)),
+        ]
+    }
+}
+
+/// Measure the loudness of a file with ffmpeg's `loudnorm` filter
+pub fn measure(file_path: &Path, config: &LoudnessConfig) -> anyhow::Result<Measurement> {
+    let filter = format!(
+        "loudnorm=I={}:TP={}:LRA={}:print_format=json",
+        config.target_lufs, config.true_peak_db, config.lra
+    );
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(file_path)
+        .args(["-vn", "-af", &filter, "-f", "null", "-"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to spawn FFmpeg. Is it installed?")?;
+
+    if !output.status.success() {
+        anyhow::bail!("FFmpeg loudness analysis failed for: {}", file_path.display());
+    }
+
+    let log = String::from_utf8_lossy(&output.stderr);
+    let measurement = parse_measurement(&log).context("FFmpeg printed no loudness measurement")?;
+    if !measurement.integrated.is_finite() {
+        anyhow::bail!("No measurable loudness (silent file?): {}", file_path.display());
+    }
+    Ok(measurement)
+}
+
+/// Parse the JSON block `loudnorm` prints at the end of its log, plus the
+/// input duration, sample rate and codec
+fn parse_measurement(log: &str) -> Option<Measurement> {
+    let start = log.rfind('{')?;
+    let end = start + log[start..].find('}')? + 1;
+    let json: serde_json::Value = serde_json::from_str(&log[start..end]).ok()?;
+    let field = |key: &str| json[key].as_str().and_then(|v| v.trim().parse::<f64>().ok());
+    let stream = converter::parse_stream_info(log);
+
+    Some(Measurement {
+        integrated: field("input_i")?,
+        true_peak: field("input_tp")?,
+        lra: field("input_lra")?,
+        threshold: field("input_thresh")?,
+        target_offset: field("target_offset")?,
+        duration_secs: stream.duration_secs.unwrap_or(0.0),
+        sample_rate: stream.sample_rate,
+        codec: stream.codec,
+        bitrate_kbps: stream.bitrate_kbps,
+    })
+}
+
+fn db_to_linear(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+/// Loudness of a set of tracks played back to back: the duration-weighted
+/// energy mean of their integrated loudness
+fn album_loudness(measurements: &[Measurement]) -> f64 {
+    let total: f64 = measurements.iter().map(|m| m.duration_secs).sum();
+    let energy: f64 = if total > 0.0 {
+        measurements
+            .iter()
+            .map(|m| m.duration_secs / total * 10f64.powf(m.integrated / 10.0))
+            .sum()
+    } else {
+        measurements.iter().map(|m| 10f64.powf(m.integrated / 10.0)).sum::<f64>()
+            / measurements.len() as f64
+    };
+    10.0 * energy.log10()
+}
+
+/// ReplayGain values for a set of measurements. With `album` set, they are
+/// treated as one album and also get album gain and peak.
+pub fn replaygain(measurements: &[Measurement], album: bool) -> Vec<ReplayGain> {
+    let album_values = (album && !measurements.is_empty()).then(|| {
+        let gain = REPLAYGAIN_REFERENCE_LUFS - album_loudness(measurements);
+        let peak = measurements
+            .iter()
+            .map(|m| db_to_linear(m.true_peak))
+            .fold(0.0, f64::max);
+        (gain, peak)
+    });
+
+    measurements
+        .iter()
+        .map(|m| ReplayGain {
+            track_gain: REPLAYGAIN_REFERENCE_LUFS - m.integrated,
+            track_peak: db_to_linear(m.true_peak),
+            album_gain: album_values.map(|(gain, _)| gain),
+            album_peak: album_values.map(|(_, peak)| peak),
+        })
+        .collect()
+}
+
+/// Group files by folder, which is how albums are laid out in the library
+pub fn group_by_folder(paths: &[PathBuf]) -> Vec<Vec<PathBuf>> {
+    let mut groups: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for path in paths {
+        let folder = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        groups.entry(folder).or_default().push(path.clone());
+    }
+    groups.into_values().collect()
+}
+
+/// Measure a set of files and write ReplayGain tags to each.
+/// Returns the number of files tagged and the number that failed.
+pub fn apply_replaygain<F>(paths: &[PathBuf], album: bool, config: &LoudnessConfig, on_output: F) -> (usize, usize)
+where
+    F: Fn(&str),
+{
+    let mut measured = Vec::new();
+    let mut failed = 0;
+
+    for path in paths {
+        match measure(path, config) {
+            Ok(m) => measured.push((path, m)),
+            Err(e) => {
+                failed += 1;
+                on_output(&format!("Loudness analysis failed: {} - {}", path.display(), e));
+            }
+        }
+    }
+
+    let measurements: Vec<Measurement> = measured.iter().map(|(_, m)| m.clone()).collect();
+    let gains = replaygain(&measurements, album);
+
+    let mut tagged = 0;
+    for ((path, m), gain) in measured.iter().zip(gains) {
+        match metadata::tag_replaygain(path, &gain) {
+            Ok(()) => {
+                tagged += 1;
+                on_output(&format!(
+                    "ReplayGain {:+.2} dB ({:.1} LUFS): {}",
+                    gain.track_gain,
+                    m.integrated,
+                    path.display()
+                ));
+            }
+            Err(e) => {
+                failed += 1;
+                on_output(&format!("ReplayGain tagging failed: {} - {}", path.display(), e));
+            }
+        }
+    }
+
+    (tagged, failed)
+}
+
+/// Destructively normalize a file to the configured loudness with a two-pass
+/// `loudnorm` (measure, then apply the measured values with linear gain).
+/// The file keeps its format and tags. Meant for devices whose players ignore
+/// ReplayGain.
+pub fn normalize<F>(file_path: &Path, config: &LoudnessConfig, on_output: F) -> anyhow::Result<()>
+where
+    F: Fn(&str),
+{
+    let m = measure(file_path, config)?;
+    on_output(&format!(
+        "Normalizing {} ({:.1} LUFS -> {} LUFS)",
+        file_path.display(),
+        m.integrated,
+        config.target_lufs
+    ));
+
+    let filter = format!(
+        "loudnorm=I={}:TP={}:LRA={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+        config.target_lufs,
+        config.true_peak_db,
+        config.lra,
+        m.integrated,
+        m.true_peak,
+        m.lra,
+        m.threshold,
+        m.target_offset
+    );
+    let stream = converter::StreamInfo {
+        codec: m.codec,
+        sample_rate: m.sample_rate,
+        duration_secs: Some(m.duration_secs),
+        bitrate_kbps: m.bitrate_kbps,
+    };
+    converter::filter_in_place(file_path, &filter, &stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = r#"Input #0, mp3, from 'a.mp3':
+  Duration: 00:03:25.50, start: 0.025057, bitrate: 320 kb/s
+  Stream #0:0: Audio: mp3 (mp3float), 44100 Hz, stereo, fltp, 320 kb/s
+[Parsed_loudnorm_0 @ 0x5581]
+{
+	"input_i" : "-9.52",
+	"input_tp" : "0.31",
+	"input_lra" : "4.10",
+	"input_thresh" : "-19.70",
+	"output_i" : "-16.02",
+	"output_tp" : "-1.50",
+	"output_lra" : "3.90",
+	"output_thresh" : "-26.11",
+	"normalization_type" : "dynamic",
+	"target_offset" : "0.02"
+}
+"#;
+
+    fn measurement(integrated: f64, true_peak: f64, duration_secs: f64) -> Measurement {
+        Measurement {
+            integrated,
+            true_peak,
+            lra: 0.0,
+            threshold: 0.0,
+            target_offset: 0.0,
+            duration_secs,
+            sample_rate: None,
+            codec: None,
+            bitrate_kbps: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_measurement() {
+        let m = parse_measurement(LOG).unwrap();
+        assert_eq!(m.integrated, -9.52);
+        assert_eq!(m.true_peak, 0.31);
+        assert_eq!(m.lra, 4.10);
+        assert_eq!(m.threshold, -19.70);
+        assert_eq!(m.target_offset, 0.02);
+        assert_eq!(m.duration_secs, 205.5);
+        assert_eq!(m.sample_rate, Some(44100));
+        assert_eq!(m.codec.as_deref(), Some("mp3"));
+        assert!(parse_measurement("no json here").is_none());
+    }
+
+    #[test]
+    fn test_replaygain() {
+        let ms = [measurement(-8.0, 0.0, 100.0), measurement(-18.0, -6.0, 100.0)];
+
+        let track_only = replaygain(&ms, false);
+        assert_eq!(track_only[0].track_gain, -10.0);
+        assert_eq!(track_only[1].track_gain, 0.0);
+        assert_eq!(track_only[0].track_peak, 1.0);
+        assert!(track_only[0].album_gain.is_none());
+
+        let album = replaygain(&ms, true);
+        // Energy mean of -8 and -18 LUFS is about -10.6 LUFS, dominated by the loud track
+        let album_gain = album[0].album_gain.unwrap();
+        assert!((album_gain - -7.4).abs() < 0.1, "album gain {}", album_gain);
+        assert_eq!(album[1].album_peak, Some(1.0));
+    }
+
+    #[test]
+    fn test_tag_values() {
+        let gain = ReplayGain {
+            track_gain: -3.5,
+            track_peak: 0.98765,
+            album_gain: None,
+            album_peak: None,
+        };
+        let values = gain.tag_values();
+        assert_eq!(values[0], ("REPLAYGAIN_TRACK_GAIN", Some("-3.50 dB".to_string())));
+        assert_eq!(values[1], ("REPLAYGAIN_TRACK_PEAK", Some("0.987650".to_string())));
+        assert_eq!(values[2].1, None);
+        // -3.5 dB to -18 LUFS is -8.5 dB to -23 LUFS
+        assert_eq!(gain.r128_values()[0].1.as_deref(), Some("-2176"));
+    }
+}
@@ -0,0 +1,315 @@
+use anyhow::Context;
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::config::LyricsConfig;
+use crate::metadata;
+
+/// What we know about a track when looking up its lyrics
+#[derive(Debug, Clone)]
+pub struct LyricsQuery {
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    /// Track length in seconds, used to pick the right version of a song
+    pub duration_secs: Option<u64>,
+}
+
+/// Lyrics returned by a provider. Synced lyrics are kept in LRC format
+/// (`[mm:ss.xx] line`), plain lyrics as unformatted text.
+#[derive(Debug, Clone, Default)]
+pub struct Lyrics {
+    pub plain: Option<String>,
+    pub synced: Option<String>,
+}
+
+impl Lyrics {
+    pub fn is_empty(&self) -> bool {
+        self.plain.is_none() && self.synced.is_none()
+    }
+
+    /// Plain text lyrics, falling back to the synced lyrics with timestamps removed
+    pub fn plain_text(&self) -> Option<String> {
+        if let Some(plain) = &self.plain {
+            return Some(plain.clone());
+        }
+        self.synced.as_ref().map(|synced| {
+            parse_lrc(synced)
+                .into_iter()
+                .map(|(_, line)| line)
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+    }
+
+    /// Synced lyrics as (milliseconds, line) pairs, empty if there are none
+    pub fn synced_lines(&self) -> Vec<(u32, String)> {
+        self.synced.as_deref().map(parse_lrc).unwrap_or_default()
+    }
+}
+
+/// A source of lyrics. Providers are object-safe so they can be swapped
+/// out (e.g. for a mock server in tests) behind a `Box<dyn LyricsProvider>`.
+pub trait LyricsProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Look up lyrics for a track. Returns `Ok(None)` if the provider has no match.
+    fn fetch<'a>(&'a self, query: &'a LyricsQuery) -> BoxFuture<'a, anyhow::Result<Option<Lyrics>>>;
+}
+
+/// LRCLIB-compatible HTTP backend (`/api/get` and `/api/search`)
+pub struct LrclibProvider {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LrclibRecord {
+    #[serde(default)]
+    duration: Option<f64>,
+    #[serde(default)]
+    instrumental: bool,
+    #[serde(default)]
+    plain_lyrics: Option<String>,
+    #[serde(default)]
+    synced_lyrics: Option<String>,
+}
+
+impl LrclibRecord {
+    fn into_lyrics(self) -> Option<Lyrics> {
+        if self.instrumental {
+            return None;
+        }
+        let lyrics = Lyrics {
+            plain: self.plain_lyrics.filter(|s| !s.trim().is_empty()),
+            synced: self.synced_lyrics.filter(|s| !s.trim().is_empty()),
+        };
+        if lyrics.is_empty() {
+            None
+        } else {
+            Some(lyrics)
+        }
+    }
+}
+
+impl LrclibProvider {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Exact lookup; requires the duration to match within a couple of seconds
+    async fn get(&self, query: &LyricsQuery, duration: u64) -> anyhow::Result<Option<Lyrics>> {
+        let mut params = vec![
+            ("artist_name", query.artist.clone()),
+            ("track_name", query.title.clone()),
+            ("duration", duration.to_string()),
+        ];
+        if let Some(album) = &query.album {
+            params.push(("album_name", album.clone()));
+        }
+
+        let response = self
+            .client
+            .get(format!("{}/api/get", self.base_url))
+            .query(&params)
+            .send()
+            .await
+            .context("requesting lyrics")?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let record: LrclibRecord = response
+            .error_for_status()
+            .context("lyrics lookup failed")?
+            .json()
+            .await
+            .context("parsing lyrics response")?;
+        Ok(record.into_lyrics())
+    }
+
+    /// Fuzzy search, preferring the result closest to the known duration
+    async fn search(&self, query: &LyricsQuery) -> anyhow::Result<Option<Lyrics>> {
+        let mut params = vec![
+            ("artist_name", query.artist.clone()),
+            ("track_name", query.title.clone()),
+        ];
+        if let Some(album) = &query.album {
+            params.push(("album_name", album.clone()));
+        }
+
+        let records: Vec<LrclibRecord> = self
+            .client
+            .get(format!("{}/api/search", self.base_url))
+            .query(&params)
+            .send()
+            .await
+            .context("searching lyrics")?
+            .error_for_status()
+            .context("lyrics search failed")?
+            .json()
+            .await
+            .context("parsing lyrics search response")?;
+
+        let best = match query.duration_secs {
+            Some(duration) => records.into_iter().min_by_key(|r| {
+                r.duration
+                    .map(|d| (d - duration as f64).abs() as u64)
+                    .unwrap_or(u64::MAX)
+            }),
+            None => records.into_iter().next(),
+        };
+        Ok(best.and_then(LrclibRecord::into_lyrics))
+    }
+}
+
+impl LyricsProvider for LrclibProvider {
+    fn name(&self) -> &str {
+        "lrclib"
+    }
+
+    fn fetch<'a>(&'a self, query: &'a LyricsQuery) -> BoxFuture<'a, anyhow::Result<Option<Lyrics>>> {
+        Box::pin(async move {
+            if let Some(duration) = query.duration_secs {
+                if let Some(lyrics) = self.get(query, duration).await? {
+                    return Ok(Some(lyrics));
+                }
+            }
+            self.search(query).await
+        })
+    }
+}
+
+/// Build the lyrics provider described by the configuration
+pub fn provider_from_config(config: &LyricsConfig) -> Box<dyn LyricsProvider> {
+    Box::new(LrclibProvider::new(&config.base_url))
+}
+
+/// Parse LRC text into (milliseconds, line) pairs sorted by time.
+/// Lines with several timestamps (`[00:12.00][01:30.00] chorus`) are repeated,
+/// metadata tags such as `[ar:Artist]` are ignored.
+pub fn parse_lrc(text: &str) -> Vec<(u32, String)> {
+    let mut lines = Vec::new();
+
+    for raw in text.lines() {
+        let mut rest = raw.trim();
+        let mut stamps = Vec::new();
+
+        while let Some(stripped) = rest.strip_prefix('[') {
+            let Some(end) = stripped.find(']') else { break };
+            match parse_timestamp(&stripped[..end]) {
+                Some(ms) => stamps.push(ms),
+                None => break,
+            }
+            rest = &stripped[end + 1..];
+        }
+
+        for ms in stamps {
+            lines.push((ms, rest.trim().to_string()));
+        }
+    }
+
+    lines.sort_by_key(|(ms, _)| *ms);
+    lines
+}
+
+/// Parse an LRC timestamp (`mm:ss`, `mm:ss.xx` or `mm:ss.xxx`) into milliseconds
+fn parse_timestamp(stamp: &str) -> Option<u32> {
+    let (minutes, seconds) = stamp.split_once(':')?;
+    let minutes: u32 = minutes.parse().ok()?;
+    let (secs, frac) = match seconds.split_once('.') {
+        Some((s, f)) => (s, f),
+        None => (seconds, ""),
+    };
+    let secs: u32 = secs.parse().ok()?;
+    // Milliseconds from the first three digits; by chars, as the text after
+    // the dot may not be ASCII
+    let digits: String = frac.chars().take(3).collect();
+    let frac_ms = match digits.chars().count() {
+        0 => 0,
+        1 => digits.parse::<u32>().ok()? * 100,
+        2 => digits.parse::<u32>().ok()? * 10,
+        _ => digits.parse::<u32>().ok()?,
+    };
+    // Tags like [99999999:00] are out of range rather than a time
+    minutes
+        .checked_mul(60_000)?
+        .checked_add(secs.checked_mul(1000)?)?
+        .checked_add(frac_ms)
+}
+
+/// Write synced lyrics to an `.lrc` file next to the audio file.
+/// Returns the sidecar path, or `None` if there were no synced lyrics to write.
+pub fn write_lrc_sidecar(audio_path: &Path, lyrics: &Lyrics) -> anyhow::Result<Option<PathBuf>> {
+    let Some(synced) = &lyrics.synced else {
+        return Ok(None);
+    };
+    let lrc_path = audio_path.with_extension("lrc");
+    std::fs::write(&lrc_path, synced)
+        .with_context(|| format!("writing {}", lrc_path.display()))?;
+    Ok(Some(lrc_path))
+}
+
+/// Fetch lyrics for a track, embed them into the file and optionally write an
+/// `.lrc` sidecar. Returns the lyrics that were applied, if any were found.
+pub async fn fetch_and_embed(
+    provider: &dyn LyricsProvider,
+    file_path: &Path,
+    query: &LyricsQuery,
+    write_lrc: bool,
+) -> anyhow::Result<Option<Lyrics>> {
+    let Some(lyrics) = provider.fetch(query).await? else {
+        return Ok(None);
+    };
+    metadata::embed_lyrics(file_path, &lyrics)?;
+    if write_lrc {
+        write_lrc_sidecar(file_path, &lyrics)?;
+    }
+    Ok(Some(lyrics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("00:00"), Some(0));
+        assert_eq!(parse_timestamp("01:02.5"), Some(62_500));
+        assert_eq!(parse_timestamp("01:02.50"), Some(62_500));
+        assert_eq!(parse_timestamp("01:02.503"), Some(62_503));
+        assert_eq!(parse_timestamp("ar:Artist"), None);
+        assert_eq!(parse_timestamp("00:01.2é45"), None);
+        assert_eq!(parse_timestamp("71582:47.296"), None);
+        assert_eq!(parse_timestamp("4294967295:00"), None);
+        assert_eq!(parse_timestamp("00:4294968"), None);
+    }
+
+    #[test]
+    fn test_parse_lrc() {
+        let lrc = "[ar:Someone]\n[00:12.00]First\n[00:05.10][00:20.00]Chorus\nno timestamp";
+        let lines = parse_lrc(lrc);
+        assert_eq!(
+            lines,
+            vec![
+                (5_100, "Chorus".to_string()),
+                (12_000, "First".to_string()),
+                (20_000, "Chorus".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plain_text_falls_back_to_synced() {
+        let lyrics = Lyrics {
+            plain: None,
+            synced: Some("[00:01.00]One\n[00:02.00]Two".to_string()),
+        };
+        assert_eq!(lyrics.plain_text().as_deref(), Some("One\nTwo"));
+    }
+}
@@ -1,22 +1,45 @@
 mod sources {
     pub mod models;
+    pub mod musicbrainz;
+    pub mod providers;
     pub mod spotify;
     pub mod youtube;
 }
+mod tags {
+    pub mod mapping;
+    pub mod mp4;
+    pub mod vorbis;
+}
+mod artwork;
 mod cli;
+mod config;
 mod converter;
 mod db;
 mod downloader;
 pub mod error_log;
+mod export;
 mod file_utils;
+mod import;
+mod loudness;
+mod lyrics;
 mod metadata;
+mod playlist;
+mod playlist_index;
+mod regenerate;
+mod smart;
+mod sync;
+mod trash;
+mod trim;
 mod tui;
 
 use crate::{
     cli::{Cli, PortableConfig},
     db::DownloadDB,
     error_log::{ErrorLogManager, ErrorType},
-    sources::spotify,
+    sources::{
+        providers::{self, Operation},
+        spotify,
+    },
     tui::{App, DownloadWorker},
 };
 use clap::Parser;
@@ -25,9 +48,10 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::prelude::*;
-use rspotify::model::PlayableItem;
+use rspotify::model::{PlayableItem, PlaylistItem};
 use std::io::stdout;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tokio::sync::{mpsc, watch};
 
 #[tokio::main]
@@ -56,14 +80,23 @@ async fn run_tui() -> anyhow::Result<()> {
     let (event_tx, event_rx) = mpsc::channel(32);
     let (pause_tx, pause_rx) = watch::channel(false);
 
-    // Spawn the download worker
-    let worker = DownloadWorker::new(download_rx, event_tx.clone(), pause_rx);
+    // Conversions run on their own lane so they don't wait behind downloads
+    let (convert_tx, convert_rx) = mpsc::channel(32);
+    let db = Arc::new(Mutex::new(DownloadDB::new("data/cache/downloaded_songs.json")));
+    let error_log = Arc::new(Mutex::new(ErrorLogManager::new("data/errors")));
+
+    // Spawn the download and conversion workers
+    let worker = DownloadWorker::new(download_rx, event_tx.clone(), pause_rx.clone(), db.clone(), error_log.clone());
     tokio::spawn(async move {
         worker.run().await;
     });
+    let convert_worker = DownloadWorker::new(convert_rx, event_tx.clone(), pause_rx, db, error_log.clone());
+    tokio::spawn(async move {
+        convert_worker.run().await;
+    });
 
     // Create app state with channels
-    let mut app = App::new(download_tx, event_tx, event_rx, pause_tx);
+    let mut app = App::new(download_tx, convert_tx, event_tx, event_rx, pause_tx, error_log);
 
     // Main loop
     while app.running {
@@ -102,13 +135,27 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
         cli::Commands::Album {
             link,
             format,
-            quality: _,
+            quality,
+            lyrics,
+            require_quality,
+            replaygain,
+            normalize,
+            trim_silence,
+            sponsorblock,
         } => {
             let actual_format = if config.enabled {
                 "mp3".to_string()
             } else {
                 format.clone()
             };
+            let source_quality = source_quality_config(*require_quality);
+            let loudness_config = config::Config::load().loudness;
+            let replaygain = *replaygain || loudness_config.replaygain;
+            let normalize = *normalize || (config.enabled && loudness_config.normalize_portable);
+            let trim_config = config::Config::load().trim;
+            let tagging = config::Config::load().tagging;
+            let trim_silence = *trim_silence || trim_config.silence;
+            let sponsorblock = *sponsorblock || trim_config.sponsorblock;
 
             let album = spotify::fetch_album(link).await?;
             let main_artist = album
@@ -117,9 +164,20 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                 .and_then(|a| a.name.clone().into())
                 .unwrap_or_else(|| "Unknown Artist".to_string());
             let album_name = album.name.clone();
+            let mut source = regenerate::PlaylistSource::new(
+                link,
+                regenerate::SourceKind::Album,
+                &format!("{} - {}", main_artist, album_name),
+                format,
+                quality,
+                config.enabled,
+            );
+            source.require_quality = *require_quality;
+            record_source(source);
 
             // Fetch genre for the album (from album or artist)
-            let album_genre = spotify::fetch_album_genres(&album).await;
+            let genre_providers = providers::providers_for(Operation::Genre, &config::Config::load().metadata);
+            let album_genre = providers::spotify_album_genre(&genre_providers, &album).await;
 
             let album_folder = if config.enabled {
                 file_utils::create_portable_folder(&music_path, &config)
@@ -127,26 +185,21 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                 file_utils::create_album_folder(&music_path, &main_artist, &album_name)
             };
 
+            // Full-size cover.jpg; embedded copies are downscaled from it
             let cover_path: Option<std::path::PathBuf> = {
-                if let Some(image) = album.images.first() {
-                    let p = album_folder.join("cover.jpg");
-                    if !p.exists() {
-                        if let Ok(response) = reqwest::blocking::get(&image.url) {
-                            if let Ok(bytes) = response.bytes() {
-                                let _ = std::fs::write(&p, &bytes);
-                            }
-                        }
-                    }
-                    if p.exists() {
-                        Some(p)
-                    } else {
-                        None
-                    }
+                let p = album_folder.join("cover.jpg");
+                if p.exists() {
+                    Some(p)
                 } else {
-                    None
+                    let mut query = artwork::ArtworkQuery::new(&main_artist, &album_name);
+                    query.image_urls = album.images.iter().map(|img| img.url.clone()).collect();
+                    fetch_artwork(&query, &p).await
                 }
             };
 
+            let mut album_paths: Vec<PathBuf> = Vec::new();
+            let mut downloaded_any = false;
+
             for (i, track) in album.tracks.items.iter().enumerate() {
                 let track_title = track.name.clone();
                 let track_artist = track
@@ -158,145 +211,466 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                 let safe_file_name = file_utils::build_filename(
                     &track_artist,
                     &track_title,
-                    &actual_format,
+                    converter::format_extension(&actual_format),
                     &config,
                 );
 
                 let file_path = album_folder.join(&safe_file_name);
 
                 let entry = db::TrackEntry {
+                    id: String::new(),
                     artist: track_artist.clone(),
                     title: track_title.clone(),
                     path: file_path.display().to_string(),
+                    source: None,
+                    trims: Vec::new(),
+                    variants: Vec::new(),
+                    duration_secs: None,
                 };
 
-                if db.contains(&entry) {
+                if let Some(existing) = db.find_download(&entry) {
                     println!("Skipping: {} — {}", track_artist, track_title);
+                    album_paths.push(PathBuf::from(&existing.path));
                     continue;
                 }
 
-                println!("Downloading: {} — {}", track_artist, track_title);
                 let query = format!("{} {}", track_artist, track_title);
+                let origin = downloader::TrackOrigin {
+                    link,
+                    link_type: "album",
+                    format: &actual_format,
+                    quality,
+                    portable: config.enabled,
+                    artist: &track_artist,
+                    title: &track_title,
+                };
+                let Some(quality_warning) = screen_track(&query, &source_quality, &origin).await else {
+                    continue;
+                };
+
+                println!("Downloading: {} — {}", track_artist, track_title);
 
                 let file_path_clone = file_path.clone();
                 let format_clone = actual_format.clone();
                 let query_clone = query.clone();
-                tokio::task::spawn_blocking(move || {
-                    downloader::download_track(&query_clone, &file_path_clone, &format_clone)
+                let downloaded = tokio::task::spawn_blocking(move || {
+                    downloader::download_track(&query_clone, &file_path_clone, &format_clone, sponsorblock)
                 })
                 .await??;
+                let file_path = downloaded.path;
+                let mut trims = downloaded.trims;
+                if trim_silence {
+                    trims.extend(trim_silence_of(&file_path).await);
+                }
+                let entry = db::TrackEntry {
+                    path: file_path.display().to_string(),
+                    source: downloaded.source,
+                    trims,
+                    variants: Vec::new(),
+                    ..entry
+                };
 
-                metadata::tag_audio(
-                    &file_path,
-                    &track_artist,
-                    &album_name,
-                    &track_title,
-                    (i + 1) as u32,
-                    album_genre.as_deref(),
-                    cover_path.as_deref(),
-                    &config,
-                )?;
+                if metadata::holds_tags(&file_path) {
+                    metadata::tag_audio(
+                        &file_path,
+                        &metadata::TrackTags {
+                            artist: &track_artist,
+                            album: &album_name,
+                            title: &track_title,
+                            track: (i + 1) as u32,
+                            genre: album_genre.as_deref(),
+                        },
+                        cover_path.as_deref(),
+                        &config,
+                        &tagging,
+                    )?;
+                } else {
+                    println!("  {}", metadata::UNTAGGED_WARNING);
+                }
+
+                if let Some(warning) = &quality_warning {
+                    if let Err(e) = metadata::tag_quality_warning(&file_path, warning) {
+                        println!("  Failed to tag quality warning: {}", e);
+                    }
+                }
+
+                if *lyrics {
+                    let query = lyrics::LyricsQuery {
+                        artist: track_artist.clone(),
+                        title: track_title.clone(),
+                        album: Some(album_name.clone()),
+                        duration_secs: Some(track.duration.num_seconds() as u64),
+                    };
+                    apply_lyrics(&file_path, &query).await;
+                }
+
+                if normalize {
+                    normalize_loudness(&file_path).await;
+                }
 
                 db.add(entry);
+                album_paths.push(file_path);
+                downloaded_any = true;
+            }
+
+            if let Err(e) = regenerate::PlaylistSources::open().record_paths(link, &album_paths) {
+                println!("Warning: album files not recorded: {}", e);
+            }
+            if replaygain && downloaded_any {
+                apply_replaygain(album_paths, true).await;
             }
 
+            if downloaded_any {
+                regenerate_smart_playlists(&mut db, &playlist_path);
+            }
             println!("Album '{}' by {} finished.", album_name, main_artist);
         }
 
         cli::Commands::Playlist {
             link,
             format,
-            quality: _,
+            quality,
+            lyrics,
+            require_quality,
+            replaygain,
+            normalize,
+            trim_silence,
+            sponsorblock,
+            playlist_formats,
         } => {
             let actual_format = if config.enabled {
                 "mp3".to_string()
             } else {
                 format.clone()
             };
+            let loudness_config = config::Config::load().loudness;
+            let replaygain = *replaygain || loudness_config.replaygain;
+            let trim_config = config::Config::load().trim;
+            let tagging = config::Config::load().tagging;
+            let settings = PlaylistDownload {
+                link,
+                link_type: "playlist",
+                format: &actual_format,
+                quality,
+                lyrics: *lyrics,
+                source_quality: source_quality_config(*require_quality),
+                normalize: *normalize || (config.enabled && loudness_config.normalize_portable),
+                trim_silence: *trim_silence || trim_config.silence,
+                sponsorblock: *sponsorblock || trim_config.sponsorblock,
+                tagging: &tagging,
+            };
+            let playlist_formats = playlist_formats_config(playlist_formats)?;
 
             let playlist = spotify::fetch_playlist(link).await?;
             let playlist_name = playlist.name.clone();
+            let mut source = regenerate::PlaylistSource::new(
+                link,
+                regenerate::SourceKind::Playlist,
+                &playlist_name,
+                format,
+                quality,
+                config.enabled,
+            );
+            source.require_quality = *require_quality;
+            record_source(source);
 
             std::fs::create_dir_all(&playlist_path)?;
-            let mut downloaded_paths: Vec<PathBuf> = Vec::new();
+            let run =
+                download_playlist_items(&playlist.tracks.items, &settings, &config, &mut db, &music_path, &playlist_path)
+                    .await?;
 
-            for item in playlist.tracks.items.iter() {
-                let track_obj = match &item.track {
-                    Some(t) => t,
-                    None => continue,
-                };
+            if replaygain {
+                apply_replaygain(run.downloaded.clone(), false).await;
+            }
 
-                let (track_title, track_artist) = match track_obj {
-                    PlayableItem::Track(track) => {
-                        let title = track.name.clone();
-                        let artist = track
-                            .artists
-                            .first()
-                            .map(|a| a.name.clone())
-                            .unwrap_or_else(|| "Unknown Artist".to_string());
-                        (title, artist)
-                    }
-                    PlayableItem::Episode(_) => continue,
-                };
+            let paths = run.paths();
+            let files = file_utils::create_playlists(&playlist_name, &paths, &playlist_path, Some(&db), &playlist_formats)?;
+            for file in files {
+                println!("Playlist saved: {}", file.display());
+            }
+            if !run.downloaded.is_empty() {
+                regenerate_smart_playlists(&mut db, &playlist_path);
+            }
+            println!("Playlist '{}' with {} tracks finished.", playlist_name, paths.len());
+        }
 
-                let output_folder = if config.enabled {
-                    file_utils::create_portable_folder(&playlist_path, &config)
-                } else {
-                    file_utils::create_album_folder(&playlist_path, &track_artist, "Singles")
+        cli::Commands::Sync {
+            playlist,
+            format,
+            quality,
+            lyrics,
+            clean,
+            list,
+            untrack,
+            playlist_formats,
+        } => {
+            let mut tracked = sync::TrackedPlaylists::open();
+
+            if *list {
+                if tracked.playlists().is_empty() {
+                    println!("No tracked playlists. Start with `rustwav sync <spotify-playlist-link>`.");
+                }
+                for p in tracked.playlists() {
+                    println!(
+                        "{}  ({} tracks, {} missing, {} {}, synced {})",
+                        p.name,
+                        p.tracks.len(),
+                        p.missing(),
+                        p.format,
+                        p.quality,
+                        p.synced_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                    );
+                    println!("    {}", p.link);
+                }
+                return Ok(());
+            }
+
+            if *untrack {
+                let Some(query) = playlist else {
+                    anyhow::bail!("Name the playlist to stop tracking");
                 };
+                match tracked.untrack(query)? {
+                    Some(p) => println!("No longer tracking '{}'; its files stay in the library", p.name),
+                    None => anyhow::bail!("Not a tracked playlist: {}", query),
+                }
+                return Ok(());
+            }
 
-                let safe_file_name = file_utils::build_filename(
-                    &track_artist,
-                    &track_title,
-                    &actual_format,
-                    &config,
+            // Tracked playlists keep the format and quality they were first synced in
+            let already_tracked = playlist.as_ref().is_none_or(|query| tracked.find(query).is_some());
+            if already_tracked && (format.is_some() || quality.is_some()) {
+                anyhow::bail!(
+                    "--format and --quality only apply to newly tracked playlists; untrack the playlist and sync it again to change them"
                 );
-                let file_path = output_folder.join(&safe_file_name);
+            }
 
-                let entry = db::TrackEntry {
-                    artist: track_artist.clone(),
-                    title: track_title.clone(),
-                    path: file_path.display().to_string(),
+            let links: Vec<String> = match playlist {
+                Some(query) => vec![tracked.find(query).map_or_else(|| query.clone(), |p| p.link.clone())],
+                None => tracked.playlists().iter().map(|p| p.link.clone()).collect(),
+            };
+            if links.is_empty() && !*clean {
+                println!("No tracked playlists. Start with `rustwav sync <spotify-playlist-link>`.");
+            }
+            let playlist_formats = playlist_formats_config(playlist_formats)?;
+            let loudness_config = config::Config::load().loudness;
+            let trim_config = config::Config::load().trim;
+            let tagging = config::Config::load().tagging;
+            let mut downloaded_any = false;
+
+            for link in &links {
+                let previous = tracked.find(link).cloned();
+                let meta = spotify::fetch_playlist(link).await?;
+                if let Some(previous) = &previous {
+                    if previous.is_current(&meta.snapshot_id) {
+                        println!("'{}' is up to date", meta.name);
+                        continue;
+                    }
+                }
+
+                // Tracked playlists keep the format they were first synced in
+                let actual_format = match (&previous, format) {
+                    _ if config.enabled => "mp3".to_string(),
+                    (Some(previous), _) => previous.format.clone(),
+                    (None, Some(format)) => format.clone(),
+                    (None, None) => "mp3".to_string(),
+                };
+                let actual_quality = match (&previous, quality) {
+                    (Some(previous), _) => previous.quality.clone(),
+                    (None, Some(quality)) => quality.clone(),
+                    (None, None) => "high".to_string(),
                 };
+                println!("Syncing '{}' ({}, {})", meta.name, actual_format, actual_quality);
+
+                let items = spotify::fetch_all_playlist_items(link).await?;
+                let settings = PlaylistDownload {
+                    link,
+                    link_type: "playlist",
+                    format: &actual_format,
+                    quality: &actual_quality,
+                    lyrics: *lyrics,
+                    source_quality: source_quality_config(None),
+                    normalize: config.enabled && loudness_config.normalize_portable,
+                    trim_silence: trim_config.silence,
+                    sponsorblock: trim_config.sponsorblock,
+                    tagging: &tagging,
+                };
+                std::fs::create_dir_all(&playlist_path)?;
+                let run = download_playlist_items(&items, &settings, &config, &mut db, &music_path, &playlist_path).await?;
+                downloaded_any |= !run.downloaded.is_empty();
+                if loudness_config.replaygain {
+                    apply_replaygain(run.downloaded.clone(), false).await;
+                }
+                for file in file_utils::create_playlists(&meta.name, &run.paths(), &playlist_path, Some(&db), &playlist_formats)? {
+                    println!("Playlist saved: {}", file.display());
+                }
 
-                if db.contains(&entry) {
-                    println!("Skipping: {} — {}", track_artist, track_title);
-                    downloaded_paths.push(std::path::PathBuf::from(entry.path.clone()));
-                    continue;
+                let diff = tracked.record(sync::TrackedPlaylist {
+                    name: meta.name.clone(),
+                    link: link.clone(),
+                    snapshot_id: meta.snapshot_id.clone(),
+                    synced_at: chrono::Utc::now(),
+                    format: actual_format,
+                    quality: actual_quality,
+                    portable: config.enabled,
+                    downloaded: run.downloaded.iter().map(|p| p.display().to_string()).collect(),
+                    tracks: run.tracks,
+                })?;
+                for track in &diff.added {
+                    println!("  + {}", track);
+                }
+                for track in &diff.removed {
+                    println!("  - {}", track);
                 }
+                for track in &diff.missing {
+                    println!("  ! {} (not downloaded)", track);
+                }
+                println!("'{}' synced: {}", meta.name, diff.summary());
+            }
 
-                println!("Downloading: {} — {}", track_artist, track_title);
-                let query = format!("{} {}", track_artist, track_title);
-                let file_path_clone = file_path.clone();
-                let format_clone = actual_format.clone();
-                let query_clone = query.clone();
-                tokio::task::spawn_blocking(move || {
-                    downloader::download_track(&query_clone, &file_path_clone, &format_clone)
-                })
-                .await??;
+            if downloaded_any {
+                regenerate_smart_playlists(&mut db, &playlist_path);
+            }
 
-                // For playlists, we don't have album-level genre info, use None
-                // The retag command can be used to add genre later
-                metadata::tag_audio(
-                    &file_path,
-                    &track_artist,
-                    "Singles",
-                    &track_title,
-                    0,
-                    None, // genre - can be added via retag command
-                    None,
-                    &config,
-                )?;
-                db.add(entry.clone());
-                downloaded_paths.push(file_path);
+            if *clean {
+                let removed = sync::clean(&mut tracked, &mut db, &mut trash::Trash::open())?;
+                for path in &removed {
+                    println!("Trashed: {}", path);
+                }
+                println!(
+                    "{} track(s) no longer in any tracked playlist moved to the trash (see `rustwav trash list`)",
+                    removed.len()
+                );
+                if !removed.is_empty() {
+                    update_playlists(&db);
+                }
             }
+        }
 
-            file_utils::create_m3u(&playlist_name, &downloaded_paths, &playlist_path)?;
-            println!(
-                "Playlist '{}' with {} tracks finished.",
-                playlist_name,
-                downloaded_paths.len()
-            );
+        cli::Commands::Import {
+            playlists,
+            format,
+            quality,
+            lyrics,
+            dry_run,
+            playlist_formats,
+        } => {
+            let actual_format = if config.enabled { "mp3".to_string() } else { format.clone() };
+            let playlist_formats = playlist_formats_config(playlist_formats)?;
+            let loudness_config = config::Config::load().loudness;
+            let trim_config = config::Config::load().trim;
+            let tagging = config::Config::load().tagging;
+
+            for file in playlists {
+                let file = Path::new(file);
+                let entries = playlist::read_playlist(file)?;
+                let name = file
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "Imported".to_string());
+                let playlist_dir = file.parent().unwrap_or(Path::new("."));
+                println!("Importing '{}' ({} entries)", name, entries.len());
+
+                let link = file.display().to_string();
+                let settings = PlaylistDownload {
+                    link: &link,
+                    link_type: "import",
+                    format: &actual_format,
+                    quality,
+                    lyrics: *lyrics,
+                    source_quality: source_quality_config(None),
+                    normalize: config.enabled && loudness_config.normalize_portable,
+                    trim_silence: trim_config.silence,
+                    sponsorblock: trim_config.sponsorblock,
+                    tagging: &tagging,
+                };
+                let mut paths: Vec<PathBuf> = Vec::new();
+                let mut downloaded: Vec<PathBuf> = Vec::new();
+                let mut unresolved: Vec<String> = Vec::new();
+                let (mut by_path, mut by_tags, mut fuzzy) = (0, 0, 0);
+                let mut library: Vec<db::TrackEntry> = db.tracks.iter().filter(|t| !t.is_missing()).cloned().collect();
+
+                for entry in &entries {
+                    if let Some(found) = import::resolve(entry, playlist_dir, &library) {
+                        match found.kind {
+                            import::MatchKind::Path => by_path += 1,
+                            import::MatchKind::Tags => by_tags += 1,
+                            import::MatchKind::Fuzzy => fuzzy += 1,
+                        }
+                        paths.push(found.path);
+                        continue;
+                    }
+                    let Some((artist, title)) = import::artist_title(entry, playlist_dir) else {
+                        println!("  ? {} (no artist/title to search for)", entry.location);
+                        unresolved.push(entry.location.clone());
+                        continue;
+                    };
+                    if *dry_run {
+                        println!("  + {} - {} (would download)", artist, title);
+                        continue;
+                    }
+
+                    let output_folder = if config.enabled {
+                        file_utils::create_portable_folder(&playlist_path, &config)
+                    } else {
+                        file_utils::create_album_folder(&playlist_path, &artist, "Singles")
+                    };
+                    let file_path = output_folder.join(file_utils::build_filename(
+                        &artist,
+                        &title,
+                        converter::format_extension(&actual_format),
+                        &config,
+                    ));
+                    let track = db::TrackEntry {
+                        id: String::new(),
+                        artist: artist.clone(),
+                        title: title.clone(),
+                        path: file_path.display().to_string(),
+                        source: None,
+                        trims: Vec::new(),
+                        variants: Vec::new(),
+                        duration_secs: None,
+                    };
+                    match download_track_entry(track, entry.duration_secs, &settings, &config, &mut db).await {
+                        Ok(Some(path)) => {
+                            // Later entries for the same track resolve to the download
+                            library.extend(db.find_by_path(&path.display().to_string()).cloned());
+                            downloaded.push(path.clone());
+                            paths.push(path);
+                        }
+                        Ok(None) => unresolved.push(format!("{} - {}", artist, title)),
+                        Err(e) => {
+                            println!("  Failed: {} - {} ({})", artist, title, e);
+                            unresolved.push(format!("{} - {}", artist, title));
+                        }
+                    }
+                }
+
+                println!(
+                    "'{}': {} by path, {} by tags, {} fuzzy, {} downloaded, {} unresolved",
+                    name,
+                    by_path,
+                    by_tags,
+                    fuzzy,
+                    downloaded.len(),
+                    unresolved.len()
+                );
+                for entry in &unresolved {
+                    println!("  Unresolved: {}", entry);
+                }
+                if *dry_run {
+                    continue;
+                }
+
+                if loudness_config.replaygain {
+                    apply_replaygain(downloaded.clone(), false).await;
+                }
+                for file in file_utils::create_playlists(&name, &paths, &playlist_path, Some(&db), &playlist_formats)? {
+                    println!("Playlist saved: {}", file.display());
+                }
+                if !downloaded.is_empty() {
+                    regenerate_smart_playlists(&mut db, &playlist_path);
+                }
+            }
         }
 
         cli::Commands::Convert {
@@ -305,6 +679,18 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
             quality,
             refresh_metadata,
             recursive,
+            replaygain,
+            normalize,
+            vbr,
+            bitrate,
+            opus_application,
+            compression_level,
+            sample_rate,
+            bit_depth,
+            mono,
+            dither,
+            output_dir,
+            keep_both,
         } => {
             // Check FFmpeg availability
             if !converter::check_ffmpeg_available() {
@@ -320,16 +706,41 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                 );
             }
 
+            let encoder = config::EncoderSettings {
+                vbr: *vbr,
+                bitrate_kbps: *bitrate,
+                opus_application: opus_application.clone(),
+                compression_level: *compression_level,
+                sample_rate: *sample_rate,
+                bit_depth: *bit_depth,
+                mono: *mono,
+                dither: *dither,
+            };
+            converter::validate_encoder(to, &encoder)?;
+
             // Collect files to convert
             let input_path = std::path::Path::new(input);
             let files: Vec<PathBuf> = if input_path.is_file() {
                 vec![input_path.to_path_buf()]
             } else if input_path.is_dir() {
-                collect_audio_files(input_path, *recursive)?
+                file_utils::collect_audio_files(input_path, *recursive)?
             } else {
                 anyhow::bail!("Input path does not exist: {}", input);
             };
 
+            // Flags take precedence over the config file
+            let convert_config = config::Config::load().convert;
+            let output_root = output_dir.clone().or(convert_config.output_dir).map(PathBuf::from);
+            let keep_both = *keep_both || convert_config.keep_both;
+            let input_root = if input_path.is_file() {
+                input_path.parent().unwrap_or(std::path::Path::new("")).to_path_buf()
+            } else {
+                input_path.to_path_buf()
+            };
+            if let Some(root) = &output_root {
+                println!("Writing converted files to {}", root.display());
+            }
+
             if files.is_empty() {
                 println!("No audio files found to convert.");
                 return Ok(());
@@ -338,27 +749,58 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
             println!("Found {} file(s) to convert to {}", files.len(), to);
 
             let mut converted_count = 0;
+            let mut converted_paths: Vec<PathBuf> = Vec::new();
+            let loudness_config = config::Config::load().loudness;
+            let metadata_providers = providers::providers_for(Operation::Refresh, &config::Config::load().metadata);
+            let tagging = config::Config::load().tagging;
             let mut failed_count = 0;
 
             for file_path in &files {
-                let current_format = converter::get_format_from_path(file_path);
-                if current_format.as_deref() == Some(to.as_str()) {
+                if converter::is_in_format(file_path, to) {
                     println!("Skipping {} (already in {} format)", file_path.display(), to);
                     continue;
                 }
 
-                println!("\nConverting: {}", file_path.display());
-
                 // Convert the file
-                let result = converter::convert_audio(file_path, to, quality, |msg| {
-                    println!("  {}", msg);
-                });
+                let new_path = converter::output_path(
+                    file_path,
+                    to,
+                    std::slice::from_ref(&input_root),
+                    output_root.as_deref(),
+                );
+                // A codec change within a container (AAC .m4a to ALAC) replaces the file
+                let in_place = new_path == *file_path;
+                if in_place && keep_both {
+                    println!(
+                        "Skipping {} (can't keep both: {} is written to the same file name; set an output folder)",
+                        file_path.display(),
+                        to
+                    );
+                    continue;
+                }
+
+                println!("\nConverting: {}", file_path.display());
+                let result = converter::convert_audio_to(
+                    file_path,
+                    &new_path,
+                    to,
+                    quality,
+                    &encoder,
+                    |msg| println!("  {}", msg),
+                    |_| {},
+                );
 
                 match result {
-                    Ok(new_path) => {
+                    Ok(()) => {
                         converted_count += 1;
 
-                        // Refresh metadata from Spotify if requested
+                        if *normalize {
+                            if let Err(e) = loudness::normalize(&new_path, &loudness_config, |msg| println!("  {}", msg)) {
+                                println!("  Normalization failed: {}", e);
+                            }
+                        }
+
+                        // Refresh metadata from the configured providers if requested
                         if *refresh_metadata {
                             if let Some(entry) = db.find_by_path(&file_path.display().to_string()) {
                                 let artist = entry.artist.clone();
@@ -366,40 +808,20 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
 
                                 println!("  Refreshing metadata for: {} - {}", artist, title);
 
-                                // Search Spotify for metadata
+                                // Search the metadata providers
                                 match tokio::runtime::Handle::current()
-                                    .block_on(spotify::search_track(&artist, &title))
+                                    .block_on(providers::search_track(&metadata_providers, &artist, &title, None))
                                 {
                                     Ok(Some(meta)) => {
                                         // Download cover art if available
-                                        let cover_path = if let Some(url) = &meta.cover_url {
-                                            let cover_file =
-                                                new_path.with_file_name("temp_cover.jpg");
-                                            if let Ok(response) = reqwest::blocking::get(url) {
-                                                if let Ok(bytes) = response.bytes() {
-                                                    let _ = std::fs::write(&cover_file, &bytes);
-                                                    Some(cover_file)
-                                                } else {
-                                                    None
-                                                }
-                                            } else {
-                                                None
-                                            }
-                                        } else {
-                                            None
-                                        };
+                                        let cover_file = new_path.with_file_name("temp_cover.jpg");
+                                        let cover_path = tokio::runtime::Handle::current().block_on(fetch_artwork(
+                                            &artwork::ArtworkQuery::from_metadata(&meta),
+                                            &cover_file,
+                                        ));
 
                                         // Apply metadata
-                                        if let Err(e) = metadata::tag_audio(
-                                            &new_path,
-                                            &meta.artist,
-                                            &meta.album,
-                                            &meta.title,
-                                            meta.track_number,
-                                            meta.genre.as_deref(),
-                                            cover_path.as_deref(),
-                                            &config,
-                                        ) {
+                                        if let Err(e) = metadata::tag_from_metadata(&new_path, &meta, cover_path.as_deref(), &config, &tagging) {
                                             println!("  Warning: Failed to apply metadata: {}", e);
                                         } else {
                                             println!("  Metadata refreshed successfully");
@@ -412,11 +834,11 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                                     }
                                     Ok(None) => {
                                         println!(
-                                            "  Could not find track on Spotify, keeping existing metadata"
+                                            "  Could not find track metadata, keeping existing metadata"
                                         );
                                     }
                                     Err(e) => {
-                                        println!("  Spotify search failed: {}", e);
+                                        println!("  Metadata lookup failed: {}", e);
                                     }
                                 }
                             }
@@ -425,6 +847,22 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                         // Update database with new path
                         let old_path_str = file_path.display().to_string();
                         let new_path_str = new_path.display().to_string();
+                        converted_paths.push(new_path.clone());
+
+                        if in_place {
+                            println!("  Converted in place; the original is in the trash.");
+                            continue;
+                        }
+                        if keep_both {
+                            if db.add_variant(&old_path_str, &new_path_str) {
+                                println!("  Original kept; recorded as a {} variant.", to);
+                            } else {
+                                println!("  Original kept.");
+                            }
+                            continue;
+                        }
+                        // As it was, so a trashed original can be restored
+                        let old_entry = db.find_by_path(&old_path_str).cloned();
                         db.update_path(&old_path_str, &new_path_str);
 
                         // Prompt for deletion
@@ -436,8 +874,9 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                         std::io::stdin().read_line(&mut response)?;
 
                         if response.trim().eq_ignore_ascii_case("y") {
-                            match converter::delete_file(file_path) {
-                                Ok(()) => println!("  Original deleted."),
+                            let reason = format!("converted to {}", to);
+                            match trash::Trash::open().trash_file(file_path, old_entry, &reason) {
+                                Ok(()) => println!("  Original moved to the trash."),
                                 Err(e) => println!("  Failed to delete original: {}", e),
                             }
                         } else {
@@ -451,6 +890,21 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                 }
             }
 
+            if !converted_paths.is_empty() {
+                update_playlists(&db);
+            }
+
+            if *replaygain && !converted_paths.is_empty() {
+                println!("\nAnalyzing loudness of {} file(s)...", converted_paths.len());
+                let (mut tagged, mut gain_failed) = (0, 0);
+                for album in loudness::group_by_folder(&converted_paths) {
+                    let (t, f) = loudness::apply_replaygain(&album, true, &loudness_config, |msg| println!("  {}", msg));
+                    tagged += t;
+                    gain_failed += f;
+                }
+                println!("ReplayGain: {} tagged, {} failed", tagged, gain_failed);
+            }
+
             println!(
                 "\nConversion complete: {} succeeded, {} failed",
                 converted_count, failed_count
@@ -465,7 +919,7 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                 let missing: Vec<_> = db
                     .all_tracks()
                     .into_iter()
-                    .filter(|entry| !std::path::Path::new(&entry.path).exists())
+                    .filter(|entry| entry.is_missing())
                     .collect();
 
                 if missing.is_empty() {
@@ -481,26 +935,25 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                     println!("\nRun without --dry-run to remove these entries.");
                 }
             } else {
-                // Collect entries to show before cleanup if verbose
-                let missing_entries: Vec<_> = if *verbose {
-                    db.all_tracks()
-                        .into_iter()
-                        .filter(|entry| !std::path::Path::new(&entry.path).exists())
-                        .cloned()
-                        .collect()
-                } else {
-                    Vec::new()
-                };
-
                 // Actually perform cleanup
-                let (removed, total_before) = db.cleanup();
+                let (removed_entries, total_before) = db.cleanup();
+                let removed = removed_entries.len();
+                update_playlists(&db);
+
+                // Keep the removed entries restorable
+                let mut trash = trash::Trash::open();
+                for entry in &removed_entries {
+                    if let Err(e) = trash.trash_entry(entry.clone(), "cleanup: file missing") {
+                        println!("Warning: {} - {} not recorded in the trash: {}", entry.artist, entry.title, e);
+                    }
+                }
 
                 if removed == 0 {
                     println!("Database is clean. All {} entries point to existing files.", total_before);
                 } else {
                     if *verbose {
                         println!("Removed {} entries:\n", removed);
-                        for entry in &missing_entries {
+                        for entry in &removed_entries {
                             println!("  {} - {}", entry.artist, entry.title);
                             println!("    Path: {}", entry.path);
                         }
@@ -512,6 +965,252 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                         total_before,
                         total_before - removed
                     );
+                    println!("Removed entries can be restored with `rustwav trash restore`.");
+                }
+            }
+        }
+
+        cli::Commands::Playlists { action } => match action {
+            cli::PlaylistsAction::Check { repair } => {
+                if *repair {
+                    update_playlists(&db);
+                }
+                let checks = playlist_index::check(&playlist_path, &db)?;
+                if checks.is_empty() {
+                    println!("No broken entries in the playlists in {}.", playlist_path.display());
+                }
+                let mut unmatched = 0;
+                for check in &checks {
+                    println!("{} ({} broken)", check.file.display(), check.broken.len());
+                    for (i, fix) in &check.broken {
+                        match fix {
+                            Some(path) => println!("  ✗ {}\n    → {}", check.entries[*i].location, path.display()),
+                            None => {
+                                unmatched += 1;
+                                println!("  ✗ {} (no match in the library)", check.entries[*i].location);
+                            }
+                        }
+                    }
+                    if !*repair {
+                        continue;
+                    }
+                    if check.has_streams() {
+                        println!("  Skipped: the playlist has stream URLs, which a rewrite would drop");
+                    } else if let Err(e) = check.repair(&db) {
+                        println!("  Repair failed: {}", e);
+                    } else {
+                        println!("  Repaired");
+                    }
+                }
+                if !checks.is_empty() && !*repair {
+                    println!(
+                        "\nRun with --repair to rewrite these playlists{}.",
+                        if unmatched > 0 { format!(" ({} entries without a match are dropped)", unmatched) } else { String::new() }
+                    );
+                }
+            }
+            cli::PlaylistsAction::Regenerate { download } => {
+                let sources = regenerate::PlaylistSources::open();
+                if sources.sources().is_empty() {
+                    println!("No album or playlist links stored yet; downloads record theirs.");
+                }
+                let app_config = config::Config::load();
+                let playlist_formats = app_config.playlist.formats;
+                let loudness_config = app_config.loudness;
+                let trim_config = app_config.trim;
+                let mut downloaded: Vec<PathBuf> = Vec::new();
+                let mut total_missing = 0;
+
+                for source in sources.sources() {
+                    let fetched = match regenerate::fetch_tracks(&source.link).await {
+                        Ok(fetched) => fetched,
+                        Err(e) => {
+                            println!("{}: {}", source.name, e);
+                            continue;
+                        }
+                    };
+                    let library: Vec<db::TrackEntry> = db.tracks.iter().filter(|t| !t.is_missing()).cloned().collect();
+                    let mut paths = regenerate::match_tracks(&fetched.tracks, &library);
+
+                    if *download && paths.iter().any(Option::is_none) {
+                        // Missing tracks are downloaded the way the source first was
+                        let config = PortableConfig::new(source.portable);
+                        let actual_format = if config.enabled { "mp3" } else { source.format.as_str() };
+                        let mut source_quality = app_config.source_quality.clone();
+                        if let Some(kbps) = source.require_quality {
+                            source_quality.min_bitrate_kbps = kbps;
+                        }
+                        let settings = PlaylistDownload {
+                            link: &source.link,
+                            link_type: source.kind.as_str(),
+                            format: actual_format,
+                            quality: &source.quality,
+                            lyrics: false,
+                            source_quality,
+                            normalize: config.enabled && loudness_config.normalize_portable,
+                            trim_silence: trim_config.silence,
+                            sponsorblock: trim_config.sponsorblock,
+                            tagging: &app_config.tagging,
+                        };
+                        for (track, path) in fetched.tracks.iter().zip(paths.iter_mut()).filter(|(_, p)| p.is_none()) {
+                            // Album tracks go to the album's folder, playlist tracks to Singles
+                            let folder = match &fetched.album {
+                                _ if config.enabled => file_utils::create_portable_folder(&music_path, &config),
+                                Some((artist, album)) => file_utils::create_album_folder(&music_path, artist, album),
+                                None => file_utils::create_album_folder(&playlist_path, &track.artist, "Singles"),
+                            };
+                            let file_path = folder.join(file_utils::build_filename(
+                                &track.artist,
+                                &track.title,
+                                converter::format_extension(actual_format),
+                                &config,
+                            ));
+                            let entry = db::TrackEntry {
+                                id: String::new(),
+                                artist: track.artist.clone(),
+                                title: track.title.clone(),
+                                path: file_path.display().to_string(),
+                                source: None,
+                                trims: Vec::new(),
+                                variants: Vec::new(),
+                                duration_secs: None,
+                            };
+                            match download_track_entry(entry, track.duration_secs, &settings, &config, &mut db).await {
+                                Ok(Some(file)) => {
+                                    downloaded.push(file.clone());
+                                    *path = Some(file);
+                                }
+                                Ok(None) => {}
+                                Err(e) => println!("  Failed: {} - {} ({})", track.artist, track.title, e),
+                            }
+                        }
+                    }
+
+                    let found: Vec<PathBuf> = paths.into_iter().flatten().collect();
+                    let missing = fetched.tracks.len() - found.len();
+                    total_missing += missing;
+                    file_utils::create_playlists(&fetched.name, &found, &playlist_path, Some(&db), &playlist_formats)?;
+                    if missing > 0 {
+                        println!("{}: {} of {} tracks ({} missing)", fetched.name, found.len(), fetched.tracks.len(), missing);
+                    } else {
+                        println!("{}: {} tracks", fetched.name, found.len());
+                    }
+                }
+
+                if !downloaded.is_empty() {
+                    if loudness_config.replaygain {
+                        apply_replaygain(downloaded.clone(), false).await;
+                    }
+                    regenerate_smart_playlists(&mut db, &playlist_path);
+                }
+                if total_missing > 0 && !*download {
+                    println!("\n{} track(s) missing. Run with --download to fetch them.", total_missing);
+                }
+            }
+        },
+
+        cli::Commands::Smart { action } => {
+            let mut smart_playlists = smart::SmartPlaylists::open();
+            match action {
+                cli::SmartAction::List => {
+                    if smart_playlists.playlists().is_empty() {
+                        println!("No smart playlists. Create one with `rustwav smart add <name> <rules>`.");
+                    }
+                    for smart in smart_playlists.playlists() {
+                        let rules = smart.rules();
+                        println!("{}  ({})", smart.name, if rules.is_empty() { "whole library" } else { &rules });
+                    }
+                }
+                cli::SmartAction::Add { name, rules } => {
+                    let smart = smart::SmartPlaylist::parse(name, rules)?;
+                    let name = smart.name.clone();
+                    smart_playlists.save_playlist(smart)?;
+                    let regenerated = smart_playlists.regenerate(Some(&name), &db, &playlist_path)?;
+                    db.set_durations(&regenerated.durations);
+                    for (name, count) in regenerated.written {
+                        println!("Smart playlist '{}' saved with {} tracks", name, count);
+                    }
+                }
+                cli::SmartAction::Remove { name } => match smart_playlists.remove(name)? {
+                    Some(removed) => println!("Removed smart playlist '{}'; its playlist files are kept", removed.name),
+                    None => anyhow::bail!("No smart playlist named '{}'", name),
+                },
+                cli::SmartAction::Run { name } => {
+                    if let Some(name) = name {
+                        if smart_playlists.find(name).is_none() {
+                            anyhow::bail!("No smart playlist named '{}'", name);
+                        }
+                    }
+                    let regenerated = smart_playlists.regenerate(name.as_deref(), &db, &playlist_path)?;
+                    db.set_durations(&regenerated.durations);
+                    let written = regenerated.written;
+                    if written.is_empty() {
+                        println!("No smart playlists. Create one with `rustwav smart add <name> <rules>`.");
+                    }
+                    for (name, count) in written {
+                        println!("Smart playlist '{}': {} tracks", name, count);
+                    }
+                }
+            }
+        }
+
+        cli::Commands::Trash { action } => {
+            let mut trash = trash::Trash::open();
+            match action {
+                cli::TrashAction::List => {
+                    if trash.items().is_empty() {
+                        println!("Trash is empty.");
+                        return Ok(());
+                    }
+                    let mut total_bytes = 0;
+                    for item in trash.items() {
+                        total_bytes += item.size();
+                        println!(
+                            "{}  {}  {} ({})",
+                            item.short_id(),
+                            item.deleted_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                            item.display_name(),
+                            item.reason
+                        );
+                        println!("    {}{}", item.original_path, if item.trashed_path.is_none() { " (cache entry only)" } else { "" });
+                    }
+                    println!(
+                        "\n{} item(s), {:.1} MB. Restore with `rustwav trash restore <id>`.",
+                        trash.items().len(),
+                        total_bytes as f64 / (1024.0 * 1024.0)
+                    );
+                }
+                cli::TrashAction::Restore { ids, all } => {
+                    let ids: Vec<String> = if *all {
+                        trash.items().iter().map(|item| item.id.clone()).collect()
+                    } else if ids.is_empty() {
+                        anyhow::bail!("Give the IDs to restore (see `rustwav trash list`) or --all");
+                    } else {
+                        ids.clone()
+                    };
+                    let (mut restored, mut failed) = (0, 0);
+                    for id in &ids {
+                        match trash.restore(id, &mut db) {
+                            Ok(item) => {
+                                println!("Restored {}", item.original_path);
+                                restored += 1;
+                            }
+                            Err(e) => {
+                                println!("Could not restore {}: {}", id, e);
+                                failed += 1;
+                            }
+                        }
+                    }
+                    println!("\nRestore complete: {} restored, {} failed", restored, failed);
+                }
+                cli::TrashAction::Empty { older_than } => {
+                    let min_age = older_than.map(|days| chrono::Duration::days(days.into()));
+                    let (removed, freed) = trash.empty(min_age)?;
+                    println!(
+                        "Permanently deleted {} item(s), freeing {:.1} MB.",
+                        removed,
+                        freed as f64 / (1024.0 * 1024.0)
+                    );
                 }
             }
         }
@@ -574,8 +1273,8 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                     return Ok(());
                 }
 
-                let (download_total, convert_total, refresh_total) = if date_filter.is_some() {
-                    error_log.get_error_counts(date_filter.unwrap())
+                let (download_total, convert_total, refresh_total) = if let Some(d) = date_filter {
+                    error_log.get_error_counts(d)
                 } else {
                     error_log.get_total_error_counts()
                 };
@@ -667,13 +1366,31 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                     // Actually retry the conversion
                     let input_path = std::path::Path::new(&entry.input_path);
                     if input_path.exists() {
-                        match converter::convert_audio(input_path, &entry.target_format, &entry.quality, |msg| {
-                            println!("  {}", msg);
-                        }) {
-                            Ok(new_path) => {
+                        let new_path = converter::output_path(
+                            input_path,
+                            &entry.target_format,
+                            &[music_path.clone(), playlist_path.clone()],
+                            entry.output_dir.as_deref().map(std::path::Path::new),
+                        );
+                        match converter::convert_audio_to(
+                            input_path,
+                            &new_path,
+                            &entry.target_format,
+                            &entry.quality,
+                            &entry.encoder,
+                            |msg| println!("  {}", msg),
+                            |_| {},
+                        ) {
+                            Ok(()) => {
                                 println!("Conversion successful: {}", new_path.display());
                                 error_log.remove_convert_error(&found_date, error_id);
-                                db.update_path(&entry.input_path, &new_path.display().to_string());
+                                let new_path = new_path.display().to_string();
+                                if entry.keep_both {
+                                    db.add_variant(&entry.input_path, &new_path);
+                                } else {
+                                    db.update_path(&entry.input_path, &new_path);
+                                    update_playlists(&db);
+                                }
                             }
                             Err(e) => {
                                 println!("Conversion failed again: {}", e);
@@ -693,34 +1410,16 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
 
                     let input_path = std::path::Path::new(&entry.input_path);
                     if input_path.exists() {
-                        match spotify::search_track(&entry.artist, &entry.title).await {
+                        let metadata_providers =
+                            providers::providers_for(Operation::Refresh, &config::Config::load().metadata);
+                        let tagging = config::Config::load().tagging;
+                        match providers::search_track(&metadata_providers, &entry.artist, &entry.title, None).await {
                             Ok(Some(meta)) => {
-                                let cover_path = if let Some(url) = &meta.cover_url {
-                                    let cover_file = input_path.with_file_name("temp_cover.jpg");
-                                    if let Ok(response) = reqwest::blocking::get(url) {
-                                        if let Ok(bytes) = response.bytes() {
-                                            let _ = std::fs::write(&cover_file, &bytes);
-                                            Some(cover_file)
-                                        } else {
-                                            None
-                                        }
-                                    } else {
-                                        None
-                                    }
-                                } else {
-                                    None
-                                };
-
-                                if let Err(e) = metadata::tag_audio(
-                                    input_path,
-                                    &meta.artist,
-                                    &meta.album,
-                                    &meta.title,
-                                    meta.track_number,
-                                    meta.genre.as_deref(),
-                                    cover_path.as_deref(),
-                                    &config,
-                                ) {
+                                let cover_file = input_path.with_file_name("temp_cover.jpg");
+                                let cover_path =
+                                    fetch_artwork(&artwork::ArtworkQuery::from_metadata(&meta), &cover_file).await;
+
+                                if let Err(e) = metadata::tag_from_metadata(input_path, &meta, cover_path.as_deref(), &config, &tagging) {
                                     println!("Failed to apply metadata: {}", e);
                                     error_log.increment_refresh_retry(&found_date, error_id);
                                 } else {
@@ -733,11 +1432,11 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                                 }
                             }
                             Ok(None) => {
-                                println!("Track not found on Spotify.");
+                                println!("Track not found by any metadata provider.");
                                 error_log.increment_refresh_retry(&found_date, error_id);
                             }
                             Err(e) => {
-                                println!("Spotify search failed: {}", e);
+                                println!("Metadata lookup failed: {}", e);
                                 error_log.increment_refresh_retry(&found_date, error_id);
                             }
                         }
@@ -772,7 +1471,7 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
             let files: Vec<PathBuf> = if input_path.is_file() {
                 vec![input_path.to_path_buf()]
             } else if input_path.is_dir() {
-                collect_audio_files(input_path, *recursive)?
+                file_utils::collect_audio_files(input_path, *recursive)?
             } else {
                 anyhow::bail!("Input path does not exist: {}", input);
             };
@@ -799,13 +1498,15 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
             album: override_album,
             genre: override_genre,
             no_lookup,
+            provider,
+            preview,
         } => {
             let input_path = std::path::Path::new(input);
 
             let files: Vec<PathBuf> = if input_path.is_file() {
                 vec![input_path.to_path_buf()]
             } else if input_path.is_dir() {
-                collect_audio_files(input_path, *recursive)?
+                file_utils::collect_audio_files(input_path, *recursive)?
             } else {
                 anyhow::bail!("Input path does not exist: {}", input);
             };
@@ -815,10 +1516,30 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                 return Ok(());
             }
 
+            // --provider overrides the retag order from data/config.json
+            let metadata_config = config::Config::load().metadata;
+            let providers = match provider {
+                Some(list) => {
+                    let names: Vec<String> = list.split(',').map(|n| n.trim().to_lowercase()).collect();
+                    if let Some(unknown) = names.iter().find(|n| !providers::PROVIDER_NAMES.contains(&n.as_str())) {
+                        anyhow::bail!(
+                            "Unknown metadata provider: {}. Available: {:?}",
+                            unknown,
+                            providers::PROVIDER_NAMES
+                        );
+                    }
+                    providers::build_providers(&names, &metadata_config)
+                }
+                None => providers::providers_for(Operation::Retag, &metadata_config),
+            };
+            let provider_names = providers.iter().map(|p| p.name()).collect::<Vec<_>>().join(", ");
+            let tagging_policy = config::Config::load().tagging;
+
             println!("Found {} file(s) to retag", files.len());
 
             let mut success_count = 0;
             let mut failed_count = 0;
+            let mut skipped_count = 0;
 
             for file_path in &files {
                 println!("\nProcessing: {}", file_path.display());
@@ -826,7 +1547,7 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                 // Read existing tags
                 let existing_tags = metadata::read_tags(file_path).ok();
 
-                // Determine artist and title for Spotify lookup
+                // Determine artist and title for the metadata lookup
                 let lookup_artist = override_artist.as_deref()
                     .or(existing_tags.as_ref().and_then(|t| t.artist.as_deref()))
                     .or_else(|| {
@@ -845,48 +1566,49 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                             .and_then(|s| s.split(" - ").nth(1))
                     });
 
-                // Search Spotify for metadata if not disabled
-                let spotify_meta = if *no_lookup {
+                // Search the metadata providers if not disabled (ISRC first when the file has one)
+                let isrc = existing_tags.as_ref().and_then(|t| t.isrc.as_deref());
+                let lookup_meta = if *no_lookup {
                     None
                 } else if let (Some(artist), Some(title)) = (lookup_artist, lookup_title) {
-                    println!("  Searching Spotify: {} - {}", artist, title);
-                    match spotify::search_track(artist, title).await {
+                    println!("  Searching {}: {} - {}", provider_names, artist, title);
+                    match providers::search_track(&providers, artist, title, isrc).await {
                         Ok(meta) => {
                             if meta.is_some() {
-                                println!("  Found on Spotify!");
+                                println!("  Found!");
                             } else {
-                                println!("  Not found on Spotify, using existing/provided values");
+                                println!("  Not found, using existing/provided values");
                             }
                             meta
                         }
                         Err(e) => {
-                            println!("  Spotify search failed: {}", e);
+                            println!("  Metadata lookup failed: {}", e);
                             None
                         }
                     }
                 } else {
-                    println!("  No artist/title available for Spotify lookup");
+                    println!("  No artist/title available for metadata lookup");
                     None
                 };
 
-                // Determine final values: override > spotify > existing
+                // Determine final values: override > provider > existing
                 let final_artist = override_artist.as_deref()
-                    .or(spotify_meta.as_ref().map(|m| m.artist.as_str()))
+                    .or(lookup_meta.as_ref().map(|m| m.artist.as_str()))
                     .or(existing_tags.as_ref().and_then(|t| t.artist.as_deref()));
 
                 let final_title = override_title.as_deref()
-                    .or(spotify_meta.as_ref().map(|m| m.title.as_str()))
+                    .or(lookup_meta.as_ref().map(|m| m.title.as_str()))
                     .or(existing_tags.as_ref().and_then(|t| t.title.as_deref()));
 
                 let final_album = override_album.as_deref()
-                    .or(spotify_meta.as_ref().map(|m| m.album.as_str()))
+                    .or(lookup_meta.as_ref().map(|m| m.album.as_str()))
                     .or(existing_tags.as_ref().and_then(|t| t.album.as_deref()));
 
                 let final_genre = override_genre.as_deref()
-                    .or(spotify_meta.as_ref().and_then(|m| m.genre.as_deref()))
+                    .or(lookup_meta.as_ref().and_then(|m| m.genre.as_deref()))
                     .or(existing_tags.as_ref().and_then(|t| t.genre.as_deref()));
 
-                let final_track = spotify_meta.as_ref().map(|m| m.track_number)
+                let final_track = lookup_meta.as_ref().map(|m| m.track_number)
                     .or(existing_tags.as_ref().and_then(|t| t.track));
 
                 // Check we have minimum required data
@@ -898,39 +1620,67 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                         continue;
                     }
                 };
+                let tags = metadata::TrackTags {
+                    artist,
+                    album,
+                    title,
+                    track: final_track.unwrap_or(0),
+                    genre: final_genre,
+                };
 
-                // Download cover art if available from Spotify
-                let cover_path = if let Some(meta) = &spotify_meta {
-                    if let Some(url) = &meta.cover_url {
+                // Download cover art through the artwork provider chain
+                let cover_path = match &lookup_meta {
+                    Some(meta) => {
                         let cover_file = file_path.with_file_name("temp_cover.jpg");
-                        if let Ok(response) = reqwest::blocking::get(url) {
-                            if let Ok(bytes) = response.bytes() {
-                                let _ = std::fs::write(&cover_file, &bytes);
-                                Some(cover_file)
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
+                        fetch_artwork(&artwork::ArtworkQuery::from_metadata(meta), &cover_file).await
                     }
-                } else {
-                    None
+                    None => None,
                 };
 
+                // Show what would change and ask before writing
+                if *preview {
+                    let changes = metadata::preview_tag_audio(
+                        file_path,
+                        &tags,
+                        cover_path.as_deref(),
+                        &tagging_policy,
+                    );
+                    let apply = if changes.is_empty() {
+                        println!("  No tag changes");
+                        false
+                    } else {
+                        for change in &changes {
+                            println!("{}", change);
+                        }
+                        print!("  Apply these changes? [y/N]: ");
+                        use std::io::Write;
+                        std::io::stdout().flush()?;
+
+                        let mut response = String::new();
+                        std::io::stdin().read_line(&mut response)?;
+                        response.trim().eq_ignore_ascii_case("y")
+                    };
+                    if !apply {
+                        skipped_count += 1;
+                        if let Some(cover) = &cover_path {
+                            let _ = std::fs::remove_file(cover);
+                        }
+                        continue;
+                    }
+                }
+
                 // Apply tags
                 match metadata::tag_audio(
                     file_path,
-                    artist,
-                    album,
-                    title,
-                    final_track.unwrap_or(0),
-                    final_genre,
+                    &tags,
                     cover_path.as_deref(),
                     &config,
-                ) {
+                    &tagging_policy,
+                )
+                .and_then(|_| match &lookup_meta {
+                    Some(meta) => metadata::tag_identifiers(file_path, meta),
+                    None => Ok(()),
+                }) {
                     Ok(()) => {
                         println!("  Tagged: {} - {} ({})", artist, title, album);
                         if let Some(g) = final_genre {
@@ -950,32 +1700,737 @@ async fn run_cli(command: &cli::Commands, cli_args: &Cli) -> anyhow::Result<()>
                 }
             }
 
-            println!("\nRetag complete: {} succeeded, {} failed", success_count, failed_count);
+            if *preview {
+                println!(
+                    "\nRetag complete: {} succeeded, {} skipped, {} failed",
+                    success_count, skipped_count, failed_count
+                );
+            } else {
+                println!("\nRetag complete: {} succeeded, {} failed", success_count, failed_count);
+            }
+        }
+
+        cli::Commands::Lyrics {
+            input,
+            recursive,
+            lrc,
+            force,
+        } => {
+            let input_path = std::path::Path::new(input);
+
+            let files: Vec<PathBuf> = if input_path.is_file() {
+                vec![input_path.to_path_buf()]
+            } else if input_path.is_dir() {
+                file_utils::collect_audio_files(input_path, *recursive)?
+            } else {
+                anyhow::bail!("Input path does not exist: {}", input);
+            };
+
+            if files.is_empty() {
+                println!("No audio files found.");
+                return Ok(());
+            }
+
+            let lyrics_config = config::Config::load().lyrics;
+            let provider = lyrics::provider_from_config(&lyrics_config);
+            let write_lrc = *lrc || lyrics_config.write_lrc;
+
+            println!("Fetching lyrics for {} file(s) from {}", files.len(), provider.name());
+
+            let mut found_count = 0;
+            let mut skipped_count = 0;
+            let mut missing_count = 0;
+            let mut failed_count = 0;
+
+            for file_path in &files {
+                println!("\nProcessing: {}", file_path.display());
+
+                let tags = metadata::read_tags(file_path).unwrap_or_default();
+                if tags.has_lyrics && !*force {
+                    println!("  Already has lyrics, skipping (use --force to refetch)");
+                    skipped_count += 1;
+                    continue;
+                }
+
+                // Fall back to "Artist - Title" filenames when tags are missing
+                let stem = file_path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+                let artist = tags.artist.clone()
+                    .or_else(|| stem.split(" - ").next().map(|s| s.to_string()));
+                let title = tags.title.clone()
+                    .or_else(|| stem.split(" - ").nth(1).map(|s| s.to_string()));
+
+                let (artist, title) = match (artist, title) {
+                    (Some(a), Some(t)) => (a, t),
+                    _ => {
+                        println!("  Error: Missing artist or title");
+                        failed_count += 1;
+                        continue;
+                    }
+                };
+
+                let query = lyrics::LyricsQuery {
+                    artist,
+                    title,
+                    album: tags.album.clone(),
+                    duration_secs: None,
+                };
+
+                match lyrics::fetch_and_embed(provider.as_ref(), file_path, &query, write_lrc).await {
+                    Ok(Some(found)) => {
+                        let kind = if found.synced.is_some() { "synced" } else { "plain" };
+                        println!("  Embedded {} lyrics: {} - {}", kind, query.artist, query.title);
+                        found_count += 1;
+                    }
+                    Ok(None) => {
+                        println!("  No lyrics found: {} - {}", query.artist, query.title);
+                        missing_count += 1;
+                    }
+                    Err(e) => {
+                        println!("  Error: {}", e);
+                        failed_count += 1;
+                    }
+                }
+            }
+
+            println!(
+                "\nLyrics complete: {} embedded, {} not found, {} skipped, {} failed",
+                found_count, missing_count, skipped_count, failed_count
+            );
+        }
+
+        cli::Commands::Cover { action } => {
+            if let cli::CoverAction::Embed { image, .. } = action {
+                if !std::path::Path::new(image).is_file() {
+                    anyhow::bail!("Image does not exist: {}", image);
+                }
+            }
+
+            let target = action.target();
+            let input_path = std::path::Path::new(&target.input);
+            let files: Vec<PathBuf> = if input_path.is_file() {
+                vec![input_path.to_path_buf()]
+            } else if input_path.is_dir() {
+                file_utils::collect_audio_files(input_path, target.recursive)?
+            } else {
+                anyhow::bail!("Input path does not exist: {}", target.input);
+            };
+
+            if files.is_empty() {
+                println!("No audio files found.");
+                return Ok(());
+            }
+
+            if let cli::CoverAction::Extract { output: Some(dir), .. } = action {
+                std::fs::create_dir_all(dir)?;
+            }
+
+            let mut done_count = 0;
+            let mut skipped_count = 0;
+            let mut failed_count = 0;
+
+            for file_path in &files {
+                let name = file_path.display();
+                match action {
+                    cli::CoverAction::Extract { output, .. } => match metadata::read_cover(file_path) {
+                        Ok(Some(cover)) => {
+                            let file_name = format!(
+                                "{}.{}",
+                                file_path.file_stem().and_then(|s| s.to_str()).unwrap_or("cover"),
+                                cover.extension()
+                            );
+                            let dest = match output {
+                                Some(dir) => std::path::Path::new(dir).join(file_name),
+                                None => file_path.with_file_name(file_name),
+                            };
+                            match std::fs::write(&dest, &cover.data) {
+                                Ok(()) => {
+                                    println!("{} -> {}", name, dest.display());
+                                    done_count += 1;
+                                }
+                                Err(e) => {
+                                    println!("{}: Error: {}", name, e);
+                                    failed_count += 1;
+                                }
+                            }
+                        }
+                        Ok(None) => {
+                            println!("{}: no embedded cover", name);
+                            skipped_count += 1;
+                        }
+                        Err(e) => {
+                            println!("{}: Error: {}", name, e);
+                            failed_count += 1;
+                        }
+                    },
+                    cli::CoverAction::Embed { .. } | cli::CoverAction::Folder { .. } => {
+                        let cover = match action {
+                            cli::CoverAction::Embed { image, .. } => Some(PathBuf::from(image)),
+                            _ => file_path.parent().and_then(file_utils::find_folder_cover),
+                        };
+                        let Some(cover) = cover else {
+                            println!("{}: no cover.jpg or folder.jpg in album folder", name);
+                            skipped_count += 1;
+                            continue;
+                        };
+                        match metadata::embed_cover(file_path, &cover, &config) {
+                            Ok(()) => {
+                                println!("{}: embedded {}", name, cover.display());
+                                done_count += 1;
+                            }
+                            Err(e) => {
+                                println!("{}: Error: {}", name, e);
+                                failed_count += 1;
+                            }
+                        }
+                    }
+                    cli::CoverAction::Strip { .. } => match metadata::strip_cover(file_path) {
+                        Ok(true) => {
+                            println!("{}: cover removed", name);
+                            done_count += 1;
+                        }
+                        Ok(false) => skipped_count += 1,
+                        Err(e) => {
+                            println!("{}: Error: {}", name, e);
+                            failed_count += 1;
+                        }
+                    },
+                    cli::CoverAction::Audit { min_size, .. } => match metadata::audit_cover(file_path, *min_size) {
+                        Ok(Some(issue)) => {
+                            println!("{}: {}", name, issue);
+                            done_count += 1;
+                        }
+                        Ok(None) => skipped_count += 1,
+                        Err(e) => {
+                            println!("{}: Error: {}", name, e);
+                            failed_count += 1;
+                        }
+                    },
+                }
+            }
+
+            if let cli::CoverAction::Audit { .. } = action {
+                println!(
+                    "\nCover audit: {} with issues, {} ok, {} unreadable",
+                    done_count, skipped_count, failed_count
+                );
+            } else {
+                println!(
+                    "\nCover {} complete: {} done, {} skipped, {} failed",
+                    action.name(), done_count, skipped_count, failed_count
+                );
+            }
+        }
+
+        cli::Commands::Export {
+            profile,
+            dest,
+            artist,
+            album,
+            playlist,
+            capacity,
+            dry_run,
+        } => {
+            let export_config = config::Config::load().export;
+            let Some(mut export_profile) = export_config.profiles.get(profile).cloned() else {
+                let names: Vec<&str> = export_config.profiles.keys().map(String::as_str).collect();
+                anyhow::bail!("Unknown export profile: {} (available: {})", profile, names.join(", "));
+            };
+            if let Some(mb) = capacity {
+                export_profile.capacity_mb = *mb;
+            }
+            if !export_profile.format.eq_ignore_ascii_case(downloader::ORIGINAL_FORMAT)
+                && !converter::is_supported_format(&export_profile.format)
+            {
+                anyhow::bail!("Export profile {} has unsupported format: {}", profile, export_profile.format);
+            }
+            if !converter::check_ffmpeg_available() {
+                println!("Warning: FFmpeg not found; files that need transcoding will fail");
+            }
+
+            let playlist_file = match playlist {
+                Some(name) if std::path::Path::new(name).is_file() => Some(PathBuf::from(name)),
+                Some(name) => {
+                    // Playlists written before M3U8 support are plain .m3u
+                    let p = playlist_path.join(file_utils::sanitize_filename(name));
+                    match ["m3u8", "m3u"].iter().map(|ext| p.with_extension(ext)).find(|p| p.is_file()) {
+                        Some(p) => Some(p),
+                        None => anyhow::bail!("Playlist not found: {}.m3u8", p.display()),
+                    }
+                }
+                None => None,
+            };
+            let selection = export::Selection {
+                artist: artist.clone(),
+                album: album.clone(),
+                playlist: playlist_file.clone(),
+                variants: db.variants(),
+            };
+
+            let dest_path = PathBuf::from(dest);
+            let roots = vec![music_path.clone(), playlist_path.clone()];
+            let dry_run = *dry_run;
+            if dry_run {
+                println!("[dry run] nothing will be written");
+            }
+            let summary = {
+                let export_profile = export_profile.clone();
+                let dest_path = dest_path.clone();
+                tokio::task::spawn_blocking(move || {
+                    export::export(&roots, &selection, &export_profile, &dest_path, dry_run, |line| {
+                        println!("{}", line)
+                    })
+                })
+                .await??
+            };
+
+            // Rebuild the playlist on the device from the exported files
+            if let (Some(playlist_file), false) = (&playlist_file, dry_run) {
+                let name = playlist_file.file_stem().unwrap_or_default().to_string_lossy();
+                let tracks: Vec<playlist::PlaylistTrack> = summary
+                    .files
+                    .iter()
+                    .map(|f| playlist::PlaylistTrack::read(&dest_path.join(f), None))
+                    .collect();
+                let mut playlist_config = config::Config::load().playlist;
+                // Absolute paths on this machine mean nothing on the device
+                if playlist_config.path_style == config::PathStyle::Absolute {
+                    playlist_config.path_style = config::PathStyle::Relative;
+                }
+                playlist::write_playlist(config::PlaylistFormat::M3u8, &name, &tracks, &dest_path, &playlist_config)?;
+            }
+
+            println!(
+                "\nExport complete: {} exported, {} unchanged, {} removed, {} failed",
+                summary.exported, summary.unchanged, summary.removed, summary.failed
+            );
+            if summary.over_budget > 0 {
+                println!(
+                    "{} files left out: the {} MB capacity budget is used up",
+                    summary.over_budget, export_profile.capacity_mb
+                );
+            }
+            if summary.skipped > 0 {
+                println!(
+                    "{} files left out: files not written by an export are in their place",
+                    summary.skipped
+                );
+            }
+            println!("Using {:.1} MB on {}", summary.used_bytes as f64 / (1024.0 * 1024.0), dest_path.display());
         }
     }
 
     Ok(())
 }
 
-/// Collect audio files from a directory, optionally recursively
-fn collect_audio_files(dir: &std::path::Path, recursive: bool) -> anyhow::Result<Vec<PathBuf>> {
-    let mut files = Vec::new();
-    let extensions = ["mp3", "flac", "wav", "aac", "m4a"];
+/// Run the artwork provider chain and save the best cover to `dest`
+async fn fetch_artwork(query: &artwork::ArtworkQuery, dest: &std::path::Path) -> Option<PathBuf> {
+    match artwork::fetch_cover(query, dest).await {
+        Ok(Some(info)) => {
+            println!("  Cover art: {} ({}x{})", info.provider, info.width, info.height);
+            Some(dest.to_path_buf())
+        }
+        Ok(None) => None,
+        Err(e) => {
+            println!("  Failed to save cover art: {}", e);
+            None
+        }
+    }
+}
+
+/// Source quality requirements from the config, with the `--require-quality`
+/// bitrate taking precedence
+fn source_quality_config(require_quality: Option<u32>) -> config::SourceQualityConfig {
+    let mut requirement = config::Config::load().source_quality;
+    if let Some(kbps) = require_quality {
+        requirement.min_bitrate_kbps = kbps;
+    }
+    requirement
+}
+
+/// Settings for downloading the tracks of a playlist
+struct PlaylistDownload<'a> {
+    link: &'a str,
+    /// Command that retries the link, recorded with deferred tracks
+    link_type: &'a str,
+    format: &'a str,
+    quality: &'a str,
+    lyrics: bool,
+    source_quality: config::SourceQualityConfig,
+    normalize: bool,
+    trim_silence: bool,
+    sponsorblock: bool,
+    tagging: &'a config::TaggingConfig,
+}
+
+/// Download the tracks of a Spotify playlist that aren't in the library yet.
+/// Episodes go to `Podcasts/{Show}/`, tracks to `{Artist}/Singles/` in the
+/// playlist folder.
+async fn download_playlist_items(
+    items: &[PlaylistItem],
+    settings: &PlaylistDownload<'_>,
+    config: &PortableConfig,
+    db: &mut DownloadDB,
+    music_path: &Path,
+    playlist_path: &Path,
+) -> anyhow::Result<sync::PlaylistRun> {
+    let mut wanted: Vec<db::TrackEntry> = Vec::new();
+    // Music tracks downloaded in this run, for ReplayGain and sync
+    let mut new_paths: Vec<PathBuf> = Vec::new();
+
+    for item in items.iter() {
+        let track_obj = match &item.track {
+            Some(t) => t,
+            None => continue,
+        };
+
+        let (track_title, track_artist, duration_secs) = match track_obj {
+            PlayableItem::Track(track) => {
+                let title = track.name.clone();
+                let artist = track
+                    .artists
+                    .first()
+                    .map(|a| a.name.clone())
+                    .unwrap_or_else(|| "Unknown Artist".to_string());
+                (title, artist, track.duration.num_seconds() as u64)
+            }
+            PlayableItem::Episode(episode) => {
+                let meta = spotify::episode_metadata(episode);
+                let output_folder = if config.enabled {
+                    file_utils::create_portable_folder(playlist_path, config)
+                } else {
+                    file_utils::create_podcast_folder(music_path, &meta.show)
+                };
+                let file_path = output_folder.join(file_utils::build_filename(
+                    &meta.show,
+                    &meta.title,
+                    converter::format_extension(settings.format),
+                    config,
+                ));
+
+                let entry = db::TrackEntry {
+                    id: String::new(),
+                    artist: meta.show.clone(),
+                    title: meta.title.clone(),
+                    path: file_path.display().to_string(),
+                    source: None,
+                    trims: Vec::new(),
+                    variants: Vec::new(),
+                    duration_secs: None,
+                };
+                wanted.push(entry.clone());
+
+                if db.find_download(&entry).is_some() {
+                    println!("Skipping: {} — {}", meta.show, meta.title);
+                    continue;
+                }
+
+                println!("Downloading episode: {} — {}", meta.show, meta.title);
+                let source = config::Config::load()
+                    .podcast
+                    .source_for(&meta.show, &meta.title);
+                let file_path_clone = file_path.clone();
+                let format_clone = settings.format.to_string();
+                let quality_clone = settings.quality.to_string();
+                let downloaded = tokio::task::spawn_blocking(move || {
+                    downloader::download_track_with_output(
+                        &source,
+                        &file_path_clone,
+                        &format_clone,
+                        &quality_clone,
+                        false,
+                        |_| {},
+                    )
+                })
+                .await??;
+                let file_path = downloaded.path;
+                let entry = db::TrackEntry {
+                    path: file_path.display().to_string(),
+                    source: downloaded.source,
+                    ..entry
+                };
 
-    for entry in std::fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
+                let cover_file = file_path.with_extension("jpg");
+                let cover_path = if meta.cover_url.is_none() {
+                    None
+                } else if cover_file.exists() {
+                    Some(cover_file)
+                } else {
+                    fetch_artwork(&artwork::ArtworkQuery::from_episode(&meta), &cover_file).await
+                };
 
-        if path.is_file() {
-            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                if extensions.contains(&ext.to_lowercase().as_str()) {
-                    files.push(path);
+                if metadata::holds_tags(&file_path) {
+                    metadata::tag_episode(&file_path, &meta, cover_path.as_deref(), config)?;
+                } else {
+                    println!("  {}", metadata::UNTAGGED_WARNING);
                 }
+                db.add(entry);
+                continue;
+            }
+        };
+
+        let output_folder = if config.enabled {
+            file_utils::create_portable_folder(playlist_path, config)
+        } else {
+            file_utils::create_album_folder(playlist_path, &track_artist, "Singles")
+        };
+
+        let safe_file_name = file_utils::build_filename(
+            &track_artist,
+            &track_title,
+            converter::format_extension(settings.format),
+            config,
+        );
+        let file_path = output_folder.join(&safe_file_name);
+
+        let entry = db::TrackEntry {
+            id: String::new(),
+            artist: track_artist.clone(),
+            title: track_title.clone(),
+            path: file_path.display().to_string(),
+            source: None,
+            trims: Vec::new(),
+            variants: Vec::new(),
+            duration_secs: None,
+        };
+        wanted.push(entry.clone());
+
+        if db.find_download(&entry).is_some() {
+            println!("Skipping: {} — {}", track_artist, track_title);
+            continue;
+        }
+
+        if let Some(file_path) = download_track_entry(entry, Some(duration_secs), settings, config, db).await? {
+            new_paths.push(file_path);
+        }
+    }
+
+    Ok(sync::PlaylistRun {
+        tracks: sync::SyncedTrack::resolve(&wanted, db),
+        downloaded: new_paths,
+    })
+}
+
+/// Search for a track and download it to its cache entry's path, then tag it
+/// and add it to the download cache. Returns `None` when the source was
+/// skipped or deferred.
+async fn download_track_entry(
+    entry: db::TrackEntry,
+    duration_secs: Option<u64>,
+    settings: &PlaylistDownload<'_>,
+    config: &PortableConfig,
+    db: &mut DownloadDB,
+) -> anyhow::Result<Option<PathBuf>> {
+    let file_path = PathBuf::from(&entry.path);
+    let query = format!("{} {}", entry.artist, entry.title);
+    let origin = downloader::TrackOrigin {
+        link: settings.link,
+        link_type: settings.link_type,
+        format: settings.format,
+        quality: settings.quality,
+        portable: config.enabled,
+        artist: &entry.artist,
+        title: &entry.title,
+    };
+    let Some(quality_warning) = screen_track(&query, &settings.source_quality, &origin).await else {
+        return Ok(None);
+    };
+
+    println!("Downloading: {} — {}", entry.artist, entry.title);
+    let file_path_clone = file_path.clone();
+    let format_clone = settings.format.to_string();
+    let query_clone = query.clone();
+    let sponsorblock = settings.sponsorblock;
+    let downloaded = tokio::task::spawn_blocking(move || {
+        downloader::download_track(&query_clone, &file_path_clone, &format_clone, sponsorblock)
+    })
+    .await??;
+    let file_path = downloaded.path;
+    let mut trims = downloaded.trims;
+    if settings.trim_silence {
+        trims.extend(trim_silence_of(&file_path).await);
+    }
+    let entry = db::TrackEntry {
+        path: file_path.display().to_string(),
+        source: downloaded.source,
+        trims,
+        variants: Vec::new(),
+        ..entry
+    };
+
+    // For playlists, we don't have album-level genre info, use None
+    // The retag command can be used to add genre later
+    if metadata::holds_tags(&file_path) {
+        let tags = metadata::TrackTags {
+            artist: &entry.artist,
+            album: "Singles",
+            title: &entry.title,
+            track: 0,
+            genre: None, // can be added via retag command
+        };
+        metadata::tag_audio(&file_path, &tags, None, config, settings.tagging)?;
+    } else {
+        println!("  {}", metadata::UNTAGGED_WARNING);
+    }
+
+    if let Some(warning) = &quality_warning {
+        if let Err(e) = metadata::tag_quality_warning(&file_path, warning) {
+            println!("  Failed to tag quality warning: {}", e);
+        }
+    }
+    if settings.lyrics {
+        let query = lyrics::LyricsQuery {
+            artist: entry.artist.clone(),
+            title: entry.title.clone(),
+            album: None,
+            duration_secs,
+        };
+        apply_lyrics(&file_path, &query).await;
+    }
+    if settings.normalize {
+        normalize_loudness(&file_path).await;
+    }
+    db.add(entry);
+    Ok(Some(file_path))
+}
+
+/// Bring smart playlists up to date after tracks were added to the library
+fn regenerate_smart_playlists(db: &mut DownloadDB, playlist_path: &Path) {
+    match smart::SmartPlaylists::open().regenerate(None, db, playlist_path) {
+        Ok(regenerated) => {
+            db.set_durations(&regenerated.durations);
+            for (name, count) in regenerated.written {
+                println!("Smart playlist updated: {} ({} tracks)", name, count);
+            }
+        }
+        Err(e) => println!("Failed to update smart playlists: {}", e),
+    }
+}
+
+/// Remember an album or playlist job's link for `playlists regenerate`
+fn record_source(source: regenerate::PlaylistSource) {
+    if let Err(e) = regenerate::PlaylistSources::open().record(source) {
+        println!("Warning: source link not recorded: {}", e);
+    }
+}
+
+/// Rewrite playlists whose tracks were converted, moved or removed
+fn update_playlists(db: &DownloadDB) {
+    match playlist_index::update_playlists(db) {
+        Ok(changes) => {
+            for change in changes {
+                println!(
+                    "Playlist updated: {} ({} moved, {} removed)",
+                    change.name,
+                    change.moved,
+                    change.removed.len()
+                );
             }
-        } else if path.is_dir() && recursive {
-            files.extend(collect_audio_files(&path, recursive)?);
         }
+        Err(e) => println!("Failed to update playlists: {}", e),
+    }
+}
+
+/// Playlist formats given on the command line, or the configured ones
+fn playlist_formats_config(names: &[String]) -> anyhow::Result<Vec<config::PlaylistFormat>> {
+    if names.is_empty() {
+        return Ok(config::Config::load().playlist.formats);
     }
+    names
+        .iter()
+        .map(|name| {
+            config::PlaylistFormat::parse(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown playlist format: {} (m3u8, pls, xspf, jspf)", name))
+        })
+        .collect()
+}
 
-    Ok(files)
+/// Screen a track's source against the required quality, printing what
+/// happens. `None` if the track isn't downloaded now, otherwise the warning
+/// to tag the file with.
+async fn screen_track(
+    query: &str,
+    requirement: &config::SourceQualityConfig,
+    origin: &downloader::TrackOrigin<'_>,
+) -> Option<Option<String>> {
+    let error_log = Mutex::new(ErrorLogManager::new("data/errors"));
+    match downloader::screen_track(query, requirement, origin, &error_log, |line| println!("  {}", line)).await {
+        Ok(warning) => Some(warning),
+        Err(downloader::HeldBack::Skipped(reason)) => {
+            println!("Skipping: {} — {} ({})", origin.artist, origin.title, reason);
+            None
+        }
+        Err(downloader::HeldBack::Deferred(reason)) => {
+            println!("Deferred: {} — {} ({})", origin.artist, origin.title, reason);
+            None
+        }
+    }
 }
+
+/// Measure loudness and write ReplayGain tags, as one album or per track.
+/// Failures are reported but never abort the download.
+async fn apply_replaygain(paths: Vec<PathBuf>, album: bool) {
+    if paths.is_empty() {
+        return;
+    }
+    println!("Analyzing loudness of {} file(s)...", paths.len());
+    let loudness_config = config::Config::load().loudness;
+    match tokio::task::spawn_blocking(move || {
+        loudness::apply_replaygain(&paths, album, &loudness_config, |msg| println!("  {}", msg))
+    })
+    .await
+    {
+        Ok((tagged, failed)) => println!("ReplayGain: {} tagged, {} failed", tagged, failed),
+        Err(e) => println!("ReplayGain failed: {}", e),
+    }
+}
+
+/// Normalize a freshly downloaded track in place.
+/// Failures are reported but never abort the download.
+async fn normalize_loudness(file_path: &std::path::Path) {
+    let loudness_config = config::Config::load().loudness;
+    let path = file_path.to_path_buf();
+    match tokio::task::spawn_blocking(move || {
+        loudness::normalize(&path, &loudness_config, |msg| println!("  {}", msg))
+    })
+    .await
+    {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => println!("  Normalization failed: {}", e),
+        Err(e) => println!("  Normalization failed: {}", e),
+    }
+}
+
+/// Cut leading and trailing silence from a freshly downloaded track.
+/// Failures are reported but never abort the download.
+async fn trim_silence_of(file_path: &std::path::Path) -> Option<trim::Trim> {
+    let trim_config = config::Config::load().trim;
+    let path = file_path.to_path_buf();
+    match tokio::task::spawn_blocking(move || trim::trim_silence(&path, &trim_config)).await {
+        Ok(Ok(Some(trim))) => {
+            println!("  Trimmed {:.1}s of silence", trim.removed_secs);
+            Some(trim)
+        }
+        Ok(Ok(None)) => None,
+        Ok(Err(e)) => {
+            println!("  Silence trimming failed: {}", e);
+            None
+        }
+        Err(e) => {
+            println!("  Silence trimming failed: {}", e);
+            None
+        }
+    }
+}
+
+/// Fetch and embed lyrics for a freshly downloaded track.
+/// Failures are reported but never abort the download.
+async fn apply_lyrics(file_path: &std::path::Path, query: &lyrics::LyricsQuery) {
+    let lyrics_config = config::Config::load().lyrics;
+    let provider = lyrics::provider_from_config(&lyrics_config);
+    match lyrics::fetch_and_embed(provider.as_ref(), file_path, query, lyrics_config.write_lrc).await {
+        Ok(Some(_)) => println!("  Lyrics embedded"),
+        Ok(None) => println!("  No lyrics found"),
+        Err(e) => println!("  Lyrics failed: {}", e),
+    }
+}
+
@@ -1,11 +1,22 @@
 use anyhow::Context;
-use id3::{frame::Picture, Tag, TagLike, Version};
+use id3::frame::{
+    Comment, Content, ExtendedText, Picture, SynchronisedLyrics, SynchronisedLyricsType,
+    TimestampFormat, UniqueFileIdentifier, Unknown,
+};
+use id3::{Frame, Tag, TagLike, Version};
 use image::codecs::jpeg::JpegEncoder;
 use image::{GenericImageView, ImageEncoder, ImageReader};
 use metaflac::block::PictureType;
 use std::path::Path;
 
 use crate::cli::PortableConfig;
+use crate::config::TaggingConfig;
+use crate::loudness::ReplayGain;
+use crate::lyrics::Lyrics;
+use crate::sources::models::{EpisodeMetadata, TrackMetadata};
+use crate::tags::mapping::{TagSet, MUSICBRAINZ_IDS, MUSICBRAINZ_UFID_OWNER};
+use crate::tags::mp4::{self, Mp4Tag};
+use crate::tags::vorbis::{self, VorbisTag};
 
 /// Struct holding all tag information from an audio file
 #[derive(Debug, Clone, Default)]
@@ -17,6 +28,10 @@ pub struct AudioTags {
     pub track: Option<u32>,
     pub year: Option<i32>,
     pub has_cover: bool,
+    pub has_lyrics: bool,
+    pub isrc: Option<String>,
+    /// Star rating from 1 to 5
+    pub rating: Option<u8>,
 }
 
 impl std::fmt::Display for AudioTags {
@@ -28,6 +43,10 @@ impl std::fmt::Display for AudioTags {
         writeln!(f, "  Track:  {}", self.track.map(|t| t.to_string()).unwrap_or_else(|| "(none)".to_string()))?;
         writeln!(f, "  Year:   {}", self.year.map(|y| y.to_string()).unwrap_or_else(|| "(none)".to_string()))?;
         writeln!(f, "  Cover:  {}", if self.has_cover { "Yes" } else { "No" })?;
+        writeln!(f, "  Lyrics: {}", if self.has_lyrics { "Yes" } else { "No" })?;
+        if let Some(rating) = self.rating {
+            writeln!(f, "  Rating: {}/5", rating)?;
+        }
         Ok(())
     }
 }
@@ -39,8 +58,12 @@ pub fn read_tags(file_path: &Path) -> anyhow::Result<AudioTags> {
         .and_then(|e| e.to_str())
         .map(|e| e.to_lowercase());
 
+    if let Some(tag) = vorbis::open(file_path, extension.as_deref()) {
+        return Ok(read_vorbis_tags(tag?.as_ref()));
+    }
+
     match extension.as_deref() {
-        Some("flac") => read_flac_tags(file_path),
+        Some(ext) if mp4::is_mp4_extension(ext) => read_mp4_tags(file_path),
         Some("mp3") => read_id3_tags(file_path),
         Some("wav") => read_wav_tags(file_path),
         Some("aiff" | "aif") => read_aiff_tags(file_path),
@@ -48,33 +71,46 @@ pub fn read_tags(file_path: &Path) -> anyhow::Result<AudioTags> {
     }
 }
 
-fn read_flac_tags(file_path: &Path) -> anyhow::Result<AudioTags> {
-    let tag = metaflac::Tag::read_from_path(file_path)
-        .context("reading FLAC file")?;
-
-    let vorbis = tag.vorbis_comments();
-
-    let get_first = |key: &str| -> Option<String> {
-        vorbis.and_then(|v| v.get(key).and_then(|vals| vals.first().cloned()))
-    };
-
-    let track = get_first("TRACKNUMBER")
-        .and_then(|s| s.parse::<u32>().ok());
+/// Tags from Vorbis comments (FLAC, Ogg Vorbis, Opus)
+fn read_vorbis_tags(tag: &dyn VorbisTag) -> AudioTags {
+    let track = tag.comment("TRACKNUMBER")
+        .and_then(|s| s.split('/').next().and_then(|n| n.trim().parse::<u32>().ok()));
 
-    let year = get_first("DATE")
-        .or_else(|| get_first("YEAR"))
+    let year = tag.comment("DATE")
+        .or_else(|| tag.comment("YEAR"))
         .and_then(|s| s.chars().take(4).collect::<String>().parse::<i32>().ok());
 
-    let has_cover = tag.pictures().next().is_some();
-
-    Ok(AudioTags {
-        title: get_first("TITLE"),
-        artist: get_first("ARTIST"),
-        album: get_first("ALBUM"),
-        genre: get_first("GENRE"),
+    AudioTags {
+        title: tag.comment("TITLE"),
+        artist: tag.comment("ARTIST"),
+        album: tag.comment("ALBUM"),
+        genre: tag.comment("GENRE"),
         track,
         year,
-        has_cover,
+        has_cover: !tag.picture_blocks().is_empty(),
+        has_lyrics: tag.has_comment("LYRICS") || tag.has_comment("UNSYNCEDLYRICS"),
+        isrc: tag.comment("ISRC"),
+        rating: tag.comment("RATING").or_else(|| tag.comment("FMPS_RATING")).and_then(|r| parse_rating(&r)),
+    }
+}
+
+/// Tags from iTunes metadata atoms (M4A/MP4)
+fn read_mp4_tags(file_path: &Path) -> anyhow::Result<AudioTags> {
+    let tag = Mp4Tag::read_from_path(file_path)?;
+
+    Ok(AudioTags {
+        title: tag.text(mp4::TITLE),
+        artist: tag.text(mp4::ARTIST),
+        album: tag.text(mp4::ALBUM),
+        genre: tag.text(mp4::GENRE),
+        track: tag.track(),
+        year: tag
+            .text(mp4::YEAR)
+            .and_then(|s| s.chars().take(4).collect::<String>().parse::<i32>().ok()),
+        has_cover: !tag.artwork().is_empty(),
+        has_lyrics: tag.has(mp4::LYRICS),
+        isrc: tag.freeform(mp4::ITUNES_MEAN, "ISRC"),
+        rating: tag.freeform(mp4::ITUNES_MEAN, "RATING").and_then(|r| parse_rating(&r)),
     })
 }
 
@@ -83,6 +119,7 @@ fn read_id3_tags(file_path: &Path) -> anyhow::Result<AudioTags> {
         .context("reading ID3 tags")?;
 
     let has_cover = tag.pictures().next().is_some();
+    let has_lyrics = tag.lyrics().next().is_some() || tag.synchronised_lyrics().next().is_some();
 
     Ok(AudioTags {
         title: tag.title().map(|s| s.to_string()),
@@ -92,6 +129,9 @@ fn read_id3_tags(file_path: &Path) -> anyhow::Result<AudioTags> {
         track: tag.track(),
         year: tag.year(),
         has_cover,
+        has_lyrics,
+        isrc: tag.get("TSRC").and_then(|f| f.content().text()).map(|s| s.to_string()),
+        rating: id3_rating(&tag),
     })
 }
 
@@ -106,6 +146,9 @@ fn read_wav_tags(file_path: &Path) -> anyhow::Result<AudioTags> {
             track: tag.track(),
             year: tag.year(),
             has_cover: tag.pictures().next().is_some(),
+            has_lyrics: tag.lyrics().next().is_some(),
+            isrc: tag.get("TSRC").and_then(|f| f.content().text()).map(|s| s.to_string()),
+            rating: id3_rating(&tag),
         }),
         Err(_) => Ok(AudioTags::default()), // WAV might have no tags
     }
@@ -122,91 +165,294 @@ fn read_aiff_tags(file_path: &Path) -> anyhow::Result<AudioTags> {
             track: tag.track(),
             year: tag.year(),
             has_cover: tag.pictures().next().is_some(),
+            has_lyrics: tag.lyrics().next().is_some(),
+            isrc: tag.get("TSRC").and_then(|f| f.content().text()).map(|s| s.to_string()),
+            rating: id3_rating(&tag),
         }),
         Err(_) => Ok(AudioTags::default()),
     }
 }
 
+/// Stars from an ID3 POPM frame (1-255, as Windows Media Player and
+/// foobar2000 map them)
+fn id3_rating(tag: &Tag) -> Option<u8> {
+    let rating = tag.frames().find_map(|f| f.content().popularimeter())?.rating;
+    match rating {
+        0 => None,
+        1..=31 => Some(1),
+        32..=95 => Some(2),
+        96..=159 => Some(3),
+        160..=223 => Some(4),
+        _ => Some(5),
+    }
+}
+
+/// Stars from a text rating: 0-5 stars, 0-100 (MusicBee and most taggers)
+/// or 0.0-1.0 (FMPS)
+fn parse_rating(text: &str) -> Option<u8> {
+    let value: f64 = text.trim().parse().ok()?;
+    let stars = if value <= 1.0 && text.contains('.') {
+        value * 5.0
+    } else if value <= 5.0 {
+        value
+    } else {
+        value / 20.0
+    };
+    Some(stars.round().clamp(0.0, 5.0) as u8).filter(|&s| s > 0)
+}
+
 /// Sanitize a string for Vorbis comments: UTF-8 only, no null bytes
 fn sanitize_vorbis_string(s: &str) -> String {
     s.chars().filter(|&c| c != '\0').collect()
 }
 
-/// Tag a FLAC file with Vorbis comments.
+/// The regular fields `tag_audio` writes
+#[derive(Debug, Clone, Copy)]
+pub struct TrackTags<'a> {
+    pub artist: &'a str,
+    pub album: &'a str,
+    pub title: &'a str,
+    /// Track number, 0 if unknown (left untouched)
+    pub track: u32,
+    pub genre: Option<&'a str>,
+}
+
+/// Merge the regular fields into Vorbis comments (FLAC, Ogg Vorbis, Opus).
 /// Field names: ARTIST, ALBUM, TITLE, TRACKNUMBER, GENRE (uppercase, UTF-8, no nulls)
-fn tag_flac(
+fn tag_vorbis(
+    tag: &mut dyn VorbisTag,
+    tags: &TrackTags,
+    cover_path: Option<&Path>,
+    config: &PortableConfig,
+    policy: &TaggingConfig,
+) {
+    let TrackTags { artist, album, title, track, genre } = *tags;
+    // set_comment replaces any existing values, so fields never end up duplicated
+    let fields = [
+        ("ARTIST", policy.artist, Some(artist)),
+        ("ALBUM", policy.album, Some(album)),
+        ("TITLE", policy.title, Some(title)),
+        ("GENRE", policy.genre, genre),
+    ];
+    for (key, field_policy, value) in fields {
+        if let Some(v) = value {
+            if field_policy.allows(tag.has_comment(key)) {
+                tag.set_comment(key, sanitize_vorbis_string(v));
+            }
+        }
+    }
+    if track > 0 && policy.track.allows(tag.has_comment("TRACKNUMBER")) {
+        tag.set_comment("TRACKNUMBER", track.to_string());
+    }
+
+    // Add cover art if provided
+    if let Some(cover) = cover_path {
+        let has_cover = tag
+            .picture_blocks()
+            .iter()
+            .any(|p| p.picture_type == PictureType::CoverFront);
+        if cover.exists() && policy.cover.allows(has_cover) {
+            if let Ok(img) = resize_and_read_image(cover, config) {
+                // Only the front cover is replaced; other pictures are kept
+                tag.remove_picture_blocks(Some(PictureType::CoverFront));
+                tag.add_picture_block(flac_cover_picture(img));
+            }
+        }
+    }
+}
+
+/// FLAC front cover picture block for a resized JPEG
+fn flac_cover_picture(img: ResizedImage) -> metaflac::block::Picture {
+    metaflac::block::Picture {
+        picture_type: PictureType::CoverFront,
+        mime_type: "image/jpeg".to_string(),
+        description: String::new(),
+        width: img.width,
+        height: img.height,
+        depth: 24, // RGB JPEG = 24-bit color depth
+        num_colors: 0,
+        data: img.data,
+    }
+}
+
+/// Merge the regular fields into iTunes metadata atoms (M4A/MP4):
+/// ©ART, ©alb, ©nam, trkn, ©gen and covr
+fn tag_mp4(
+    tag: &mut Mp4Tag,
+    tags: &TrackTags,
+    cover_path: Option<&Path>,
+    config: &PortableConfig,
+    policy: &TaggingConfig,
+) {
+    let TrackTags { artist, album, title, track, genre } = *tags;
+    let fields = [
+        (mp4::ARTIST, policy.artist, Some(artist)),
+        (mp4::ALBUM, policy.album, Some(album)),
+        (mp4::TITLE, policy.title, Some(title)),
+        (mp4::GENRE, policy.genre, genre),
+    ];
+    for (kind, field_policy, value) in fields {
+        if let Some(v) = value {
+            if field_policy.allows(tag.has(kind)) {
+                tag.set_text(kind, v);
+            }
+        }
+    }
+    if track > 0 && policy.track.allows(tag.track().is_some()) {
+        tag.set_track(track);
+    }
+
+    if let Some(cover) = cover_path {
+        if cover.exists() && policy.cover.allows(!tag.artwork().is_empty()) {
+            if let Ok(img) = resize_and_read_image(cover, config) {
+                tag.set_artwork(img.data);
+            }
+        }
+    }
+}
+
+/// Tag an audio file with appropriate metadata format, merging into any
+/// existing tag. Each field is written according to its overwrite policy
+/// (`tagging` in data/config.json); everything else in the tag is preserved.
+/// - FLAC, Ogg Vorbis and Opus files: Vorbis comments (ARTIST, ALBUM, TITLE, TRACKNUMBER, GENRE)
+/// - M4A/MP4 files: iTunes metadata atoms
+/// - WAV/AIFF/MP3: ID3v2.3 tags
+pub fn tag_audio(
     file_path: &Path,
-    artist: &str,
-    album: &str,
-    title: &str,
-    track: u32,
-    genre: Option<&str>,
+    tags: &TrackTags,
     cover_path: Option<&Path>,
     config: &PortableConfig,
+    policy: &TaggingConfig,
 ) -> anyhow::Result<()> {
-    let mut flac_tag = metaflac::Tag::read_from_path(file_path)
-        .context("reading FLAC file")?;
-
-    // Remove existing Vorbis comments for these fields to avoid duplicates
-    flac_tag.remove_vorbis("ARTIST");
-    flac_tag.remove_vorbis("ALBUM");
-    flac_tag.remove_vorbis("TITLE");
-    flac_tag.remove_vorbis("TRACKNUMBER");
-    flac_tag.remove_vorbis("GENRE");
-
-    // Set Vorbis comments with sanitized UTF-8 strings (no null bytes)
-    flac_tag.set_vorbis("ARTIST", vec![sanitize_vorbis_string(artist)]);
-    flac_tag.set_vorbis("ALBUM", vec![sanitize_vorbis_string(album)]);
-    flac_tag.set_vorbis("TITLE", vec![sanitize_vorbis_string(title)]);
-    flac_tag.set_vorbis("TRACKNUMBER", vec![track.to_string()]);
-
-    // Set genre if provided
+    let TrackTags { artist, album, title, track, genre } = *tags;
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    if let Some(tag) = vorbis::open(file_path, extension.as_deref()) {
+        let mut tag = tag?;
+        tag_vorbis(tag.as_mut(), tags, cover_path, config, policy);
+        return tag.save(file_path);
+    }
+
+    if extension.as_deref().is_some_and(mp4::is_mp4_extension) {
+        let mut tag = Mp4Tag::read_from_path(file_path)?;
+        tag_mp4(&mut tag, tags, cover_path, config, policy);
+        return tag.write_to_path(file_path);
+    }
+
+    // Use ID3 tags for other formats. Unreadable tags are replaced.
+    let mut tag = read_id3_tag(file_path, extension.as_deref()).unwrap_or_else(|_| Tag::new());
+
+    if policy.artist.allows(tag.artist().is_some()) {
+        tag.set_artist(artist);
+    }
+    if policy.album.allows(tag.album().is_some()) {
+        tag.set_album(album);
+    }
+    if policy.title.allows(tag.title().is_some()) {
+        tag.set_title(title);
+    }
+    if track > 0 && policy.track.allows(tag.track().is_some()) {
+        tag.set_track(track);
+    }
     if let Some(g) = genre {
-        flac_tag.set_vorbis("GENRE", vec![sanitize_vorbis_string(g)]);
+        if policy.genre.allows(tag.genre().is_some()) {
+            tag.set_genre(g);
+        }
     }
 
-    // Add cover art if provided
     if let Some(cover) = cover_path {
-        if cover.exists() {
+        let has_cover = tag
+            .pictures()
+            .any(|p| p.picture_type == id3::frame::PictureType::CoverFront);
+        if cover.exists() && policy.cover.allows(has_cover) {
             if let Ok(img) = resize_and_read_image(cover, config) {
-                // Remove existing pictures first
-                flac_tag.remove_picture_type(PictureType::CoverFront);
-
-                let picture = metaflac::block::Picture {
-                    picture_type: PictureType::CoverFront,
+                // Only the front cover is replaced; other pictures are kept
+                tag.remove_picture_by_type(id3::frame::PictureType::CoverFront);
+                tag.add_frame(Picture {
                     mime_type: "image/jpeg".to_string(),
-                    description: String::new(),
-                    width: img.width,
-                    height: img.height,
-                    depth: 24, // RGB JPEG = 24-bit color depth
-                    num_colors: 0,
+                    picture_type: id3::frame::PictureType::CoverFront,
+                    description: "cover".to_string(),
                     data: img.data,
-                };
-                flac_tag.add_picture(
-                    picture.mime_type,
-                    picture.picture_type,
-                    picture.data,
-                );
+                });
             }
         }
     }
 
-    flac_tag.write_to_path(file_path)
-        .context("writing FLAC Vorbis comments")?;
+    write_id3_tag(&tag, file_path, extension.as_deref())
+}
 
-    Ok(())
+/// A tag field `tag_audio` would change
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagChange {
+    pub field: &'static str,
+    pub old: Option<String>,
+    pub new: String,
 }
 
-/// Tag an audio file with appropriate metadata format.
-/// - FLAC files: Vorbis comments (ARTIST, ALBUM, TITLE, TRACKNUMBER, GENRE)
-/// - WAV/AIFF/MP3/etc: ID3v2.3 tags
-pub fn tag_audio(
+impl std::fmt::Display for TagChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "  {:<7} {} -> {}",
+            format!("{}:", self.field),
+            self.old.as_deref().unwrap_or("(none)"),
+            self.new
+        )
+    }
+}
+
+/// Work out which fields `tag_audio` would change with the given values,
+/// without writing anything. Used to show a diff before retagging.
+pub fn preview_tag_audio(
     file_path: &Path,
-    artist: &str,
-    album: &str,
-    title: &str,
-    track: u32,
-    genre: Option<&str>,
+    tags: &TrackTags,
+    cover_path: Option<&Path>,
+    policy: &TaggingConfig,
+) -> Vec<TagChange> {
+    let TrackTags { artist, album, title, track, genre } = *tags;
+    let existing = read_tags(file_path).unwrap_or_default();
+    let mut changes = Vec::new();
+
+    let track = (track > 0).then(|| track.to_string());
+    let fields = [
+        ("Artist", policy.artist, existing.artist, Some(artist.to_string())),
+        ("Album", policy.album, existing.album, Some(album.to_string())),
+        ("Title", policy.title, existing.title, Some(title.to_string())),
+        ("Track", policy.track, existing.track.map(|t| t.to_string()), track),
+        ("Genre", policy.genre, existing.genre, genre.map(|g| g.to_string())),
+    ];
+    for (field, field_policy, old, new) in fields {
+        let Some(new) = new else { continue };
+        if old.as_deref() != Some(new.as_str()) && field_policy.allows(old.is_some()) {
+            changes.push(TagChange { field, old, new });
+        }
+    }
+
+    if let Some(cover) = cover_path {
+        if cover.exists() && policy.cover.allows(existing.has_cover) {
+            changes.push(TagChange {
+                field: "Cover",
+                old: existing.has_cover.then(|| "embedded".to_string()),
+                new: cover.display().to_string(),
+            });
+        }
+    }
+
+    changes
+}
+
+/// Tag a podcast episode. The show becomes the album, the publisher the artist
+/// and the genre is set to "Podcast".
+/// - FLAC/Ogg files: Vorbis comments plus DESCRIPTION, RELEASEDATE and PODCAST=1
+/// - M4A/MP4 files: iTunes atoms plus ©day, desc/©cmt and the pcst flag
+/// - Other formats: ID3v2.3 tags plus TDRL (release date), TDES/COMM
+///   (description) and the PCST podcast flag
+pub fn tag_episode(
+    file_path: &Path,
+    episode: &EpisodeMetadata,
     cover_path: Option<&Path>,
     config: &PortableConfig,
 ) -> anyhow::Result<()> {
@@ -215,22 +461,75 @@ pub fn tag_audio(
         .and_then(|e| e.to_str())
         .map(|e| e.to_lowercase());
 
-    // Use Vorbis comments for FLAC files
-    if extension.as_deref() == Some("flac") {
-        return tag_flac(file_path, artist, album, title, track, genre, cover_path, config);
+    let artist = if episode.publisher.is_empty() {
+        &episode.show
+    } else {
+        &episode.publisher
+    };
+    let tags = TrackTags {
+        artist,
+        album: &episode.show,
+        title: &episode.title,
+        track: 0,
+        genre: Some("Podcast"),
+    };
+
+    if let Some(tag) = vorbis::open(file_path, extension.as_deref()) {
+        let mut tag = tag?;
+        tag_vorbis(tag.as_mut(), &tags, cover_path, config, &TaggingConfig::default());
+        tag.remove_comment("TRACKNUMBER");
+        tag.set_comment("DESCRIPTION", sanitize_vorbis_string(&episode.description));
+        tag.set_comment("DATE", sanitize_vorbis_string(&episode.release_date));
+        tag.set_comment("RELEASEDATE", sanitize_vorbis_string(&episode.release_date));
+        tag.set_comment("PODCAST", "1".to_string());
+        return tag.save(file_path);
+    }
+
+    if extension.as_deref().is_some_and(mp4::is_mp4_extension) {
+        let mut tag = Mp4Tag::read_from_path(file_path)?;
+        tag_mp4(&mut tag, &tags, cover_path, config, &TaggingConfig::default());
+        if !episode.release_date.is_empty() {
+            tag.set_text(mp4::YEAR, &episode.release_date);
+        }
+        if !episode.description.is_empty() {
+            tag.set_text(mp4::DESCRIPTION, &episode.description);
+            tag.set_text(mp4::COMMENT, &episode.description);
+        }
+        tag.set_flag(mp4::PODCAST, true);
+        return tag.write_to_path(file_path);
     }
 
-    // Use ID3 tags for other formats
     let mut tag = Tag::new();
     tag.set_artist(artist);
-    tag.set_album(album);
-    tag.set_title(title);
-    tag.set_track(track);
+    tag.set_album(&episode.show);
+    tag.set_title(&episode.title);
+    tag.set_genre("Podcast");
 
-    // Set genre if provided
-    if let Some(g) = genre {
-        tag.set_genre(g);
+    if !episode.release_date.is_empty() {
+        tag.add_frame(Frame::text("TDRL", &episode.release_date));
+    }
+    if !episode.description.is_empty() {
+        tag.add_frame(Frame::text("TDES", &episode.description));
+        tag.add_frame(Comment {
+            lang: "eng".to_string(),
+            description: String::new(),
+            text: episode.description.clone(),
+        });
     }
+    if episode.duration_ms > 0 {
+        tag.add_frame(Frame::text("TLEN", episode.duration_ms.to_string()));
+    }
+    if let Some(url) = &episode.episode_url {
+        tag.add_frame(Frame::link("WOAS", url));
+    }
+    // PCST is a four-byte flag frame (iTunes); any non-zero value marks a podcast
+    tag.add_frame(Frame::with_content(
+        "PCST",
+        Content::Unknown(Unknown {
+            data: vec![0, 0, 0, 1],
+            version: Version::Id3v23,
+        }),
+    ));
 
     if let Some(cover) = cover_path {
         if cover.exists() {
@@ -246,8 +545,403 @@ pub fn tag_audio(
         }
     }
 
-    #[allow(deprecated)]
-    match extension.as_deref() {
+    write_id3_tag(&tag, file_path, extension.as_deref())
+}
+
+/// Tag an audio file from a metadata provider lookup: the regular tags plus
+/// any identifiers the provider returned (ISRC, year, MusicBrainz IDs)
+pub fn tag_from_metadata(
+    file_path: &Path,
+    meta: &TrackMetadata,
+    cover_path: Option<&Path>,
+    config: &PortableConfig,
+    policy: &TaggingConfig,
+) -> anyhow::Result<()> {
+    let tags = TrackTags {
+        artist: &meta.artist,
+        album: &meta.album,
+        title: &meta.title,
+        track: meta.track_number,
+        genre: meta.genre.as_deref(),
+    };
+    tag_audio(file_path, &tags, cover_path, config, policy)?;
+    tag_identifiers(file_path, meta)
+}
+
+/// Write identifiers from a metadata lookup (ISRC, year, MusicBrainz IDs) into
+/// an already tagged file, using the field names MusicBrainz Picard writes.
+/// - FLAC/Ogg files: ISRC, DATE, MUSICBRAINZ_TRACKID/ALBUMID/RELEASEGROUPID/ARTISTID
+/// - M4A/MP4 files: ©day and `----:com.apple.iTunes:` ISRC / "MusicBrainz ..." atoms
+/// - Other formats: TSRC, TYER, UFID (recording) and TXXX "MusicBrainz ..." frames
+pub fn tag_identifiers(file_path: &Path, meta: &TrackMetadata) -> anyhow::Result<()> {
+    if meta.isrc.is_none() && meta.year.is_none() && meta.mbids.is_empty() {
+        return Ok(());
+    }
+
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    let values = [
+        &meta.mbids.recording,
+        &meta.mbids.release,
+        &meta.mbids.release_group,
+        &meta.mbids.artist,
+    ];
+    let ids: Vec<_> = MUSICBRAINZ_IDS
+        .iter()
+        .zip(values)
+        .map(|(&(key, name), value)| (key, name, value))
+        .collect();
+
+    if let Some(tag) = vorbis::open(file_path, extension.as_deref()) {
+        let mut tag = tag?;
+        if let Some(isrc) = &meta.isrc {
+            tag.set_comment("ISRC", sanitize_vorbis_string(isrc));
+        }
+        if let Some(year) = meta.year {
+            tag.set_comment("DATE", year.to_string());
+        }
+        for &(key, _, value) in &ids {
+            if let Some(v) = value {
+                tag.set_comment(key, sanitize_vorbis_string(v));
+            }
+        }
+        return tag.save(file_path);
+    }
+
+    if extension.as_deref().is_some_and(mp4::is_mp4_extension) {
+        let mut tag = Mp4Tag::read_from_path(file_path)?;
+        if let Some(isrc) = &meta.isrc {
+            tag.set_freeform(mp4::ITUNES_MEAN, "ISRC", isrc);
+        }
+        if let Some(year) = meta.year {
+            tag.set_text(mp4::YEAR, &year.to_string());
+        }
+        for &(_, name, value) in &ids {
+            if let Some(v) = value {
+                tag.set_freeform(mp4::ITUNES_MEAN, name, v);
+            }
+        }
+        return tag.write_to_path(file_path);
+    }
+
+    let mut tag = read_id3_tag(file_path, extension.as_deref()).unwrap_or_else(|_| Tag::new());
+
+    if let Some(isrc) = &meta.isrc {
+        tag.set_text("TSRC", isrc);
+    }
+    if let Some(year) = meta.year {
+        tag.set_year(year);
+    }
+    if let Some(recording) = &meta.mbids.recording {
+        tag.remove_unique_file_identifier_by_owner_identifier(MUSICBRAINZ_UFID_OWNER);
+        tag.add_frame(UniqueFileIdentifier {
+            owner_identifier: MUSICBRAINZ_UFID_OWNER.to_string(),
+            identifier: recording.as_bytes().to_vec(),
+        });
+    }
+    for (_, description, value) in ids {
+        if let Some(v) = value {
+            tag.remove_extended_text(Some(description), None);
+            tag.add_frame(ExtendedText {
+                description: description.to_string(),
+                value: v.clone(),
+            });
+        }
+    }
+
+    write_id3_tag(&tag, file_path, extension.as_deref())
+}
+
+/// Mark a file downloaded from a source below the required quality.
+/// - FLAC/Ogg files: QUALITY_WARNING Vorbis comment
+/// - M4A/MP4 files: iTunes freeform QUALITY_WARNING atom
+/// - Other formats: ID3 TXXX frame "QUALITY_WARNING"
+pub fn tag_quality_warning(file_path: &Path, warning: &str) -> anyhow::Result<()> {
+    const KEY: &str = "QUALITY_WARNING";
+
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    if let Some(tag) = vorbis::open(file_path, extension.as_deref()) {
+        let mut tag = tag?;
+        tag.set_comment(KEY, sanitize_vorbis_string(warning));
+        return tag.save(file_path);
+    }
+
+    if extension.as_deref().is_some_and(mp4::is_mp4_extension) {
+        let mut tag = Mp4Tag::read_from_path(file_path)?;
+        tag.set_freeform(mp4::ITUNES_MEAN, KEY, warning);
+        return tag.write_to_path(file_path);
+    }
+
+    let mut tag = read_id3_tag(file_path, extension.as_deref())?;
+    tag.remove_extended_text(Some(KEY), None);
+    tag.add_frame(ExtendedText {
+        description: KEY.to_string(),
+        value: warning.to_string(),
+    });
+    write_id3_tag(&tag, file_path, extension.as_deref())
+}
+
+/// Write ReplayGain track (and album, when known) gain and peak.
+/// - FLAC/Ogg files: REPLAYGAIN_* Vorbis comments, plus R128_* gains for Opus
+/// - M4A/MP4 files: iTunes freeform replaygain_* atoms
+/// - Other formats: ID3 TXXX frames "REPLAYGAIN_*"
+pub fn tag_replaygain(file_path: &Path, gain: &ReplayGain) -> anyhow::Result<()> {
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    if let Some(tag) = vorbis::open(file_path, extension.as_deref()) {
+        let mut tag = tag?;
+        let mut values = gain.tag_values().to_vec();
+        if extension.as_deref() == Some("opus") {
+            values.extend(gain.r128_values());
+        }
+        for (key, value) in values {
+            match value {
+                Some(v) => tag.set_comment(key, v),
+                None => tag.remove_comment(key),
+            }
+        }
+        return tag.save(file_path);
+    }
+
+    if extension.as_deref().is_some_and(mp4::is_mp4_extension) {
+        let mut tag = Mp4Tag::read_from_path(file_path)?;
+        for (key, value) in gain.tag_values() {
+            let name = key.to_lowercase();
+            match value {
+                Some(v) => tag.set_freeform(mp4::ITUNES_MEAN, &name, &v),
+                None => tag.remove_freeform(mp4::ITUNES_MEAN, &name),
+            }
+        }
+        return tag.write_to_path(file_path);
+    }
+
+    let mut tag = read_id3_tag(file_path, extension.as_deref())?;
+    for (key, value) in gain.tag_values() {
+        tag.remove_extended_text(Some(key), None);
+        if let Some(v) = value {
+            tag.add_frame(ExtendedText {
+                description: key.to_string(),
+                value: v,
+            });
+        }
+    }
+    write_id3_tag(&tag, file_path, extension.as_deref())
+}
+
+/// Copy every tag and picture from one file to another of the same tag
+/// family (ID3, Vorbis comments or MP4 atoms), replacing the target's tags.
+/// Used when FFmpeg rewrites a file and drops what it can't carry over.
+pub fn copy_tags(from: &Path, to: &Path) -> anyhow::Result<()> {
+    let extension = |p: &Path| p.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    let (from_ext, to_ext) = (extension(from), extension(to));
+
+    if let Some(src) = vorbis::open(from, from_ext.as_deref()) {
+        let mut dest = vorbis::open(to, to_ext.as_deref())
+            .context("Vorbis comments can only be copied to FLAC or Ogg files")??;
+        vorbis::copy(src?.as_ref(), dest.as_mut());
+        return dest.save(to);
+    }
+
+    if from_ext.as_deref().is_some_and(mp4::is_mp4_extension) {
+        if !to_ext.as_deref().is_some_and(mp4::is_mp4_extension) {
+            anyhow::bail!("MP4 tags can only be copied to M4A/MP4 files");
+        }
+        return Mp4Tag::read_from_path(from)?.write_to_path(to);
+    }
+
+    // Raw AAC has no tags to copy
+    if from_ext.as_deref() == Some("aac") {
+        return Ok(());
+    }
+    let tag = read_id3_tag(from, from_ext.as_deref())?;
+    write_id3_tag(&tag, to, to_ext.as_deref())
+}
+
+/// Carry every tag and picture over to a converted file, replacing the
+/// target's tags. Files in the same tag family are copied as-is; across
+/// families fields are mapped the way MusicBrainz Picard names them (e.g.
+/// TPE2 ↔ ALBUMARTIST ↔ aART), and anything without a native field goes to
+/// TXXX frames or iTunes freeform atoms. Raw AAC can't hold tags either way.
+pub fn transfer_tags(from: &Path, to: &Path) -> anyhow::Result<()> {
+    let extension = |p: &Path| p.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    let (from_ext, to_ext) = (extension(from), extension(to));
+    if from_ext.as_deref() == Some("aac") || to_ext.as_deref() == Some("aac") {
+        return Ok(());
+    }
+
+    let is_vorbis = |e: Option<&str>| e.is_some_and(|e| e == "flac" || vorbis::is_ogg_extension(e));
+    let is_mp4 = |e: Option<&str>| e.is_some_and(mp4::is_mp4_extension);
+    let family = |e: Option<&str>| (is_vorbis(e), is_mp4(e));
+    let to_opus = to_ext.as_deref() == Some("opus");
+    if family(from_ext.as_deref()) == family(to_ext.as_deref()) && (to_opus || from_ext.as_deref() != Some("opus")) {
+        return copy_tags(from, to);
+    }
+
+    let mut tags = if let Some(tag) = vorbis::open(from, from_ext.as_deref()) {
+        TagSet::from_vorbis(tag?.as_ref())
+    } else if is_mp4(from_ext.as_deref()) {
+        TagSet::from_mp4(&Mp4Tag::read_from_path(from)?)
+    } else {
+        TagSet::from_id3(&read_id3_tag(from, from_ext.as_deref())?)
+    };
+    // R128 gains are relative to Opus output gain and mean nothing elsewhere
+    if !to_opus {
+        tags.remove_prefixed("R128_");
+    }
+
+    if let Some(tag) = vorbis::open(to, to_ext.as_deref()) {
+        let mut tag = tag?;
+        tags.write_vorbis(tag.as_mut());
+        return tag.save(to);
+    }
+    if is_mp4(to_ext.as_deref()) {
+        return tags.to_mp4().write_to_path(to);
+    }
+    write_id3_tag(&tags.to_id3(), to, to_ext.as_deref())
+}
+
+/// Embed lyrics into an already tagged audio file, replacing any existing lyrics.
+/// - FLAC/Ogg files: LYRICS Vorbis comment (plain text, or LRC if only synced lyrics exist)
+/// - M4A/MP4 files: ©lyr atom (plain text, or LRC if only synced lyrics exist)
+/// - Other formats: ID3 USLT (plain) and SYLT (synced, millisecond timestamps)
+pub fn embed_lyrics(file_path: &Path, lyrics: &Lyrics) -> anyhow::Result<()> {
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    if let Some(tag) = vorbis::open(file_path, extension.as_deref()) {
+        let mut tag = tag?;
+        tag.remove_comment("LYRICS");
+        tag.remove_comment("UNSYNCEDLYRICS");
+        if let Some(text) = lyrics.plain.as_ref().or(lyrics.synced.as_ref()) {
+            tag.set_comment("LYRICS", sanitize_vorbis_string(text));
+        }
+        return tag.save(file_path);
+    }
+
+    if extension.as_deref().is_some_and(mp4::is_mp4_extension) {
+        let mut tag = Mp4Tag::read_from_path(file_path)?;
+        match lyrics.plain.as_ref().or(lyrics.synced.as_ref()) {
+            Some(text) => tag.set_text(mp4::LYRICS, text),
+            None => tag.remove(mp4::LYRICS),
+        }
+        return tag.write_to_path(file_path);
+    }
+
+    let mut tag = read_id3_tag(file_path, extension.as_deref()).unwrap_or_else(|_| Tag::new());
+
+    tag.remove_all_lyrics();
+    tag.remove_all_synchronised_lyrics();
+
+    if let Some(text) = lyrics.plain_text() {
+        tag.add_frame(id3::frame::Lyrics {
+            lang: "eng".to_string(),
+            description: String::new(),
+            text,
+        });
+    }
+    let synced = lyrics.synced_lines();
+    if !synced.is_empty() {
+        tag.add_frame(SynchronisedLyrics {
+            lang: "eng".to_string(),
+            timestamp_format: TimestampFormat::Ms,
+            content_type: SynchronisedLyricsType::Lyrics,
+            description: String::new(),
+            content: synced,
+        });
+    }
+
+    write_id3_tag(&tag, file_path, extension.as_deref())
+}
+
+/// A picture embedded in an audio file
+pub struct EmbeddedCover {
+    pub data: Vec<u8>,
+    pub mime_type: String,
+}
+
+impl EmbeddedCover {
+    /// File extension matching the picture's MIME type
+    pub fn extension(&self) -> &'static str {
+        match self.mime_type.as_str() {
+            "image/png" => "png",
+            "image/gif" => "gif",
+            "image/bmp" => "bmp",
+            "image/webp" => "webp",
+            _ => "jpg",
+        }
+    }
+
+    /// Pixel dimensions, read from the image header
+    pub fn dimensions(&self) -> anyhow::Result<(u32, u32)> {
+        ImageReader::new(std::io::Cursor::new(&self.data))
+            .with_guessed_format()?
+            .into_dimensions()
+            .context("reading cover dimensions")
+    }
+}
+
+/// Problems `audit_cover` can report
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoverIssue {
+    Missing,
+    Unreadable,
+    TooSmall { width: u32, height: u32 },
+    NotSquare { width: u32, height: u32 },
+}
+
+impl std::fmt::Display for CoverIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoverIssue::Missing => write!(f, "no cover"),
+            CoverIssue::Unreadable => write!(f, "cover cannot be decoded"),
+            CoverIssue::TooSmall { width, height } => write!(f, "tiny cover ({}x{})", width, height),
+            CoverIssue::NotSquare { width, height } => write!(f, "non-square cover ({}x{})", width, height),
+        }
+    }
+}
+
+#[allow(deprecated)]
+fn read_id3_tag(file_path: &Path, extension: Option<&str>) -> anyhow::Result<Tag> {
+    let tag = match extension {
+        Some("wav") => Tag::read_from_wav_path(file_path),
+        Some("aif" | "aiff") => Tag::read_from_aiff_path(file_path),
+        _ => Tag::read_from_path(file_path),
+    };
+    match tag {
+        Ok(tag) => Ok(tag),
+        Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => Ok(Tag::new()),
+        Err(e) => Err(e).context("reading ID3 tags"),
+    }
+}
+
+/// Whether a file's format can hold tags. Players ignore ID3 in raw ADTS
+/// AAC, so downloads in that format are kept untagged.
+pub fn holds_tags(file_path: &Path) -> bool {
+    !file_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("aac"))
+}
+
+/// Logged for downloads that are kept untagged
+pub const UNTAGGED_WARNING: &str = "Raw AAC can't hold tags; kept untagged (convert to m4a to tag it)";
+
+#[allow(deprecated)]
+fn write_id3_tag(tag: &Tag, file_path: &Path, extension: Option<&str>) -> anyhow::Result<()> {
+    match extension {
+        // Players ignore ID3 in raw ADTS streams, so don't pretend it worked
+        Some("aac") => anyhow::bail!("Raw AAC streams can't hold tags; convert to m4a instead"),
         Some("wav") => tag
             .write_to_wav_path(file_path, Version::Id3v23)
             .context("writing ID3 tag to WAV")?,
@@ -258,10 +952,139 @@ pub fn tag_audio(
             .write_to_path(file_path, Version::Id3v23)
             .context("writing ID3 tag")?,
     }
-
     Ok(())
 }
 
+/// Read the embedded front cover, falling back to the first picture of any type
+pub fn read_cover(file_path: &Path) -> anyhow::Result<Option<EmbeddedCover>> {
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    if let Some(tag) = vorbis::open(file_path, extension.as_deref()) {
+        let pictures = tag?.picture_blocks();
+        let picture = pictures
+            .iter()
+            .find(|p| p.picture_type == PictureType::CoverFront)
+            .or_else(|| pictures.first());
+        return Ok(picture.map(|p| EmbeddedCover {
+            data: p.data.clone(),
+            mime_type: p.mime_type.clone(),
+        }));
+    }
+
+    if extension.as_deref().is_some_and(mp4::is_mp4_extension) {
+        let tag = Mp4Tag::read_from_path(file_path)?;
+        return Ok(tag.artwork().first().map(|(mime, data)| EmbeddedCover {
+            data: data.to_vec(),
+            mime_type: mime.to_string(),
+        }));
+    }
+
+    let tag = read_id3_tag(file_path, extension.as_deref())?;
+    let picture = tag
+        .pictures()
+        .find(|p| p.picture_type == id3::frame::PictureType::CoverFront)
+        .or_else(|| tag.pictures().next());
+    Ok(picture.map(|p| EmbeddedCover {
+        data: p.data.clone(),
+        mime_type: p.mime_type.clone(),
+    }))
+}
+
+/// Replace the embedded front cover with `cover`, downscaled to the configured
+/// size limits. Other tags are left untouched.
+pub fn embed_cover(file_path: &Path, cover: &Path, config: &PortableConfig) -> anyhow::Result<()> {
+    let img = resize_and_read_image(cover, config)
+        .with_context(|| format!("reading image {}", cover.display()))?;
+
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    if let Some(tag) = vorbis::open(file_path, extension.as_deref()) {
+        let mut tag = tag?;
+        tag.remove_picture_blocks(Some(PictureType::CoverFront));
+        tag.add_picture_block(flac_cover_picture(img));
+        return tag.save(file_path);
+    }
+
+    if extension.as_deref().is_some_and(mp4::is_mp4_extension) {
+        let mut tag = Mp4Tag::read_from_path(file_path)?;
+        tag.set_artwork(img.data);
+        return tag.write_to_path(file_path);
+    }
+
+    let mut tag = read_id3_tag(file_path, extension.as_deref())?;
+    tag.remove_picture_by_type(id3::frame::PictureType::CoverFront);
+    tag.add_frame(Picture {
+        mime_type: "image/jpeg".to_string(),
+        picture_type: id3::frame::PictureType::CoverFront,
+        description: "cover".to_string(),
+        data: img.data,
+    });
+    write_id3_tag(&tag, file_path, extension.as_deref())
+}
+
+/// Remove every embedded picture. Returns false if the file had none.
+pub fn strip_cover(file_path: &Path) -> anyhow::Result<bool> {
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    if let Some(tag) = vorbis::open(file_path, extension.as_deref()) {
+        let mut tag = tag?;
+        if tag.picture_blocks().is_empty() {
+            return Ok(false);
+        }
+        tag.remove_picture_blocks(None);
+        tag.save(file_path)?;
+        return Ok(true);
+    }
+
+    if extension.as_deref().is_some_and(mp4::is_mp4_extension) {
+        let mut tag = Mp4Tag::read_from_path(file_path)?;
+        if tag.artwork().is_empty() {
+            return Ok(false);
+        }
+        tag.remove(mp4::COVER);
+        tag.write_to_path(file_path)?;
+        return Ok(true);
+    }
+
+    let mut tag = read_id3_tag(file_path, extension.as_deref())?;
+    if tag.pictures().next().is_none() {
+        return Ok(false);
+    }
+    tag.remove_all_pictures();
+    write_id3_tag(&tag, file_path, extension.as_deref())?;
+    Ok(true)
+}
+
+/// Check the embedded cover: missing, smaller than `min_dim` on either side, or not square
+pub fn audit_cover(file_path: &Path, min_dim: u32) -> anyhow::Result<Option<CoverIssue>> {
+    let Some(cover) = read_cover(file_path)? else {
+        return Ok(Some(CoverIssue::Missing));
+    };
+    let Ok((width, height)) = cover.dimensions() else {
+        return Ok(Some(CoverIssue::Unreadable));
+    };
+    Ok(cover_issue(width, height, min_dim))
+}
+
+fn cover_issue(width: u32, height: u32, min_dim: u32) -> Option<CoverIssue> {
+    if width.min(height) < min_dim {
+        Some(CoverIssue::TooSmall { width, height })
+    } else if width != height {
+        Some(CoverIssue::NotSquare { width, height })
+    } else {
+        None
+    }
+}
+
 fn encode_jpeg(img: &image::DynamicImage, quality: u8) -> anyhow::Result<Vec<u8>> {
     let mut buf: Vec<u8> = Vec::new();
     let rgb_img = img.to_rgb8();
@@ -313,3 +1136,31 @@ fn resize_and_read_image(cover: &Path, config: &PortableConfig) -> anyhow::Resul
         height: new_h,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cover_issue() {
+        assert_eq!(cover_issue(600, 600, 300), None);
+        assert_eq!(cover_issue(200, 200, 300), Some(CoverIssue::TooSmall { width: 200, height: 200 }));
+        assert_eq!(cover_issue(1280, 720, 300), Some(CoverIssue::NotSquare { width: 1280, height: 720 }));
+    }
+
+    #[test]
+    fn test_holds_tags() {
+        assert!(holds_tags(Path::new("/lib/a.m4a")));
+        assert!(!holds_tags(Path::new("/lib/a.AAC")));
+    }
+
+    #[test]
+    fn test_parse_rating() {
+        assert_eq!(parse_rating("4"), Some(4));
+        assert_eq!(parse_rating("80"), Some(4));
+        assert_eq!(parse_rating("0.6"), Some(3));
+        assert_eq!(parse_rating("1"), Some(1));
+        assert_eq!(parse_rating("0"), None);
+        assert_eq!(parse_rating("great"), None);
+    }
+}
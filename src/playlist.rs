@@ -0,0 +1,587 @@
+use anyhow::Context;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, PathStyle, PlaylistConfig, PlaylistFormat};
+use crate::db::{DownloadDB, TrackEntry};
+use crate::trash::{self, Trash};
+use crate::{converter, file_utils, metadata};
+
+/// A playlist entry with the details shown by players
+#[derive(Debug, Clone, Default)]
+pub struct PlaylistTrack {
+    pub path: PathBuf,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub album: Option<String>,
+    pub duration_secs: Option<u64>,
+    /// Album cover next to the file
+    pub cover: Option<PathBuf>,
+}
+
+impl PlaylistTrack {
+    /// Artist, title and album from the file's tags, falling back to its
+    /// download cache entry, and the duration from FFmpeg
+    pub fn read(path: &Path, entry: Option<&TrackEntry>) -> Self {
+        let tags = metadata::read_tags(path).unwrap_or_default();
+        Self {
+            path: path.to_path_buf(),
+            artist: tags.artist.or_else(|| entry.map(|e| e.artist.clone())),
+            title: tags.title.or_else(|| entry.map(|e| e.title.clone())),
+            album: tags.album,
+            duration_secs: converter::probe_duration(path).map(|secs| secs.round() as u64),
+            cover: path.parent().and_then(file_utils::find_folder_cover),
+        }
+    }
+
+    /// "Artist - Title", or the file name when the tags are missing
+    fn display_title(&self) -> String {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+            (None, Some(title)) => title.clone(),
+            _ => self
+                .path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The writer for a playlist format
+fn writer(format: PlaylistFormat) -> &'static dyn PlaylistWriter {
+    match format {
+        PlaylistFormat::M3u8 => &M3u8Writer,
+        PlaylistFormat::Pls => &PlsWriter,
+        PlaylistFormat::Xspf => &XspfWriter,
+        PlaylistFormat::Jspf => &JspfWriter,
+    }
+}
+
+/// A playlist file format. Writers get the tracks and a `PathWriter` that
+/// formats their locations the way the playlist config asks for.
+trait PlaylistWriter: Sync {
+    fn render(&self, name: &str, tracks: &[PlaylistTrack], paths: &PathWriter, config: &PlaylistConfig) -> String;
+}
+
+/// Extended M3U in UTF-8
+struct M3u8Writer;
+
+impl PlaylistWriter for M3u8Writer {
+    fn render(&self, name: &str, tracks: &[PlaylistTrack], paths: &PathWriter, config: &PlaylistConfig) -> String {
+        let mut out = String::from("#EXTM3U\n");
+        if config.playlist_name {
+            let _ = writeln!(out, "#PLAYLIST:{}", name);
+        }
+        for track in tracks {
+            // -1 is the standard "unknown length"
+            let duration = track.duration_secs.map_or(-1, |secs| secs as i64);
+            let _ = writeln!(out, "#EXTINF:{},{}", duration, track.display_title());
+            if let (true, Some(cover)) = (config.extimg, &track.cover) {
+                let _ = writeln!(out, "#EXTIMG:{}", paths.format(cover));
+            }
+            let _ = writeln!(out, "{}", paths.format(&track.path));
+        }
+        out
+    }
+}
+
+/// PLS version 2, as read by Winamp-era players and many car units
+struct PlsWriter;
+
+impl PlaylistWriter for PlsWriter {
+    fn render(&self, _name: &str, tracks: &[PlaylistTrack], paths: &PathWriter, _config: &PlaylistConfig) -> String {
+        let mut out = String::from("[playlist]\n");
+        for (i, track) in tracks.iter().enumerate() {
+            let n = i + 1;
+            let _ = writeln!(out, "File{}={}", n, paths.format(&track.path));
+            let _ = writeln!(out, "Title{}={}", n, track.display_title());
+            let _ = writeln!(out, "Length{}={}", n, track.duration_secs.map_or(-1, |secs| secs as i64));
+        }
+        let _ = writeln!(out, "NumberOfEntries={}", tracks.len());
+        out.push_str("Version=2\n");
+        out
+    }
+}
+
+/// XML Shareable Playlist Format
+struct XspfWriter;
+
+impl PlaylistWriter for XspfWriter {
+    fn render(&self, name: &str, tracks: &[PlaylistTrack], paths: &PathWriter, _config: &PlaylistConfig) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n");
+        let _ = writeln!(out, "  <title>{}</title>", xml_escape(name));
+        out.push_str("  <trackList>\n");
+        for track in tracks {
+            out.push_str("    <track>\n");
+            let _ = writeln!(out, "      <location>{}</location>", xml_escape(&paths.uri(&track.path)));
+            for (element, value) in [("title", &track.title), ("creator", &track.artist), ("album", &track.album)] {
+                if let Some(value) = value {
+                    let _ = writeln!(out, "      <{0}>{1}</{0}>", element, xml_escape(value));
+                }
+            }
+            if let Some(secs) = track.duration_secs {
+                let _ = writeln!(out, "      <duration>{}</duration>", secs * 1000);
+            }
+            if let Some(cover) = &track.cover {
+                let _ = writeln!(out, "      <image>{}</image>", xml_escape(&paths.uri(cover)));
+            }
+            out.push_str("    </track>\n");
+        }
+        out.push_str("  </trackList>\n</playlist>\n");
+        out
+    }
+}
+
+/// XSPF's JSON form
+struct JspfWriter;
+
+#[derive(Serialize)]
+struct Jspf {
+    playlist: JspfPlaylist,
+}
+
+#[derive(Serialize)]
+struct JspfPlaylist {
+    title: String,
+    track: Vec<JspfTrack>,
+}
+
+#[derive(Serialize)]
+struct JspfTrack {
+    location: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    creator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    album: Option<String>,
+    /// Milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<String>,
+}
+
+impl PlaylistWriter for JspfWriter {
+    fn render(&self, name: &str, tracks: &[PlaylistTrack], paths: &PathWriter, _config: &PlaylistConfig) -> String {
+        let jspf = Jspf {
+            playlist: JspfPlaylist {
+                title: name.to_string(),
+                track: tracks
+                    .iter()
+                    .map(|track| JspfTrack {
+                        location: vec![paths.uri(&track.path)],
+                        title: track.title.clone(),
+                        creator: track.artist.clone(),
+                        album: track.album.clone(),
+                        duration: track.duration_secs.map(|secs| secs * 1000),
+                        image: track.cover.as_deref().map(|cover| paths.uri(cover)),
+                    })
+                    .collect(),
+            },
+        };
+        let mut out = serde_json::to_string_pretty(&jspf).unwrap_or_default();
+        out.push('\n');
+        out
+    }
+}
+
+/// Rewrite an existing M3U/M3U8 or PLS file in place, keeping its name and
+/// format
+pub fn rewrite_playlist(file: &Path, tracks: &[PathBuf], db: Option<&DownloadDB>) -> anyhow::Result<()> {
+    let format = file
+        .extension()
+        .and_then(|ext| PlaylistFormat::parse(&ext.to_string_lossy()))
+        .with_context(|| format!("unknown playlist format: {}", file.display()))?;
+    let name = file.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let playlist_dir = file.parent().unwrap_or(Path::new("."));
+    let tracks = read_tracks(tracks, db);
+    let config = Config::load().playlist;
+    let paths = PathWriter::new(playlist_dir, &tracks, &config);
+    std::fs::write(file, writer(format).render(&name, &tracks, &paths, &config))
+        .with_context(|| format!("writing {}", file.display()))
+}
+
+pub fn read_tracks(tracks: &[PathBuf], db: Option<&DownloadDB>) -> Vec<PlaylistTrack> {
+    tracks
+        .iter()
+        .map(|path| PlaylistTrack::read(path, db.and_then(|db| db.find_by_path(&path.display().to_string()))))
+        .collect()
+}
+
+/// Write `{name}.{ext}` (UTF-8) to the playlist folder
+pub fn write_playlist(
+    format: PlaylistFormat,
+    name: &str,
+    tracks: &[PlaylistTrack],
+    playlist_dir: &Path,
+    config: &PlaylistConfig,
+) -> anyhow::Result<PathBuf> {
+    std::fs::create_dir_all(playlist_dir)?;
+    let playlist_file = playlist_dir.join(format!("{}.{}", file_utils::sanitize_filename(name), format.extension()));
+    let paths = PathWriter::new(playlist_dir, tracks, config);
+    std::fs::write(&playlist_file, writer(format).render(name, tracks, &paths, config))?;
+    // Older versions wrote plain .m3u files, which the .m3u8 replaces. The
+    // name may also belong to a playlist the user made, so it goes to the
+    // trash rather than being deleted.
+    if format == PlaylistFormat::M3u8 {
+        let legacy = playlist_file.with_extension("m3u");
+        if legacy.is_file() {
+            let _guard = trash::lock();
+            Trash::open()
+                .trash_file(&legacy, None, &format!("replaced by {}", playlist_file.display()))
+                .with_context(|| format!("moving {} to the trash", legacy.display()))?;
+        }
+    }
+    Ok(playlist_file)
+}
+
+/// An entry read from an existing M3U or PLS playlist
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlaylistEntry {
+    /// Location as written in the playlist: a path or a URI
+    pub location: String,
+    /// Display title from `#EXTINF` or `TitleN`, usually "Artist - Title"
+    pub title: Option<String>,
+    pub duration_secs: Option<u64>,
+}
+
+impl PlaylistEntry {
+    /// The file the entry points at, resolved against the playlist's folder.
+    /// `None` for URLs other than `file://`.
+    pub fn path(&self, playlist_dir: &Path) -> Option<PathBuf> {
+        let location = match self.location.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("file") => {
+                uri_decode(rest.strip_prefix("localhost").unwrap_or(rest))
+            }
+            // A single letter is a Windows drive, not a scheme
+            Some((scheme, _)) if scheme.len() > 1 => return None,
+            _ => self.location.clone(),
+        };
+        // Playlists written for devices may use backslashes
+        Some(playlist_dir.join(location.replace('\\', "/")))
+    }
+}
+
+/// Read an M3U/M3U8 or PLS playlist (chosen by extension). M3U files from
+/// other tools that aren't UTF-8 are read as Latin-1.
+pub fn read_playlist(path: &Path) -> anyhow::Result<Vec<PlaylistEntry>> {
+    let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+    };
+    let is_pls = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pls"));
+    Ok(if is_pls { parse_pls(&text) } else { parse_m3u(&text) })
+}
+
+fn parse_m3u(text: &str) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut extinf: Option<(Option<u64>, String)> = None;
+    for line in text.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            // "#EXTINF:<seconds>[ attributes],<title>"
+            let (duration, title) = info.split_once(',').unwrap_or((info, ""));
+            let duration = duration.split_whitespace().next().and_then(|d| d.parse::<f64>().ok());
+            extinf = Some((duration.filter(|d| *d > 0.0).map(|d| d.round() as u64), title.trim().to_string()));
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let (duration_secs, title) = extinf.take().unwrap_or_default();
+            entries.push(PlaylistEntry {
+                location: line.to_string(),
+                title: Some(title).filter(|t| !t.is_empty()),
+                duration_secs,
+            });
+        }
+    }
+    entries
+}
+
+fn parse_pls(text: &str) -> Vec<PlaylistEntry> {
+    let mut entries: std::collections::BTreeMap<u32, PlaylistEntry> = std::collections::BTreeMap::new();
+    for line in text.lines() {
+        let Some((key, value)) = line.trim_start_matches('\u{feff}').split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+        let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+        let Ok(n) = key[split..].parse::<u32>() else {
+            continue;
+        };
+        let entry = entries.entry(n).or_default();
+        match &key[..split] {
+            "file" => entry.location = value.to_string(),
+            "title" if !value.is_empty() => entry.title = Some(value.to_string()),
+            "length" => entry.duration_secs = value.parse::<i64>().ok().filter(|d| *d > 0).map(|d| d as u64),
+            _ => {}
+        }
+    }
+    entries.into_values().filter(|e| !e.location.is_empty()).collect()
+}
+
+/// Formats track paths the way the playlist config asks for
+struct PathWriter<'a> {
+    config: &'a PlaylistConfig,
+    playlist_dir: &'a Path,
+    /// Folder shared by the playlist and its tracks, for the prefix style
+    root: PathBuf,
+}
+
+impl<'a> PathWriter<'a> {
+    fn new(playlist_dir: &'a Path, tracks: &[PlaylistTrack], config: &'a PlaylistConfig) -> Self {
+        let root = tracks
+            .iter()
+            .filter_map(|t| t.path.parent())
+            .fold(absolute(playlist_dir), |root, dir| common_ancestor(&root, &absolute(dir)));
+        Self {
+            config,
+            playlist_dir,
+            root,
+        }
+    }
+
+    /// Path as written to M3U and PLS files
+    fn format(&self, file: &Path) -> String {
+        let path = match self.config.path_style {
+            PathStyle::Relative => relative_path_from(self.playlist_dir, file).display().to_string(),
+            PathStyle::Absolute => absolute(file).display().to_string(),
+            PathStyle::Prefix => self.with_prefix(&self.below_root(file)),
+        };
+        if self.config.backslashes {
+            path.replace('/', "\\")
+        } else {
+            path
+        }
+    }
+
+    /// Location as a URI reference, for XSPF and JSPF
+    fn uri(&self, file: &Path) -> String {
+        match self.config.path_style {
+            PathStyle::Relative => uri_encode(&relative_path_from(self.playlist_dir, file).display().to_string()),
+            PathStyle::Absolute => format!("file://{}", uri_encode(&absolute(file).display().to_string())),
+            // The prefix may already be a URL, so it's kept as it is
+            PathStyle::Prefix => self.with_prefix(&uri_encode(&self.below_root(file))),
+        }
+    }
+
+    fn below_root(&self, file: &Path) -> String {
+        let file = absolute(file);
+        file.strip_prefix(&self.root).unwrap_or(&file).display().to_string()
+    }
+
+    fn with_prefix(&self, path: &str) -> String {
+        let prefix = &self.config.path_prefix;
+        if prefix.is_empty() || prefix.ends_with(['/', '\\']) {
+            format!("{}{}", prefix, path)
+        } else {
+            format!("{}/{}", prefix, path)
+        }
+    }
+}
+
+/// Calculate relative path from one directory to a file
+fn relative_path_from(from_dir: &Path, to_file: &Path) -> PathBuf {
+    // Canonicalize both paths to get absolute paths
+    let from_abs = std::fs::canonicalize(from_dir).unwrap_or_else(|_| from_dir.to_path_buf());
+    let to_abs = std::fs::canonicalize(to_file).unwrap_or_else(|_| to_file.to_path_buf());
+
+    // Find common ancestor
+    let from_parts: Vec<_> = from_abs.components().collect();
+    let to_parts: Vec<_> = to_abs.components().collect();
+
+    let mut common_len = 0;
+    for (a, b) in from_parts.iter().zip(to_parts.iter()) {
+        if a == b {
+            common_len += 1;
+        } else {
+            break;
+        }
+    }
+
+    // Build relative path: go up from 'from_dir' then down to 'to_file'
+    let mut result = PathBuf::new();
+    for _ in common_len..from_parts.len() {
+        result.push("..");
+    }
+    for part in &to_parts[common_len..] {
+        result.push(part);
+    }
+
+    result
+}
+
+/// Absolute form of a path, resolving symlinks when it exists
+fn absolute(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn common_ancestor(a: &Path, b: &Path) -> PathBuf {
+    a.components()
+        .zip(b.components())
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x)
+        .collect()
+}
+
+/// Percent-encode a path for use in a URI, keeping the `/` separators
+fn uri_encode(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => out.push(byte as char),
+            _ => {
+                let _ = write!(out, "%{:02X}", byte);
+            }
+        }
+    }
+    out
+}
+
+/// Undo percent-encoding in a URI path
+fn uri_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracks() -> Vec<PlaylistTrack> {
+        vec![
+            PlaylistTrack {
+                path: PathBuf::from("/lib/music/Björk/Post/01 - Army of Me.flac"),
+                artist: Some("Björk".to_string()),
+                title: Some("Army of Me".to_string()),
+                album: Some("Post".to_string()),
+                duration_secs: Some(234),
+                cover: Some(PathBuf::from("/lib/music/Björk/Post/cover.jpg")),
+            },
+            PlaylistTrack {
+                path: PathBuf::from("/lib/music/Unknown/02 - Untitled.mp3"),
+                ..PlaylistTrack::default()
+            },
+        ]
+    }
+
+    fn render(format: PlaylistFormat, config: &PlaylistConfig) -> String {
+        let tracks = tracks();
+        let paths = PathWriter::new(Path::new("/lib/playlists"), &tracks, config);
+        writer(format).render("Mix", &tracks, &paths, config)
+    }
+
+    #[test]
+    fn test_render_m3u8() {
+        let config = PlaylistConfig {
+            extimg: true,
+            ..PlaylistConfig::default()
+        };
+        assert_eq!(
+            render(PlaylistFormat::M3u8, &config),
+            "#EXTM3U\n#PLAYLIST:Mix\n\
+             #EXTINF:234,Björk - Army of Me\n\
+             #EXTIMG:../music/Björk/Post/cover.jpg\n\
+             ../music/Björk/Post/01 - Army of Me.flac\n\
+             #EXTINF:-1,02 - Untitled\n\
+             ../music/Unknown/02 - Untitled.mp3\n"
+        );
+
+        let config = PlaylistConfig {
+            path_style: PathStyle::Prefix,
+            path_prefix: "E:\\".to_string(),
+            backslashes: true,
+            playlist_name: false,
+            ..PlaylistConfig::default()
+        };
+        let out = render(PlaylistFormat::M3u8, &config);
+        assert!(out.starts_with("#EXTM3U\n#EXTINF:234,"));
+        assert!(out.contains("\nE:\\music\\Björk\\Post\\01 - Army of Me.flac\n"));
+
+        let config = PlaylistConfig {
+            path_style: PathStyle::Absolute,
+            ..PlaylistConfig::default()
+        };
+        assert!(render(PlaylistFormat::M3u8, &config).contains("\n/lib/music/Unknown/02 - Untitled.mp3\n"));
+    }
+
+    #[test]
+    fn test_render_other_formats() {
+        let config = PlaylistConfig::default();
+        assert_eq!(
+            render(PlaylistFormat::Pls, &config),
+            "[playlist]\n\
+             File1=../music/Björk/Post/01 - Army of Me.flac\nTitle1=Björk - Army of Me\nLength1=234\n\
+             File2=../music/Unknown/02 - Untitled.mp3\nTitle2=02 - Untitled\nLength2=-1\n\
+             NumberOfEntries=2\nVersion=2\n"
+        );
+
+        let xspf = render(PlaylistFormat::Xspf, &config);
+        assert!(xspf.contains("<location>../music/Bj%C3%B6rk/Post/01%20-%20Army%20of%20Me.flac</location>"));
+        assert!(xspf.contains("<creator>Björk</creator>\n      <album>Post</album>\n      <duration>234000</duration>"));
+
+        let config = PlaylistConfig {
+            path_style: PathStyle::Absolute,
+            ..PlaylistConfig::default()
+        };
+        let jspf: serde_json::Value = serde_json::from_str(&render(PlaylistFormat::Jspf, &config)).unwrap();
+        let first = &jspf["playlist"]["track"][0];
+        assert_eq!(first["location"][0], "file:///lib/music/Bj%C3%B6rk/Post/01%20-%20Army%20of%20Me.flac");
+        assert_eq!(first["duration"], 234000);
+        assert!(jspf["playlist"]["track"][1].get("title").is_none());
+
+        assert_eq!(PlaylistFormat::parse("M3U"), Some(PlaylistFormat::M3u8));
+        assert_eq!(PlaylistFormat::parse("wpl"), None);
+    }
+
+    #[test]
+    fn test_parse_playlists() {
+        let m3u = parse_m3u(
+            "\u{feff}#EXTM3U\n#EXTINF:234,Björk - Army of Me\nMusic\\Björk\\Army of Me.mp3\n\n# comment\nfile:///lib/A%20B.flac\n#EXTINF:-1,Radio\nhttp://stream.example/live\n",
+        );
+        assert_eq!(m3u.len(), 3);
+        assert_eq!(m3u[0].title.as_deref(), Some("Björk - Army of Me"));
+        assert_eq!(m3u[0].duration_secs, Some(234));
+        assert_eq!(m3u[0].path(Path::new("/pl")), Some(PathBuf::from("/pl/Music/Björk/Army of Me.mp3")));
+        assert_eq!(m3u[1].title, None);
+        assert_eq!(m3u[1].path(Path::new("/pl")), Some(PathBuf::from("/lib/A B.flac")));
+        assert_eq!(m3u[2].duration_secs, None);
+        assert_eq!(m3u[2].path(Path::new("/pl")), None);
+
+        let pls = parse_pls(
+            "[playlist]\nFile2=b.mp3\nTitle2=B - Two\nFile1=C:\\Music\\a.mp3\nLength1=61\nNumberOfEntries=2\nVersion=2\n",
+        );
+        assert_eq!(pls.len(), 2);
+        assert_eq!(pls[0].location, "C:\\Music\\a.mp3");
+        assert_eq!(pls[0].duration_secs, Some(61));
+        assert!(pls[0].path(Path::new("/pl")).is_some());
+        assert_eq!(pls[1].title.as_deref(), Some("B - Two"));
+    }
+}
@@ -0,0 +1,297 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::PlaylistFormat;
+use crate::db::{DownloadDB, TrackEntry};
+use crate::playlist::{self, PlaylistEntry};
+use crate::sync::TrackedPlaylists;
+use crate::{converter, file_utils, import};
+
+/// Default location of the playlist index
+pub const INDEX_FILE: &str = "data/cache/playlist_index.json";
+
+/// A track as referenced by a written playlist
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexedTrack {
+    /// Download cache ID; unset for files that aren't in the cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The file when the playlist was written
+    pub path: String,
+}
+
+/// A playlist rustwav wrote, with the tracks it references
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexedPlaylist {
+    pub name: String,
+    /// Folder the playlist files are in
+    pub dir: PathBuf,
+    pub formats: Vec<PlaylistFormat>,
+    pub tracks: Vec<IndexedTrack>,
+}
+
+/// How a playlist's tracks changed in the library since it was written
+#[derive(Debug, Default, PartialEq)]
+pub struct PlaylistChange {
+    pub name: String,
+    /// The playlist's files as they are now
+    pub paths: Vec<PathBuf>,
+    /// Tracks whose file moved, e.g. by a conversion
+    pub moved: usize,
+    /// Files of tracks that left the library
+    pub removed: Vec<String>,
+}
+
+impl IndexedPlaylist {
+    /// Follow the tracks through the download cache by ID. `None` if every
+    /// track is still where the playlist says.
+    pub fn follow(&self, db: &DownloadDB) -> Option<PlaylistChange> {
+        let mut change = PlaylistChange {
+            name: self.name.clone(),
+            ..PlaylistChange::default()
+        };
+        for track in &self.tracks {
+            match track.id.as_deref().map(|id| db.find_by_id(id)) {
+                Some(Some(entry)) => {
+                    if entry.path != track.path {
+                        change.moved += 1;
+                    }
+                    change.paths.push(PathBuf::from(&entry.path));
+                }
+                // Gone from the cache; a file that's still there is kept, as
+                // the cache may have been loaded before the track was added
+                Some(None) if !Path::new(&track.path).exists() => change.removed.push(track.path.clone()),
+                _ => change.paths.push(PathBuf::from(&track.path)),
+            }
+        }
+        (change.moved > 0 || !change.removed.is_empty()).then_some(change)
+    }
+}
+
+/// Playlists written to the library, so they can follow their tracks
+pub struct PlaylistIndex {
+    path: PathBuf,
+    playlists: Vec<IndexedPlaylist>,
+}
+
+impl PlaylistIndex {
+    pub fn new(path: &str) -> Self {
+        let playlists = fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path: PathBuf::from(path),
+            playlists,
+        }
+    }
+
+    /// Open the default playlist index
+    pub fn open() -> Self {
+        Self::new(INDEX_FILE)
+    }
+
+    pub fn playlists(&self) -> &[IndexedPlaylist] {
+        &self.playlists
+    }
+
+    /// Store the tracks of a written playlist. Formats written earlier are
+    /// kept, since their files are still there.
+    pub fn record(
+        &mut self,
+        name: &str,
+        dir: &Path,
+        formats: &[PlaylistFormat],
+        tracks: &[PathBuf],
+        db: Option<&DownloadDB>,
+    ) -> anyhow::Result<()> {
+        let tracks = tracks
+            .iter()
+            .map(|path| {
+                let path = path.display().to_string();
+                IndexedTrack {
+                    id: db.and_then(|db| db.find_by_path(&path)).map(|e| e.id.clone()),
+                    path,
+                }
+            })
+            .collect();
+        let mut playlist = IndexedPlaylist {
+            name: name.to_string(),
+            dir: dir.to_path_buf(),
+            formats: formats.to_vec(),
+            tracks,
+        };
+        if let Some(i) = self.playlists.iter().position(|p| p.name == name && p.dir == dir) {
+            let previous = self.playlists.remove(i);
+            playlist
+                .formats
+                .extend(previous.formats.into_iter().filter(|f| !formats.contains(f)));
+        }
+        self.playlists.push(playlist);
+        self.save()
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(&self.playlists)?;
+        fs::write(&self.path, json).context("writing playlist index")
+    }
+}
+
+/// Rewrite indexed playlists whose tracks were converted, moved or removed
+/// from the library, and follow those tracks in tracked playlists. Returns
+/// what changed in each rewritten playlist.
+pub fn update_playlists(db: &DownloadDB) -> anyhow::Result<Vec<PlaylistChange>> {
+    let index = PlaylistIndex::open();
+    let mut changes = Vec::new();
+    for indexed in index.playlists() {
+        let Some(change) = indexed.follow(db) else {
+            continue;
+        };
+        // Writing records the new paths in the index
+        file_utils::create_playlists(&indexed.name, &change.paths, &indexed.dir, Some(db), &indexed.formats)?;
+        changes.push(change);
+    }
+    // Tracked playlists keep their own track list for syncing
+    TrackedPlaylists::open().follow(db)?;
+    Ok(changes)
+}
+
+/// A playlist file with entries whose files don't exist
+#[derive(Debug)]
+pub struct PlaylistCheck {
+    pub file: PathBuf,
+    pub entries: Vec<PlaylistEntry>,
+    /// Index of each broken entry, with the library file it most likely
+    /// meant if one was found
+    pub broken: Vec<(usize, Option<PathBuf>)>,
+}
+
+impl PlaylistCheck {
+    /// Whether the playlist has stream URLs, which a rewrite would drop
+    pub fn has_streams(&self) -> bool {
+        let dir = self.file.parent().unwrap_or(Path::new("."));
+        self.entries.iter().any(|e| e.path(dir).is_none())
+    }
+
+    /// Rewrite the playlist file with broken entries replaced by their
+    /// matches; entries without one are left out
+    pub fn repair(&self, db: &DownloadDB) -> anyhow::Result<()> {
+        let dir = self.file.parent().unwrap_or(Path::new("."));
+        let paths: Vec<PathBuf> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| match self.broken.iter().find(|(b, _)| *b == i) {
+                Some((_, fix)) => fix.clone(),
+                None => entry.path(dir),
+            })
+            .collect();
+        playlist::rewrite_playlist(&self.file, &paths, Some(db))
+    }
+}
+
+/// Check the M3U/M3U8 and PLS files in a folder for entries whose files are
+/// gone, looking for a converted copy next to each one, then in the library
+/// like an import would. Returns the playlists that have broken entries.
+pub fn check(playlist_dir: &Path, db: &DownloadDB) -> anyhow::Result<Vec<PlaylistCheck>> {
+    let mut files: Vec<PathBuf> = match fs::read_dir(playlist_dir) {
+        Ok(dir) => dir.filter_map(|e| e.ok().map(|e| e.path())).collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", playlist_dir.display())),
+    };
+    files.retain(|f| {
+        f.extension()
+            .is_some_and(|ext| ["m3u", "m3u8", "pls"].iter().any(|e| ext.eq_ignore_ascii_case(e)))
+    });
+    files.sort();
+
+    let library: Vec<TrackEntry> = db.tracks.iter().filter(|t| Path::new(&t.path).exists()).cloned().collect();
+    let mut checks = Vec::new();
+    for file in files {
+        let entries = playlist::read_playlist(&file)?;
+        let broken: Vec<(usize, Option<PathBuf>)> = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.path(playlist_dir).is_some_and(|p| !p.exists()))
+            .map(|(i, entry)| {
+                let fix = entry
+                    .path(playlist_dir)
+                    .and_then(|path| converted_copy(&path))
+                    .or_else(|| import::resolve(entry, playlist_dir, &library).map(|r| r.path));
+                (i, fix)
+            })
+            .collect();
+        if !broken.is_empty() {
+            checks.push(PlaylistCheck { file, entries, broken });
+        }
+    }
+    Ok(checks)
+}
+
+/// The file converted from a missing one: same folder and name, another
+/// audio format
+fn converted_copy(path: &Path) -> Option<PathBuf> {
+    converter::SUPPORTED_FORMATS
+        .iter()
+        .map(|format| path.with_extension(converter::format_extension(format)))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, path: &str) -> TrackEntry {
+        TrackEntry {
+            id: id.to_string(),
+            artist: "Artist".to_string(),
+            title: id.to_string(),
+            path: path.to_string(),
+            source: None,
+            trims: Vec::new(),
+            variants: Vec::new(),
+            duration_secs: None,
+        }
+    }
+
+    fn indexed(id: Option<&str>, path: &str) -> IndexedTrack {
+        IndexedTrack {
+            id: id.map(str::to_string),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_follow() {
+        let mut db = DownloadDB::new("/nonexistent/downloaded_songs.json");
+        db.tracks.insert(entry("a", "/lib/a.flac"));
+        db.tracks.insert(entry("b", "/lib/b.mp3"));
+        let mut playlist = IndexedPlaylist {
+            name: "Mix".to_string(),
+            dir: PathBuf::from("/lib/playlists"),
+            formats: vec![PlaylistFormat::M3u8],
+            tracks: vec![
+                indexed(Some("a"), "/lib/a.mp3"),
+                indexed(None, "/elsewhere/x.mp3"),
+                indexed(Some("gone"), "/lib/gone.mp3"),
+                indexed(Some("b"), "/lib/b.mp3"),
+            ],
+        };
+        let change = playlist.follow(&db).unwrap();
+        assert_eq!(change.moved, 1);
+        assert_eq!(change.removed, vec!["/lib/gone.mp3"]);
+        assert_eq!(
+            change.paths,
+            vec![PathBuf::from("/lib/a.flac"), PathBuf::from("/elsewhere/x.mp3"), PathBuf::from("/lib/b.mp3")]
+        );
+
+        playlist.tracks.truncate(2);
+        playlist.tracks[0].path = "/lib/a.flac".to_string();
+        assert_eq!(playlist.follow(&db), None);
+    }
+}
@@ -0,0 +1,272 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rspotify::model::PlayableItem;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::db::TrackEntry;
+use crate::import;
+use crate::sources::spotify;
+
+/// Default location of the stored playlist and album sources
+pub const SOURCES_FILE: &str = "data/cache/playlist_sources.json";
+
+/// What a source link points at
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    Album,
+    Playlist,
+}
+
+impl SourceKind {
+    /// Kind of a Spotify link; `None` for anything but albums and playlists
+    pub fn of(link: &str) -> Option<Self> {
+        if link.contains("/album/") || link.starts_with("spotify:album:") {
+            Some(SourceKind::Album)
+        } else if link.contains("/playlist/") || link.starts_with("spotify:playlist:") {
+            Some(SourceKind::Playlist)
+        } else {
+            None
+        }
+    }
+
+    /// Name as used for commands and error log link types
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SourceKind::Album => "album",
+            SourceKind::Playlist => "playlist",
+        }
+    }
+}
+
+/// A Spotify album or playlist that was downloaded or had a playlist
+/// generated, with the settings it was downloaded with
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlaylistSource {
+    pub link: String,
+    pub kind: SourceKind,
+    pub name: String,
+    pub format: String,
+    pub quality: String,
+    #[serde(default)]
+    pub portable: bool,
+    /// Minimum source bitrate the download asked for with `--require-quality`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub require_quality: Option<u32>,
+    pub recorded_at: DateTime<Utc>,
+    /// Library files of an album as of its last download, so sync cleanups
+    /// leave them alone. Playlists keep theirs in the playlist index.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+}
+
+impl PlaylistSource {
+    pub fn new(link: &str, kind: SourceKind, name: &str, format: &str, quality: &str, portable: bool) -> Self {
+        Self {
+            link: link.to_string(),
+            kind,
+            name: name.to_string(),
+            format: format.to_string(),
+            quality: quality.to_string(),
+            portable,
+            require_quality: None,
+            recorded_at: Utc::now(),
+            paths: Vec::new(),
+        }
+    }
+}
+
+/// Sources of every album and playlist job, for regenerating their playlists
+pub struct PlaylistSources {
+    path: PathBuf,
+    sources: Vec<PlaylistSource>,
+}
+
+impl PlaylistSources {
+    pub fn new(path: &str) -> Self {
+        let sources = fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path: PathBuf::from(path),
+            sources,
+        }
+    }
+
+    /// Open the default source list
+    pub fn open() -> Self {
+        Self::new(SOURCES_FILE)
+    }
+
+    pub fn sources(&self) -> &[PlaylistSource] {
+        &self.sources
+    }
+
+    /// Store a source, replacing an earlier one with the same link. The
+    /// files of the earlier one are kept until `record_paths` replaces them.
+    pub fn record(&mut self, mut source: PlaylistSource) -> anyhow::Result<()> {
+        match self.sources.iter_mut().find(|s| s.link == source.link) {
+            Some(existing) => {
+                if source.paths.is_empty() {
+                    source.paths = std::mem::take(&mut existing.paths);
+                }
+                *existing = source;
+            }
+            None => self.sources.push(source),
+        }
+        self.save()
+    }
+
+    /// Store the library files of a downloaded source
+    pub fn record_paths(&mut self, link: &str, paths: &[PathBuf]) -> anyhow::Result<()> {
+        if let Some(source) = self.sources.iter_mut().find(|s| s.link == link) {
+            source.paths = paths.iter().map(|p| p.display().to_string()).collect();
+        }
+        self.save()
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(&self.sources)?;
+        fs::write(&self.path, json).context("writing playlist sources")
+    }
+}
+
+/// A track as Spotify lists it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceTrack {
+    pub artist: String,
+    pub title: String,
+    pub duration_secs: Option<u64>,
+}
+
+/// The tracks of an album or playlist as they are now
+pub struct SourceTracks {
+    /// Playlist name, or "Artist - Album"
+    pub name: String,
+    /// Album artist and title, for the folder missing album tracks go to
+    pub album: Option<(String, String)>,
+    pub tracks: Vec<SourceTrack>,
+}
+
+/// Fetch the track list of a Spotify album or playlist
+pub async fn fetch_tracks(link: &str) -> anyhow::Result<SourceTracks> {
+    match SourceKind::of(link) {
+        Some(SourceKind::Album) => {
+            let album = spotify::fetch_album(link).await?;
+            let main_artist = album
+                .artists
+                .first()
+                .map(|a| a.name.clone())
+                .unwrap_or_else(|| "Unknown".to_string());
+            let tracks = spotify::fetch_all_album_tracks(link)
+                .await?
+                .iter()
+                .map(|t| SourceTrack {
+                    artist: t.artists.first().map(|a| a.name.clone()).unwrap_or_else(|| main_artist.clone()),
+                    title: t.name.clone(),
+                    duration_secs: Some(t.duration.num_seconds() as u64),
+                })
+                .collect();
+            Ok(SourceTracks {
+                name: format!("{} - {}", main_artist, album.name),
+                album: Some((main_artist, album.name.clone())),
+                tracks,
+            })
+        }
+        Some(SourceKind::Playlist) => {
+            let playlist = spotify::fetch_playlist(link).await?;
+            let items = spotify::fetch_all_playlist_items(link).await?;
+            let tracks = items
+                .iter()
+                .filter_map(|item| match &item.track {
+                    Some(PlayableItem::Track(t)) => Some(SourceTrack {
+                        artist: t.artists.first().map(|a| a.name.clone()).unwrap_or_else(|| "Unknown".to_string()),
+                        title: t.name.clone(),
+                        duration_secs: Some(t.duration.num_seconds() as u64),
+                    }),
+                    // Episodes are stored in the DB under their show name
+                    Some(PlayableItem::Episode(e)) => Some(SourceTrack {
+                        artist: e.show.name.clone(),
+                        title: e.name.clone(),
+                        duration_secs: Some(e.duration.num_seconds() as u64),
+                    }),
+                    None => None,
+                })
+                .collect();
+            Ok(SourceTracks {
+                name: playlist.name,
+                album: None,
+                tracks,
+            })
+        }
+        None => anyhow::bail!("Invalid Spotify link (must be album or playlist)"),
+    }
+}
+
+/// The library file for each track, in order, matching names the way
+/// imports do: ignoring case, punctuation, featured artists and remaster
+/// notes. `library` is the download cache's tracks whose files exist.
+pub fn match_tracks(tracks: &[SourceTrack], library: &[TrackEntry]) -> Vec<Option<PathBuf>> {
+    tracks
+        .iter()
+        .map(|t| import::find_track(&t.artist, &t.title, library).map(|(entry, _)| PathBuf::from(&entry.path)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(artist: &str, title: &str) -> SourceTrack {
+        SourceTrack {
+            artist: artist.to_string(),
+            title: title.to_string(),
+            duration_secs: None,
+        }
+    }
+
+    fn entry(artist: &str, title: &str, path: &str) -> TrackEntry {
+        TrackEntry {
+            id: String::new(),
+            artist: artist.to_string(),
+            title: title.to_string(),
+            path: path.to_string(),
+            source: None,
+            trims: Vec::new(),
+            variants: Vec::new(),
+            duration_secs: None,
+        }
+    }
+
+    #[test]
+    fn test_match_tracks() {
+        let library = vec![
+            entry("The Beatles", "Let It Be", "/lib/let-it-be.flac"),
+            entry("Daft Punk", "Get Lucky (feat. Pharrell Williams)", "/lib/get-lucky.mp3"),
+            entry("Simon & Garfunkel", "Mrs. Robinson", "/lib/mrs-robinson.mp3"),
+        ];
+        let tracks = vec![
+            track("The Beatles", "Let It Be - Remastered 2009"),
+            track("Daft Punk", "Get Lucky"),
+            track("Simon and Garfunkel", "Mrs Robinson"),
+            track("Radiohead", "Creep"),
+        ];
+        assert_eq!(
+            match_tracks(&tracks, &library),
+            vec![
+                Some(PathBuf::from("/lib/let-it-be.flac")),
+                Some(PathBuf::from("/lib/get-lucky.mp3")),
+                Some(PathBuf::from("/lib/mrs-robinson.mp3")),
+                None,
+            ]
+        );
+        assert_eq!(SourceKind::of("https://open.spotify.com/album/abc?si=1"), Some(SourceKind::Album));
+        assert_eq!(SourceKind::of("https://youtube.com/playlist?list=x"), None);
+    }
+}
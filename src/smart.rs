@@ -0,0 +1,473 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::config::Config;
+use crate::db::{DownloadDB, TrackEntry};
+use crate::{converter, file_utils, metadata};
+
+/// Default location of the smart playlist definitions
+pub const SMART_FILE: &str = "data/smart_playlists.json";
+
+/// Folder below the playlist folder smart playlists are written to, so they
+/// can't overwrite downloaded playlists of the same name
+pub const SMART_DIR: &str = "smart";
+
+/// Order of a smart playlist's tracks
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmartSort {
+    #[default]
+    Artist,
+    Title,
+    Year,
+    Added,
+    Duration,
+    Rating,
+}
+
+impl SmartSort {
+    const ALL: [(SmartSort, &'static str); 6] = [
+        (SmartSort::Artist, "artist"),
+        (SmartSort::Title, "title"),
+        (SmartSort::Year, "year"),
+        (SmartSort::Added, "added"),
+        (SmartSort::Duration, "duration"),
+        (SmartSort::Rating, "rating"),
+    ];
+
+    fn name(self) -> &'static str {
+        Self::ALL.iter().find(|(sort, _)| *sort == self).map_or("artist", |(_, name)| name)
+    }
+}
+
+/// A playlist built from library tracks matching a set of rules. Empty
+/// rules match everything.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SmartPlaylist {
+    pub name: String,
+    /// Any of these genres (case-insensitive)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub genres: Vec<String>,
+    /// Any of these artists (case-insensitive)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub artists: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year_from: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year_to: Option<i32>,
+    /// Files added to the library within this many days
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub added_within_days: Option<u32>,
+    /// File extensions, e.g. "flac"
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub formats: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_duration_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_duration_secs: Option<u64>,
+    /// Minimum star rating (1-5)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_rating: Option<u8>,
+    pub sort: SmartSort,
+    pub descending: bool,
+    /// Keep at most this many tracks, after sorting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// What a smart playlist rule looks at in a library track
+#[derive(Debug, Clone, Default)]
+pub struct LibraryTrack {
+    pub path: PathBuf,
+    pub artist: String,
+    pub title: String,
+    pub genre: Option<String>,
+    pub year: Option<i32>,
+    pub added: Option<SystemTime>,
+    pub duration_secs: Option<u64>,
+    pub rating: Option<u8>,
+}
+
+impl LibraryTrack {
+    /// Details of a library file from its tags, falling back to its download
+    /// cache entry. A duration the cache doesn't have is only probed when
+    /// asked for, since it takes an FFmpeg run per file.
+    pub fn read(entry: &TrackEntry, probe_duration: bool) -> Self {
+        let path = Path::new(&entry.path);
+        let tags = metadata::read_tags(path).unwrap_or_default();
+        // Creation time survives retagging; not every filesystem records it
+        let added = fs::metadata(path).ok().and_then(|m| m.created().or_else(|_| m.modified()).ok());
+        let duration_secs = entry.duration_secs.or_else(|| {
+            probe_duration
+                .then(|| converter::probe_duration(path))
+                .flatten()
+                .map(|secs| secs.round() as u64)
+        });
+        Self {
+            path: path.to_path_buf(),
+            artist: tags.artist.unwrap_or_else(|| entry.artist.clone()),
+            title: tags.title.unwrap_or_else(|| entry.title.clone()),
+            genre: tags.genre,
+            year: tags.year,
+            added,
+            duration_secs,
+            rating: tags.rating,
+        }
+    }
+}
+
+impl SmartPlaylist {
+    /// Parse rules written as `key=value` pairs separated by `;`, e.g.
+    /// `genre=ambient,chill; year=1990-1999; added=30; limit=50; sort=-added`
+    pub fn parse(name: &str, rules: &str) -> anyhow::Result<Self> {
+        let mut playlist = SmartPlaylist {
+            name: name.trim().to_string(),
+            ..SmartPlaylist::default()
+        };
+        if playlist.name.is_empty() {
+            anyhow::bail!("A smart playlist needs a name");
+        }
+        let list = |value: &str| -> Vec<String> {
+            value.split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect()
+        };
+        for rule in rules.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+            let (key, value) = rule
+                .split_once('=')
+                .with_context(|| format!("Expected key=value, got '{}'", rule))?;
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "genre" => playlist.genres = list(value),
+                "artist" => playlist.artists = list(value),
+                "format" => playlist.formats = list(&value.to_lowercase()),
+                "year" => (playlist.year_from, playlist.year_to) = parse_range(value)?,
+                "duration" => (playlist.min_duration_secs, playlist.max_duration_secs) = parse_range(value)?,
+                "added" => playlist.added_within_days = Some(value.trim_end_matches('d').parse()?),
+                "rating" => {
+                    let rating: u8 = value.trim_end_matches('+').parse()?;
+                    if !(1..=5).contains(&rating) {
+                        anyhow::bail!("Rating must be 1 to 5 stars");
+                    }
+                    playlist.min_rating = Some(rating);
+                }
+                "limit" => playlist.limit = Some(value.parse()?),
+                "sort" => {
+                    let (descending, field) = match value.strip_prefix('-') {
+                        Some(field) => (true, field),
+                        None => (false, value),
+                    };
+                    playlist.descending = descending;
+                    playlist.sort = SmartSort::ALL
+                        .iter()
+                        .find(|(_, name)| name.eq_ignore_ascii_case(field))
+                        .map(|(sort, _)| *sort)
+                        .with_context(|| format!("Unknown sort '{}' (artist, title, year, added, duration, rating)", field))?;
+                }
+                other => anyhow::bail!(
+                    "Unknown rule '{}' (genre, artist, year, added, format, duration, rating, limit, sort)",
+                    other
+                ),
+            }
+        }
+        Ok(playlist)
+    }
+
+    /// The rules in the form `parse` reads
+    pub fn rules(&self) -> String {
+        let range = |from: Option<String>, to: Option<String>| format!("{}-{}", from.unwrap_or_default(), to.unwrap_or_default());
+        let mut rules = Vec::new();
+        if !self.genres.is_empty() {
+            rules.push(format!("genre={}", self.genres.join(",")));
+        }
+        if !self.artists.is_empty() {
+            rules.push(format!("artist={}", self.artists.join(",")));
+        }
+        if self.year_from.is_some() || self.year_to.is_some() {
+            rules.push(format!("year={}", range(self.year_from.map(|y| y.to_string()), self.year_to.map(|y| y.to_string()))));
+        }
+        if let Some(days) = self.added_within_days {
+            rules.push(format!("added={}", days));
+        }
+        if !self.formats.is_empty() {
+            rules.push(format!("format={}", self.formats.join(",")));
+        }
+        if self.min_duration_secs.is_some() || self.max_duration_secs.is_some() {
+            rules.push(format!(
+                "duration={}",
+                range(self.min_duration_secs.map(|d| d.to_string()), self.max_duration_secs.map(|d| d.to_string()))
+            ));
+        }
+        if let Some(rating) = self.min_rating {
+            rules.push(format!("rating={}", rating));
+        }
+        if let Some(limit) = self.limit {
+            rules.push(format!("limit={}", limit));
+        }
+        if self.sort != SmartSort::Artist || self.descending {
+            rules.push(format!("sort={}{}", if self.descending { "-" } else { "" }, self.sort.name()));
+        }
+        rules.join("; ")
+    }
+
+    /// Whether probing durations is needed to evaluate the playlist
+    pub fn needs_duration(&self) -> bool {
+        self.min_duration_secs.is_some() || self.max_duration_secs.is_some() || self.sort == SmartSort::Duration
+    }
+
+    pub fn matches(&self, track: &LibraryTrack, now: SystemTime) -> bool {
+        let any_of = |wanted: &[String], actual: Option<&str>| {
+            wanted.is_empty() || actual.is_some_and(|a| wanted.iter().any(|w| w.trim().eq_ignore_ascii_case(a.trim())))
+        };
+        let extension = track.path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        let in_range = |value: Option<u64>, min: Option<u64>, max: Option<u64>| {
+            (min.is_none() && max.is_none())
+                || value.is_some_and(|v| min.is_none_or(|min| v >= min) && max.is_none_or(|max| v <= max))
+        };
+        // Genre tags may list several genres
+        let genres: Vec<&str> = track.genre.as_deref().map(|g| g.split([';', '/', ',']).collect()).unwrap_or_default();
+
+        (self.genres.is_empty() || genres.iter().any(|g| any_of(&self.genres, Some(g))))
+            && any_of(&self.artists, Some(&track.artist))
+            && any_of(&self.formats, extension.as_deref())
+            && (self.year_from.is_none() && self.year_to.is_none()
+                || track.year.is_some_and(|y| {
+                    self.year_from.is_none_or(|from| y >= from) && self.year_to.is_none_or(|to| y <= to)
+                }))
+            && self.added_within_days.is_none_or(|days| {
+                let window = Duration::from_secs(u64::from(days) * 24 * 60 * 60);
+                track.added.is_some_and(|added| now.duration_since(added).map_or(true, |age| age <= window))
+            })
+            && in_range(track.duration_secs, self.min_duration_secs, self.max_duration_secs)
+            && self.min_rating.is_none_or(|min| track.rating.is_some_and(|r| r >= min))
+    }
+
+    /// The matching tracks, sorted and limited
+    pub fn evaluate(&self, library: &[LibraryTrack], now: SystemTime) -> Vec<PathBuf> {
+        let mut tracks: Vec<&LibraryTrack> = library.iter().filter(|t| self.matches(t, now)).collect();
+        let lower = |s: &str| s.to_lowercase();
+        tracks.sort_by(|a, b| {
+            // Tracks without a value go last either way
+            let ordering = match self.sort {
+                SmartSort::Artist => lower(&a.artist).cmp(&lower(&b.artist)).then(lower(&a.title).cmp(&lower(&b.title))),
+                SmartSort::Title => lower(&a.title).cmp(&lower(&b.title)),
+                SmartSort::Year => compare_present(a.year, b.year, self.descending),
+                SmartSort::Added => compare_present(a.added, b.added, self.descending),
+                SmartSort::Duration => compare_present(a.duration_secs, b.duration_secs, self.descending),
+                SmartSort::Rating => compare_present(a.rating, b.rating, self.descending),
+            };
+            match self.sort {
+                SmartSort::Artist | SmartSort::Title if self.descending => ordering.reverse(),
+                _ => ordering,
+            }
+        });
+        tracks
+            .into_iter()
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|t| t.path.clone())
+            .collect()
+    }
+}
+
+/// Order optional values, keeping missing ones at the end
+fn compare_present<T: Ord>(a: Option<T>, b: Option<T>, descending: bool) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    match (a, b) {
+        (Some(a), Some(b)) if descending => b.cmp(&a),
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// "1990-1999", "1990-", "-1999" or "1995"
+fn parse_range<T: std::str::FromStr>(value: &str) -> anyhow::Result<(Option<T>, Option<T>)>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let bound = |s: &str| -> anyhow::Result<Option<T>> {
+        let s = s.trim();
+        Ok(if s.is_empty() { None } else { Some(s.parse()?) })
+    };
+    match value.split_once('-') {
+        Some((from, to)) => Ok((bound(from)?, bound(to)?)),
+        None => Ok((bound(value)?, bound(value)?)),
+    }
+}
+
+/// Outcome of regenerating smart playlists
+#[derive(Debug, Default)]
+pub struct Regenerated {
+    /// Each written playlist's name and size
+    pub written: Vec<(String, usize)>,
+    /// Durations probed for tracks the download cache had none for, by path
+    pub durations: HashMap<String, u64>,
+}
+
+/// Saved smart playlist definitions
+pub struct SmartPlaylists {
+    path: PathBuf,
+    playlists: Vec<SmartPlaylist>,
+}
+
+impl SmartPlaylists {
+    pub fn new(path: &str) -> Self {
+        let playlists = fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path: PathBuf::from(path),
+            playlists,
+        }
+    }
+
+    /// Open the default smart playlist definitions
+    pub fn open() -> Self {
+        Self::new(SMART_FILE)
+    }
+
+    pub fn playlists(&self) -> &[SmartPlaylist] {
+        &self.playlists
+    }
+
+    pub fn find(&self, name: &str) -> Option<&SmartPlaylist> {
+        self.playlists.iter().find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Add a definition, replacing one with the same name
+    pub fn save_playlist(&mut self, playlist: SmartPlaylist) -> anyhow::Result<()> {
+        match self.playlists.iter_mut().find(|p| p.name.eq_ignore_ascii_case(&playlist.name)) {
+            Some(existing) => *existing = playlist,
+            None => self.playlists.push(playlist),
+        }
+        self.save()
+    }
+
+    /// Delete a definition; the playlist files it wrote are left alone
+    pub fn remove(&mut self, name: &str) -> anyhow::Result<Option<SmartPlaylist>> {
+        let index = self.playlists.iter().position(|p| p.name.eq_ignore_ascii_case(name.trim()));
+        let removed = index.map(|i| self.playlists.remove(i));
+        self.save()?;
+        Ok(removed)
+    }
+
+    /// Evaluate smart playlists against the library and write them with the
+    /// configured playlist formats to the `smart` folder below `playlist_dir`.
+    /// Durations it had to probe are returned for the caller to store in the
+    /// download cache.
+    pub fn regenerate(&self, only: Option<&str>, db: &DownloadDB, playlist_dir: &Path) -> anyhow::Result<Regenerated> {
+        let selected: Vec<&SmartPlaylist> = self
+            .playlists
+            .iter()
+            .filter(|p| only.is_none_or(|name| p.name.eq_ignore_ascii_case(name.trim())))
+            .collect();
+        let mut regenerated = Regenerated::default();
+        if selected.is_empty() {
+            return Ok(regenerated);
+        }
+
+        let probe_duration = selected.iter().any(|p| p.needs_duration());
+        let library: Vec<LibraryTrack> = db
+            .tracks
+            .iter()
+            .filter(|t| Path::new(&t.path).exists())
+            .map(|t| {
+                let track = LibraryTrack::read(t, probe_duration);
+                if let (None, Some(secs)) = (t.duration_secs, track.duration_secs) {
+                    regenerated.durations.insert(t.path.clone(), secs);
+                }
+                track
+            })
+            .collect();
+        let formats = Config::load().playlist.formats;
+        let smart_dir = playlist_dir.join(SMART_DIR);
+        let now = SystemTime::now();
+
+        for smart in selected {
+            let paths = smart.evaluate(&library, now);
+            file_utils::create_playlists(&smart.name, &paths, &smart_dir, Some(db), &formats)?;
+            regenerated.written.push((smart.name.clone(), paths.len()));
+        }
+        Ok(regenerated)
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(&self.playlists)?;
+        fs::write(&self.path, json).context("writing smart playlists")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(artist: &str, genre: &str, year: i32, days_ago: u64, rating: Option<u8>) -> LibraryTrack {
+        LibraryTrack {
+            path: PathBuf::from(format!("/lib/{} - {}.flac", artist, year)),
+            artist: artist.to_string(),
+            title: format!("Song {}", year),
+            genre: Some(genre.to_string()),
+            year: Some(year),
+            added: SystemTime::now().checked_sub(Duration::from_secs(days_ago * 24 * 60 * 60)),
+            duration_secs: None,
+            rating,
+        }
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let playlist = SmartPlaylist::parse(
+            "Chill 90s",
+            "genre=Ambient, Chill; year=1990-1999; added=30d; format=FLAC; duration=-600; rating=4+; limit=50; sort=-added",
+        )
+        .unwrap();
+        assert_eq!(playlist.genres, vec!["Ambient", "Chill"]);
+        assert_eq!((playlist.year_from, playlist.year_to), (Some(1990), Some(1999)));
+        assert_eq!(playlist.added_within_days, Some(30));
+        assert_eq!(playlist.formats, vec!["flac"]);
+        assert_eq!((playlist.min_duration_secs, playlist.max_duration_secs), (None, Some(600)));
+        assert_eq!(playlist.min_rating, Some(4));
+        assert_eq!(playlist.limit, Some(50));
+        assert_eq!((playlist.sort, playlist.descending), (SmartSort::Added, true));
+        assert!(playlist.needs_duration());
+        assert_eq!(SmartPlaylist::parse("Chill 90s", &playlist.rules()).unwrap(), playlist);
+
+        assert_eq!(SmartPlaylist::parse("Year", "year=1995").unwrap().year_to, Some(1995));
+        assert!(SmartPlaylist::parse("Bad", "mood=happy").is_err());
+        assert!(SmartPlaylist::parse("Bad", "rating=7").is_err());
+        assert!(SmartPlaylist::parse(" ", "").is_err());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let library = vec![
+            track("Boards of Canada", "Electronic; Ambient", 1998, 3, Some(5)),
+            track("Aphex Twin", "Ambient", 1994, 200, Some(4)),
+            track("Moby", "Ambient", 1999, 10, None),
+            track("Nirvana", "Grunge", 1991, 1, Some(5)),
+        ];
+        let now = SystemTime::now();
+
+        let playlist = SmartPlaylist::parse("Ambient", "genre=ambient; sort=-year").unwrap();
+        let names: Vec<_> = playlist.evaluate(&library, now).iter().map(|p| p.display().to_string()).collect();
+        assert_eq!(names, vec!["/lib/Moby - 1999.flac", "/lib/Boards of Canada - 1998.flac", "/lib/Aphex Twin - 1994.flac"]);
+
+        let playlist = SmartPlaylist::parse("Recent", "added=30; rating=4; sort=artist").unwrap();
+        assert_eq!(playlist.evaluate(&library, now).len(), 2);
+
+        let playlist = SmartPlaylist::parse("Top", "sort=-rating; limit=3").unwrap();
+        let top = playlist.evaluate(&library, now);
+        assert_eq!(top.len(), 3);
+        assert!(top[2].display().to_string().contains("Aphex Twin"));
+
+        assert!(SmartPlaylist::parse("Mp3", "format=mp3").unwrap().evaluate(&library, now).is_empty());
+    }
+}
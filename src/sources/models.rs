@@ -0,0 +1,47 @@
+/// Track metadata returned by a metadata provider (Spotify, MusicBrainz, ...)
+#[derive(Debug, Clone, Default)]
+pub struct TrackMetadata {
+    pub artist: String,
+    pub album: String,
+    pub title: String,
+    pub track_number: u32,
+    pub cover_url: Option<String>,
+    pub genre: Option<String>,
+    pub isrc: Option<String>,
+    pub year: Option<i32>,
+    /// MusicBrainz identifiers, when the provider knows them
+    pub mbids: MusicBrainzIds,
+}
+
+/// MusicBrainz identifiers for a track, written to tags so other tools
+/// (Picard, beets, players) can match the file unambiguously
+#[derive(Debug, Clone, Default)]
+pub struct MusicBrainzIds {
+    pub recording: Option<String>,
+    pub release: Option<String>,
+    pub release_group: Option<String>,
+    pub artist: Option<String>,
+}
+
+impl MusicBrainzIds {
+    pub fn is_empty(&self) -> bool {
+        self.recording.is_none()
+            && self.release.is_none()
+            && self.release_group.is_none()
+            && self.artist.is_none()
+    }
+}
+
+/// Metadata for a podcast episode and the show it belongs to
+#[derive(Debug, Clone)]
+pub struct EpisodeMetadata {
+    pub show: String,
+    pub publisher: String,
+    pub title: String,
+    pub description: String,
+    /// Release date as reported by Spotify (YYYY, YYYY-MM or YYYY-MM-DD)
+    pub release_date: String,
+    pub duration_ms: u64,
+    pub cover_url: Option<String>,
+    pub episode_url: Option<String>,
+}
@@ -0,0 +1,387 @@
+use anyhow::Context;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use super::models::{MusicBrainzIds, TrackMetadata};
+
+/// MusicBrainz asks clients to identify themselves and stay under one request per second
+const USER_AGENT: &str = concat!("rustwav/", env!("CARGO_PKG_VERSION"), " ( https://github.com/khanCurtis/rustwav )");
+const MIN_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Client for the MusicBrainz web service (`/ws/2`).
+/// The base URL is configurable so it can point at a local mirror or a mock server.
+pub struct MusicBrainzClient {
+    base_url: String,
+    client: reqwest::Client,
+    last_request: Mutex<Option<Instant>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RecordingSearch {
+    #[serde(default)]
+    recordings: Vec<Recording>,
+}
+
+#[derive(Deserialize, Debug)]
+struct IsrcLookup {
+    #[serde(default)]
+    recordings: Vec<Recording>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Recording {
+    id: String,
+    title: String,
+    #[serde(default)]
+    isrcs: Vec<String>,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    releases: Vec<Release>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ArtistCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+    artist: Option<Artist>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Artist {
+    id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Release {
+    id: String,
+    title: String,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(rename = "release-group")]
+    release_group: Option<ReleaseGroup>,
+    #[serde(default)]
+    media: Vec<Medium>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReleaseGroup {
+    id: String,
+    #[serde(rename = "primary-type", default)]
+    primary_type: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Medium {
+    #[serde(default)]
+    track: Vec<MediumTrack>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MediumTrack {
+    #[serde(default)]
+    number: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReleaseGroupSearch {
+    #[serde(rename = "release-groups", default)]
+    release_groups: Vec<ReleaseGroup>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Tagged {
+    #[serde(default)]
+    genres: Vec<Tag>,
+    #[serde(default)]
+    tags: Vec<Tag>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Tag {
+    name: String,
+    #[serde(default)]
+    count: i64,
+}
+
+impl MusicBrainzClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// GET a `/ws/2` resource as JSON, respecting the one-request-per-second limit
+    async fn get_json<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> anyhow::Result<T> {
+        self.get_json_if_found(path, params)
+            .await?
+            .context("MusicBrainz request failed: not found")
+    }
+
+    /// Like `get_json`, but a 404 (e.g. an unknown ISRC) is `None`
+    async fn get_json_if_found<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> anyhow::Result<Option<T>> {
+        {
+            let mut last = self.last_request.lock().await;
+            if let Some(previous) = *last {
+                let elapsed = previous.elapsed();
+                if elapsed < MIN_REQUEST_INTERVAL {
+                    tokio::time::sleep(MIN_REQUEST_INTERVAL - elapsed).await;
+                }
+            }
+            *last = Some(Instant::now());
+        }
+
+        let url = format!("{}/ws/2/{}", self.base_url, path);
+        let response = self
+            .client
+            .get(&url)
+            .header(reqwest::header::USER_AGENT, USER_AGENT)
+            .query(params)
+            .query(&[("fmt", "json")])
+            .send()
+            .await
+            .with_context(|| format!("requesting {}", url))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        response
+            .error_for_status()
+            .context("MusicBrainz request failed")?
+            .json()
+            .await
+            .map(Some)
+            .context("parsing MusicBrainz response")
+    }
+
+    /// Search recordings by artist and title, returning the best match
+    pub async fn search_recording(&self, artist: &str, title: &str) -> anyhow::Result<Option<TrackMetadata>> {
+        let query = format!(
+            "recording:\"{}\" AND artist:\"{}\"",
+            escape_query(title),
+            escape_query(artist)
+        );
+        let result: RecordingSearch = self
+            .get_json("recording", &[("query", &query), ("limit", "5")])
+            .await?;
+        Ok(result.recordings.first().map(recording_to_metadata))
+    }
+
+    /// Look up recordings by ISRC, returning the best match
+    pub async fn lookup_isrc(&self, isrc: &str) -> anyhow::Result<Option<TrackMetadata>> {
+        let Some(result): Option<IsrcLookup> = self
+            .get_json_if_found(
+                &format!("isrc/{}", isrc),
+                &[("inc", "artist-credits+releases+release-groups+media")],
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+        Ok(result.recordings.first().map(|rec| {
+            let mut meta = recording_to_metadata(rec);
+            meta.isrc.get_or_insert_with(|| isrc.to_string());
+            meta
+        }))
+    }
+
+    /// Most-voted genre of a release group, falling back to its tags
+    pub async fn release_group_genre(&self, release_group_id: &str) -> anyhow::Result<Option<String>> {
+        let tagged: Tagged = self
+            .get_json(
+                &format!("release-group/{}", release_group_id),
+                &[("inc", "genres+tags")],
+            )
+            .await?;
+        Ok(pick_genre(tagged))
+    }
+
+    /// Most-voted genre of an artist, used when the release group has none
+    pub async fn artist_genre(&self, artist_id: &str) -> anyhow::Result<Option<String>> {
+        let tagged: Tagged = self
+            .get_json(&format!("artist/{}", artist_id), &[("inc", "genres+tags")])
+            .await?;
+        Ok(pick_genre(tagged))
+    }
+
+    /// Find the release group ID of an album by artist and album name
+    pub async fn search_release_group(&self, artist: &str, album: &str) -> anyhow::Result<Option<String>> {
+        let query = format!(
+            "releasegroup:\"{}\" AND artist:\"{}\"",
+            escape_query(album),
+            escape_query(artist)
+        );
+        let result: ReleaseGroupSearch = self
+            .get_json("release-group", &[("query", &query), ("limit", "1")])
+            .await?;
+        Ok(result.release_groups.into_iter().next().map(|g| g.id))
+    }
+
+    /// Genre for an album found by artist and album name
+    pub async fn search_album_genre(&self, artist: &str, album: &str) -> anyhow::Result<Option<String>> {
+        match self.search_release_group(artist, album).await? {
+            Some(id) => self.release_group_genre(&id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Fill in the genre of a looked-up track from its release group or artist
+    pub async fn fill_genre(&self, meta: &mut TrackMetadata) -> anyhow::Result<()> {
+        if meta.genre.is_some() {
+            return Ok(());
+        }
+        if let Some(group) = meta.mbids.release_group.clone() {
+            meta.genre = self.release_group_genre(&group).await?;
+        }
+        if meta.genre.is_none() {
+            if let Some(artist) = meta.mbids.artist.clone() {
+                meta.genre = self.artist_genre(&artist).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Escape Lucene special characters inside a quoted search term
+fn escape_query(term: &str) -> String {
+    term.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Prefer an official album release, then any official release, then the first one
+fn pick_release(releases: &[Release]) -> Option<&Release> {
+    let official = |r: &&Release| r.status.as_deref() == Some("Official");
+    let album = |r: &&Release| {
+        r.release_group
+            .as_ref()
+            .and_then(|g| g.primary_type.as_deref())
+            == Some("Album")
+    };
+    releases
+        .iter()
+        .find(|r| official(r) && album(r))
+        .or_else(|| releases.iter().find(official))
+        .or_else(|| releases.first())
+}
+
+fn recording_to_metadata(rec: &Recording) -> TrackMetadata {
+    let artist = rec
+        .artist_credit
+        .iter()
+        .map(|c| format!("{}{}", c.name, c.joinphrase))
+        .collect::<String>();
+    let release = pick_release(&rec.releases);
+
+    let track_number = release
+        .and_then(|r| r.media.iter().flat_map(|m| m.track.iter()).next())
+        .and_then(|t| t.number.parse().ok())
+        .unwrap_or(0);
+    let year = release
+        .and_then(|r| r.date.as_deref())
+        .and_then(|d| d.get(..4))
+        .and_then(|y| y.parse().ok());
+
+    TrackMetadata {
+        artist,
+        album: release.map(|r| r.title.clone()).unwrap_or_default(),
+        title: rec.title.clone(),
+        track_number,
+        cover_url: None,
+        genre: None,
+        isrc: rec.isrcs.first().cloned(),
+        year,
+        mbids: MusicBrainzIds {
+            recording: Some(rec.id.clone()),
+            release: release.map(|r| r.id.clone()),
+            release_group: release.and_then(|r| r.release_group.as_ref()).map(|g| g.id.clone()),
+            artist: rec
+                .artist_credit
+                .first()
+                .and_then(|c| c.artist.as_ref())
+                .map(|a| a.id.clone()),
+        },
+    }
+}
+
+/// Pick the genre with the most votes; fall back to the most-voted tag
+fn pick_genre(tagged: Tagged) -> Option<String> {
+    let best = |tags: Vec<Tag>| {
+        tags.into_iter()
+            .filter(|t| t.count > 0)
+            .max_by_key(|t| t.count)
+            .map(|t| t.name)
+    };
+    best(tagged.genres).or_else(|| best(tagged.tags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEARCH_RESPONSE: &str = r#"{
+        "recordings": [{
+            "id": "rec-1",
+            "title": "Song",
+            "isrcs": ["USABC1234567"],
+            "artist-credit": [
+                {"name": "Alpha", "joinphrase": " & ", "artist": {"id": "artist-1"}},
+                {"name": "Beta", "artist": {"id": "artist-2"}}
+            ],
+            "releases": [
+                {"id": "rel-single", "title": "Song (Single)", "status": "Official",
+                 "release-group": {"id": "rg-single", "primary-type": "Single"},
+                 "media": [{"track": [{"number": "1"}]}]},
+                {"id": "rel-album", "title": "The Album", "status": "Official", "date": "2019-05-03",
+                 "release-group": {"id": "rg-album", "primary-type": "Album"},
+                 "media": [{"track": [{"number": "7"}]}]}
+            ]
+        }]
+    }"#;
+
+    #[test]
+    fn test_recording_to_metadata_prefers_official_album() {
+        let search: RecordingSearch = serde_json::from_str(SEARCH_RESPONSE).unwrap();
+        let meta = recording_to_metadata(&search.recordings[0]);
+        assert_eq!(meta.artist, "Alpha & Beta");
+        assert_eq!(meta.album, "The Album");
+        assert_eq!(meta.track_number, 7);
+        assert_eq!(meta.year, Some(2019));
+        assert_eq!(meta.isrc.as_deref(), Some("USABC1234567"));
+        assert_eq!(meta.mbids.recording.as_deref(), Some("rec-1"));
+        assert_eq!(meta.mbids.release.as_deref(), Some("rel-album"));
+        assert_eq!(meta.mbids.release_group.as_deref(), Some("rg-album"));
+        assert_eq!(meta.mbids.artist.as_deref(), Some("artist-1"));
+    }
+
+    #[test]
+    fn test_pick_genre() {
+        let tagged: Tagged = serde_json::from_str(
+            r#"{"genres": [{"name": "rock", "count": 2}, {"name": "indie rock", "count": 5}],
+                "tags": [{"name": "favourite", "count": 9}]}"#,
+        )
+        .unwrap();
+        assert_eq!(pick_genre(tagged).as_deref(), Some("indie rock"));
+
+        let tags_only: Tagged =
+            serde_json::from_str(r#"{"tags": [{"name": "shoegaze", "count": 1}]}"#).unwrap();
+        assert_eq!(pick_genre(tags_only).as_deref(), Some("shoegaze"));
+        assert_eq!(pick_genre(Tagged::default()), None);
+    }
+
+    #[test]
+    fn test_escape_query() {
+        assert_eq!(escape_query(r#"Say "Hi""#), r#"Say \"Hi\""#);
+    }
+}
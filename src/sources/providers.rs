@@ -0,0 +1,205 @@
+use futures::future::BoxFuture;
+use rspotify::model::FullAlbum;
+
+use super::models::TrackMetadata;
+use super::musicbrainz::MusicBrainzClient;
+use super::spotify;
+use crate::config::MetadataConfig;
+
+/// A source of track and album metadata. Object-safe so callers can hold an
+/// ordered list of providers and fall back from one to the next.
+pub trait MetadataProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Find a track by artist and title
+    fn search_track<'a>(
+        &'a self,
+        artist: &'a str,
+        title: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<TrackMetadata>>>;
+
+    /// Find a track by ISRC. Providers without ISRC support return `Ok(None)`.
+    fn lookup_isrc<'a>(&'a self, _isrc: &'a str) -> BoxFuture<'a, anyhow::Result<Option<TrackMetadata>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Find the genre of an album by artist and album name
+    fn album_genre<'a>(
+        &'a self,
+        artist: &'a str,
+        album: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<String>>>;
+}
+
+/// Spotify Web API (requires RSPOTIFY_CLIENT_ID / RSPOTIFY_CLIENT_SECRET)
+pub struct SpotifyProvider;
+
+impl MetadataProvider for SpotifyProvider {
+    fn name(&self) -> &'static str {
+        "spotify"
+    }
+
+    fn search_track<'a>(
+        &'a self,
+        artist: &'a str,
+        title: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<TrackMetadata>>> {
+        Box::pin(spotify::search_track(artist, title))
+    }
+
+    fn album_genre<'a>(
+        &'a self,
+        artist: &'a str,
+        album: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<String>>> {
+        Box::pin(spotify::search_album_genre(artist, album))
+    }
+}
+
+/// MusicBrainz web service; works without any credentials
+pub struct MusicBrainzProvider {
+    client: MusicBrainzClient,
+}
+
+impl MusicBrainzProvider {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            client: MusicBrainzClient::new(base_url),
+        }
+    }
+}
+
+impl MetadataProvider for MusicBrainzProvider {
+    fn name(&self) -> &'static str {
+        "musicbrainz"
+    }
+
+    fn search_track<'a>(
+        &'a self,
+        artist: &'a str,
+        title: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<TrackMetadata>>> {
+        Box::pin(async move {
+            let mut meta = self.client.search_recording(artist, title).await?;
+            if let Some(m) = meta.as_mut() {
+                self.client.fill_genre(m).await?;
+            }
+            Ok(meta)
+        })
+    }
+
+    fn lookup_isrc<'a>(&'a self, isrc: &'a str) -> BoxFuture<'a, anyhow::Result<Option<TrackMetadata>>> {
+        Box::pin(async move {
+            let mut meta = self.client.lookup_isrc(isrc).await?;
+            if let Some(m) = meta.as_mut() {
+                self.client.fill_genre(m).await?;
+            }
+            Ok(meta)
+        })
+    }
+
+    fn album_genre<'a>(
+        &'a self,
+        artist: &'a str,
+        album: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<String>>> {
+        Box::pin(self.client.search_album_genre(artist, album))
+    }
+}
+
+/// Operations that look up metadata; each has its own provider order in the config
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    /// Refreshing tags of library tracks (TUI refresh, convert, retry)
+    Refresh,
+    /// The `retag` command
+    Retag,
+    /// Filling in album genres after a download
+    Genre,
+}
+
+/// Names accepted in provider order lists
+pub const PROVIDER_NAMES: &[&str] = &["spotify", "musicbrainz"];
+
+/// Build a single provider by name
+pub fn provider_by_name(name: &str, config: &MetadataConfig) -> Option<Box<dyn MetadataProvider>> {
+    match name.trim().to_lowercase().as_str() {
+        "spotify" => Some(Box::new(SpotifyProvider)),
+        "musicbrainz" | "mb" => Some(Box::new(MusicBrainzProvider::new(&config.musicbrainz_url))),
+        _ => None,
+    }
+}
+
+/// Build the ordered provider list for an operation. Unknown names are skipped.
+pub fn providers_for(operation: Operation, config: &MetadataConfig) -> Vec<Box<dyn MetadataProvider>> {
+    build_providers(config.order(operation), config)
+}
+
+/// Build providers from an explicit list of names (e.g. `--provider musicbrainz,spotify`)
+pub fn build_providers(names: &[String], config: &MetadataConfig) -> Vec<Box<dyn MetadataProvider>> {
+    names
+        .iter()
+        .filter_map(|name| provider_by_name(name, config))
+        .collect()
+}
+
+/// Search providers in order, returning the first match. If the match has no
+/// genre, later providers are asked for the album genre. Provider errors
+/// (e.g. missing Spotify credentials) only fail the lookup if every provider failed.
+pub async fn search_track(
+    providers: &[Box<dyn MetadataProvider>],
+    artist: &str,
+    title: &str,
+    isrc: Option<&str>,
+) -> anyhow::Result<Option<TrackMetadata>> {
+    let mut errors = Vec::new();
+
+    for (i, provider) in providers.iter().enumerate() {
+        let mut result = Ok(None);
+        if let Some(isrc) = isrc {
+            result = provider.lookup_isrc(isrc).await;
+        }
+        // An ISRC the provider doesn't know, or a failed lookup, falls back
+        // to searching by artist and title
+        if !matches!(result, Ok(Some(_))) {
+            result = provider.search_track(artist, title).await;
+        }
+
+        match result {
+            Ok(Some(mut meta)) => {
+                if meta.genre.is_none() {
+                    for fallback in &providers[i + 1..] {
+                        if let Ok(Some(genre)) = fallback.album_genre(&meta.artist, &meta.album).await {
+                            meta.genre = Some(genre);
+                            break;
+                        }
+                    }
+                }
+                return Ok(Some(meta));
+            }
+            Ok(None) => {}
+            Err(e) => errors.push(format!("{}: {}", provider.name(), e)),
+        }
+    }
+
+    if !errors.is_empty() && errors.len() == providers.len() {
+        anyhow::bail!("all metadata providers failed ({})", errors.join("; "));
+    }
+    Ok(None)
+}
+
+/// Genre of a Spotify album that was already fetched, asking the genre
+/// providers in their configured order. Spotify answers from the album itself.
+pub async fn spotify_album_genre(providers: &[Box<dyn MetadataProvider>], album: &FullAlbum) -> Option<String> {
+    let artist = album.artists.first().map(|a| a.name.as_str()).unwrap_or("Unknown");
+    for provider in providers {
+        let genre = match provider.name() {
+            "spotify" => spotify::fetch_album_genres(album).await,
+            _ => provider.album_genre(artist, &album.name).await.ok().flatten(),
+        };
+        if genre.is_some() {
+            return genre;
+        }
+    }
+    None
+}
@@ -1,20 +1,14 @@
 use anyhow::Result;
 use futures::stream::TryStreamExt;
 use rspotify::clients::BaseClient;
-use rspotify::model::{AlbumId, ArtistId, FullAlbum, FullPlaylist, PlaylistId, PlaylistItem, SearchType};
+use rspotify::model::{
+    AlbumId, ArtistId, FullAlbum, FullEpisode, FullPlaylist, PlaylistId, PlaylistItem, SearchType,
+    SimplifiedTrack,
+};
 use rspotify::prelude::Id;
 use rspotify::{ClientCredsSpotify, Credentials};
 
-/// Metadata fetched from Spotify for a track
-#[derive(Debug, Clone)]
-pub struct TrackMetadata {
-    pub artist: String,
-    pub album: String,
-    pub title: String,
-    pub track_number: u32,
-    pub cover_url: Option<String>,
-    pub genre: Option<String>,
-}
+use super::models::{EpisodeMetadata, TrackMetadata};
 
 async fn get_spotify_client() -> Result<ClientCredsSpotify, anyhow::Error> {
     let creds = Credentials::from_env().ok_or_else(|| {
@@ -55,6 +49,42 @@ pub async fn fetch_all_playlist_items(link: &str) -> Result<Vec<PlaylistItem>, a
     Ok(items)
 }
 
+/// Fetch all tracks of an album with pagination (the album itself only
+/// lists the first 50)
+pub async fn fetch_all_album_tracks(link: &str) -> Result<Vec<SimplifiedTrack>, anyhow::Error> {
+    let album_id = AlbumId::from_id(extract_id(link, "album")?)?;
+    let spotify = get_spotify_client().await?;
+
+    let tracks: Vec<SimplifiedTrack> = spotify.album_track(album_id, None).try_collect().await?;
+
+    Ok(tracks)
+}
+
+/// Build episode metadata from a playlist episode and its embedded show
+pub fn episode_metadata(episode: &FullEpisode) -> EpisodeMetadata {
+    let cover_url = episode
+        .images
+        .first()
+        .or_else(|| episode.show.images.first())
+        .map(|img| img.url.clone());
+
+    EpisodeMetadata {
+        show: episode.show.name.clone(),
+        publisher: episode.show.publisher.clone(),
+        title: episode.name.clone(),
+        description: episode.description.clone(),
+        release_date: episode.release_date.clone(),
+        duration_ms: episode.duration.num_milliseconds().max(0) as u64,
+        cover_url,
+        episode_url: episode.external_urls.get("spotify").cloned(),
+    }
+}
+
+/// Spotify ID of a playlist link (or a bare ID)
+pub fn playlist_id(link: &str) -> anyhow::Result<String> {
+    Ok(extract_id(link, "playlist")?.to_string())
+}
+
 fn extract_id<'a>(link: &'a str, kind: &str) -> Result<&'a str, anyhow::Error> {
     // Handle both full URLs and bare IDs
     // e.g., "https://open.spotify.com/album/abc123?si=xyz" -> "abc123"
@@ -101,6 +131,25 @@ pub async fn fetch_album_genres(album: &FullAlbum) -> Option<String> {
     None
 }
 
+/// Search for an album on Spotify by artist and name and return its genre
+pub async fn search_album_genre(artist: &str, album: &str) -> Result<Option<String>, anyhow::Error> {
+    let spotify = get_spotify_client().await?;
+    let query = format!("artist:{} album:{}", artist, album);
+
+    let result = spotify
+        .search(&query, SearchType::Album, None, None, Some(1), None)
+        .await?;
+
+    if let rspotify::model::SearchResult::Albums(albums) = result {
+        if let Some(id) = albums.items.into_iter().next().and_then(|a| a.id) {
+            let full = spotify.album(id, None).await?;
+            return Ok(fetch_album_genres(&full).await);
+        }
+    }
+
+    Ok(None)
+}
+
 /// Search for a track on Spotify by artist and title.
 /// Returns metadata if found, None if no results.
 pub async fn search_track(artist: &str, title: &str) -> Result<Option<TrackMetadata>, anyhow::Error> {
@@ -143,6 +192,13 @@ pub async fn search_track(artist: &str, title: &str) -> Result<Option<TrackMetad
                 None
             };
 
+            let year = track
+                .album
+                .release_date
+                .as_deref()
+                .and_then(|d| d.get(..4))
+                .and_then(|y| y.parse().ok());
+
             return Ok(Some(TrackMetadata {
                 artist: artist_name,
                 album: album_name,
@@ -150,6 +206,9 @@ pub async fn search_track(artist: &str, title: &str) -> Result<Option<TrackMetad
                 track_number,
                 cover_url,
                 genre,
+                isrc: track.external_ids.get("isrc").cloned(),
+                year,
+                ..Default::default()
             }));
         }
     }
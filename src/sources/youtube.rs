@@ -15,7 +15,6 @@ pub struct YouTubeTrack {
 #[derive(Debug, Clone)]
 pub struct YouTubePlaylist {
     pub title: String,
-    pub uploader: String,
     pub tracks: Vec<YouTubeTrack>,
 }
 
@@ -47,21 +46,6 @@ pub fn is_youtube_playlist(url: &str) -> bool {
     is_youtube_url(url) && (url.contains("playlist?list=") || url.contains("&list="))
 }
 
-/// Extract playlist ID from a YouTube playlist URL
-pub fn extract_playlist_id(url: &str) -> Option<String> {
-    // Handle both formats:
-    // https://www.youtube.com/playlist?list=PLxxxxx
-    // https://www.youtube.com/watch?v=xxxxx&list=PLxxxxx
-    if let Some(pos) = url.find("list=") {
-        let start = pos + 5;
-        let rest = &url[start..];
-        let end = rest.find('&').unwrap_or(rest.len());
-        Some(rest[..end].to_string())
-    } else {
-        None
-    }
-}
-
 /// Fetch playlist information from YouTube using yt-dlp
 pub fn fetch_playlist(url: &str) -> Result<YouTubePlaylist> {
     // Use yt-dlp to get playlist info as JSON
@@ -85,7 +69,6 @@ pub fn fetch_playlist(url: &str) -> Result<YouTubePlaylist> {
     // yt-dlp with --flat-playlist outputs one JSON object per line for each video
     let mut tracks = Vec::new();
     let mut playlist_title = String::new();
-    let mut playlist_uploader = String::new();
 
     for line in stdout.lines() {
         if line.trim().is_empty() {
@@ -107,11 +90,6 @@ pub fn fetch_playlist(url: &str) -> Result<YouTubePlaylist> {
                 continue;
             }
 
-            // Use first entry's uploader as playlist uploader if not set
-            if playlist_uploader.is_empty() {
-                playlist_uploader = artist.clone();
-            }
-
             tracks.push(YouTubeTrack {
                 title: entry.title.unwrap_or_else(|| "Unknown Title".to_string()),
                 artist,
@@ -138,7 +116,7 @@ pub fn fetch_playlist(url: &str) -> Result<YouTubePlaylist> {
             let stdout = String::from_utf8_lossy(&output.stdout);
             if let Ok(info) = serde_json::from_str::<YtDlpPlaylistInfo>(&stdout) {
                 playlist_title = info.title.unwrap_or_else(|| "YouTube Playlist".to_string());
-                playlist_uploader = info.uploader
+                let playlist_uploader = info.uploader
                     .or(info.channel)
                     .unwrap_or_else(|| "Unknown".to_string());
 
@@ -179,7 +157,6 @@ pub fn fetch_playlist(url: &str) -> Result<YouTubePlaylist> {
 
     Ok(YouTubePlaylist {
         title: playlist_title,
-        uploader: playlist_uploader,
         tracks,
     })
 }
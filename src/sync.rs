@@ -0,0 +1,407 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::db::{DownloadDB, TrackEntry};
+use crate::playlist_index::PlaylistIndex;
+use crate::regenerate::PlaylistSources;
+use crate::smart;
+use crate::sources::spotify;
+use crate::trash::Trash;
+
+/// Default location of the tracked playlist list
+pub const TRACKED_FILE: &str = "data/cache/tracked_playlists.json";
+
+/// A playlist entry as of the last sync
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyncedTrack {
+    pub artist: String,
+    pub title: String,
+    /// File in the library; unset if the track couldn't be downloaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+impl SyncedTrack {
+    /// Playlist entries with the files the library has for them
+    pub fn resolve(wanted: &[TrackEntry], db: &DownloadDB) -> Vec<SyncedTrack> {
+        wanted
+            .iter()
+            .map(|entry| SyncedTrack {
+                artist: entry.artist.clone(),
+                title: entry.title.clone(),
+                path: db.find_download(entry).map(|e| e.path.clone()),
+            })
+            .collect()
+    }
+
+    fn key(&self) -> (String, String) {
+        (self.artist.to_lowercase(), self.title.to_lowercase())
+    }
+
+    fn label(&self) -> String {
+        format!("{} - {}", self.artist, self.title)
+    }
+}
+
+/// Outcome of downloading a playlist
+pub struct PlaylistRun {
+    /// Every entry, in playlist order
+    pub tracks: Vec<SyncedTrack>,
+    /// Files downloaded in this run
+    pub downloaded: Vec<PathBuf>,
+}
+
+impl PlaylistRun {
+    /// Files of the playlist in order, leaving out tracks without one
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.tracks.iter().filter_map(|t| t.path.as_ref().map(PathBuf::from)).collect()
+    }
+}
+
+/// A Spotify playlist kept in sync with the library
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrackedPlaylist {
+    pub name: String,
+    pub link: String,
+    /// Spotify's version of the playlist at the last sync
+    pub snapshot_id: String,
+    pub synced_at: DateTime<Utc>,
+    pub format: String,
+    pub quality: String,
+    #[serde(default)]
+    pub portable: bool,
+    pub tracks: Vec<SyncedTrack>,
+    /// Files syncs downloaded for this playlist. Only these are ever removed
+    /// by `--clean`; tracks that were already in the library are left alone.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub downloaded: Vec<String>,
+}
+
+impl TrackedPlaylist {
+    /// Whether Spotify's copy is unchanged and every track still has its file
+    pub fn is_current(&self, snapshot_id: &str) -> bool {
+        self.snapshot_id == snapshot_id
+            && self
+                .tracks
+                .iter()
+                .all(|t| t.path.as_ref().is_some_and(|p| Path::new(p).exists()))
+    }
+
+    /// Tracks that have no file in the library
+    pub fn missing(&self) -> usize {
+        self.tracks.iter().filter(|t| t.path.is_none()).count()
+    }
+}
+
+/// What a sync changed in a playlist
+#[derive(Debug, Default, PartialEq)]
+pub struct SyncDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub unchanged: usize,
+    /// Tracks in both versions are in a different order
+    pub reordered: bool,
+    /// Tracks that couldn't be downloaded
+    pub missing: Vec<String>,
+}
+
+impl SyncDiff {
+    pub fn between(old: &[SyncedTrack], new: &[SyncedTrack]) -> Self {
+        let old_keys: HashSet<_> = old.iter().map(SyncedTrack::key).collect();
+        let new_keys: HashSet<_> = new.iter().map(SyncedTrack::key).collect();
+        let kept_old: Vec<_> = old.iter().map(SyncedTrack::key).filter(|k| new_keys.contains(k)).collect();
+        let kept_new: Vec<_> = new.iter().map(SyncedTrack::key).filter(|k| old_keys.contains(k)).collect();
+        Self {
+            added: new.iter().filter(|t| !old_keys.contains(&t.key())).map(SyncedTrack::label).collect(),
+            removed: old.iter().filter(|t| !new_keys.contains(&t.key())).map(SyncedTrack::label).collect(),
+            unchanged: kept_new.len(),
+            reordered: kept_old != kept_new,
+            missing: new.iter().filter(|t| t.path.is_none()).map(SyncedTrack::label).collect(),
+        }
+    }
+
+    /// One-line summary, e.g. "+2 added, -1 removed, 40 unchanged (reordered)"
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "+{} added, -{} removed, {} unchanged",
+            self.added.len(),
+            self.removed.len(),
+            self.unchanged
+        );
+        if self.reordered {
+            summary.push_str(" (reordered)");
+        }
+        if !self.missing.is_empty() {
+            summary.push_str(&format!(", {} not downloaded", self.missing.len()));
+        }
+        summary
+    }
+}
+
+/// Spotify playlists tracked with `rustwav sync`
+pub struct TrackedPlaylists {
+    path: PathBuf,
+    playlists: Vec<TrackedPlaylist>,
+}
+
+impl TrackedPlaylists {
+    pub fn new(path: &str) -> Self {
+        let playlists = fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path: PathBuf::from(path),
+            playlists,
+        }
+    }
+
+    /// Open the default tracked playlist list
+    pub fn open() -> Self {
+        Self::new(TRACKED_FILE)
+    }
+
+    pub fn playlists(&self) -> &[TrackedPlaylist] {
+        &self.playlists
+    }
+
+    /// Find a tracked playlist by its link, Spotify ID or name
+    pub fn find(&self, query: &str) -> Option<&TrackedPlaylist> {
+        let id = spotify::playlist_id(query).ok();
+        self.playlists.iter().find(|p| {
+            (id.is_some() && spotify::playlist_id(&p.link).ok() == id) || p.name.eq_ignore_ascii_case(query.trim())
+        })
+    }
+
+    /// Store the result of a sync, returning what changed since the last one
+    pub fn record(&mut self, mut playlist: TrackedPlaylist) -> anyhow::Result<SyncDiff> {
+        let id = spotify::playlist_id(&playlist.link).ok();
+        let previous = self
+            .playlists
+            .iter()
+            .position(|p| spotify::playlist_id(&p.link).ok() == id)
+            .map(|i| self.playlists.remove(i));
+        let diff = match &previous {
+            Some(previous) => {
+                let mut downloaded = previous.downloaded.clone();
+                downloaded.retain(|p| !playlist.downloaded.contains(p));
+                downloaded.append(&mut playlist.downloaded);
+                playlist.downloaded = downloaded;
+                SyncDiff::between(&previous.tracks, &playlist.tracks)
+            }
+            None => SyncDiff::between(&[], &playlist.tracks),
+        };
+        self.playlists.push(playlist);
+        self.save()?;
+        Ok(diff)
+    }
+
+    /// Stop tracking a playlist; its files stay in the library
+    pub fn untrack(&mut self, query: &str) -> anyhow::Result<Option<TrackedPlaylist>> {
+        let Some(link) = self.find(query).map(|p| p.link.clone()) else {
+            return Ok(None);
+        };
+        let index = self.playlists.iter().position(|p| p.link == link);
+        let removed = index.map(|i| self.playlists.remove(i));
+        self.save()?;
+        Ok(removed)
+    }
+
+    /// Files syncs downloaded that no tracked playlist has any more
+    pub fn orphans(&self) -> Vec<String> {
+        let referenced: HashSet<&String> = self
+            .playlists
+            .iter()
+            .flat_map(|p| p.tracks.iter().filter_map(|t| t.path.as_ref()))
+            .collect();
+        let mut orphans: Vec<String> = self
+            .playlists
+            .iter()
+            .flat_map(|p| &p.downloaded)
+            .filter(|path| !referenced.contains(path))
+            .cloned()
+            .collect();
+        orphans.sort();
+        orphans.dedup();
+        orphans
+    }
+
+    /// Point tracks whose file was converted or moved at their file in the
+    /// download cache, and unset those whose file left the library
+    pub fn follow(&mut self, db: &DownloadDB) -> anyhow::Result<()> {
+        let mut changed = false;
+        for playlist in &mut self.playlists {
+            let mut moved = Vec::new();
+            for track in &mut playlist.tracks {
+                let Some(path) = track.path.clone().filter(|p| !Path::new(p).exists()) else {
+                    continue;
+                };
+                track.path = db.find_track(&track.artist, &track.title).map(|e| e.path.clone());
+                if let Some(new_path) = &track.path {
+                    moved.push((path, new_path.clone()));
+                }
+                changed = true;
+            }
+            for (old, new) in moved {
+                for downloaded in playlist.downloaded.iter_mut().filter(|p| **p == old) {
+                    *downloaded = new.clone();
+                }
+            }
+        }
+        if changed {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Forget downloaded files after they were removed from the library
+    pub fn forget_downloads(&mut self, paths: &[String]) -> anyhow::Result<()> {
+        for playlist in &mut self.playlists {
+            playlist.downloaded.retain(|p| !paths.contains(p));
+        }
+        self.save()
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(&self.playlists)?;
+        fs::write(&self.path, json).context("writing tracked playlists")
+    }
+}
+
+/// Files syncs downloaded that no tracked playlist has any more, leaving
+/// out those a downloaded album or another playlist still uses
+pub fn cleanable(tracked: &TrackedPlaylists) -> Vec<String> {
+    // Tracked playlists are already covered, and smart playlists follow the library
+    let tracked_names: HashSet<&String> = tracked.playlists.iter().map(|p| &p.name).collect();
+    let referenced: HashSet<String> = PlaylistIndex::open()
+        .playlists()
+        .iter()
+        .filter(|p| !tracked_names.contains(&p.name) && !p.dir.ends_with(smart::SMART_DIR))
+        .flat_map(|p| p.tracks.iter().map(|t| t.path.clone()))
+        .chain(PlaylistSources::open().sources().iter().flat_map(|s| s.paths.clone()))
+        .collect();
+    let mut orphans = tracked.orphans();
+    orphans.retain(|path| !referenced.contains(path));
+    orphans
+}
+
+/// Move files syncs downloaded that no tracked playlist, album or other
+/// playlist has any more to the trash, with their download cache entries.
+/// Returns the removed files.
+pub fn clean(tracked: &mut TrackedPlaylists, db: &mut DownloadDB, trash: &mut Trash) -> anyhow::Result<Vec<String>> {
+    let orphans = cleanable(tracked);
+    let mut removed = Vec::new();
+    for path in &orphans {
+        let entry = db.find_by_path(path).cloned();
+        if Path::new(path).exists() {
+            trash.trash_file(Path::new(path), entry, "sync: no longer in a tracked playlist")?;
+        } else if let Some(entry) = entry {
+            trash.trash_entry(entry, "sync: no longer in a tracked playlist")?;
+        }
+        db.remove_by_path(path);
+        removed.push(path.clone());
+    }
+    tracked.forget_downloads(&orphans)?;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(artist: &str, title: &str, path: Option<&str>) -> SyncedTrack {
+        SyncedTrack {
+            artist: artist.to_string(),
+            title: title.to_string(),
+            path: path.map(str::to_string),
+        }
+    }
+
+    fn playlist(link: &str, tracks: Vec<SyncedTrack>, downloaded: &[&str]) -> TrackedPlaylist {
+        TrackedPlaylist {
+            name: "Mix".to_string(),
+            link: link.to_string(),
+            snapshot_id: "snap".to_string(),
+            synced_at: Utc::now(),
+            format: "mp3".to_string(),
+            quality: "high".to_string(),
+            portable: false,
+            tracks,
+            downloaded: downloaded.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_sync_diff() {
+        let old = vec![track("A", "One", Some("a")), track("B", "Two", Some("b")), track("C", "Three", Some("c"))];
+        let new = vec![track("b", "two", Some("b")), track("A", "One", Some("a")), track("D", "Four", None)];
+        let diff = SyncDiff::between(&old, &new);
+        assert_eq!(diff.added, vec!["D - Four"]);
+        assert_eq!(diff.removed, vec!["C - Three"]);
+        assert_eq!(diff.unchanged, 2);
+        assert!(diff.reordered);
+        assert_eq!(diff.summary(), "+1 added, -1 removed, 2 unchanged (reordered), 1 not downloaded");
+    }
+
+    #[test]
+    fn test_orphans() {
+        let tracked = TrackedPlaylists {
+            path: PathBuf::from(TRACKED_FILE),
+            playlists: vec![
+                playlist("first", vec![track("A", "One", Some("a"))], &["a", "b", "c"]),
+                playlist("second", vec![track("C", "Three", Some("c"))], &["d"]),
+            ],
+        };
+        assert_eq!(tracked.orphans(), vec!["b", "d"]);
+    }
+
+    #[test]
+    fn test_follow_converted_tracks() {
+        let dir = std::env::temp_dir().join(format!("rustwav-sync-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let flac = dir.join("One.flac").display().to_string();
+        let mp3 = dir.join("One.mp3").display().to_string();
+        fs::write(&mp3, "mp3").unwrap();
+        let wanted = TrackEntry {
+            id: String::new(),
+            artist: "A".to_string(),
+            title: "One".to_string(),
+            path: flac.clone(),
+            source: None,
+            trims: Vec::new(),
+            variants: Vec::new(),
+            duration_secs: None,
+        };
+        let mut db = DownloadDB::new(dir.join("db.json").to_str().unwrap());
+        db.add(TrackEntry {
+            path: mp3.clone(),
+            ..wanted.clone()
+        });
+        // The converted file counts as the download, so syncs don't fetch it again
+        assert_eq!(db.find_download(&wanted).map(|e| e.path.as_str()), Some(mp3.as_str()));
+
+        let mut tracked = TrackedPlaylists {
+            path: dir.join("tracked.json"),
+            playlists: vec![playlist(
+                "first",
+                vec![track("A", "One", Some(&flac)), track("B", "Two", Some("gone.flac"))],
+                &[&flac],
+            )],
+        };
+        tracked.follow(&db).unwrap();
+        let synced = &tracked.playlists()[0];
+        assert_eq!(synced.tracks[0].path.as_deref(), Some(mp3.as_str()));
+        assert_eq!(synced.tracks[1].path, None);
+        assert_eq!(synced.downloaded, vec![mp3.clone()]);
+        assert!(tracked.orphans().is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,571 @@
+use id3::frame::{Comment, ExtendedText, Lyrics, Picture, UniqueFileIdentifier};
+use id3::{Tag, TagLike};
+use image::ImageReader;
+use metaflac::block::{PictureType, VorbisComment};
+
+use super::mp4::{self, Mp4Tag};
+use super::vorbis::VorbisTag;
+
+/// Text fields with their own frame: (Vorbis key, ID3 frame, MP4 atom).
+/// Fields without an MP4 atom are stored as iTunes freeform atoms named
+/// after the Vorbis key, like Picard does.
+const TEXT_FIELDS: [(&str, &str, Option<[u8; 4]>); 19] = [
+    ("TITLE", "TIT2", Some(mp4::TITLE)),
+    ("ARTIST", "TPE1", Some(mp4::ARTIST)),
+    ("ALBUM", "TALB", Some(mp4::ALBUM)),
+    ("ALBUMARTIST", "TPE2", Some(mp4::ALBUM_ARTIST)),
+    ("GENRE", "TCON", Some(mp4::GENRE)),
+    ("COMPOSER", "TCOM", Some(mp4::COMPOSER)),
+    ("GROUPING", "TIT1", Some(mp4::GROUPING)),
+    ("COPYRIGHT", "TCOP", Some(mp4::COPYRIGHT)),
+    ("TITLESORT", "TSOT", Some(mp4::TITLE_SORT)),
+    ("ARTISTSORT", "TSOP", Some(mp4::ARTIST_SORT)),
+    ("ALBUMSORT", "TSOA", Some(mp4::ALBUM_SORT)),
+    ("ALBUMARTISTSORT", "TSO2", Some(mp4::ALBUM_ARTIST_SORT)),
+    ("ISRC", "TSRC", None),
+    ("LABEL", "TPUB", None),
+    ("BPM", "TBPM", None),
+    ("CONDUCTOR", "TPE3", None),
+    ("LYRICIST", "TEXT", None),
+    ("SUBTITLE", "TIT3", None),
+    ("MEDIA", "TMED", None),
+];
+
+/// MusicBrainz IDs: Vorbis key and the ID3 TXXX / MP4 freeform name
+pub const MUSICBRAINZ_IDS: [(&str, &str); 4] = [
+    ("MUSICBRAINZ_TRACKID", "MusicBrainz Track Id"),
+    ("MUSICBRAINZ_ALBUMID", "MusicBrainz Album Id"),
+    ("MUSICBRAINZ_RELEASEGROUPID", "MusicBrainz Release Group Id"),
+    ("MUSICBRAINZ_ARTISTID", "MusicBrainz Artist Id"),
+];
+
+/// UFID owner of MusicBrainz recording IDs
+pub const MUSICBRAINZ_UFID_OWNER: &str = "http://musicbrainz.org";
+
+/// Fields with special handling in every format
+const SPECIAL_FIELDS: [&str; 7] = [
+    "DATE",
+    "TRACKNUMBER",
+    "TRACKTOTAL",
+    "DISCNUMBER",
+    "DISCTOTAL",
+    "COMMENT",
+    "LYRICS",
+];
+
+/// Encoder stamps describe the old file, not the new one
+const SKIPPED_FIELDS: [&str; 2] = ["ENCODER", "ENCODEDBY"];
+
+/// FLAC picture types by their code, which ID3 APIC frames share
+const PICTURE_TYPES: [PictureType; 21] = [
+    PictureType::Other,
+    PictureType::Icon,
+    PictureType::OtherIcon,
+    PictureType::CoverFront,
+    PictureType::CoverBack,
+    PictureType::Leaflet,
+    PictureType::Media,
+    PictureType::LeadArtist,
+    PictureType::Artist,
+    PictureType::Conductor,
+    PictureType::Band,
+    PictureType::Composer,
+    PictureType::Lyricist,
+    PictureType::RecordingLocation,
+    PictureType::DuringRecording,
+    PictureType::DuringPerformance,
+    PictureType::ScreenCapture,
+    PictureType::BrightFish,
+    PictureType::Illustration,
+    PictureType::BandLogo,
+    PictureType::PublisherLogo,
+];
+
+/// An embedded picture in any tag format
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagPicture {
+    pub picture_type: PictureType,
+    pub mime_type: String,
+    pub description: String,
+    pub data: Vec<u8>,
+}
+
+/// Every tag of a file in a format-neutral form. Fields use Vorbis comment
+/// names (as Picard writes them) and may repeat; track and disc totals are
+/// kept apart in TRACKTOTAL and DISCTOTAL.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TagSet {
+    pub fields: Vec<(String, String)>,
+    pub pictures: Vec<TagPicture>,
+}
+
+impl TagSet {
+    fn push(&mut self, key: &str, value: &str) {
+        let value = value.trim_matches('\0');
+        if !value.is_empty() && !SKIPPED_FIELDS.contains(&key) {
+            self.fields.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    /// First value of a field
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Drop every field whose key starts with `prefix`
+    pub fn remove_prefixed(&mut self, prefix: &str) {
+        self.fields.retain(|(k, _)| !k.starts_with(prefix));
+    }
+
+    /// Fields grouped by key, in order of first appearance
+    fn grouped(&self) -> Vec<(&str, Vec<&str>)> {
+        let mut groups: Vec<(&str, Vec<&str>)> = Vec::new();
+        for (key, value) in &self.fields {
+            match groups.iter_mut().find(|(k, _)| k == key) {
+                Some((_, values)) => values.push(value),
+                None => groups.push((key, vec![value])),
+            }
+        }
+        groups
+    }
+
+    /// Record a "3/12" style number, splitting off the total
+    fn push_number(&mut self, number_key: &str, total_key: &str, value: &str) {
+        let mut parts = value.splitn(2, '/');
+        if let Some(number) = parts.next() {
+            self.push(number_key, number.trim());
+        }
+        if let Some(total) = parts.next() {
+            if self.get(total_key).is_none() {
+                self.push(total_key, total.trim());
+            }
+        }
+    }
+
+    fn number(&self, key: &str) -> Option<u32> {
+        self.get(key)?.trim().parse().ok().filter(|n| *n > 0)
+    }
+
+    pub fn from_vorbis(tag: &dyn VorbisTag) -> Self {
+        let mut set = TagSet::default();
+        let comments = tag.comments().comments;
+        let mut keys: Vec<&String> = comments.keys().collect();
+        keys.sort();
+        for key in keys {
+            let upper = key.to_uppercase();
+            for value in &comments[key] {
+                match upper.as_str() {
+                    "TRACKNUMBER" => set.push_number("TRACKNUMBER", "TRACKTOTAL", value),
+                    "DISCNUMBER" => set.push_number("DISCNUMBER", "DISCTOTAL", value),
+                    "TOTALTRACKS" => set.push("TRACKTOTAL", value),
+                    "TOTALDISCS" => set.push("DISCTOTAL", value),
+                    "YEAR" if !comments.keys().any(|k| k.eq_ignore_ascii_case("DATE")) => set.push("DATE", value),
+                    "YEAR" => {}
+                    other => set.push(other, value),
+                }
+            }
+        }
+        set.pictures = tag
+            .picture_blocks()
+            .into_iter()
+            .map(|p| TagPicture {
+                picture_type: p.picture_type,
+                mime_type: p.mime_type,
+                description: p.description,
+                data: p.data,
+            })
+            .collect();
+        set
+    }
+
+    /// Replace the comments and pictures of a FLAC or Ogg file's tag
+    pub fn write_vorbis(&self, tag: &mut dyn VorbisTag) {
+        let mut comments = VorbisComment::new();
+        for (key, values) in self.grouped() {
+            comments.set(key, values);
+        }
+        tag.set_comments(comments);
+
+        tag.remove_picture_blocks(None);
+        for picture in &self.pictures {
+            let (width, height) = image_dimensions(&picture.data).unwrap_or((0, 0));
+            tag.add_picture_block(metaflac::block::Picture {
+                picture_type: picture.picture_type,
+                mime_type: picture.mime_type.clone(),
+                description: picture.description.clone(),
+                width,
+                height,
+                depth: if width > 0 { 24 } else { 0 },
+                num_colors: 0,
+                data: picture.data.clone(),
+            });
+        }
+    }
+
+    pub fn from_id3(tag: &Tag) -> Self {
+        let mut set = TagSet::default();
+        for (key, frame_id, _) in TEXT_FIELDS {
+            let value = if frame_id == "TCON" {
+                tag.genre_parsed().map(|g| g.into_owned())
+            } else {
+                tag.get(frame_id)
+                    .and_then(|f| f.content().text())
+                    .map(str::to_string)
+            };
+            // ID3v2.4 separates multiple values with null bytes
+            for v in value.iter().flat_map(|v| v.split('\0')) {
+                set.push(key, v);
+            }
+        }
+
+        let date = tag
+            .date_recorded()
+            .map(|d| d.to_string())
+            .or_else(|| tag.year().map(|y| y.to_string()));
+        if let Some(date) = date {
+            set.push("DATE", &date);
+        }
+        for (frame_id, number_key, total_key) in [("TRCK", "TRACKNUMBER", "TRACKTOTAL"), ("TPOS", "DISCNUMBER", "DISCTOTAL")] {
+            if let Some(value) = tag.get(frame_id).and_then(|f| f.content().text()) {
+                set.push_number(number_key, total_key, value);
+            }
+        }
+        if let Some(comment) = tag.comments().find(|c| c.description.is_empty()) {
+            set.push("COMMENT", &comment.text);
+        }
+        if let Some(lyrics) = tag.lyrics().next() {
+            set.push("LYRICS", &lyrics.text);
+        }
+
+        for text in tag.extended_texts() {
+            let key = MUSICBRAINZ_IDS
+                .iter()
+                .find(|(_, name)| name.eq_ignore_ascii_case(&text.description))
+                .map_or_else(|| text.description.to_uppercase(), |(key, _)| key.to_string());
+            set.push(&key, &text.value);
+        }
+        if set.get("MUSICBRAINZ_TRACKID").is_none() {
+            let recording = tag
+                .unique_file_identifiers()
+                .find(|u| u.owner_identifier == MUSICBRAINZ_UFID_OWNER);
+            if let Some(ufid) = recording {
+                set.push("MUSICBRAINZ_TRACKID", &String::from_utf8_lossy(&ufid.identifier));
+            }
+        }
+
+        set.pictures = tag
+            .pictures()
+            .map(|p| TagPicture {
+                picture_type: PICTURE_TYPES
+                    .get(u8::from(p.picture_type) as usize)
+                    .copied()
+                    .unwrap_or(PictureType::Other),
+                mime_type: p.mime_type.clone(),
+                description: p.description.clone(),
+                data: p.data.clone(),
+            })
+            .collect();
+        set
+    }
+
+    /// A new ID3 tag holding these fields. Values of repeated fields are
+    /// joined with "/", as ID3v2.3 has no multi-value frames.
+    pub fn to_id3(&self) -> Tag {
+        let mut tag = Tag::new();
+        for (key, values) in self.grouped() {
+            let joined = values.join("/");
+            if let Some((_, frame_id, _)) = TEXT_FIELDS.iter().find(|(k, _, _)| *k == key) {
+                tag.set_text(*frame_id, joined);
+            } else if !SPECIAL_FIELDS.contains(&key) {
+                let description = MUSICBRAINZ_IDS
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map_or(key, |(_, name)| name);
+                tag.add_frame(ExtendedText {
+                    description: description.to_string(),
+                    value: joined,
+                });
+            }
+        }
+
+        if let Some(year) = self
+            .get("DATE")
+            .and_then(|d| d.chars().take(4).collect::<String>().parse::<i32>().ok())
+        {
+            tag.set_year(year);
+        }
+        if let Some(track) = self.number("TRACKNUMBER") {
+            tag.set_track(track);
+            if let Some(total) = self.number("TRACKTOTAL") {
+                tag.set_total_tracks(total);
+            }
+        }
+        if let Some(disc) = self.number("DISCNUMBER") {
+            tag.set_disc(disc);
+            if let Some(total) = self.number("DISCTOTAL") {
+                tag.set_total_discs(total);
+            }
+        }
+        if let Some(text) = self.get("COMMENT") {
+            tag.add_frame(Comment {
+                lang: "eng".to_string(),
+                description: String::new(),
+                text: text.to_string(),
+            });
+        }
+        if let Some(text) = self.get("LYRICS") {
+            tag.add_frame(Lyrics {
+                lang: "eng".to_string(),
+                description: String::new(),
+                text: text.to_string(),
+            });
+        }
+        if let Some(recording) = self.get("MUSICBRAINZ_TRACKID") {
+            tag.add_frame(UniqueFileIdentifier {
+                owner_identifier: MUSICBRAINZ_UFID_OWNER.to_string(),
+                identifier: recording.as_bytes().to_vec(),
+            });
+        }
+
+        for picture in &self.pictures {
+            let code = PICTURE_TYPES
+                .iter()
+                .position(|t| *t == picture.picture_type)
+                .unwrap_or(0) as u8;
+            tag.add_frame(Picture {
+                mime_type: picture.mime_type.clone(),
+                picture_type: id3_picture_type(code),
+                description: picture.description.clone(),
+                data: picture.data.clone(),
+            });
+        }
+        tag
+    }
+
+    pub fn from_mp4(tag: &Mp4Tag) -> Self {
+        let mut set = TagSet::default();
+        for (atom, value) in tag.texts() {
+            let key = match atom {
+                mp4::YEAR => "DATE",
+                mp4::COMMENT => "COMMENT",
+                mp4::LYRICS => "LYRICS",
+                _ => match TEXT_FIELDS.iter().find(|(_, _, a)| *a == Some(atom)) {
+                    Some((key, _, _)) => key,
+                    None => continue,
+                },
+            };
+            set.push(key, &value);
+        }
+        for (kind, number_key, total_key) in [(mp4::TRACK, "TRACKNUMBER", "TRACKTOTAL"), (mp4::DISC, "DISCNUMBER", "DISCTOTAL")] {
+            if let Some((number, total)) = tag.number_pair(kind) {
+                set.push(number_key, &number.to_string());
+                if total > 0 {
+                    set.push(total_key, &total.to_string());
+                }
+            }
+        }
+        for (name, value) in tag.freeforms(mp4::ITUNES_MEAN) {
+            // iTunNORM, iTunSMPB etc. describe the old encode
+            if name.starts_with("iTun") {
+                continue;
+            }
+            let key = MUSICBRAINZ_IDS
+                .iter()
+                .find(|(_, n)| n.eq_ignore_ascii_case(&name))
+                .map_or_else(|| name.to_uppercase(), |(key, _)| key.to_string());
+            set.push(&key, &value);
+        }
+
+        // MP4 artwork has no picture types; the first image is the cover
+        set.pictures = tag
+            .artwork()
+            .into_iter()
+            .enumerate()
+            .map(|(i, (mime, data))| TagPicture {
+                picture_type: if i == 0 { PictureType::CoverFront } else { PictureType::Other },
+                mime_type: mime.to_string(),
+                description: String::new(),
+                data: data.to_vec(),
+            })
+            .collect();
+        set
+    }
+
+    /// A new MP4 tag holding these fields. Values of repeated fields are
+    /// joined with "; ". Only JPEG and PNG pictures can be stored, front
+    /// cover first.
+    pub fn to_mp4(&self) -> Mp4Tag {
+        let mut tag = Mp4Tag::default();
+        for (key, values) in self.grouped() {
+            let joined = values.join("; ");
+            match key {
+                "DATE" => tag.set_text(mp4::YEAR, &joined),
+                "COMMENT" => tag.set_text(mp4::COMMENT, &joined),
+                "LYRICS" => tag.set_text(mp4::LYRICS, &joined),
+                "TRACKNUMBER" | "TRACKTOTAL" | "DISCNUMBER" | "DISCTOTAL" => {}
+                _ => match TEXT_FIELDS.iter().find(|(k, _, _)| *k == key) {
+                    Some((_, _, Some(atom))) => tag.set_text(*atom, &joined),
+                    _ => {
+                        let name = match MUSICBRAINZ_IDS.iter().find(|(k, _)| *k == key) {
+                            Some((_, name)) => name.to_string(),
+                            // tag_replaygain writes these in lower case
+                            None if key.starts_with("REPLAYGAIN_") => key.to_lowercase(),
+                            None => key.to_string(),
+                        };
+                        tag.set_freeform(mp4::ITUNES_MEAN, &name, &joined);
+                    }
+                },
+            }
+        }
+        if let Some(track) = self.number("TRACKNUMBER") {
+            tag.set_number_pair(mp4::TRACK, track, self.number("TRACKTOTAL").unwrap_or(0));
+        }
+        if let Some(disc) = self.number("DISCNUMBER") {
+            tag.set_number_pair(mp4::DISC, disc, self.number("DISCTOTAL").unwrap_or(0));
+        }
+
+        let mut pictures: Vec<&TagPicture> = self
+            .pictures
+            .iter()
+            .filter(|p| matches!(p.mime_type.as_str(), "image/jpeg" | "image/jpg" | "image/png"))
+            .collect();
+        pictures.sort_by_key(|p| p.picture_type != PictureType::CoverFront);
+        tag.set_artworks(
+            pictures
+                .into_iter()
+                .map(|p| (p.mime_type.clone(), p.data.clone()))
+                .collect(),
+        );
+        tag
+    }
+}
+
+/// ID3 picture type for a FLAC/ID3 picture type code
+fn id3_picture_type(code: u8) -> id3::frame::PictureType {
+    use id3::frame::PictureType as T;
+    const TYPES: [T; 21] = [
+        T::Other,
+        T::Icon,
+        T::OtherIcon,
+        T::CoverFront,
+        T::CoverBack,
+        T::Leaflet,
+        T::Media,
+        T::LeadArtist,
+        T::Artist,
+        T::Conductor,
+        T::Band,
+        T::Composer,
+        T::Lyricist,
+        T::RecordingLocation,
+        T::DuringRecording,
+        T::DuringPerformance,
+        T::ScreenCapture,
+        T::BrightFish,
+        T::Illustration,
+        T::BandLogo,
+        T::PublisherLogo,
+    ];
+    TYPES.get(code as usize).copied().unwrap_or(T::Other)
+}
+
+fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    ImageReader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> TagSet {
+        let mut set = TagSet::default();
+        for (key, value) in [
+            ("TITLE", "Svefn-g-englar"),
+            ("ARTIST", "Sigur Rós"),
+            ("ALBUMARTIST", "Sigur Rós"),
+            ("ALBUM", "Ágætis byrjun"),
+            ("GENRE", "Post-Rock"),
+            ("COMPOSER", "Jón Þór Birgisson"),
+            ("DATE", "1999"),
+            ("TRACKNUMBER", "2"),
+            ("TRACKTOTAL", "10"),
+            ("DISCNUMBER", "1"),
+            ("DISCTOTAL", "1"),
+            ("ISRC", "GBAAA9900001"),
+            ("LABEL", "Smekkleysa"),
+            ("COMMENT", "Remastered"),
+            ("LYRICS", "Tjú tjú"),
+            ("MUSICBRAINZ_TRACKID", "d6e1a4e1-0000-0000-0000-000000000001"),
+            ("MUSICBRAINZ_ALBUMID", "d6e1a4e1-0000-0000-0000-000000000002"),
+            ("REPLAYGAIN_TRACK_GAIN", "-6.20 dB"),
+            ("QUALITY_WARNING", "low bitrate source"),
+        ] {
+            set.push(key, value);
+        }
+        set.pictures.push(TagPicture {
+            picture_type: PictureType::CoverFront,
+            mime_type: "image/jpeg".to_string(),
+            description: "cover".to_string(),
+            data: vec![0xff, 0xd8, 0xff],
+        });
+        set
+    }
+
+    fn sorted(set: &TagSet) -> Vec<(String, String)> {
+        let mut fields = set.fields.clone();
+        fields.sort();
+        fields
+    }
+
+    #[test]
+    fn test_id3_roundtrip() {
+        let set = sample();
+        let back = TagSet::from_id3(&set.to_id3());
+        assert_eq!(sorted(&back), sorted(&set));
+        assert_eq!(back.pictures, set.pictures);
+    }
+
+    #[test]
+    fn test_mp4_roundtrip() {
+        let set = sample();
+        let mp4 = set.to_mp4();
+        assert_eq!(mp4.freeform(mp4::ITUNES_MEAN, "replaygain_track_gain").as_deref(), Some("-6.20 dB"));
+        assert_eq!(mp4.number_pair(mp4::TRACK), Some((2, 10)));
+
+        let back = TagSet::from_mp4(&mp4);
+        assert_eq!(sorted(&back), sorted(&set));
+        // MP4 keeps no picture descriptions
+        assert_eq!(back.pictures[0].data, set.pictures[0].data);
+        assert_eq!(back.pictures[0].picture_type, PictureType::CoverFront);
+    }
+
+    #[test]
+    fn test_from_vorbis_normalizes_numbers() {
+        let mut tag = metaflac::Tag::new();
+        tag.set_vorbis("TRACKNUMBER", vec!["3/12"]);
+        tag.set_vorbis("TOTALDISCS", vec!["2"]);
+        tag.set_vorbis("YEAR", vec!["2001"]);
+        tag.set_vorbis("ENCODER", vec!["Lavf60.3.100"]);
+        tag.set_vorbis("ARTIST", vec!["A", "B"]);
+
+        let set = TagSet::from_vorbis(&tag);
+        assert_eq!(set.get("TRACKNUMBER"), Some("3"));
+        assert_eq!(set.get("TRACKTOTAL"), Some("12"));
+        assert_eq!(set.get("DISCTOTAL"), Some("2"));
+        assert_eq!(set.get("DATE"), Some("2001"));
+        assert_eq!(set.get("ENCODER"), None);
+        assert_eq!(set.to_id3().artist(), Some("A/B"));
+
+        let mut flac = metaflac::Tag::new();
+        set.write_vorbis(&mut flac);
+        assert_eq!(flac.get_vorbis("ARTIST").unwrap().collect::<Vec<_>>(), ["A", "B"]);
+        assert_eq!(flac.get_vorbis("TRACKNUMBER").unwrap().next(), Some("3"));
+    }
+}
@@ -0,0 +1,644 @@
+use anyhow::Context;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Data atom type codes used by iTunes
+const TYPE_IMPLICIT: u32 = 0;
+const TYPE_UTF8: u32 = 1;
+const TYPE_JPEG: u32 = 13;
+const TYPE_PNG: u32 = 14;
+const TYPE_INTEGER: u32 = 21;
+
+/// Mean of the freeform (`----`) atoms Picard and iTunes use for extra fields
+pub const ITUNES_MEAN: &str = "com.apple.iTunes";
+
+/// Well-known iTunes item atoms
+pub const TITLE: [u8; 4] = *b"\xa9nam";
+pub const ARTIST: [u8; 4] = *b"\xa9ART";
+pub const ALBUM: [u8; 4] = *b"\xa9alb";
+pub const GENRE: [u8; 4] = *b"\xa9gen";
+pub const YEAR: [u8; 4] = *b"\xa9day";
+pub const LYRICS: [u8; 4] = *b"\xa9lyr";
+pub const COMMENT: [u8; 4] = *b"\xa9cmt";
+pub const DESCRIPTION: [u8; 4] = *b"desc";
+pub const ALBUM_ARTIST: [u8; 4] = *b"aART";
+pub const COMPOSER: [u8; 4] = *b"\xa9wrt";
+pub const GROUPING: [u8; 4] = *b"\xa9grp";
+pub const COPYRIGHT: [u8; 4] = *b"cprt";
+pub const TITLE_SORT: [u8; 4] = *b"sonm";
+pub const ARTIST_SORT: [u8; 4] = *b"soar";
+pub const ALBUM_SORT: [u8; 4] = *b"soal";
+pub const ALBUM_ARTIST_SORT: [u8; 4] = *b"soaa";
+pub const TRACK: [u8; 4] = *b"trkn";
+pub const DISC: [u8; 4] = *b"disk";
+pub const COVER: [u8; 4] = *b"covr";
+pub const PODCAST: [u8; 4] = *b"pcst";
+/// ID3v1 numeric genre, superseded by `©gen`
+const GENRE_ID: [u8; 4] = *b"gnre";
+
+/// A raw MP4 atom: four character type and payload (without the header)
+#[derive(Debug, Clone)]
+struct Atom {
+    kind: [u8; 4],
+    payload: Vec<u8>,
+}
+
+impl Atom {
+    fn new(kind: [u8; 4], payload: Vec<u8>) -> Self {
+        Self { kind, payload }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.payload.len() + 8);
+        bytes.extend(((self.payload.len() + 8) as u32).to_be_bytes());
+        bytes.extend(self.kind);
+        bytes.extend(&self.payload);
+        bytes
+    }
+}
+
+/// Split a buffer into consecutive atoms
+fn parse_atoms(mut data: &[u8]) -> anyhow::Result<Vec<Atom>> {
+    let mut atoms = Vec::new();
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes(data[0..4].try_into().unwrap()) as u64;
+        let kind: [u8; 4] = data[4..8].try_into().unwrap();
+        let (header, size) = match size {
+            0 => (8, data.len() as u64),
+            1 => {
+                anyhow::ensure!(data.len() >= 16, "truncated MP4 atom");
+                (16, u64::from_be_bytes(data[8..16].try_into().unwrap()))
+            }
+            n => (8, n),
+        };
+        anyhow::ensure!(
+            size >= header && size <= data.len() as u64,
+            "malformed MP4 atom {:?}",
+            String::from_utf8_lossy(&kind)
+        );
+        atoms.push(Atom::new(kind, data[header as usize..size as usize].to_vec()));
+        data = &data[size as usize..];
+    }
+    Ok(atoms)
+}
+
+fn serialize_atoms(atoms: &[Atom]) -> Vec<u8> {
+    atoms.iter().flat_map(|a| a.to_bytes()).collect()
+}
+
+/// One value inside an item atom
+#[derive(Debug, Clone)]
+pub struct Data {
+    pub type_code: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// Item identifier: a plain four character code or a freeform `----` mean/name pair
+#[derive(Debug, Clone, PartialEq)]
+enum Ident {
+    Fourcc([u8; 4]),
+    Freeform { mean: String, name: String },
+}
+
+#[derive(Debug, Clone)]
+struct Item {
+    ident: Ident,
+    data: Vec<Data>,
+    /// Item atoms we don't understand are written back unchanged
+    raw: Option<Atom>,
+}
+
+impl Item {
+    fn parse(atom: &Atom) -> Self {
+        let children = parse_atoms(&atom.payload).unwrap_or_default();
+        let mut data = Vec::new();
+        let mut mean = None;
+        let mut name = None;
+        for child in &children {
+            let text = || String::from_utf8_lossy(child.payload.get(4..).unwrap_or_default()).to_string();
+            match &child.kind {
+                b"data" if child.payload.len() >= 8 => data.push(Data {
+                    type_code: u32::from_be_bytes(child.payload[0..4].try_into().unwrap()) & 0x00ff_ffff,
+                    bytes: child.payload[8..].to_vec(),
+                }),
+                b"mean" => mean = Some(text()),
+                b"name" => name = Some(text()),
+                _ => {}
+            }
+        }
+
+        let ident = match (&atom.kind, mean, name) {
+            (b"----", Some(mean), Some(name)) => Ident::Freeform { mean, name },
+            (b"----", _, _) => {
+                return Self {
+                    ident: Ident::Fourcc(atom.kind),
+                    data,
+                    raw: Some(atom.clone()),
+                }
+            }
+            _ => Ident::Fourcc(atom.kind),
+        };
+        Self {
+            ident,
+            data,
+            raw: None,
+        }
+    }
+
+    fn to_atom(&self) -> Atom {
+        if let Some(raw) = &self.raw {
+            return raw.clone();
+        }
+        let mut children = Vec::new();
+        let kind = match &self.ident {
+            Ident::Fourcc(kind) => *kind,
+            Ident::Freeform { mean, name } => {
+                for (kind, value) in [(*b"mean", mean), (*b"name", name)] {
+                    let mut payload = vec![0; 4];
+                    payload.extend(value.as_bytes());
+                    children.push(Atom::new(kind, payload));
+                }
+                *b"----"
+            }
+        };
+        for d in &self.data {
+            let mut payload = d.type_code.to_be_bytes().to_vec();
+            payload.extend([0; 4]);
+            payload.extend(&d.bytes);
+            children.push(Atom::new(*b"data", payload));
+        }
+        Atom::new(kind, serialize_atoms(&children))
+    }
+}
+
+/// iTunes-style metadata (`moov/udta/meta/ilst`) of an MP4/M4A file
+#[derive(Debug, Clone, Default)]
+pub struct Mp4Tag {
+    items: Vec<Item>,
+}
+
+impl Mp4Tag {
+    /// Read the `ilst` atom. Files without one return an empty tag.
+    pub fn read_from_path(file_path: &Path) -> anyhow::Result<Self> {
+        let mut file = File::open(file_path)
+            .with_context(|| format!("opening {}", file_path.display()))?;
+        let moov = read_moov(&mut file)?.context("not an MP4 file (no moov atom)")?;
+        let items = find_ilst(&moov)
+            .map(|ilst| ilst.iter().map(Item::parse).collect())
+            .unwrap_or_default();
+        Ok(Self { items })
+    }
+
+    /// Write the tag back, replacing the `ilst` atom. Chunk offsets are
+    /// adjusted when the `moov` atom sits before the audio data.
+    pub fn write_to_path(&self, file_path: &Path) -> anyhow::Result<()> {
+        let data = std::fs::read(file_path)
+            .with_context(|| format!("reading {}", file_path.display()))?;
+        let (moov_start, moov_end) = locate_top_level(&data, b"moov")?
+            .context("not an MP4 file (no moov atom)")?;
+        let moov_header = if u32::from_be_bytes(data[moov_start..moov_start + 4].try_into().unwrap()) == 1 {
+            16
+        } else {
+            8
+        };
+
+        let mut moov = parse_atoms(&data[moov_start + moov_header..moov_end])?;
+        let ilst = Atom::new(
+            *b"ilst",
+            serialize_atoms(&self.items.iter().map(Item::to_atom).collect::<Vec<_>>()),
+        );
+        replace_ilst(&mut moov, ilst)?;
+
+        let old_len = (moov_end - moov_start) as i64;
+        let new_len = serialize_atoms(&moov).len() as i64 + 8;
+        let delta = new_len - old_len;
+        if delta != 0 && moov_end < data.len() {
+            // Audio after moov moves by `delta` bytes
+            shift_chunk_offsets(&mut moov, moov_end as u64, delta)?;
+        }
+
+        let mut out = Vec::with_capacity(data.len() + delta.max(0) as usize);
+        out.extend(&data[..moov_start]);
+        out.extend(Atom::new(*b"moov", serialize_atoms(&moov)).to_bytes());
+        out.extend(&data[moov_end..]);
+
+        let temp_path = file_path.with_extension("tagtmp");
+        std::fs::write(&temp_path, out)
+            .with_context(|| format!("writing {}", temp_path.display()))?;
+        std::fs::rename(&temp_path, file_path)
+            .with_context(|| format!("replacing {}", file_path.display()))
+    }
+
+    fn item(&self, ident: &Ident) -> Option<&Item> {
+        self.items.iter().find(|i| &i.ident == ident)
+    }
+
+    fn set_item(&mut self, ident: Ident, data: Vec<Data>) {
+        self.items.retain(|i| i.ident != ident);
+        self.items.push(Item {
+            ident,
+            data,
+            raw: None,
+        });
+    }
+
+    fn remove_item(&mut self, ident: &Ident) {
+        self.items.retain(|i| &i.ident != ident);
+    }
+
+    /// Text value of an item atom
+    pub fn text(&self, kind: [u8; 4]) -> Option<String> {
+        let item = self.item(&Ident::Fourcc(kind))?;
+        let data = item.data.iter().find(|d| d.type_code == TYPE_UTF8)?;
+        Some(String::from_utf8_lossy(&data.bytes).to_string())
+    }
+
+    pub fn set_text(&mut self, kind: [u8; 4], value: &str) {
+        if kind == GENRE {
+            self.remove_item(&Ident::Fourcc(GENRE_ID));
+        }
+        self.set_item(
+            Ident::Fourcc(kind),
+            vec![Data {
+                type_code: TYPE_UTF8,
+                bytes: value.as_bytes().to_vec(),
+            }],
+        );
+    }
+
+    pub fn remove(&mut self, kind: [u8; 4]) {
+        self.remove_item(&Ident::Fourcc(kind));
+    }
+
+    pub fn has(&self, kind: [u8; 4]) -> bool {
+        self.item(&Ident::Fourcc(kind)).is_some_and(|i| !i.data.is_empty())
+    }
+
+    /// Freeform `----:mean:name` text value
+    pub fn freeform(&self, mean: &str, name: &str) -> Option<String> {
+        let item = self.item(&Ident::Freeform {
+            mean: mean.to_string(),
+            name: name.to_string(),
+        })?;
+        item.data
+            .first()
+            .map(|d| String::from_utf8_lossy(&d.bytes).to_string())
+    }
+
+    pub fn set_freeform(&mut self, mean: &str, name: &str, value: &str) {
+        self.set_item(
+            Ident::Freeform {
+                mean: mean.to_string(),
+                name: name.to_string(),
+            },
+            vec![Data {
+                type_code: TYPE_UTF8,
+                bytes: value.as_bytes().to_vec(),
+            }],
+        );
+    }
+
+    pub fn remove_freeform(&mut self, mean: &str, name: &str) {
+        self.remove_item(&Ident::Freeform {
+            mean: mean.to_string(),
+            name: name.to_string(),
+        });
+    }
+
+    /// Track number from `trkn`
+    pub fn track(&self) -> Option<u32> {
+        self.number_pair(TRACK).map(|(number, _)| number)
+    }
+
+    pub fn set_track(&mut self, track: u32) {
+        self.set_number_pair(TRACK, track, 0);
+    }
+
+    /// Number and total (0 if unknown) from `trkn` or `disk`
+    pub fn number_pair(&self, kind: [u8; 4]) -> Option<(u32, u32)> {
+        let data = self.item(&Ident::Fourcc(kind))?.data.first()?;
+        let number = u16::from_be_bytes(data.bytes.get(2..4)?.try_into().ok()?);
+        let total = data
+            .bytes
+            .get(4..6)
+            .map_or(0, |b| u16::from_be_bytes(b.try_into().unwrap()));
+        (number > 0).then_some((number as u32, total as u32))
+    }
+
+    /// Set `trkn` or `disk`; `disk` has no trailing padding
+    pub fn set_number_pair(&mut self, kind: [u8; 4], number: u32, total: u32) {
+        let mut bytes = vec![0, 0];
+        bytes.extend((number.min(u16::MAX as u32) as u16).to_be_bytes());
+        bytes.extend((total.min(u16::MAX as u32) as u16).to_be_bytes());
+        if kind == TRACK {
+            bytes.extend([0, 0]);
+        }
+        self.set_item(
+            Ident::Fourcc(kind),
+            vec![Data {
+                type_code: TYPE_IMPLICIT,
+                bytes,
+            }],
+        );
+    }
+
+    /// Every plain item atom holding text, as (atom, value)
+    pub fn texts(&self) -> Vec<([u8; 4], String)> {
+        self.items
+            .iter()
+            .filter_map(|item| match item.ident {
+                Ident::Fourcc(kind) => {
+                    let data = item.data.iter().find(|d| d.type_code == TYPE_UTF8)?;
+                    Some((kind, String::from_utf8_lossy(&data.bytes).to_string()))
+                }
+                Ident::Freeform { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Every freeform item under `mean`, as (name, value)
+    pub fn freeforms(&self, mean: &str) -> Vec<(String, String)> {
+        self.items
+            .iter()
+            .filter_map(|item| match &item.ident {
+                Ident::Freeform { mean: m, name } if m == mean => {
+                    let data = item.data.first()?;
+                    Some((name.clone(), String::from_utf8_lossy(&data.bytes).to_string()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Set a one-byte integer flag item (e.g. `pcst`)
+    pub fn set_flag(&mut self, kind: [u8; 4], value: bool) {
+        self.set_item(
+            Ident::Fourcc(kind),
+            vec![Data {
+                type_code: TYPE_INTEGER,
+                bytes: vec![value as u8],
+            }],
+        );
+    }
+
+    /// Embedded artwork as (MIME type, image bytes)
+    pub fn artwork(&self) -> Vec<(&'static str, &[u8])> {
+        self.item(&Ident::Fourcc(COVER))
+            .map(|item| {
+                item.data
+                    .iter()
+                    .map(|d| {
+                        let mime = if d.type_code == TYPE_PNG { "image/png" } else { "image/jpeg" };
+                        (mime, d.bytes.as_slice())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Replace all artwork with the given (MIME type, image bytes); only
+    /// JPEG and PNG can be stored
+    pub fn set_artworks(&mut self, images: Vec<(String, Vec<u8>)>) {
+        let data: Vec<Data> = images
+            .into_iter()
+            .map(|(mime, bytes)| Data {
+                type_code: if mime == "image/png" { TYPE_PNG } else { TYPE_JPEG },
+                bytes,
+            })
+            .collect();
+        if data.is_empty() {
+            self.remove(COVER);
+        } else {
+            self.set_item(Ident::Fourcc(COVER), data);
+        }
+    }
+
+    /// Replace all artwork with a single JPEG image
+    pub fn set_artwork(&mut self, jpeg: Vec<u8>) {
+        self.set_item(
+            Ident::Fourcc(COVER),
+            vec![Data {
+                type_code: TYPE_JPEG,
+                bytes: jpeg,
+            }],
+        );
+    }
+}
+
+/// Whether a file extension is an MP4 container we tag
+pub fn is_mp4_extension(extension: &str) -> bool {
+    matches!(extension, "m4a" | "m4b" | "mp4")
+}
+
+/// Byte range of a top-level atom
+fn locate_top_level(data: &[u8], kind: &[u8; 4]) -> anyhow::Result<Option<(usize, usize)>> {
+    let mut pos = 0usize;
+    while pos + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as u64;
+        let size = match size {
+            0 => (data.len() - pos) as u64,
+            1 => {
+                anyhow::ensure!(pos + 16 <= data.len(), "truncated MP4 atom");
+                u64::from_be_bytes(data[pos + 8..pos + 16].try_into().unwrap())
+            }
+            n => n,
+        };
+        anyhow::ensure!(size >= 8 && pos as u64 + size <= data.len() as u64, "malformed MP4 file");
+        if &data[pos + 4..pos + 8] == kind {
+            return Ok(Some((pos, pos + size as usize)));
+        }
+        pos += size as usize;
+    }
+    Ok(None)
+}
+
+/// Read the children of the `moov` atom, seeking past the audio data
+fn read_moov(file: &mut File) -> anyhow::Result<Option<Vec<Atom>>> {
+    let len = file.metadata()?.len();
+    let mut pos = 0u64;
+    while pos + 8 <= len {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut header)?;
+        let mut header_len = 8;
+        let size = match u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64 {
+            0 => len - pos,
+            1 => {
+                let mut large = [0u8; 8];
+                file.read_exact(&mut large)?;
+                header_len = 16;
+                u64::from_be_bytes(large)
+            }
+            n => n,
+        };
+        anyhow::ensure!(size >= header_len && pos + size <= len, "malformed MP4 file");
+        if &header[4..8] == b"moov" {
+            let mut payload = vec![0u8; (size - header_len) as usize];
+            file.read_exact(&mut payload)?;
+            return parse_atoms(&payload).map(Some);
+        }
+        pos += size;
+    }
+    Ok(None)
+}
+
+/// Offset of the children inside a `meta` atom. iTunes writes it as a full box
+/// (4 bytes of version/flags), QuickTime as a plain container.
+fn meta_children_offset(payload: &[u8]) -> usize {
+    if payload.get(4..8) == Some(b"hdlr") {
+        0
+    } else {
+        4
+    }
+}
+
+fn find_ilst(moov: &[Atom]) -> Option<Vec<Atom>> {
+    let udta = moov.iter().find(|a| &a.kind == b"udta")?;
+    let udta = parse_atoms(&udta.payload).ok()?;
+    let meta = udta.iter().find(|a| &a.kind == b"meta")?;
+    let meta = parse_atoms(&meta.payload[meta_children_offset(&meta.payload)..]).ok()?;
+    let ilst = meta.iter().find(|a| &a.kind == b"ilst")?;
+    parse_atoms(&ilst.payload).ok()
+}
+
+/// Put `ilst` into `moov/udta/meta`, creating the containers if needed
+fn replace_ilst(moov: &mut Vec<Atom>, ilst: Atom) -> anyhow::Result<()> {
+    let udta_index = match moov.iter().position(|a| &a.kind == b"udta") {
+        Some(i) => i,
+        None => {
+            moov.push(Atom::new(*b"udta", Vec::new()));
+            moov.len() - 1
+        }
+    };
+    let mut udta = parse_atoms(&moov[udta_index].payload)?;
+
+    let meta_index = match udta.iter().position(|a| &a.kind == b"meta") {
+        Some(i) => i,
+        None => {
+            // Full box header followed by the iTunes metadata handler
+            let mut hdlr = vec![0u8; 8];
+            hdlr.extend(b"mdir");
+            hdlr.extend(b"appl");
+            hdlr.extend([0u8; 9]);
+            let mut payload = vec![0u8; 4];
+            payload.extend(Atom::new(*b"hdlr", hdlr).to_bytes());
+            udta.push(Atom::new(*b"meta", payload));
+            udta.len() - 1
+        }
+    };
+    let meta_payload = &udta[meta_index].payload;
+    let offset = meta_children_offset(meta_payload);
+    let mut meta = parse_atoms(&meta_payload[offset..])?;
+    // Drop padding next to the old ilst; the file is rewritten anyway
+    meta.retain(|a| &a.kind != b"free");
+    match meta.iter_mut().find(|a| &a.kind == b"ilst") {
+        Some(existing) => *existing = ilst,
+        None => meta.push(ilst),
+    }
+
+    let mut payload = meta_payload[..offset].to_vec();
+    payload.extend(serialize_atoms(&meta));
+    udta[meta_index].payload = payload;
+    moov[udta_index].payload = serialize_atoms(&udta);
+    Ok(())
+}
+
+/// Add `delta` to every chunk offset (stco/co64) that points at or past `from`
+fn shift_chunk_offsets(atoms: &mut [Atom], from: u64, delta: i64) -> anyhow::Result<()> {
+    for atom in atoms.iter_mut() {
+        match &atom.kind {
+            b"trak" | b"mdia" | b"minf" | b"stbl" => {
+                let mut children = parse_atoms(&atom.payload)?;
+                shift_chunk_offsets(&mut children, from, delta)?;
+                atom.payload = serialize_atoms(&children);
+            }
+            b"stco" | b"co64" => {
+                let wide = &atom.kind == b"co64";
+                let width = if wide { 8 } else { 4 };
+                anyhow::ensure!(atom.payload.len() >= 8, "malformed chunk offset table");
+                let count = u32::from_be_bytes(atom.payload[4..8].try_into().unwrap()) as usize;
+                anyhow::ensure!(atom.payload.len() >= 8 + count * width, "malformed chunk offset table");
+                for i in 0..count {
+                    let at = 8 + i * width;
+                    let entry = &mut atom.payload[at..at + width];
+                    let offset = if wide {
+                        u64::from_be_bytes(entry[..].try_into().unwrap())
+                    } else {
+                        u32::from_be_bytes(entry[..].try_into().unwrap()) as u64
+                    };
+                    if offset < from {
+                        continue;
+                    }
+                    let shifted = (offset as i64 + delta) as u64;
+                    if wide {
+                        entry.copy_from_slice(&shifted.to_be_bytes());
+                    } else {
+                        let shifted = u32::try_from(shifted).context("chunk offset overflow, file too large")?;
+                        entry.copy_from_slice(&shifted.to_be_bytes());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_roundtrip() {
+        let mut tag = Mp4Tag::default();
+        tag.set_text(TITLE, "Song");
+        tag.set_track(7);
+        tag.set_freeform(ITUNES_MEAN, "ISRC", "USABC1234567");
+        tag.set_artwork(vec![0xff, 0xd8]);
+
+        let ilst = serialize_atoms(&tag.items.iter().map(Item::to_atom).collect::<Vec<_>>());
+        let parsed = Mp4Tag {
+            items: parse_atoms(&ilst).unwrap().iter().map(Item::parse).collect(),
+        };
+        assert_eq!(parsed.text(TITLE).as_deref(), Some("Song"));
+        assert_eq!(parsed.track(), Some(7));
+        assert_eq!(parsed.freeform(ITUNES_MEAN, "ISRC").as_deref(), Some("USABC1234567"));
+        assert_eq!(parsed.artwork(), vec![("image/jpeg", &[0xff, 0xd8][..])]);
+    }
+
+    #[test]
+    fn test_number_pairs_and_listing() {
+        let mut tag = Mp4Tag::default();
+        tag.set_number_pair(TRACK, 3, 12);
+        tag.set_number_pair(DISC, 1, 2);
+        tag.set_text(ALBUM_ARTIST, "Various");
+        tag.set_freeform(ITUNES_MEAN, "LABEL", "Warp");
+
+        assert_eq!(tag.track(), Some(3));
+        assert_eq!(tag.number_pair(TRACK), Some((3, 12)));
+        assert_eq!(tag.number_pair(DISC), Some((1, 2)));
+        assert_eq!(tag.texts(), vec![(ALBUM_ARTIST, "Various".to_string())]);
+        assert_eq!(tag.freeforms(ITUNES_MEAN), vec![("LABEL".to_string(), "Warp".to_string())]);
+
+        tag.set_artworks(vec![("image/png".to_string(), vec![0x89]), ("image/jpeg".to_string(), vec![0xff])]);
+        assert_eq!(tag.artwork(), vec![("image/png", &[0x89][..]), ("image/jpeg", &[0xff][..])]);
+    }
+
+    #[test]
+    fn test_shift_chunk_offsets() {
+        let mut stco = vec![0u8; 4];
+        stco.extend(2u32.to_be_bytes());
+        stco.extend(50u32.to_be_bytes());
+        stco.extend(500u32.to_be_bytes());
+        let stbl = Atom::new(*b"stbl", Atom::new(*b"stco", stco).to_bytes());
+        let mut atoms = vec![Atom::new(*b"trak", stbl.to_bytes())];
+
+        shift_chunk_offsets(&mut atoms, 100, 20).unwrap();
+
+        let stbl = &parse_atoms(&atoms[0].payload).unwrap()[0];
+        let stco = &parse_atoms(&stbl.payload).unwrap()[0];
+        assert_eq!(&stco.payload[8..12], &50u32.to_be_bytes());
+        assert_eq!(&stco.payload[12..16], &520u32.to_be_bytes());
+    }
+}
@@ -0,0 +1,369 @@
+use anyhow::Context;
+use base64::Engine;
+use metaflac::block::{Picture, PictureType, VorbisComment};
+use ogg::{PacketReader, PacketWriteEndInfo, PacketWriter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Vorbis comment field holding base64 encoded FLAC picture blocks (Ogg files only)
+const PICTURE_KEY: &str = "METADATA_BLOCK_PICTURE";
+
+/// Vorbis comments and pictures, shared by FLAC and Ogg (Vorbis/Opus) files so
+/// tagging code can treat them the same way
+pub trait VorbisTag {
+    /// First value of a field (keys are upper case)
+    fn comment(&self, key: &str) -> Option<String>;
+    /// Replace all values of a field
+    fn set_comment(&mut self, key: &str, value: String);
+    fn remove_comment(&mut self, key: &str);
+    /// All text comments (pictures excluded)
+    fn comments(&self) -> VorbisComment;
+    /// Replace all text comments, keeping pictures
+    fn set_comments(&mut self, comments: VorbisComment);
+    fn picture_blocks(&self) -> Vec<Picture>;
+    fn add_picture_block(&mut self, picture: Picture);
+    /// Remove pictures of the given type, or all pictures for `None`
+    fn remove_picture_blocks(&mut self, picture_type: Option<PictureType>);
+    fn save(&mut self, file_path: &Path) -> anyhow::Result<()>;
+
+    fn has_comment(&self, key: &str) -> bool {
+        self.comment(key).is_some()
+    }
+}
+
+impl VorbisTag for metaflac::Tag {
+    fn comment(&self, key: &str) -> Option<String> {
+        self.get_vorbis(key).and_then(|mut v| v.next()).map(|s| s.to_string())
+    }
+
+    fn set_comment(&mut self, key: &str, value: String) {
+        self.set_vorbis(key, vec![value]);
+    }
+
+    fn remove_comment(&mut self, key: &str) {
+        self.remove_vorbis(key);
+    }
+
+    fn comments(&self) -> VorbisComment {
+        self.vorbis_comments().cloned().unwrap_or_else(VorbisComment::new)
+    }
+
+    fn set_comments(&mut self, comments: VorbisComment) {
+        *self.vorbis_comments_mut() = comments;
+    }
+
+    fn picture_blocks(&self) -> Vec<Picture> {
+        self.pictures().cloned().collect()
+    }
+
+    fn add_picture_block(&mut self, picture: Picture) {
+        self.push_block(metaflac::Block::Picture(picture));
+    }
+
+    fn remove_picture_blocks(&mut self, picture_type: Option<PictureType>) {
+        match picture_type {
+            Some(t) => self.remove_picture_type(t),
+            None => self.remove_blocks(metaflac::BlockType::Picture),
+        }
+    }
+
+    fn save(&mut self, file_path: &Path) -> anyhow::Result<()> {
+        self.write_to_path(file_path)
+            .context("writing FLAC Vorbis comments")
+    }
+}
+
+/// Codec of an Ogg stream, which decides how the comment header is framed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OggCodec {
+    Vorbis,
+    Opus,
+}
+
+impl OggCodec {
+    fn detect(id_header: &[u8]) -> Option<Self> {
+        if id_header.starts_with(b"\x01vorbis") {
+            Some(OggCodec::Vorbis)
+        } else if id_header.starts_with(b"OpusHead") {
+            Some(OggCodec::Opus)
+        } else {
+            None
+        }
+    }
+
+    fn comment_magic(self) -> &'static [u8] {
+        match self {
+            OggCodec::Vorbis => b"\x03vorbis",
+            OggCodec::Opus => b"OpusTags",
+        }
+    }
+}
+
+/// The comment header of an Ogg Vorbis or Opus file
+pub struct OggTag {
+    codec: OggCodec,
+    comments: VorbisComment,
+}
+
+impl OggTag {
+    /// Read the comment header (the second packet of the first logical stream)
+    pub fn read_from_path(file_path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(file_path)
+            .with_context(|| format!("opening {}", file_path.display()))?;
+        let mut reader = PacketReader::new(BufReader::new(file));
+
+        let id_header = reader
+            .read_packet()
+            .context("reading Ogg stream")?
+            .context("empty Ogg file")?;
+        let codec = OggCodec::detect(&id_header.data)
+            .context("Ogg stream is neither Vorbis nor Opus")?;
+
+        let serial = id_header.stream_serial();
+        loop {
+            let packet = reader
+                .read_packet()
+                .context("reading Ogg stream")?
+                .context("Ogg comment header missing")?;
+            if packet.stream_serial() == serial {
+                return Ok(Self {
+                    codec,
+                    comments: parse_comment_packet(codec, &packet.data)?,
+                });
+            }
+        }
+    }
+
+    /// Rewrite the file with the new comment header. Every other packet is
+    /// copied with its original page boundaries and granule positions.
+    pub fn write_to_path(&self, file_path: &Path) -> anyhow::Result<()> {
+        let file = File::open(file_path)
+            .with_context(|| format!("opening {}", file_path.display()))?;
+        let mut reader = PacketReader::new(BufReader::new(file));
+
+        let temp_path = file_path.with_extension("tagtmp");
+        let out = File::create(&temp_path)
+            .with_context(|| format!("creating {}", temp_path.display()))?;
+        let mut writer = PacketWriter::new(BufWriter::new(out));
+
+        let result = (|| -> anyhow::Result<()> {
+            let mut tagged_serial: Option<u32> = None;
+            let mut packet_index = 0;
+
+            while let Some(packet) = reader.read_packet().context("reading Ogg stream")? {
+                let serial = packet.stream_serial();
+                let serial = *tagged_serial.get_or_insert(serial);
+                let data = if packet.stream_serial() == serial {
+                    packet_index += 1;
+                    if packet_index == 2 {
+                        self.comment_packet()
+                    } else {
+                        packet.data.clone()
+                    }
+                } else {
+                    packet.data.clone()
+                };
+
+                let end_info = if packet.last_in_stream() {
+                    PacketWriteEndInfo::EndStream
+                } else if packet.last_in_page() {
+                    PacketWriteEndInfo::EndPage
+                } else {
+                    PacketWriteEndInfo::NormalPacket
+                };
+                writer
+                    .write_packet(data.into_boxed_slice(), packet.stream_serial(), end_info, packet.absgp_page())
+                    .context("writing Ogg stream")?;
+            }
+
+            writer.inner_mut().flush().context("writing Ogg stream")?;
+            Ok(())
+        })();
+
+        drop(writer);
+        match result {
+            Ok(()) => std::fs::rename(&temp_path, file_path)
+                .with_context(|| format!("replacing {}", file_path.display())),
+            Err(e) => {
+                let _ = std::fs::remove_file(&temp_path);
+                Err(e)
+            }
+        }
+    }
+
+    fn comment_packet(&self) -> Vec<u8> {
+        let mut data = self.codec.comment_magic().to_vec();
+        data.extend(self.comments.to_bytes());
+        if self.codec == OggCodec::Vorbis {
+            // Vorbis comment headers end with a framing bit
+            data.push(1);
+        }
+        data
+    }
+}
+
+fn parse_comment_packet(codec: OggCodec, data: &[u8]) -> anyhow::Result<VorbisComment> {
+    let body = data
+        .strip_prefix(codec.comment_magic())
+        .context("malformed Ogg comment header")?;
+    VorbisComment::from_bytes(body).context("parsing Ogg comment header")
+}
+
+impl VorbisTag for OggTag {
+    fn comment(&self, key: &str) -> Option<String> {
+        self.comments.get(key).and_then(|v| v.first()).cloned()
+    }
+
+    fn set_comment(&mut self, key: &str, value: String) {
+        self.comments.set(key, vec![value]);
+    }
+
+    fn remove_comment(&mut self, key: &str) {
+        self.comments.remove(key);
+    }
+
+    fn comments(&self) -> VorbisComment {
+        let mut comments = self.comments.clone();
+        comments.remove(PICTURE_KEY);
+        comments
+    }
+
+    fn set_comments(&mut self, mut comments: VorbisComment) {
+        if let Some(pictures) = self.comments.get(PICTURE_KEY) {
+            comments.set(PICTURE_KEY, pictures.clone());
+        } else {
+            comments.remove(PICTURE_KEY);
+        }
+        self.comments = comments;
+    }
+
+    fn picture_blocks(&self) -> Vec<Picture> {
+        self.comments
+            .get(PICTURE_KEY)
+            .into_iter()
+            .flatten()
+            .filter_map(|encoded| base64::engine::general_purpose::STANDARD.decode(encoded).ok())
+            .filter_map(|bytes| Picture::from_bytes(&bytes).ok())
+            .collect()
+    }
+
+    fn add_picture_block(&mut self, picture: Picture) {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(picture.to_bytes());
+        self.comments
+            .comments
+            .entry(PICTURE_KEY.to_string())
+            .or_default()
+            .push(encoded);
+    }
+
+    fn remove_picture_blocks(&mut self, picture_type: Option<PictureType>) {
+        let keep: Vec<Picture> = match picture_type {
+            Some(t) => self
+                .picture_blocks()
+                .into_iter()
+                .filter(|p| p.picture_type != t)
+                .collect(),
+            None => Vec::new(),
+        };
+        self.comments.remove(PICTURE_KEY);
+        for picture in keep {
+            self.add_picture_block(picture);
+        }
+    }
+
+    fn save(&mut self, file_path: &Path) -> anyhow::Result<()> {
+        self.write_to_path(file_path)
+    }
+}
+
+/// Copy all comments and pictures from one tag to another, replacing what
+/// `dest` had
+pub fn copy(src: &dyn VorbisTag, dest: &mut dyn VorbisTag) {
+    dest.set_comments(src.comments());
+    dest.remove_picture_blocks(None);
+    for picture in src.picture_blocks() {
+        dest.add_picture_block(picture);
+    }
+}
+
+/// Whether a file extension is one of the Ogg containers we tag
+pub fn is_ogg_extension(extension: &str) -> bool {
+    matches!(extension, "ogg" | "oga" | "opus")
+}
+
+/// Open the Vorbis comments of a FLAC or Ogg file, or `None` for other formats
+pub fn open(file_path: &Path, extension: Option<&str>) -> Option<anyhow::Result<Box<dyn VorbisTag>>> {
+    match extension {
+        Some("flac") => Some(
+            metaflac::Tag::read_from_path(file_path)
+                .context("reading FLAC file")
+                .map(|t| Box::new(t) as Box<dyn VorbisTag>),
+        ),
+        Some(ext) if is_ogg_extension(ext) => {
+            Some(OggTag::read_from_path(file_path).map(|t| Box::new(t) as Box<dyn VorbisTag>))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comment_packet_roundtrip() {
+        for codec in [OggCodec::Vorbis, OggCodec::Opus] {
+            let mut comments = VorbisComment::new();
+            comments.vendor_string = "rustwav".to_string();
+            let mut tag = OggTag { codec, comments };
+            tag.set_comment("TITLE", "Song".to_string());
+            tag.add_picture_block(Picture {
+                picture_type: PictureType::CoverFront,
+                mime_type: "image/jpeg".to_string(),
+                description: String::new(),
+                width: 1,
+                height: 1,
+                depth: 24,
+                num_colors: 0,
+                data: vec![1, 2, 3],
+            });
+
+            let parsed = parse_comment_packet(codec, &tag.comment_packet()).unwrap();
+            let parsed = OggTag { codec, comments: parsed };
+            assert_eq!(parsed.comment("TITLE").as_deref(), Some("Song"));
+            assert_eq!(parsed.picture_blocks()[0].data, vec![1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn test_copy_replaces_comments_and_pictures() {
+        let picture = |data: Vec<u8>| Picture {
+            picture_type: PictureType::CoverFront,
+            mime_type: "image/jpeg".to_string(),
+            description: String::new(),
+            width: 1,
+            height: 1,
+            depth: 24,
+            num_colors: 0,
+            data,
+        };
+
+        let mut src = OggTag { codec: OggCodec::Opus, comments: VorbisComment::new() };
+        src.set_comment("TITLE", "New".to_string());
+        src.add_picture_block(picture(vec![1]));
+
+        let mut dest = metaflac::Tag::new();
+        dest.set_comment("TITLE", "Old".to_string());
+        dest.set_comment("ARTIST", "Gone".to_string());
+        dest.add_picture_block(picture(vec![2]));
+
+        copy(&src, &mut dest);
+        assert_eq!(dest.comment("TITLE").as_deref(), Some("New"));
+        assert!(!dest.has_comment("ARTIST"));
+        assert!(!dest.has_comment(PICTURE_KEY));
+        let pictures = dest.picture_blocks();
+        assert_eq!(pictures.len(), 1);
+        assert_eq!(pictures[0].data, vec![1]);
+    }
+}
@@ -0,0 +1,252 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use uuid::Uuid;
+
+use crate::db::{DownloadDB, TrackEntry};
+
+/// Default location of the trash
+pub const TRASH_DIR: &str = "data/trash";
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// Hold while opening and writing the trash from a thread that may run
+/// alongside others, so parallel writers don't drop each other's items
+pub fn lock() -> MutexGuard<'static, ()> {
+    MANIFEST_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Something removed from the library: a file, its download cache entry,
+/// or both
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrashItem {
+    pub id: String,
+    pub deleted_at: DateTime<Utc>,
+    /// Where the file was in the library
+    pub original_path: String,
+    /// Where the file is kept in the trash; unset when only the cache entry
+    /// was removed (the file was already gone)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trashed_path: Option<String>,
+    /// The track's download cache entry, as it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry: Option<TrackEntry>,
+    /// Why it was removed, e.g. "converted to mp3"
+    pub reason: String,
+}
+
+impl TrashItem {
+    /// Short ID for display; any unique prefix of the ID can be restored
+    pub fn short_id(&self) -> &str {
+        &self.id[..8.min(self.id.len())]
+    }
+
+    /// Size of the trashed file in bytes (0 if there is none)
+    pub fn size(&self) -> u64 {
+        self.trashed_path
+            .as_ref()
+            .and_then(|p| fs::metadata(p).ok())
+            .map_or(0, |m| m.len())
+    }
+
+    /// "Artist - Title" if the cache entry is known, else the file name
+    pub fn display_name(&self) -> String {
+        match &self.entry {
+            Some(entry) => format!("{} - {}", entry.artist, entry.title),
+            None => Path::new(&self.original_path)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| self.original_path.clone()),
+        }
+    }
+}
+
+/// rustwav-managed trash. Library files are moved here instead of being
+/// deleted, with a manifest recording where they came from, so they can be
+/// restored until the trash is emptied.
+pub struct Trash {
+    dir: PathBuf,
+    items: Vec<TrashItem>,
+}
+
+impl Trash {
+    pub fn new(dir: &str) -> Self {
+        let dir = PathBuf::from(dir);
+        let items = fs::read_to_string(dir.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self { dir, items }
+    }
+
+    /// Open the default trash
+    pub fn open() -> Self {
+        Self::new(TRASH_DIR)
+    }
+
+    /// Trashed items, oldest first
+    pub fn items(&self) -> &[TrashItem] {
+        &self.items
+    }
+
+    /// Move a library file into the trash, along with its cache entry
+    pub fn trash_file(&mut self, path: &Path, entry: Option<TrackEntry>, reason: &str) -> anyhow::Result<()> {
+        let id = Uuid::new_v4().to_string();
+        let file_name = path.file_name().context("not a file path")?;
+        // One folder per item keeps the file name readable without collisions
+        let item_dir = self.dir.join("files").join(&id);
+        fs::create_dir_all(&item_dir).with_context(|| format!("creating {}", item_dir.display()))?;
+        let trashed = item_dir.join(file_name);
+        if let Err(e) = move_file(path, &trashed) {
+            let _ = fs::remove_dir(&item_dir);
+            return Err(e);
+        }
+
+        self.items.push(TrashItem {
+            id,
+            deleted_at: Utc::now(),
+            original_path: path.display().to_string(),
+            trashed_path: Some(trashed.display().to_string()),
+            entry,
+            reason: reason.to_string(),
+        });
+        self.save()
+    }
+
+    /// Record a cache entry removed without a file (the file was already gone)
+    pub fn trash_entry(&mut self, entry: TrackEntry, reason: &str) -> anyhow::Result<()> {
+        self.items.push(TrashItem {
+            id: Uuid::new_v4().to_string(),
+            deleted_at: Utc::now(),
+            original_path: entry.path.clone(),
+            trashed_path: None,
+            entry: Some(entry),
+            reason: reason.to_string(),
+        });
+        self.save()
+    }
+
+    /// Find an item by a unique prefix of its ID
+    pub fn find(&self, id: &str) -> anyhow::Result<&TrashItem> {
+        let mut matches = self.items.iter().filter(|item| item.id.starts_with(id));
+        match (matches.next(), matches.next()) {
+            (Some(item), None) if !id.is_empty() => Ok(item),
+            (Some(_), _) => anyhow::bail!("Trash ID {} is ambiguous", id),
+            (None, _) => anyhow::bail!("Nothing in the trash with ID {}", id),
+        }
+    }
+
+    /// Put an item back where it was and re-add its cache entry. A file
+    /// whose track was converted in the meantime comes back as a format
+    /// variant of the converted file.
+    pub fn restore(&mut self, id: &str, db: &mut DownloadDB) -> anyhow::Result<TrashItem> {
+        let item = self.find(id)?.clone();
+        let original = Path::new(&item.original_path);
+
+        if let Some(trashed) = &item.trashed_path {
+            if original.exists() {
+                anyhow::bail!("{} already exists; move it away first", original.display());
+            }
+            if let Some(parent) = original.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+            }
+            move_file(Path::new(trashed), original)?;
+            if let Some(item_dir) = Path::new(trashed).parent() {
+                let _ = fs::remove_dir(item_dir);
+            }
+        }
+
+        if let Some(entry) = &item.entry {
+            let current = db
+                .all_tracks()
+                .into_iter()
+                .find(|t| t.artist == entry.artist && t.title == entry.title && t.path != entry.path)
+                .map(|t| t.path.clone());
+            match current {
+                Some(current) if item.trashed_path.is_some() => {
+                    db.add_variant(&current, &entry.path);
+                }
+                _ => {
+                    if db.find_by_path(&entry.path).is_none() {
+                        db.add(entry.clone());
+                    }
+                }
+            }
+        }
+
+        self.items.retain(|i| i.id != item.id);
+        self.save()?;
+        Ok(item)
+    }
+
+    /// Permanently delete trashed items, all of them or only those older
+    /// than `min_age`. Returns the number of items and bytes freed.
+    pub fn empty(&mut self, min_age: Option<chrono::Duration>) -> anyhow::Result<(usize, u64)> {
+        let cutoff = min_age.map(|age| Utc::now() - age);
+        let (expired, kept): (Vec<TrashItem>, Vec<TrashItem>) = self
+            .items
+            .drain(..)
+            .partition(|item| cutoff.is_none_or(|cutoff| item.deleted_at <= cutoff));
+        self.items = kept;
+
+        let mut freed = 0;
+        for item in &expired {
+            freed += item.size();
+            if let Some(item_dir) = item.trashed_path.as_ref().and_then(|p| Path::new(p).parent()) {
+                let _ = fs::remove_dir_all(item_dir);
+            }
+        }
+        self.save()?;
+        Ok((expired.len(), freed))
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir).with_context(|| format!("creating {}", self.dir.display()))?;
+        let json = serde_json::to_string_pretty(&self.items)?;
+        fs::write(self.dir.join(MANIFEST_FILE), json).context("writing trash manifest")
+    }
+}
+
+/// Move a file, copying it when source and target are on different filesystems
+fn move_file(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to).with_context(|| format!("moving {} to {}", from.display(), to.display()))?;
+    fs::remove_file(from).with_context(|| format!("removing {}", from.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str) -> TrashItem {
+        TrashItem {
+            id: id.to_string(),
+            deleted_at: Utc::now(),
+            original_path: "data/music/A/B/01 - Song.flac".to_string(),
+            trashed_path: None,
+            entry: None,
+            reason: "converted to mp3".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_find_by_prefix() {
+        let trash = Trash {
+            dir: PathBuf::from(TRASH_DIR),
+            items: vec![item("3f2a9c10-aaaa"), item("3f2b0000-bbbb")],
+        };
+        assert_eq!(trash.find("3f2a").unwrap().id, "3f2a9c10-aaaa");
+        assert!(trash.find("3f2").is_err());
+        assert!(trash.find("ffff").is_err());
+        assert!(trash.find("").is_err());
+        assert_eq!(trash.items()[0].short_id(), "3f2a9c10");
+        assert_eq!(trash.items()[0].display_name(), "01 - Song.flac");
+    }
+}
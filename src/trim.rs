@@ -0,0 +1,154 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::config::TrimConfig;
+use crate::converter;
+
+/// Cuts shorter than this are not worth a re-encode
+const MIN_TRIM_SECS: f64 = 0.05;
+
+/// Shortest stretch `silencedetect` reports
+const MIN_SILENCE_SECS: f64 = 0.1;
+
+/// What shortened a download
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrimKind {
+    /// Leading and trailing silence
+    Silence,
+    /// SponsorBlock `music_offtopic` segments removed by yt-dlp
+    Sponsorblock,
+}
+
+/// A cut applied to a download. Kept in the database so the file's duration
+/// can still be compared with the original track length.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Trim {
+    pub kind: TrimKind,
+    /// Audio removed, in seconds
+    pub removed_secs: f64,
+}
+
+/// Total length of possibly overlapping `(start, end)` segments
+pub fn covered_secs(mut segments: Vec<(f64, f64)>) -> f64 {
+    segments.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut total = 0.0;
+    let mut reached = f64::NEG_INFINITY;
+    for (start, end) in segments {
+        let start = start.max(reached);
+        if end > start {
+            total += end - start;
+            reached = end;
+        }
+    }
+    total
+}
+
+/// Find the part of a file between its leading and trailing silence from a
+/// `silencedetect` log. Returns `None` when there is nothing worth cutting.
+fn audible_range(log: &str, duration: f64, keep_secs: f64) -> Option<(f64, f64)> {
+    let value = |line: &str, key: &str| -> Option<f64> {
+        let rest = &line[line.find(key)? + key.len()..];
+        rest.split_whitespace().next()?.parse().ok()
+    };
+
+    let mut silences: Vec<(f64, f64)> = Vec::new();
+    for line in log.lines().filter(|l| l.contains("silencedetect")) {
+        if let Some(start) = value(line, "silence_start: ") {
+            silences.push((start.max(0.0), duration));
+        } else if let Some(end) = value(line, "silence_end: ") {
+            if let Some(last) = silences.last_mut() {
+                last.1 = end;
+            }
+        }
+    }
+
+    let leading = silences
+        .first()
+        .filter(|(start, _)| *start <= MIN_TRIM_SECS)
+        .map_or(0.0, |(_, end)| end - keep_secs);
+    let trailing = silences
+        .last()
+        .filter(|(_, end)| *end >= duration - MIN_TRIM_SECS)
+        .map_or(duration, |(start, _)| start + keep_secs);
+
+    let start = leading.max(0.0);
+    let end = trailing.min(duration);
+    if end <= start || start + (duration - end) < MIN_TRIM_SECS {
+        return None;
+    }
+    Some((start, end))
+}
+
+/// Cut leading and trailing silence from a file in place. Silence is found
+/// with FFmpeg's `silencedetect` first so files without any are never
+/// re-encoded. Returns the applied trim, if any.
+pub fn trim_silence(file_path: &Path, config: &TrimConfig) -> anyhow::Result<Option<Trim>> {
+    let filter = format!(
+        "silencedetect=noise={}dB:duration={}",
+        config.threshold_db, MIN_SILENCE_SECS
+    );
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(file_path)
+        .args(["-vn", "-af", &filter, "-f", "null", "-"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to spawn FFmpeg. Is it installed?")?;
+
+    if !output.status.success() {
+        anyhow::bail!("FFmpeg silence detection failed for: {}", file_path.display());
+    }
+
+    let log = String::from_utf8_lossy(&output.stderr);
+    let stream = converter::parse_stream_info(&log);
+    let duration = stream.duration_secs.context("FFmpeg reported no duration")?;
+    let Some((start, end)) = audible_range(&log, duration, config.keep_secs) else {
+        return Ok(None);
+    };
+
+    let filter = format!("atrim=start={:.3}:end={:.3},asetpts=PTS-STARTPTS", start, end);
+    converter::filter_in_place(file_path, &filter, &stream)?;
+    Ok(Some(Trim {
+        kind: TrimKind::Silence,
+        removed_secs: start + (duration - end),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_covered_secs() {
+        assert_eq!(covered_secs(vec![]), 0.0);
+        assert_eq!(covered_secs(vec![(10.0, 20.0), (0.0, 5.0)]), 15.0);
+        // Overlapping segments are only counted once
+        assert_eq!(covered_secs(vec![(0.0, 10.0), (5.0, 12.0), (6.0, 8.0)]), 12.0);
+    }
+
+    #[test]
+    fn test_audible_range() {
+        let log = "[silencedetect @ 0x1] silence_start: 0\n\
+                   [silencedetect @ 0x1] silence_end: 2.5 | silence_duration: 2.5\n\
+                   [silencedetect @ 0x1] silence_start: 60.25\n\
+                   [silencedetect @ 0x1] silence_end: 61 | silence_duration: 0.75\n\
+                   [silencedetect @ 0x1] silence_start: 170\n\
+                   [silencedetect @ 0x1] silence_end: 180 | silence_duration: 10\n";
+        assert_eq!(audible_range(log, 180.0, 0.0), Some((2.5, 170.0)));
+        assert_eq!(audible_range(log, 180.0, 0.5), Some((2.0, 170.5)));
+
+        // Trailing silence running into the end of the stream has no silence_end
+        let log = "[silencedetect @ 0x1] silence_start: 175.5\n";
+        assert_eq!(audible_range(log, 180.0, 0.0), Some((0.0, 175.5)));
+
+        // Only silence in the middle of the track
+        let log = "[silencedetect @ 0x1] silence_start: 60\n\
+                   [silencedetect @ 0x1] silence_end: 62 | silence_duration: 2\n";
+        assert_eq!(audible_range(log, 180.0, 0.0), None);
+        assert_eq!(audible_range("", 180.0, 0.0), None);
+    }
+}
@@ -1,17 +1,29 @@
+use crate::config::{Config, EncoderSettings, PlaylistFormat};
+use crate::converter;
 use crate::db::{DownloadDB, TrackEntry};
 use crate::error_log::{
     ConvertErrorEntry, DownloadErrorEntry, ErrorLogManager, RefreshErrorEntry,
 };
 use crate::file_utils;
+use crate::playlist_index;
+use crate::regenerate::{self, PlaylistSource, PlaylistSources, SourceKind};
+use crate::smart::{SmartPlaylist, SmartPlaylists};
 use crate::sources::{spotify, youtube};
+use crate::sync::{self, TrackedPlaylists};
+use crate::trash::Trash;
 use std::collections::VecDeque;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tokio::sync::{mpsc, watch};
 
-use super::worker::{ConvertTrackInfo, DownloadEvent, DownloadRequest};
+use super::worker::{lock, ConvertTrackInfo, DownloadEvent, DownloadRequest};
 
 // Format and quality options
-pub const FORMAT_OPTIONS: [&str; 4] = ["mp3", "flac", "wav", "aac"];
+pub const FORMAT_OPTIONS: [&str; 9] = ["mp3", "flac", "wav", "aac", "opus", "ogg", "m4a", "alac", "aiff"];
+/// Downloads can also keep the source codec as-is
+pub const DOWNLOAD_FORMAT_OPTIONS: [&str; 10] =
+    ["mp3", "flac", "wav", "aac", "opus", "ogg", "m4a", "alac", "aiff", "original"];
 pub const QUALITY_OPTIONS: [&str; 3] = ["high", "medium", "low"];
 
 #[derive(Debug, Clone, PartialEq)]
@@ -28,7 +40,12 @@ pub enum View {
     ConvertConfirm,
     ConvertBatchConfirm,
     CleanupConfirm,
+    NormalizeConfirm,
     ErrorLog,
+    Trash,
+    Tracked,
+    Smart,
+    RegenerateConfirm,
 }
 
 /// Tab for error log view (Download/Convert/Refresh)
@@ -43,6 +60,8 @@ pub enum ErrorTab {
 pub enum LinkType {
     Album,
     Playlist,
+    /// Spotify playlist to download and keep in sync
+    TrackedPlaylist,
     YouTubePlaylist,
 }
 
@@ -50,6 +69,139 @@ pub enum LinkType {
 pub enum SettingsField {
     Format,
     Quality,
+    Lyrics,
+    Playlists,
+}
+
+/// Advanced encoder option rows of the convert settings view
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncoderField {
+    Vbr,
+    Bitrate,
+    OpusApplication,
+    CompressionLevel,
+    SampleRate,
+    BitDepth,
+    Channels,
+    Dither,
+}
+
+pub const ENCODER_FIELDS: [EncoderField; 8] = [
+    EncoderField::Vbr,
+    EncoderField::Bitrate,
+    EncoderField::OpusApplication,
+    EncoderField::CompressionLevel,
+    EncoderField::SampleRate,
+    EncoderField::BitDepth,
+    EncoderField::Channels,
+    EncoderField::Dither,
+];
+
+const SAMPLE_RATE_OPTIONS: [Option<u32>; 6] =
+    [None, Some(22050), Some(32000), Some(44100), Some(48000), Some(96000)];
+const BIT_DEPTH_OPTIONS: [Option<u8>; 3] = [None, Some(16), Some(24)];
+const BITRATE_OPTIONS: [Option<u32>; 8] =
+    [None, Some(64), Some(96), Some(128), Some(160), Some(192), Some(256), Some(320)];
+
+impl EncoderField {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EncoderField::Vbr => "VBR",
+            EncoderField::Bitrate => "Bitrate",
+            EncoderField::OpusApplication => "Opus app",
+            EncoderField::CompressionLevel => "FLAC level",
+            EncoderField::SampleRate => "Sample rate",
+            EncoderField::BitDepth => "Bit depth",
+            EncoderField::Channels => "Channels",
+            EncoderField::Dither => "Dither",
+        }
+    }
+
+    /// Whether the option means anything for the target format
+    pub fn applies(&self, format: &str) -> bool {
+        match self {
+            EncoderField::Vbr => converter::supports_vbr(format),
+            EncoderField::Bitrate => !converter::is_lossless_format(format),
+            EncoderField::OpusApplication => converter::supports_opus_application(format),
+            EncoderField::CompressionLevel => converter::supports_compression_level(format),
+            EncoderField::BitDepth | EncoderField::Dither => converter::supports_bit_depth(format),
+            EncoderField::SampleRate | EncoderField::Channels => true,
+        }
+    }
+
+    /// Current value for display
+    pub fn value(&self, settings: &EncoderSettings) -> String {
+        match self {
+            EncoderField::Vbr => settings.vbr.map_or("off (CBR)".to_string(), |v| format!("V{}", v)),
+            EncoderField::Bitrate => settings
+                .bitrate_kbps
+                .map_or("quality preset".to_string(), |k| format!("{} kbit/s", k)),
+            EncoderField::OpusApplication => settings
+                .opus_application
+                .clone()
+                .unwrap_or_else(|| "default".to_string()),
+            EncoderField::CompressionLevel => settings
+                .compression_level
+                .map_or("default".to_string(), |l| l.to_string()),
+            EncoderField::SampleRate => settings
+                .sample_rate
+                .map_or("source".to_string(), |r| format!("{} Hz", r)),
+            EncoderField::BitDepth => settings
+                .bit_depth
+                .map_or("source".to_string(), |d| format!("{}-bit", d)),
+            EncoderField::Channels => if settings.mono { "mono" } else { "source" }.to_string(),
+            EncoderField::Dither => if settings.dither { "on" } else { "off" }.to_string(),
+        }
+    }
+
+    /// Step the option to its next (or previous) value
+    fn cycle(&self, settings: &mut EncoderSettings, forward: bool) {
+        match self {
+            EncoderField::Vbr => {
+                let options: Vec<Option<u8>> = std::iter::once(None).chain((0..=9).map(Some)).collect();
+                settings.vbr = cycle(&options, &settings.vbr, forward);
+                // VBR replaces the fixed bitrate
+                if settings.vbr.is_some() {
+                    settings.bitrate_kbps = None;
+                }
+            }
+            EncoderField::Bitrate => {
+                settings.bitrate_kbps = cycle(&BITRATE_OPTIONS, &settings.bitrate_kbps, forward);
+                if settings.bitrate_kbps.is_some() {
+                    settings.vbr = None;
+                }
+            }
+            EncoderField::OpusApplication => {
+                let options: Vec<Option<String>> = std::iter::once(None)
+                    .chain(converter::OPUS_APPLICATIONS.iter().map(|a| Some(a.to_string())))
+                    .collect();
+                settings.opus_application = cycle(&options, &settings.opus_application, forward);
+            }
+            EncoderField::CompressionLevel => {
+                let options: Vec<Option<u8>> = std::iter::once(None).chain((0..=12).map(Some)).collect();
+                settings.compression_level = cycle(&options, &settings.compression_level, forward);
+            }
+            EncoderField::SampleRate => {
+                settings.sample_rate = cycle(&SAMPLE_RATE_OPTIONS, &settings.sample_rate, forward);
+            }
+            EncoderField::BitDepth => {
+                settings.bit_depth = cycle(&BIT_DEPTH_OPTIONS, &settings.bit_depth, forward);
+            }
+            EncoderField::Channels => settings.mono = !settings.mono,
+            EncoderField::Dither => settings.dither = !settings.dither,
+        }
+    }
+}
+
+/// The option after (or before) `current`, wrapping around
+fn cycle<T: PartialEq + Clone>(options: &[T], current: &T, forward: bool) -> T {
+    let index = options.iter().position(|o| o == current).unwrap_or(0);
+    let next = if forward {
+        (index + 1) % options.len()
+    } else {
+        (index + options.len() - 1) % options.len()
+    };
+    options[next].clone()
 }
 
 #[derive(Debug, Clone)]
@@ -59,14 +211,55 @@ pub struct QueueItem {
     pub status: JobStatus,
     pub current_track: Option<String>,
     pub progress: (usize, usize), // (completed, total)
+    /// Set for batch conversions once they start running
+    pub throughput: Option<Throughput>,
+}
+
+/// Running totals of a batch conversion, used for speed and ETA
+#[derive(Debug, Clone)]
+pub struct Throughput {
+    pub started: Instant,
+    /// Completed fraction of the whole batch
+    pub fraction: f64,
+    /// Seconds of audio converted so far
+    pub audio_secs: f64,
+}
+
+impl Throughput {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            fraction: 0.0,
+            audio_secs: 0.0,
+        }
+    }
+
+    /// Seconds of audio converted per second of wall time
+    pub fn speed(&self) -> f64 {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.audio_secs / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    /// Estimated seconds left, extrapolated from the time taken so far
+    pub fn eta_secs(&self) -> Option<f64> {
+        if self.fraction <= 0.0 {
+            return None;
+        }
+        let elapsed = self.started.elapsed().as_secs_f64();
+        Some(elapsed * (1.0 - self.fraction) / self.fraction)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-#[allow(dead_code)]
 pub enum JobStatus {
     Pending,
     Fetching,
     Downloading,
+    Converting,
     Complete,
     Failed(String),
 }
@@ -84,12 +277,11 @@ pub struct App {
     pub library_selected: usize,
     pub status_message: String,
     pub db: DownloadDB,
-    #[allow(dead_code)]
-    pub music_path: PathBuf,
-    #[allow(dead_code)]
     pub playlist_path: PathBuf,
     // Channels
     pub download_tx: mpsc::Sender<DownloadRequest>,
+    /// Conversions go to their own worker lane
+    pub convert_tx: mpsc::Sender<DownloadRequest>,
     pub event_tx: mpsc::Sender<DownloadEvent>,
     pub event_rx: mpsc::Receiver<DownloadEvent>,
     next_id: usize,
@@ -97,6 +289,11 @@ pub struct App {
     pub pending_link: Option<String>,
     pub selected_format: usize,
     pub selected_quality: usize,
+    pub fetch_lyrics: bool,
+    /// Playlist files written for playlist downloads and `m`
+    pub playlist_formats: Vec<PlaylistFormat>,
+    /// Format under the cursor in the playlists row
+    pub playlist_format_cursor: usize,
     pub settings_field: SettingsField,
     // Logs state
     pub download_logs: VecDeque<String>,
@@ -108,18 +305,46 @@ pub struct App {
     // M3U generation state
     pub m3u_generating: bool,
     pub m3u_pending: Option<M3UPending>,
+    /// Sources with missing tracks after regenerating all playlists
+    pub regen_missing: Vec<(PlaylistSource, usize)>,
     // Conversion state
     pub convert_pending: Option<ConvertPending>,
     pub convert_target_format: usize,
     pub convert_quality: usize,
     pub convert_refresh_metadata: bool,
+    /// Advanced encoder options, kept across formats
+    pub convert_encoder: EncoderSettings,
+    /// Selected row among `ENCODER_FIELDS`
+    pub convert_field: usize,
+    /// Folder converted files are written under (from the config file)
+    pub convert_output_dir: Option<String>,
+    /// Keep originals and record conversions as format variants
+    pub convert_keep_both: bool,
     pub convert_delete_pending: Option<ConvertDeletePending>,
     pub convert_all_mode: bool,
     pub convert_batch_delete_pending: Option<Vec<(String, String)>>,
     // Cleanup state
     pub cleanup_preview: Option<CleanupPreview>,
+    /// Tracks waiting for confirmation before being normalized in place
+    pub normalize_pending: Option<Vec<ConvertTrackInfo>>,
+    // Trash state
+    pub trash: Trash,
+    pub trash_selected: usize,
+    /// Set after the first 'D', so emptying the trash takes two presses
+    pub trash_confirm_empty: bool,
+    // Tracked playlist state
+    pub tracked: TrackedPlaylists,
+    pub tracked_selected: usize,
+    /// Set after the first 'c', so cleaning takes two presses
+    pub tracked_confirm_clean: bool,
+    // Smart playlist state
+    pub smart: SmartPlaylists,
+    pub smart_selected: usize,
+    /// Set after the first 'd', so deleting a definition takes two presses
+    pub smart_confirm_delete: bool,
     // Error log state
-    pub error_log: ErrorLogManager,
+    /// Shared with the workers
+    pub error_log: Arc<Mutex<ErrorLogManager>>,
     pub error_dates: Vec<String>,
     pub error_date_selected: usize,
     pub error_tab: ErrorTab,
@@ -139,6 +364,8 @@ pub struct CleanupPreview {
 /// Pending M3U data waiting for user confirmation
 #[derive(Debug, Clone)]
 pub struct M3UPending {
+    /// Spotify link, to queue a download of the missing tracks
+    pub link: String,
     pub name: String,
     pub found: usize,
     pub missing: usize,
@@ -163,13 +390,16 @@ pub struct ConvertDeletePending {
 impl App {
     pub fn new(
         download_tx: mpsc::Sender<DownloadRequest>,
+        convert_tx: mpsc::Sender<DownloadRequest>,
         event_tx: mpsc::Sender<DownloadEvent>,
         event_rx: mpsc::Receiver<DownloadEvent>,
         pause_tx: watch::Sender<bool>,
+        error_log: Arc<Mutex<ErrorLogManager>>,
     ) -> Self {
         let music_path = PathBuf::from("data/music");
         let playlist_path = PathBuf::from("data/playlists");
         let cache_path = "data/cache/downloaded_songs.json";
+        let convert_config = Config::load().convert;
 
         let _ = std::fs::create_dir_all(&music_path);
         let _ = std::fs::create_dir_all(&playlist_path);
@@ -178,8 +408,7 @@ impl App {
         let db = DownloadDB::new(cache_path);
         let library: Vec<TrackEntry> = db.tracks.iter().cloned().collect();
 
-        let error_log = ErrorLogManager::new("data/errors");
-        let error_dates = error_log.list_dates();
+        let error_dates = lock(&error_log).list_dates();
 
         Self {
             running: true,
@@ -195,9 +424,9 @@ impl App {
             status_message: "Welcome! Press 'a' for album, 'p' for playlist, 'P' for portable mode"
                 .to_string(),
             db,
-            music_path,
             playlist_path,
             download_tx,
+            convert_tx,
             event_tx,
             event_rx,
             next_id: 0,
@@ -205,6 +434,9 @@ impl App {
             pending_link: None,
             selected_format: 0,  // mp3
             selected_quality: 0, // high
+            fetch_lyrics: false,
+            playlist_formats: Config::load().playlist.formats,
+            playlist_format_cursor: 0,
             settings_field: SettingsField::Format,
             // Logs
             download_logs: VecDeque::with_capacity(500),
@@ -216,15 +448,30 @@ impl App {
             // M3U
             m3u_generating: false,
             m3u_pending: None,
+            regen_missing: Vec::new(),
             // Conversion
             convert_pending: None,
             convert_target_format: 0,
             convert_quality: 0,
             convert_refresh_metadata: true,
+            convert_encoder: EncoderSettings::default(),
+            convert_field: 0,
+            convert_output_dir: convert_config.output_dir,
+            convert_keep_both: convert_config.keep_both,
             convert_delete_pending: None,
             convert_all_mode: false,
             convert_batch_delete_pending: None,
             cleanup_preview: None,
+            normalize_pending: None,
+            trash: Trash::open(),
+            trash_selected: 0,
+            trash_confirm_empty: false,
+            tracked: TrackedPlaylists::open(),
+            tracked_selected: 0,
+            tracked_confirm_clean: false,
+            smart: SmartPlaylists::open(),
+            smart_selected: 0,
+            smart_confirm_delete: false,
             // Error log
             error_log,
             error_dates,
@@ -262,13 +509,16 @@ impl App {
                     ));
                 }
                 DownloadEvent::TrackStarted {
-                    id, artist, title, ..
+                    id, artist, title, track_num,
                 } => {
                     if let Some(item) = self.queue.iter_mut().find(|q| q.id == id) {
                         item.current_track = Some(format!("{} - {}", artist, title));
                     }
                     self.status_message = format!("Downloading: {} - {}", artist, title);
-                    self.add_log(format!("[{}] Downloading: {} - {}", id, artist, title));
+                    self.add_log(format!(
+                        "[{}] Downloading #{}: {} - {}",
+                        id, track_num, artist, title
+                    ));
                 }
                 DownloadEvent::TrackComplete {
                     id,
@@ -282,9 +532,14 @@ impl App {
                     }
                     // Add to library
                     let entry = TrackEntry {
+                        id: String::new(),
                         artist: artist.clone(),
                         title: title.clone(),
                         path,
+                        source: None,
+                        trims: Vec::new(),
+                        variants: Vec::new(),
+                        duration_secs: None,
                     };
                     if !self
                         .library
@@ -296,12 +551,18 @@ impl App {
                     self.status_message = format!("Complete: {} - {}", artist, title);
                     self.add_log(format!("[{}] Complete: {} - {}", id, artist, title));
                 }
-                DownloadEvent::TrackSkipped { id, artist, title } => {
+                DownloadEvent::TrackSkipped {
+                    id,
+                    artist,
+                    title,
+                    reason,
+                } => {
                     if let Some(item) = self.queue.iter_mut().find(|q| q.id == id) {
                         item.progress.0 += 1;
+                        item.current_track = None;
                     }
-                    self.status_message = format!("Skipped (exists): {} - {}", artist, title);
-                    self.add_log(format!("[{}] Skipped: {} - {}", id, artist, title));
+                    self.status_message = format!("Skipped ({}): {} - {}", reason, artist, title);
+                    self.add_log(format!("[{}] Skipped: {} - {} ({})", id, artist, title, reason));
                 }
                 DownloadEvent::TrackFailed {
                     id,
@@ -341,6 +602,7 @@ impl App {
                     self.status_message = result;
                 }
                 DownloadEvent::M3UConfirm {
+                    link,
                     name,
                     found,
                     missing,
@@ -348,6 +610,7 @@ impl App {
                 } => {
                     self.m3u_generating = false;
                     self.m3u_pending = Some(M3UPending {
+                        link,
                         name,
                         found,
                         missing,
@@ -355,35 +618,81 @@ impl App {
                     });
                     self.view = View::M3UConfirm;
                     self.status_message =
-                        "Some tracks are missing. Press Enter to generate anyway, Esc to cancel."
+                        "Some tracks are missing. Enter generates anyway, 'd' also downloads them, Esc cancels."
                             .to_string();
                 }
+                DownloadEvent::PlaylistsRegenerated { log, written, missing } => {
+                    self.m3u_generating = false;
+                    for line in log {
+                        self.add_log(line);
+                    }
+                    let total: usize = missing.iter().map(|(_, count)| count).sum();
+                    if missing.is_empty() {
+                        self.status_message = format!("Regenerated {} playlist(s)", written);
+                    } else {
+                        self.status_message = format!(
+                            "Regenerated {} playlist(s); {} track(s) missing. 'y' queues downloads, 'n' skips.",
+                            written, total
+                        );
+                        self.regen_missing = missing;
+                        self.view = View::RegenerateConfirm;
+                    }
+                }
                 DownloadEvent::ConvertStarted {
                     id,
                     path,
                     target_format,
                 } => {
+                    if let Some(item) = self.queue.iter_mut().find(|q| q.id == id) {
+                        if item.status == JobStatus::Pending {
+                            item.status = JobStatus::Converting;
+                            item.throughput = Some(Throughput::new());
+                        }
+                        item.current_track = Some(path.clone());
+                    }
                     self.add_log(format!(
                         "[{}] Converting: {} -> {}",
                         id, path, target_format
                     ));
                     self.status_message = format!("Converting to {}...", target_format);
                 }
+                DownloadEvent::ConvertProgress {
+                    id,
+                    fraction,
+                    audio_secs,
+                } => {
+                    if let Some(throughput) = self
+                        .queue
+                        .iter_mut()
+                        .find(|q| q.id == id)
+                        .and_then(|q| q.throughput.as_mut())
+                    {
+                        throughput.fraction = fraction;
+                        throughput.audio_secs = audio_secs;
+                    }
+                }
                 DownloadEvent::ConvertComplete {
                     id,
                     old_path,
                     new_path,
                 } => {
+                    if let Some(item) = self.queue.iter_mut().find(|q| q.id == id) {
+                        item.progress.0 += 1;
+                    }
                     self.add_log(format!("[{}] Converted: {} -> {}", id, old_path, new_path));
                     self.status_message = format!("Conversion complete: {}", new_path);
                     // Refresh library to show updated path
                     self.refresh_library();
                 }
                 DownloadEvent::ConvertFailed { id, path, error } => {
+                    if let Some(item) = self.queue.iter_mut().find(|q| q.id == id) {
+                        item.progress.0 += 1;
+                    }
                     self.add_log(format!("[{}] Conversion failed: {} - {}", id, path, error));
                     self.status_message = format!("Conversion failed: {}", error);
                 }
-                DownloadEvent::ConvertDeleteConfirm { old_path, new_path, .. } => {
+                DownloadEvent::ConvertDeleteConfirm { id, old_path, new_path } => {
+                    self.add_log(format!("[{}] Waiting to delete original: {}", id, old_path));
                     self.convert_delete_pending = Some(ConvertDeletePending {
                         old_path,
                         new_path,
@@ -392,7 +701,11 @@ impl App {
                     self.status_message =
                         "Delete original file? Press 'y' to delete, 'n' to keep.".to_string();
                 }
-                DownloadEvent::ConvertBatchComplete { total, successful, .. } => {
+                DownloadEvent::ConvertBatchComplete { id, total, successful } => {
+                    if let Some(item) = self.queue.iter_mut().find(|q| q.id == id) {
+                        item.status = JobStatus::Complete;
+                        item.current_track = None;
+                    }
                     self.add_log(format!(
                         "Batch conversion complete: {}/{} successful",
                         successful, total
@@ -417,16 +730,50 @@ impl App {
                     ));
                     self.status_message = format!("Refresh failed: {} - {}", artist, title);
                 }
-                DownloadEvent::RefreshBatchComplete { total, successful, .. } => {
+                DownloadEvent::RefreshBatchComplete { id, total, successful } => {
                     self.add_log(format!(
-                        "Batch metadata refresh complete: {}/{} successful",
-                        successful, total
+                        "[{}] Batch metadata refresh complete: {}/{} successful",
+                        id, successful, total
                     ));
                     self.status_message = format!(
                         "Batch metadata refresh complete: {}/{} tracks refreshed",
                         successful, total
                     );
                 }
+                DownloadEvent::LyricsComplete { id, artist, title, found } => {
+                    if found {
+                        self.add_log(format!("[{}] Lyrics embedded: {} - {}", id, artist, title));
+                        self.status_message = format!("Lyrics embedded: {} - {}", artist, title);
+                    } else {
+                        self.add_log(format!("[{}] No lyrics found: {} - {}", id, artist, title));
+                        self.status_message = format!("No lyrics found: {} - {}", artist, title);
+                    }
+                }
+                DownloadEvent::LyricsFailed { id, artist, title, error } => {
+                    self.add_log(format!(
+                        "[{}] Lyrics failed: {} - {} - {}",
+                        id, artist, title, error
+                    ));
+                    self.status_message = format!("Lyrics failed: {} - {}", artist, title);
+                }
+                DownloadEvent::LoudnessComplete { id, tagged, failed, total } => {
+                    self.add_log(format!(
+                        "[{}] ReplayGain: {}/{} tagged, {} failed",
+                        id, tagged, total, failed
+                    ));
+                    self.status_message =
+                        format!("ReplayGain: {}/{} tagged, {} failed", tagged, total, failed);
+                }
+                DownloadEvent::CoverComplete { id, embedded, issues, total } => {
+                    self.add_log(format!(
+                        "[{}] Cover art: {}/{} embedded, {} with issues",
+                        id, embedded, total, issues
+                    ));
+                    self.status_message = format!(
+                        "Cover art: {}/{} embedded, {} with issues",
+                        embedded, total, issues
+                    );
+                }
                 DownloadEvent::ConvertBatchDeleteConfirm { converted_files } => {
                     let count = converted_files.len();
                     self.convert_batch_delete_pending = Some(converted_files);
@@ -469,7 +816,12 @@ impl App {
             View::ConvertConfirm => View::Main,
             View::ConvertBatchConfirm => View::Main,
             View::CleanupConfirm => View::Main,
+            View::NormalizeConfirm => View::Main,
             View::ErrorLog => View::Main,
+            View::Trash => View::Main,
+            View::Tracked => View::Main,
+            View::Smart => View::Main,
+            View::RegenerateConfirm => View::Main,
         };
     }
 
@@ -508,6 +860,19 @@ impl App {
         self.status_message = format!("Enter Spotify playlist link{}:", mode);
     }
 
+    pub fn start_track_playlist(&mut self) {
+        self.view = View::AddLink;
+        self.input_mode = true;
+        self.input.clear();
+        self.link_type = LinkType::TrackedPlaylist;
+        let mode = if self.portable_mode {
+            " [portable]"
+        } else {
+            ""
+        };
+        self.status_message = format!("Enter Spotify playlist link to track{}:", mode);
+    }
+
     pub fn start_add_youtube_playlist(&mut self) {
         self.view = View::AddLink;
         self.input_mode = true;
@@ -525,7 +890,10 @@ impl App {
         self.input_mode = false;
         self.input.clear();
         self.pending_link = None;
-        self.view = View::Main;
+        // Smart playlist rules are typed inside their own view
+        if self.view != View::Smart {
+            self.view = View::Main;
+        }
         self.status_message = "Cancelled".to_string();
     }
 
@@ -577,7 +945,7 @@ impl App {
         let format = if self.portable_mode {
             "mp3".to_string() // Portable mode forces MP3
         } else {
-            FORMAT_OPTIONS[self.selected_format].to_string()
+            DOWNLOAD_FORMAT_OPTIONS[self.selected_format].to_string()
         };
         let quality = QUALITY_OPTIONS[self.selected_quality].to_string();
 
@@ -593,6 +961,7 @@ impl App {
                     status: JobStatus::Fetching,
                     current_track: None,
                     progress: (0, 0),
+                    throughput: None,
                 });
                 DownloadRequest::Album {
                     id,
@@ -600,15 +969,17 @@ impl App {
                     portable: self.portable_mode,
                     format: format.clone(),
                     quality: quality.clone(),
+                    lyrics: self.fetch_lyrics,
                 }
             }
-            LinkType::Playlist => {
+            LinkType::Playlist | LinkType::TrackedPlaylist => {
                 self.queue.push(QueueItem {
                     id,
                     name: "Fetching playlist...".to_string(),
                     status: JobStatus::Fetching,
                     current_track: None,
                     progress: (0, 0),
+                    throughput: None,
                 });
                 DownloadRequest::Playlist {
                     id,
@@ -616,6 +987,9 @@ impl App {
                     portable: self.portable_mode,
                     format: format.clone(),
                     quality: quality.clone(),
+                    lyrics: self.fetch_lyrics,
+                    playlist_formats: self.playlist_formats.clone(),
+                    sync: self.link_type == LinkType::TrackedPlaylist,
                 }
             }
             LinkType::YouTubePlaylist => {
@@ -625,6 +999,7 @@ impl App {
                     status: JobStatus::Fetching,
                     current_track: None,
                     progress: (0, 0),
+                    throughput: None,
                 });
                 DownloadRequest::YouTubePlaylist {
                     id,
@@ -632,6 +1007,8 @@ impl App {
                     portable: self.portable_mode,
                     format: format.clone(),
                     quality: quality.clone(),
+                    lyrics: self.fetch_lyrics,
+                    playlist_formats: self.playlist_formats.clone(),
                 }
             }
         };
@@ -651,7 +1028,7 @@ impl App {
                         None
                     }
                 }
-                LinkType::Playlist => {
+                LinkType::Playlist | LinkType::TrackedPlaylist => {
                     if let Ok(playlist) = spotify::fetch_playlist(&link_clone).await {
                         Some(playlist.name)
                     } else {
@@ -676,16 +1053,44 @@ impl App {
             let _ = tx.send(request).await;
         });
 
-        self.status_message = format!("Added to queue ({}, {})", format, quality);
+        self.status_message = if self.fetch_lyrics {
+            format!("Added to queue ({}, {}, lyrics)", format, quality)
+        } else {
+            format!("Added to queue ({}, {})", format, quality)
+        };
     }
 
     // Settings navigation
     pub fn settings_up(&mut self) {
-        self.settings_field = SettingsField::Format;
+        self.settings_field = match self.settings_field {
+            SettingsField::Playlists => SettingsField::Lyrics,
+            SettingsField::Lyrics => SettingsField::Quality,
+            _ => SettingsField::Format,
+        };
     }
 
     pub fn settings_down(&mut self) {
-        self.settings_field = SettingsField::Quality;
+        self.settings_field = match self.settings_field {
+            SettingsField::Format => SettingsField::Quality,
+            SettingsField::Quality => SettingsField::Lyrics,
+            _ => SettingsField::Playlists,
+        };
+    }
+
+    /// Turn the playlist format under the cursor on or off; at least one stays on
+    pub fn settings_toggle(&mut self) {
+        match self.settings_field {
+            SettingsField::Lyrics => self.fetch_lyrics = !self.fetch_lyrics,
+            SettingsField::Playlists => {
+                let format = PlaylistFormat::ALL[self.playlist_format_cursor];
+                if !self.playlist_formats.contains(&format) {
+                    self.playlist_formats.push(format);
+                } else if self.playlist_formats.len() > 1 {
+                    self.playlist_formats.retain(|f| *f != format);
+                }
+            }
+            _ => {}
+        }
     }
 
     pub fn settings_left(&mut self) {
@@ -700,13 +1105,17 @@ impl App {
                     self.selected_quality -= 1;
                 }
             }
+            SettingsField::Lyrics => self.fetch_lyrics = !self.fetch_lyrics,
+            SettingsField::Playlists => {
+                self.playlist_format_cursor = self.playlist_format_cursor.saturating_sub(1);
+            }
         }
     }
 
     pub fn settings_right(&mut self) {
         match self.settings_field {
             SettingsField::Format => {
-                if self.selected_format < FORMAT_OPTIONS.len() - 1 {
+                if self.selected_format < DOWNLOAD_FORMAT_OPTIONS.len() - 1 {
                     self.selected_format += 1;
                 }
             }
@@ -715,6 +1124,12 @@ impl App {
                     self.selected_quality += 1;
                 }
             }
+            SettingsField::Lyrics => self.fetch_lyrics = !self.fetch_lyrics,
+            SettingsField::Playlists => {
+                if self.playlist_format_cursor < PlaylistFormat::ALL.len() - 1 {
+                    self.playlist_format_cursor += 1;
+                }
+            }
         }
     }
 
@@ -819,16 +1234,25 @@ impl App {
         // Clone needed data for async task
         let db_tracks: Vec<TrackEntry> = self.db.tracks.iter().cloned().collect();
         let playlist_path = self.playlist_path.clone();
+        let playlist_formats = self.playlist_formats.clone();
         let event_tx = self.event_tx.clone();
+        let (format, quality) = self.selected_settings();
+        let portable = self.portable_mode;
 
         tokio::spawn(async move {
-            match check_m3u_tracks(&link, &db_tracks).await {
+            let result = check_m3u_tracks(&link, &db_tracks).await;
+            // Remember the link so the playlist can be regenerated later
+            if let (Some(kind), Some(name)) = (SourceKind::of(&link), result.name()) {
+                let source = PlaylistSource::new(&link, kind, name, &format, &quality, portable);
+                let _ = PlaylistSources::open().record(source);
+            }
+            match result {
                 M3UCheckResult::Error(msg) => {
                     let _ = event_tx.send(DownloadEvent::M3UGenerated { result: msg }).await;
                 }
                 M3UCheckResult::AllFound { name, paths } => {
                     // All tracks found, generate directly
-                    let result = do_generate_m3u(&name, &paths, &playlist_path);
+                    let result = do_generate_m3u(&name, &paths, &playlist_path, &playlist_formats);
                     let _ = event_tx.send(DownloadEvent::M3UGenerated { result }).await;
                 }
                 M3UCheckResult::SomeMissing {
@@ -840,6 +1264,7 @@ impl App {
                     // Ask for confirmation
                     let _ = event_tx
                         .send(DownloadEvent::M3UConfirm {
+                            link,
                             name,
                             found,
                             missing,
@@ -847,7 +1272,7 @@ impl App {
                         })
                         .await;
                 }
-                M3UCheckResult::NoneFound { total } => {
+                M3UCheckResult::NoneFound { total, .. } => {
                     let _ = event_tx
                         .send(DownloadEvent::M3UGenerated {
                             result: format!("No tracks found in library (0/{} total)", total),
@@ -860,18 +1285,157 @@ impl App {
 
     pub fn confirm_m3u(&mut self) {
         if let Some(pending) = self.m3u_pending.take() {
-            let result = do_generate_m3u(&pending.name, &pending.paths, &self.playlist_path);
+            let result = do_generate_m3u(&pending.name, &pending.paths, &self.playlist_path, &self.playlist_formats);
             self.status_message = result;
         }
         self.view = View::Main;
     }
 
+    /// Generate the playlist with the tracks found and queue a download of
+    /// the album or playlist for the rest
+    pub fn confirm_m3u_and_download(&mut self) {
+        let Some(pending) = self.m3u_pending.take() else {
+            self.view = View::Main;
+            return;
+        };
+        let result = do_generate_m3u(&pending.name, &pending.paths, &self.playlist_path, &self.playlist_formats);
+        let Some(kind) = SourceKind::of(&pending.link) else {
+            self.status_message = result;
+            self.view = View::Main;
+            return;
+        };
+        let (format, quality) = self.selected_settings();
+        let source = PlaylistSource::new(&pending.link, kind, &pending.name, &format, &quality, self.portable_mode);
+        self.queue_source_download(&source);
+        self.view = View::Queue;
+        self.status_message = format!("{}; queued download of {} missing track(s)", result, pending.missing);
+    }
+
     pub fn cancel_m3u(&mut self) {
         self.m3u_pending = None;
         self.view = View::Main;
         self.status_message = "M3U generation cancelled".to_string();
     }
 
+    /// Format and quality selected in the link settings
+    fn selected_settings(&self) -> (String, String) {
+        let format = if self.portable_mode {
+            "mp3".to_string()
+        } else {
+            DOWNLOAD_FORMAT_OPTIONS[self.selected_format].to_string()
+        };
+        (format, QUALITY_OPTIONS[self.selected_quality].to_string())
+    }
+
+    /// Rewrite the playlist of every stored album and playlist link,
+    /// matching its tracks to the library by name
+    pub fn regenerate_all_playlists(&mut self) {
+        let sources = PlaylistSources::open().sources().to_vec();
+        if sources.is_empty() {
+            self.status_message = "No album or playlist links stored yet; downloads record theirs.".to_string();
+            return;
+        }
+        if self.m3u_generating {
+            self.status_message = "Playlist generation already running".to_string();
+            return;
+        }
+        self.m3u_generating = true;
+        self.status_message = format!("Regenerating {} playlist(s) from Spotify...", sources.len());
+
+        // The worker may have added tracks since the library was loaded
+        self.db = DownloadDB::new("data/cache/downloaded_songs.json");
+        let library: Vec<TrackEntry> = self.db.tracks.iter().filter(|t| !t.is_missing()).cloned().collect();
+        let playlist_path = self.playlist_path.clone();
+        let playlist_formats = self.playlist_formats.clone();
+        let event_tx = self.event_tx.clone();
+
+        tokio::spawn(async move {
+            let mut log = Vec::new();
+            let mut missing = Vec::new();
+            let mut written = 0;
+            for source in sources {
+                let fetched = match regenerate::fetch_tracks(&source.link).await {
+                    Ok(fetched) => fetched,
+                    Err(e) => {
+                        log.push(format!("Regenerate {}: {}", source.name, e));
+                        continue;
+                    }
+                };
+                let paths: Vec<PathBuf> = regenerate::match_tracks(&fetched.tracks, &library)
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                let absent = fetched.tracks.len() - paths.len();
+                let result = do_generate_m3u(&fetched.name, &paths, &playlist_path, &playlist_formats);
+                log.push(format!("Regenerate {}: {} ({} missing)", fetched.name, result, absent));
+                written += 1;
+                if absent > 0 {
+                    missing.push((source, absent));
+                }
+            }
+            let _ = event_tx
+                .send(DownloadEvent::PlaylistsRegenerated { log, written, missing })
+                .await;
+        });
+    }
+
+    /// Queue downloads for the sources that had missing tracks
+    pub fn confirm_regenerate_downloads(&mut self) {
+        let missing = std::mem::take(&mut self.regen_missing);
+        for (source, _) in &missing {
+            self.queue_source_download(source);
+        }
+        self.view = View::Queue;
+        self.status_message = format!(
+            "Queued {} download(s); tracks already in the library are skipped",
+            missing.len()
+        );
+    }
+
+    pub fn cancel_regenerate_downloads(&mut self) {
+        self.regen_missing.clear();
+        self.view = View::Main;
+        self.status_message = "Missing tracks not downloaded".to_string();
+    }
+
+    /// Queue an album or playlist download with the settings it was stored with
+    fn queue_source_download(&mut self, source: &PlaylistSource) {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.queue.push(QueueItem {
+            id,
+            name: source.name.clone(),
+            status: JobStatus::Fetching,
+            current_track: None,
+            progress: (0, 0),
+            throughput: None,
+        });
+        let request = match source.kind {
+            SourceKind::Album => DownloadRequest::Album {
+                id,
+                link: source.link.clone(),
+                portable: source.portable,
+                format: source.format.clone(),
+                quality: source.quality.clone(),
+                lyrics: self.fetch_lyrics,
+            },
+            SourceKind::Playlist => DownloadRequest::Playlist {
+                id,
+                link: source.link.clone(),
+                portable: source.portable,
+                format: source.format.clone(),
+                quality: source.quality.clone(),
+                lyrics: self.fetch_lyrics,
+                playlist_formats: self.playlist_formats.clone(),
+                sync: false,
+            },
+        };
+        let tx = self.download_tx.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(request).await;
+        });
+    }
+
     // Conversion methods
     pub fn start_convert(&mut self) {
         if self.library.is_empty() {
@@ -920,19 +1484,26 @@ impl App {
     }
 
     pub fn submit_convert(&mut self) {
-        if self.convert_pending.is_none() {
+        let Some(pending) = self.convert_pending.clone() else {
             self.view = View::Library;
             self.status_message = "No conversion pending".to_string();
             return;
-        }
-        self.convert_pending = None;
-
-        self.view = View::Logs;
+        };
 
         let format = FORMAT_OPTIONS[self.convert_target_format].to_string();
         let quality = QUALITY_OPTIONS[self.convert_quality].to_string();
         let refresh_metadata = self.convert_refresh_metadata;
 
+        // Options that don't apply to the format are greyed out and ignored
+        let encoder = converter::encoder_for_format(&format, &self.convert_encoder);
+        if let Err(e) = converter::validate_encoder(&format, &encoder) {
+            self.status_message = e.to_string();
+            return;
+        }
+
+        self.convert_pending = None;
+        self.view = View::Logs;
+
         if self.convert_all_mode {
             // Queue batch conversion for all tracks in the library
             let tracks: Vec<ConvertTrackInfo> = self
@@ -949,15 +1520,27 @@ impl App {
             self.next_id += 1;
             let id = self.next_id;
 
+            self.queue.push(QueueItem {
+                id,
+                name: format!("Convert {} tracks to {}", track_count, format),
+                status: JobStatus::Pending,
+                current_track: None,
+                progress: (0, track_count),
+                throughput: None,
+            });
+
             let request = DownloadRequest::ConvertBatch {
                 id,
                 tracks,
                 target_format: format.clone(),
                 quality: quality.clone(),
+                encoder,
+                output_dir: self.convert_output_dir.clone(),
+                keep_both: self.convert_keep_both,
                 refresh_metadata,
             };
 
-            let tx = self.download_tx.clone();
+            let tx = self.convert_tx.clone();
             tokio::spawn(async move {
                 let _ = tx.send(request).await;
             });
@@ -968,22 +1551,24 @@ impl App {
                 track_count, format, quality
             );
         } else {
-            // Single track conversion (use selected track)
-            let selected = &self.library[self.library_selected];
+            // Single track conversion (the track picked when the settings opened)
             self.next_id += 1;
             let id = self.next_id;
 
             let request = DownloadRequest::Convert {
                 id,
-                input_path: selected.path.clone(),
+                input_path: pending.track_path,
                 target_format: format.clone(),
                 quality: quality.clone(),
+                encoder,
+                output_dir: self.convert_output_dir.clone(),
+                keep_both: self.convert_keep_both,
                 refresh_metadata,
-                artist: selected.artist.clone(),
-                title: selected.title.clone(),
+                artist: pending.artist,
+                title: pending.title,
             };
 
-            let tx = self.download_tx.clone();
+            let tx = self.convert_tx.clone();
             tokio::spawn(async move {
                 let _ = tx.send(request).await;
             });
@@ -993,13 +1578,18 @@ impl App {
     }
 
     pub fn convert_settings_up(&mut self) {
-        // Cycle through: Format -> Quality -> Refresh Metadata
-        // Currently on refresh metadata, go to quality
-        // Just use a simple toggle for now
+        self.convert_field = self.convert_field.saturating_sub(1);
     }
 
     pub fn convert_settings_down(&mut self) {
-        // Cycle through settings fields
+        if self.convert_field < ENCODER_FIELDS.len() - 1 {
+            self.convert_field += 1;
+        }
+    }
+
+    /// Change the value of the selected encoder option
+    pub fn convert_encoder_cycle(&mut self, forward: bool) {
+        ENCODER_FIELDS[self.convert_field].cycle(&mut self.convert_encoder, forward);
     }
 
     pub fn convert_settings_left(&mut self) {
@@ -1018,6 +1608,10 @@ impl App {
         self.convert_refresh_metadata = !self.convert_refresh_metadata;
     }
 
+    pub fn convert_toggle_keep_both(&mut self) {
+        self.convert_keep_both = !self.convert_keep_both;
+    }
+
     pub fn convert_quality_left(&mut self) {
         if self.convert_quality > 0 {
             self.convert_quality -= 1;
@@ -1032,15 +1626,33 @@ impl App {
 
     pub fn confirm_delete_original(&mut self) {
         if let Some(pending) = self.convert_delete_pending.take() {
-            if let Err(e) = std::fs::remove_file(&pending.old_path) {
+            if let Err(e) = self.trash_original(&pending.old_path, &pending.new_path) {
                 self.status_message = format!("Failed to delete original: {}", e);
             } else {
-                self.status_message = "Original file deleted".to_string();
+                self.status_message = "Original moved to the trash ('t' to view)".to_string();
             }
         }
         self.view = View::Library;
     }
 
+    /// Move a converted file's original to the trash, recording its cache
+    /// entry as it was before the conversion
+    fn trash_original(&mut self, old_path: &str, new_path: &str) -> anyhow::Result<()> {
+        // The worker has updated the cache on disk since it was loaded
+        self.db = DownloadDB::new("data/cache/downloaded_songs.json");
+        let entry = self.db.find_by_path(new_path).map(|entry| TrackEntry {
+            path: old_path.to_string(),
+            variants: Vec::new(),
+            ..entry.clone()
+        });
+        let format = std::path::Path::new(new_path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        self.trash
+            .trash_file(std::path::Path::new(old_path), entry, &format!("converted to {}", format))
+    }
+
     pub fn cancel_delete_original(&mut self) {
         self.convert_delete_pending = None;
         self.view = View::Library;
@@ -1051,8 +1663,8 @@ impl App {
         if let Some(files) = self.convert_batch_delete_pending.take() {
             let mut deleted = 0;
             let mut failed = 0;
-            for (old_path, _) in &files {
-                if let Err(_) = std::fs::remove_file(old_path) {
+            for (old_path, new_path) in &files {
+                if self.trash_original(old_path, new_path).is_err() {
                     failed += 1;
                 } else {
                     deleted += 1;
@@ -1064,7 +1676,7 @@ impl App {
                     deleted, failed
                 );
             } else {
-                self.status_message = format!("Deleted {} original files", deleted);
+                self.status_message = format!("Moved {} original files to the trash", deleted);
             }
         }
         self.view = View::Library;
@@ -1144,15 +1756,151 @@ impl App {
         self.status_message = format!("Refreshing metadata for {} tracks...", track_count);
     }
 
+    /// Fetch and embed lyrics for the selected library track
+    pub fn start_fetch_lyrics(&mut self) {
+        if self.library.is_empty() {
+            self.status_message = "Library is empty, nothing to fetch lyrics for".to_string();
+            return;
+        }
+
+        let selected = &self.library[self.library_selected];
+        self.next_id += 1;
+        let id = self.next_id;
+
+        let request = DownloadRequest::FetchLyrics {
+            id,
+            tracks: vec![ConvertTrackInfo {
+                input_path: selected.path.clone(),
+                artist: selected.artist.clone(),
+                title: selected.title.clone(),
+            }],
+        };
+
+        let tx = self.download_tx.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(request).await;
+        });
+
+        self.view = View::Logs;
+        self.status_message = format!(
+            "Fetching lyrics for: {} - {}",
+            selected.artist, selected.title
+        );
+    }
+
+    /// Re-embed the folder cover for every library track in the selected track's album folder
+    pub fn start_reembed_cover(&mut self) {
+        if self.library.is_empty() {
+            self.status_message = "Library is empty, nothing to re-embed".to_string();
+            return;
+        }
+
+        let selected = &self.library[self.library_selected];
+        let folder = std::path::Path::new(&selected.path).parent().map(|p| p.to_path_buf());
+        let tracks: Vec<ConvertTrackInfo> = self
+            .library
+            .iter()
+            .filter(|t| std::path::Path::new(&t.path).parent().map(|p| p.to_path_buf()) == folder)
+            .map(|t| ConvertTrackInfo {
+                input_path: t.path.clone(),
+                artist: t.artist.clone(),
+                title: t.title.clone(),
+            })
+            .collect();
+        let track_count = tracks.len();
+
+        self.next_id += 1;
+        let request = DownloadRequest::ReembedCover {
+            id: self.next_id,
+            tracks,
+            portable: self.portable_mode,
+        };
+
+        let tx = self.download_tx.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(request).await;
+        });
+
+        self.view = View::Logs;
+        self.status_message = format!("Re-embedding cover art for {} tracks...", track_count);
+    }
+
+    /// Write ReplayGain tags to the selected track's album folder, or to the
+    /// whole library with `all`. In portable mode the files are normalized
+    /// first when `loudness.normalize_portable` is set.
+    pub fn start_analyze_loudness(&mut self, all: bool) {
+        if self.library.is_empty() {
+            self.status_message = "Library is empty, nothing to analyze".to_string();
+            return;
+        }
+
+        let selected = &self.library[self.library_selected];
+        let folder = std::path::Path::new(&selected.path).parent().map(|p| p.to_path_buf());
+        let tracks: Vec<ConvertTrackInfo> = self
+            .library
+            .iter()
+            .filter(|t| all || std::path::Path::new(&t.path).parent().map(|p| p.to_path_buf()) == folder)
+            .map(|t| ConvertTrackInfo {
+                input_path: t.path.clone(),
+                artist: t.artist.clone(),
+                title: t.title.clone(),
+            })
+            .collect();
+        // Normalizing re-encodes every file in place, so ask first
+        if self.portable_mode && Config::load().loudness.normalize_portable {
+            self.status_message = format!(
+                "Normalize {} tracks in place? Press 'y' to confirm, 'n' to cancel.",
+                tracks.len()
+            );
+            self.normalize_pending = Some(tracks);
+            self.view = View::NormalizeConfirm;
+            return;
+        }
+        self.queue_analyze_loudness(tracks, false);
+    }
+
+    /// Confirm normalizing the pending tracks
+    pub fn confirm_normalize(&mut self) {
+        if let Some(tracks) = self.normalize_pending.take() {
+            self.queue_analyze_loudness(tracks, true);
+        }
+    }
+
+    pub fn cancel_normalize(&mut self) {
+        self.normalize_pending = None;
+        self.view = View::Library;
+        self.status_message = "Normalization cancelled.".to_string();
+    }
+
+    fn queue_analyze_loudness(&mut self, tracks: Vec<ConvertTrackInfo>, normalize: bool) {
+        let track_count = tracks.len();
+        self.next_id += 1;
+        let request = DownloadRequest::AnalyzeLoudness {
+            id: self.next_id,
+            tracks,
+            normalize,
+        };
+
+        let tx = self.download_tx.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(request).await;
+        });
+
+        self.view = View::Logs;
+        self.status_message = format!("Analyzing loudness of {} tracks...", track_count);
+    }
+
     /// Start the cleanup process - shows confirmation with preview
     pub fn start_cleanup_database(&mut self) {
+        // Conversions in the worker may have moved files since the library was loaded
+        self.db = DownloadDB::new("data/cache/downloaded_songs.json");
         // Count how many entries have missing files
         let total_count = self.db.tracks.len();
         let missing_count = self
             .db
             .tracks
             .iter()
-            .filter(|entry| !std::path::Path::new(&entry.path).exists())
+            .filter(|entry| entry.is_missing())
             .count();
 
         self.cleanup_preview = Some(CleanupPreview {
@@ -1174,7 +1922,16 @@ impl App {
 
     /// Confirm and execute the cleanup
     pub fn confirm_cleanup(&mut self) {
-        let (removed, total_before) = self.db.cleanup();
+        self.db = DownloadDB::new("data/cache/downloaded_songs.json");
+        let (removed_entries, total_before) = self.db.cleanup();
+        let removed = removed_entries.len();
+        // Keep the removed entries restorable
+        for entry in removed_entries {
+            if let Err(e) = self.trash.trash_entry(entry, "cleanup: file missing") {
+                self.add_log(format!("Cleanup entry not recorded in the trash: {}", e));
+            }
+        }
+        self.update_playlists();
 
         // Refresh the library view
         self.library = self.db.tracks.iter().cloned().collect();
@@ -1205,12 +1962,352 @@ impl App {
         self.status_message = "Cleanup cancelled.".to_string();
     }
 
+    // ============ Trash Methods ============
+
+    /// Show what was removed from the library
+    pub fn show_trash(&mut self) {
+        self.trash = Trash::open();
+        self.trash_selected = 0;
+        self.trash_confirm_empty = false;
+        self.view = View::Trash;
+        self.status_message = if self.trash.items().is_empty() {
+            "Trash is empty.".to_string()
+        } else {
+            format!("Trash: {} item(s). 'r' restores, 'D' empties.", self.trash.items().len())
+        };
+    }
+
+    pub fn trash_up(&mut self) {
+        self.trash_confirm_empty = false;
+        self.trash_selected = self.trash_selected.saturating_sub(1);
+    }
+
+    pub fn trash_down(&mut self) {
+        self.trash_confirm_empty = false;
+        if self.trash_selected + 1 < self.trash.items().len() {
+            self.trash_selected += 1;
+        }
+    }
+
+    /// Put the selected item back into the library
+    pub fn restore_selected_trash(&mut self) {
+        self.trash_confirm_empty = false;
+        let Some(id) = self.trash.items().get(self.trash_selected).map(|item| item.id.clone()) else {
+            self.status_message = "Nothing selected".to_string();
+            return;
+        };
+        self.db = DownloadDB::new("data/cache/downloaded_songs.json");
+        match self.trash.restore(&id, &mut self.db) {
+            Ok(item) => {
+                self.library = self.db.tracks.iter().cloned().collect();
+                self.add_log(format!("Restored from trash: {}", item.original_path));
+                self.status_message = format!("Restored {}", item.display_name());
+            }
+            Err(e) => self.status_message = format!("Could not restore: {}", e),
+        }
+        if self.trash_selected >= self.trash.items().len() {
+            self.trash_selected = self.trash.items().len().saturating_sub(1);
+        }
+    }
+
+    /// Permanently delete everything in the trash, on the second press
+    pub fn empty_trash(&mut self) {
+        if self.trash.items().is_empty() {
+            self.status_message = "Trash is empty.".to_string();
+            return;
+        }
+        if !self.trash_confirm_empty {
+            self.trash_confirm_empty = true;
+            self.status_message = format!(
+                "Press 'D' again to permanently delete {} item(s).",
+                self.trash.items().len()
+            );
+            return;
+        }
+        self.trash_confirm_empty = false;
+        self.trash_selected = 0;
+        self.status_message = match self.trash.empty(None) {
+            Ok((removed, freed)) => format!(
+                "Permanently deleted {} item(s), freeing {:.1} MB",
+                removed,
+                freed as f64 / (1024.0 * 1024.0)
+            ),
+            Err(e) => format!("Failed to empty the trash: {}", e),
+        };
+    }
+
+    // ============ Tracked Playlist Methods ============
+
+    /// Show the Spotify playlists kept in sync
+    pub fn show_tracked(&mut self) {
+        self.tracked = TrackedPlaylists::open();
+        self.tracked_selected = 0;
+        self.tracked_confirm_clean = false;
+        self.view = View::Tracked;
+        self.status_message = if self.tracked.playlists().is_empty() {
+            "No tracked playlists. Press 'n' to track one.".to_string()
+        } else {
+            format!(
+                "{} tracked playlist(s). 's' syncs, 'S' syncs all.",
+                self.tracked.playlists().len()
+            )
+        };
+    }
+
+    pub fn tracked_up(&mut self) {
+        self.tracked_confirm_clean = false;
+        self.tracked_selected = self.tracked_selected.saturating_sub(1);
+    }
+
+    pub fn tracked_down(&mut self) {
+        self.tracked_confirm_clean = false;
+        if self.tracked_selected + 1 < self.tracked.playlists().len() {
+            self.tracked_selected += 1;
+        }
+    }
+
+    /// Queue a sync of the selected playlist, or of all of them
+    pub fn sync_tracked(&mut self, all: bool) {
+        self.tracked_confirm_clean = false;
+        let playlists: Vec<sync::TrackedPlaylist> = if all {
+            self.tracked.playlists().to_vec()
+        } else {
+            self.tracked.playlists().get(self.tracked_selected).cloned().into_iter().collect()
+        };
+        if playlists.is_empty() {
+            self.status_message = "No tracked playlists. Press 'n' to track one.".to_string();
+            return;
+        }
+
+        for tracked in &playlists {
+            self.next_id += 1;
+            let id = self.next_id;
+            self.queue.push(QueueItem {
+                id,
+                name: format!("Syncing {}...", tracked.name),
+                status: JobStatus::Fetching,
+                current_track: None,
+                progress: (0, 0),
+                throughput: None,
+            });
+            // Tracked playlists keep the settings they were first synced with
+            let request = DownloadRequest::Playlist {
+                id,
+                link: tracked.link.clone(),
+                portable: tracked.portable,
+                format: tracked.format.clone(),
+                quality: tracked.quality.clone(),
+                lyrics: self.fetch_lyrics,
+                playlist_formats: self.playlist_formats.clone(),
+                sync: true,
+            };
+            let tx = self.download_tx.clone();
+            tokio::spawn(async move {
+                let _ = tx.send(request).await;
+            });
+        }
+        self.status_message = format!("Queued sync of {} playlist(s)", playlists.len());
+    }
+
+    /// Stop tracking the selected playlist; its files stay in the library
+    pub fn untrack_selected(&mut self) {
+        self.tracked_confirm_clean = false;
+        let Some(link) = self.tracked.playlists().get(self.tracked_selected).map(|p| p.link.clone()) else {
+            self.status_message = "Nothing selected".to_string();
+            return;
+        };
+        // A sync in the worker may have changed the list since it was loaded
+        self.tracked = TrackedPlaylists::open();
+        self.status_message = match self.tracked.untrack(&link) {
+            Ok(Some(removed)) => format!("No longer tracking '{}'", removed.name),
+            Ok(None) => "Nothing selected".to_string(),
+            Err(e) => format!("Could not untrack: {}", e),
+        };
+        if self.tracked_selected >= self.tracked.playlists().len() {
+            self.tracked_selected = self.tracked.playlists().len().saturating_sub(1);
+        }
+    }
+
+    /// Trash files syncs downloaded that no tracked playlist has any more,
+    /// on the second press
+    pub fn clean_tracked(&mut self) {
+        self.tracked = TrackedPlaylists::open();
+        let orphans = sync::cleanable(&self.tracked);
+        if orphans.is_empty() {
+            self.tracked_confirm_clean = false;
+            self.status_message = "Nothing to clean up.".to_string();
+            return;
+        }
+        if !self.tracked_confirm_clean {
+            self.tracked_confirm_clean = true;
+            self.status_message = format!(
+                "Press 'c' again to move {} track(s) no longer in any tracked playlist to the trash.",
+                orphans.len()
+            );
+            return;
+        }
+        self.tracked_confirm_clean = false;
+        self.db = DownloadDB::new("data/cache/downloaded_songs.json");
+        self.trash = Trash::open();
+        match sync::clean(&mut self.tracked, &mut self.db, &mut self.trash) {
+            Ok(removed) => {
+                for path in &removed {
+                    self.add_log(format!("Trashed (sync clean): {}", path));
+                }
+                if !removed.is_empty() {
+                    self.update_playlists();
+                }
+                self.library = self.db.tracks.iter().cloned().collect();
+                self.status_message = format!("Moved {} track(s) to the trash ('t' to view)", removed.len());
+            }
+            Err(e) => self.status_message = format!("Clean failed: {}", e),
+        }
+    }
+
+    /// Rewrite playlists whose tracks were moved or removed from the library
+    fn update_playlists(&mut self) {
+        let result = playlist_index::update_playlists(&self.db);
+        // Tracked playlists may have followed their tracks too
+        self.tracked = TrackedPlaylists::open();
+        match result {
+            Ok(changes) => {
+                for change in changes {
+                    self.add_log(format!(
+                        "Playlist updated: {} ({} moved, {} removed)",
+                        change.name,
+                        change.moved,
+                        change.removed.len()
+                    ));
+                }
+            }
+            Err(e) => self.add_log(format!("Failed to update playlists: {}", e)),
+        }
+    }
+
+    // ============ Smart Playlist Methods ============
+
+    /// Show the smart playlist definitions
+    pub fn show_smart(&mut self) {
+        self.smart = SmartPlaylists::open();
+        self.smart_selected = 0;
+        self.smart_confirm_delete = false;
+        self.view = View::Smart;
+        self.status_message = if self.smart.playlists().is_empty() {
+            "No smart playlists. Press 'n' to create one.".to_string()
+        } else {
+            format!(
+                "{} smart playlist(s). 'g' regenerates, 'G' regenerates all.",
+                self.smart.playlists().len()
+            )
+        };
+    }
+
+    pub fn smart_up(&mut self) {
+        self.smart_confirm_delete = false;
+        self.smart_selected = self.smart_selected.saturating_sub(1);
+    }
+
+    pub fn smart_down(&mut self) {
+        self.smart_confirm_delete = false;
+        if self.smart_selected + 1 < self.smart.playlists().len() {
+            self.smart_selected += 1;
+        }
+    }
+
+    /// Type a new definition as "Name: rules"; 'e' starts from the selected one
+    pub fn start_add_smart(&mut self, edit: bool) {
+        self.smart_confirm_delete = false;
+        self.input = match self.smart.playlists().get(self.smart_selected) {
+            Some(smart) if edit => format!("{}: {}", smart.name, smart.rules()),
+            _ => String::new(),
+        };
+        self.input_mode = true;
+        self.status_message =
+            "Name: rules, e.g. Chill 90s: genre=ambient,chill; year=1990-1999; limit=50; sort=-added".to_string();
+    }
+
+    pub fn submit_smart_input(&mut self) {
+        let input = std::mem::take(&mut self.input);
+        self.input_mode = false;
+        let (name, rules) = input.split_once(':').unwrap_or((&input, ""));
+        let smart = match SmartPlaylist::parse(name, rules) {
+            Ok(smart) => smart,
+            Err(e) => {
+                self.status_message = format!("Invalid smart playlist: {}", e);
+                return;
+            }
+        };
+        let name = smart.name.clone();
+        if let Err(e) = self.smart.save_playlist(smart) {
+            self.status_message = format!("Could not save smart playlist: {}", e);
+            return;
+        }
+        self.smart_selected = self
+            .smart
+            .playlists()
+            .iter()
+            .position(|p| p.name == name)
+            .unwrap_or(0);
+        self.regenerate_smart(false);
+    }
+
+    /// Write the selected smart playlist, or all of them
+    pub fn regenerate_smart(&mut self, all: bool) {
+        self.smart_confirm_delete = false;
+        let only = match self.smart.playlists().get(self.smart_selected) {
+            _ if all => None,
+            Some(smart) => Some(smart.name.clone()),
+            None => {
+                self.status_message = "No smart playlists. Press 'n' to create one.".to_string();
+                return;
+            }
+        };
+        // The worker may have added tracks since the library was loaded
+        self.db = DownloadDB::new("data/cache/downloaded_songs.json");
+        self.status_message = match self.smart.regenerate(only.as_deref(), &self.db, &self.playlist_path) {
+            Ok(regenerated) => {
+                self.db.set_durations(&regenerated.durations);
+                let written = regenerated.written;
+                for (name, count) in &written {
+                    self.add_log(format!("Smart playlist written: {} ({} tracks)", name, count));
+                }
+                match written.as_slice() {
+                    [(name, count)] => format!("Smart playlist '{}' written with {} tracks", name, count),
+                    _ => format!("{} smart playlists written", written.len()),
+                }
+            }
+            Err(e) => format!("Failed to write smart playlists: {}", e),
+        };
+    }
+
+    /// Delete the selected definition on the second press; its playlist
+    /// files are kept
+    pub fn delete_selected_smart(&mut self) {
+        let Some(name) = self.smart.playlists().get(self.smart_selected).map(|p| p.name.clone()) else {
+            self.status_message = "Nothing selected".to_string();
+            return;
+        };
+        if !self.smart_confirm_delete {
+            self.smart_confirm_delete = true;
+            self.status_message = format!("Press 'd' again to delete smart playlist '{}'.", name);
+            return;
+        }
+        self.smart_confirm_delete = false;
+        self.status_message = match self.smart.remove(&name) {
+            Ok(_) => format!("Deleted smart playlist '{}'; its playlist files are kept", name),
+            Err(e) => format!("Could not delete: {}", e),
+        };
+        if self.smart_selected >= self.smart.playlists().len() {
+            self.smart_selected = self.smart.playlists().len().saturating_sub(1);
+        }
+    }
+
     // ============ Error Log Methods ============
 
     /// Show the error log view
     pub fn show_error_log(&mut self) {
         // Refresh dates list
-        self.error_dates = self.error_log.list_dates();
+        self.error_dates = lock(&self.error_log).list_dates();
 
         // Load errors for the first date if available
         if !self.error_dates.is_empty() {
@@ -1226,7 +2323,7 @@ impl App {
         self.error_selected = 0;
         self.view = View::ErrorLog;
 
-        let (d, c, r) = self.error_log.get_total_error_counts();
+        let (d, c, r) = lock(&self.error_log).get_total_error_counts();
         let total = d + c + r;
         if total == 0 {
             self.status_message = "No errors logged.".to_string();
@@ -1244,9 +2341,9 @@ impl App {
             return;
         }
         let date = &self.error_dates[self.error_date_selected];
-        self.download_errors = self.error_log.get_download_errors_for_date(date);
-        self.convert_errors = self.error_log.get_convert_errors_for_date(date);
-        self.refresh_errors = self.error_log.get_refresh_errors_for_date(date);
+        self.download_errors = lock(&self.error_log).get_download_errors_for_date(date);
+        self.convert_errors = lock(&self.error_log).get_convert_errors_for_date(date);
+        self.refresh_errors = lock(&self.error_log).get_refresh_errors_for_date(date);
         self.error_selected = 0;
     }
 
@@ -1326,7 +2423,7 @@ impl App {
             ErrorTab::Download => {
                 if self.error_selected < self.download_errors.len() {
                     let id = self.download_errors[self.error_selected].id.clone();
-                    self.error_log.remove_download_error(&date, &id)
+                    lock(&self.error_log).remove_download_error(&date, &id)
                 } else {
                     false
                 }
@@ -1334,7 +2431,7 @@ impl App {
             ErrorTab::Convert => {
                 if self.error_selected < self.convert_errors.len() {
                     let id = self.convert_errors[self.error_selected].id.clone();
-                    self.error_log.remove_convert_error(&date, &id)
+                    lock(&self.error_log).remove_convert_error(&date, &id)
                 } else {
                     false
                 }
@@ -1342,7 +2439,7 @@ impl App {
             ErrorTab::Refresh => {
                 if self.error_selected < self.refresh_errors.len() {
                     let id = self.refresh_errors[self.error_selected].id.clone();
-                    self.error_log.remove_refresh_error(&date, &id)
+                    lock(&self.error_log).remove_refresh_error(&date, &id)
                 } else {
                     false
                 }
@@ -1351,7 +2448,7 @@ impl App {
 
         if removed {
             // Refresh the view
-            self.error_dates = self.error_log.list_dates();
+            self.error_dates = lock(&self.error_log).list_dates();
             if self.error_date_selected >= self.error_dates.len() && !self.error_dates.is_empty() {
                 self.error_date_selected = self.error_dates.len() - 1;
             }
@@ -1370,62 +2467,18 @@ impl App {
             return;
         }
         let date = self.error_dates[self.error_date_selected].clone();
-        self.error_log.clear_date(&date);
+        lock(&self.error_log).clear_date(&date);
 
         // Refresh
-        self.error_dates = self.error_log.list_dates();
+        self.error_dates = lock(&self.error_log).list_dates();
         self.error_date_selected = 0;
         self.load_errors_for_current_date();
         self.status_message = format!("Cleared all errors from {}.", date);
     }
 
-    /// Get the selected error's ID and date for retry
-    pub fn get_selected_error_info(&self) -> Option<(String, String, ErrorTab)> {
-        if self.error_dates.is_empty() {
-            return None;
-        }
-        let date = self.error_dates[self.error_date_selected].clone();
-
-        match self.error_tab {
-            ErrorTab::Download => {
-                if self.error_selected < self.download_errors.len() {
-                    Some((
-                        self.download_errors[self.error_selected].id.clone(),
-                        date,
-                        ErrorTab::Download,
-                    ))
-                } else {
-                    None
-                }
-            }
-            ErrorTab::Convert => {
-                if self.error_selected < self.convert_errors.len() {
-                    Some((
-                        self.convert_errors[self.error_selected].id.clone(),
-                        date,
-                        ErrorTab::Convert,
-                    ))
-                } else {
-                    None
-                }
-            }
-            ErrorTab::Refresh => {
-                if self.error_selected < self.refresh_errors.len() {
-                    Some((
-                        self.refresh_errors[self.error_selected].id.clone(),
-                        date,
-                        ErrorTab::Refresh,
-                    ))
-                } else {
-                    None
-                }
-            }
-        }
-    }
-
     /// Refresh error log data
     pub fn refresh_error_logs(&mut self) {
-        self.error_dates = self.error_log.list_dates();
+        self.error_dates = lock(&self.error_log).list_dates();
         if self.error_date_selected >= self.error_dates.len() {
             self.error_date_selected = 0;
         }
@@ -1456,6 +2509,12 @@ impl App {
                     "album" => LinkType::Album,
                     "playlist" => LinkType::Playlist,
                     "youtube_playlist" => LinkType::YouTubePlaylist,
+                    // Imports run from the command line only; the entry stays
+                    // in the log until the import is re-run
+                    "import" => {
+                        self.status_message = format!("Retry imported tracks with: rustwav import \"{}\"", error.link);
+                        return;
+                    }
                     _ => {
                         self.status_message =
                             format!("Unknown link type: {}", error.link_type);
@@ -1478,6 +2537,7 @@ impl App {
                     status: JobStatus::Fetching,
                     current_track: None,
                     progress: (0, 0),
+                    throughput: None,
                 });
 
                 let request = match link_type {
@@ -1487,13 +2547,17 @@ impl App {
                         portable: error.portable,
                         format: error.format.clone(),
                         quality: error.quality.clone(),
+                        lyrics: self.fetch_lyrics,
                     },
-                    LinkType::Playlist => DownloadRequest::Playlist {
+                    LinkType::Playlist | LinkType::TrackedPlaylist => DownloadRequest::Playlist {
                         id,
                         link: error.link.clone(),
                         portable: error.portable,
                         format: error.format.clone(),
                         quality: error.quality.clone(),
+                        lyrics: self.fetch_lyrics,
+                        playlist_formats: self.playlist_formats.clone(),
+                        sync: false,
                     },
                     LinkType::YouTubePlaylist => DownloadRequest::YouTubePlaylist {
                         id,
@@ -1501,6 +2565,8 @@ impl App {
                         portable: error.portable,
                         format: error.format.clone(),
                         quality: error.quality.clone(),
+                        lyrics: self.fetch_lyrics,
+                        playlist_formats: self.playlist_formats.clone(),
                     },
                 };
 
@@ -1510,8 +2576,8 @@ impl App {
                 });
 
                 // Increment retry count and remove from error log
-                self.error_log.increment_download_retry(&date, &error_id);
-                self.error_log.remove_download_error(&date, &error_id);
+                lock(&self.error_log).increment_download_retry(&date, &error_id);
+                lock(&self.error_log).remove_download_error(&date, &error_id);
                 self.refresh_error_logs();
 
                 self.view = View::Queue;
@@ -1543,19 +2609,22 @@ impl App {
                     input_path: error.input_path.clone(),
                     target_format: error.target_format.clone(),
                     quality: error.quality.clone(),
+                    encoder: error.encoder.clone(),
+                    output_dir: error.output_dir.clone(),
+                    keep_both: error.keep_both,
                     refresh_metadata: error.refresh_metadata,
                     artist: error.artist.clone(),
                     title: error.title.clone(),
                 };
 
-                let tx = self.download_tx.clone();
+                let tx = self.convert_tx.clone();
                 tokio::spawn(async move {
                     let _ = tx.send(request).await;
                 });
 
                 // Increment retry count and remove from error log
-                self.error_log.increment_convert_retry(&date, &error_id);
-                self.error_log.remove_convert_error(&date, &error_id);
+                lock(&self.error_log).increment_convert_retry(&date, &error_id);
+                lock(&self.error_log).remove_convert_error(&date, &error_id);
                 self.refresh_error_logs();
 
                 self.view = View::Logs;
@@ -1595,8 +2664,8 @@ impl App {
                 });
 
                 // Increment retry count and remove from error log
-                self.error_log.increment_refresh_retry(&date, &error_id);
-                self.error_log.remove_refresh_error(&date, &error_id);
+                lock(&self.error_log).increment_refresh_retry(&date, &error_id);
+                lock(&self.error_log).remove_refresh_error(&date, &error_id);
                 self.refresh_error_logs();
 
                 self.view = View::Logs;
@@ -1620,107 +2689,57 @@ enum M3UCheckResult {
         paths: Vec<PathBuf>,
     },
     NoneFound {
+        name: String,
         total: usize,
     },
 }
 
-/// Check which tracks from a Spotify link are in the database
-async fn check_m3u_tracks(link: &str, db_tracks: &[TrackEntry]) -> M3UCheckResult {
-    let is_album = link.contains("/album/");
-    let is_playlist = link.contains("/playlist/");
+impl M3UCheckResult {
+    /// Name of the album or playlist, once it was fetched
+    fn name(&self) -> Option<&str> {
+        match self {
+            M3UCheckResult::Error(_) => None,
+            M3UCheckResult::AllFound { name, .. }
+            | M3UCheckResult::SomeMissing { name, .. }
+            | M3UCheckResult::NoneFound { name, .. } => Some(name),
+        }
+    }
+}
 
-    if !is_album && !is_playlist {
+/// Check which tracks from a Spotify link are in the library, matching
+/// names like imports do
+async fn check_m3u_tracks(link: &str, db_tracks: &[TrackEntry]) -> M3UCheckResult {
+    if SourceKind::of(link).is_none() {
         return M3UCheckResult::Error(
             "Error: Invalid Spotify link (must be album or playlist)".to_string(),
         );
     }
 
-    // Fetch tracks from Spotify and get the name
-    let (spotify_tracks, m3u_name): (Vec<(String, String)>, String) = if is_album {
-        match spotify::fetch_album(link).await {
-            Ok(album) => {
-                let album_name = album.name.clone();
-                let main_artist = album
-                    .artists
-                    .first()
-                    .map(|a| a.name.clone())
-                    .unwrap_or_else(|| "Unknown".to_string());
-                let tracks: Vec<(String, String)> = album
-                    .tracks
-                    .items
-                    .iter()
-                    .map(|t| {
-                        let artist = t
-                            .artists
-                            .first()
-                            .map(|a| a.name.clone())
-                            .unwrap_or_else(|| main_artist.clone());
-                        (artist, t.name.clone())
-                    })
-                    .collect();
-                (tracks, format!("{} - {}", main_artist, album_name))
-            }
-            Err(e) => return M3UCheckResult::Error(format!("Spotify error: {}", e)),
-        }
-    } else {
-        match spotify::fetch_playlist(link).await {
-            Ok(playlist) => {
-                let playlist_name = playlist.name.clone();
-                match spotify::fetch_all_playlist_items(link).await {
-                    Ok(items) => {
-                        let tracks: Vec<(String, String)> = items
-                            .iter()
-                            .filter_map(|item| {
-                                if let Some(rspotify::model::PlayableItem::Track(t)) = &item.track {
-                                    let artist = t
-                                        .artists
-                                        .first()
-                                        .map(|a| a.name.clone())
-                                        .unwrap_or_else(|| "Unknown".to_string());
-                                    Some((artist, t.name.clone()))
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect();
-                        (tracks, playlist_name)
-                    }
-                    Err(e) => return M3UCheckResult::Error(format!("Spotify error: {}", e)),
-                }
-            }
-            Err(e) => return M3UCheckResult::Error(format!("Spotify error: {}", e)),
-        }
+    let fetched = match regenerate::fetch_tracks(link).await {
+        Ok(fetched) => fetched,
+        Err(e) => return M3UCheckResult::Error(format!("Spotify error: {}", e)),
     };
 
-    // Match against database
-    let mut found_paths: Vec<PathBuf> = Vec::new();
-    let mut missing = 0;
-
-    for (artist, title) in &spotify_tracks {
-        let found = db_tracks.iter().find(|e| {
-            e.artist.to_lowercase() == artist.to_lowercase()
-                && e.title.to_lowercase() == title.to_lowercase()
-        });
-
-        if let Some(entry) = found {
-            found_paths.push(PathBuf::from(&entry.path));
-        } else {
-            missing += 1;
-        }
-    }
+    let library: Vec<TrackEntry> = db_tracks.iter().filter(|t| !t.is_missing()).cloned().collect();
+    let found_paths: Vec<PathBuf> = regenerate::match_tracks(&fetched.tracks, &library)
+        .into_iter()
+        .flatten()
+        .collect();
+    let missing = fetched.tracks.len() - found_paths.len();
 
     if found_paths.is_empty() {
         M3UCheckResult::NoneFound {
-            total: spotify_tracks.len(),
+            name: fetched.name,
+            total: fetched.tracks.len(),
         }
     } else if missing == 0 {
         M3UCheckResult::AllFound {
-            name: m3u_name,
+            name: fetched.name,
             paths: found_paths,
         }
     } else {
         M3UCheckResult::SomeMissing {
-            name: m3u_name,
+            name: fetched.name,
             found: found_paths.len(),
             missing,
             paths: found_paths,
@@ -1728,12 +2747,22 @@ async fn check_m3u_tracks(link: &str, db_tracks: &[TrackEntry]) -> M3UCheckResul
     }
 }
 
-/// Actually generate the M3U file
-fn do_generate_m3u(name: &str, paths: &[PathBuf], playlist_path: &std::path::Path) -> String {
-    match file_utils::create_m3u(name, paths, playlist_path) {
-        Ok(_) => format!(
-            "Created: {}.m3u ({} tracks)",
-            file_utils::sanitize_filename(name),
+/// Actually generate the playlist files
+fn do_generate_m3u(
+    name: &str,
+    paths: &[PathBuf],
+    playlist_path: &std::path::Path,
+    formats: &[PlaylistFormat],
+) -> String {
+    let db = DownloadDB::new("data/cache/downloaded_songs.json");
+    match file_utils::create_playlists(name, paths, playlist_path, Some(&db), formats) {
+        Ok(files) => format!(
+            "Created: {} ({} tracks)",
+            files
+                .iter()
+                .map(|f| f.file_name().unwrap_or_default().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", "),
             paths.len()
         ),
         Err(e) => format!("Failed: {}", e),
@@ -17,7 +17,12 @@ pub fn handle_events(app: &mut App) -> anyhow::Result<()> {
                     View::ConvertConfirm => handle_convert_confirm_mode(app, key.code),
                     View::ConvertBatchConfirm => handle_convert_batch_confirm_mode(app, key.code),
                     View::CleanupConfirm => handle_cleanup_confirm_mode(app, key.code),
+                    View::NormalizeConfirm => handle_normalize_confirm_mode(app, key.code),
                     View::ErrorLog => handle_error_log_mode(app, key.code, key.modifiers),
+                    View::Trash => handle_trash_mode(app, key.code, key.modifiers),
+                    View::Tracked => handle_tracked_mode(app, key.code, key.modifiers),
+                    View::Smart => handle_smart_mode(app, key.code, key.modifiers),
+                    View::RegenerateConfirm => handle_regenerate_confirm_mode(app, key.code),
                     _ => handle_normal_mode(app, key.code, key.modifiers),
                 }
             }
@@ -31,6 +36,8 @@ fn handle_input_mode(app: &mut App, key: KeyCode) {
         KeyCode::Enter => {
             if app.view == View::GenerateM3U {
                 app.submit_m3u_input();
+            } else if app.view == View::Smart {
+                app.submit_smart_input();
             } else {
                 app.submit_input();
             }
@@ -54,6 +61,7 @@ fn handle_settings_mode(app: &mut App, key: KeyCode) {
         KeyCode::Down | KeyCode::Char('j') => app.settings_down(),
         KeyCode::Left | KeyCode::Char('h') => app.settings_left(),
         KeyCode::Right | KeyCode::Char('l') => app.settings_right(),
+        KeyCode::Char(' ') => app.settings_toggle(),
         _ => {}
     }
 }
@@ -61,11 +69,20 @@ fn handle_settings_mode(app: &mut App, key: KeyCode) {
 fn handle_m3u_confirm_mode(app: &mut App, key: KeyCode) {
     match key {
         KeyCode::Enter | KeyCode::Char('y') => app.confirm_m3u(),
+        KeyCode::Char('d') => app.confirm_m3u_and_download(),
         KeyCode::Esc | KeyCode::Char('n') => app.cancel_m3u(),
         _ => {}
     }
 }
 
+fn handle_regenerate_confirm_mode(app: &mut App, key: KeyCode) {
+    match key {
+        KeyCode::Enter | KeyCode::Char('y') => app.confirm_regenerate_downloads(),
+        KeyCode::Esc | KeyCode::Char('n') => app.cancel_regenerate_downloads(),
+        _ => {}
+    }
+}
+
 fn handle_logs_mode(app: &mut App, key: KeyCode, modifiers: KeyModifiers) {
     match key {
         KeyCode::Char('q') => app.quit(),
@@ -84,6 +101,7 @@ fn handle_logs_mode(app: &mut App, key: KeyCode, modifiers: KeyModifiers) {
         KeyCode::Char('P') => app.toggle_portable(),
         KeyCode::Char('r') => app.refresh_library(),
         KeyCode::Char('m') => app.start_generate_m3u(),
+        KeyCode::Char('R') => app.regenerate_all_playlists(),
         KeyCode::Char(' ') => app.toggle_pause(),
         _ => {}
     }
@@ -103,6 +121,13 @@ fn handle_normal_mode(app: &mut App, key: KeyCode, modifiers: KeyModifiers) {
         KeyCode::Char('X') if app.view == View::Library => app.start_refresh_all_metadata(),
         // 'z' in Library view starts database cleanup
         KeyCode::Char('z') if app.view == View::Library => app.start_cleanup_database(),
+        // 'L' in Library view fetches lyrics for selected track
+        KeyCode::Char('L') if app.view == View::Library => app.start_fetch_lyrics(),
+        // 'v' in Library view re-embeds and audits cover art for the selected album folder
+        KeyCode::Char('v') if app.view == View::Library => app.start_reembed_cover(),
+        // 'g'/'G' in Library view write ReplayGain tags for the selected album folder / ALL tracks
+        KeyCode::Char('g') if app.view == View::Library => app.start_analyze_loudness(false),
+        KeyCode::Char('G') if app.view == View::Library => app.start_analyze_loudness(true),
         KeyCode::Tab => app.next_view(),
         KeyCode::Char('a') => app.start_add_album(),
         KeyCode::Char('p') => app.start_add_playlist(),
@@ -110,7 +135,11 @@ fn handle_normal_mode(app: &mut App, key: KeyCode, modifiers: KeyModifiers) {
         KeyCode::Char('P') => app.toggle_portable(),
         KeyCode::Char('l') => app.show_logs(),
         KeyCode::Char('e') => app.show_error_log(),
+        KeyCode::Char('t') => app.show_trash(),
+        KeyCode::Char('T') => app.show_tracked(),
+        KeyCode::Char('S') => app.show_smart(),
         KeyCode::Char('m') => app.start_generate_m3u(),
+        KeyCode::Char('R') => app.regenerate_all_playlists(),
         KeyCode::Char(' ') => app.toggle_pause(),
         KeyCode::Up | KeyCode::Char('k') => match app.view {
             View::Queue => app.queue_up(),
@@ -136,6 +165,11 @@ fn handle_convert_settings_mode(app: &mut App, key: KeyCode) {
         KeyCode::Char('h') => app.convert_quality_left(),
         KeyCode::Char('l') => app.convert_quality_right(),
         KeyCode::Char(' ') => app.convert_toggle_refresh(),
+        KeyCode::Char('k') => app.convert_toggle_keep_both(),
+        KeyCode::Up => app.convert_settings_up(),
+        KeyCode::Down => app.convert_settings_down(),
+        KeyCode::Char('-') => app.convert_encoder_cycle(false),
+        KeyCode::Char('+') | KeyCode::Char('=') => app.convert_encoder_cycle(true),
         _ => {}
     }
 }
@@ -164,6 +198,14 @@ fn handle_cleanup_confirm_mode(app: &mut App, key: KeyCode) {
     }
 }
 
+fn handle_normalize_confirm_mode(app: &mut App, key: KeyCode) {
+    match key {
+        KeyCode::Char('y') => app.confirm_normalize(),
+        KeyCode::Char('n') | KeyCode::Esc => app.cancel_normalize(),
+        _ => {}
+    }
+}
+
 fn handle_error_log_mode(app: &mut App, key: KeyCode, modifiers: KeyModifiers) {
     match key {
         KeyCode::Char('q') => app.quit(),
@@ -195,3 +237,58 @@ fn handle_error_log_mode(app: &mut App, key: KeyCode, modifiers: KeyModifiers) {
         _ => {}
     }
 }
+
+fn handle_trash_mode(app: &mut App, key: KeyCode, modifiers: KeyModifiers) {
+    match key {
+        KeyCode::Char('q') => app.quit(),
+        KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => app.quit(),
+        KeyCode::Esc => {
+            app.view = View::Library;
+            app.status_message = "Returned to library".to_string();
+        }
+        KeyCode::Up | KeyCode::Char('k') => app.trash_up(),
+        KeyCode::Down | KeyCode::Char('j') => app.trash_down(),
+        KeyCode::Char('r') | KeyCode::Enter => app.restore_selected_trash(),
+        KeyCode::Char('D') => app.empty_trash(),
+        _ => {}
+    }
+}
+
+fn handle_tracked_mode(app: &mut App, key: KeyCode, modifiers: KeyModifiers) {
+    match key {
+        KeyCode::Char('q') => app.quit(),
+        KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => app.quit(),
+        KeyCode::Esc => {
+            app.view = View::Main;
+            app.status_message = "Returned to main view".to_string();
+        }
+        KeyCode::Up | KeyCode::Char('k') => app.tracked_up(),
+        KeyCode::Down | KeyCode::Char('j') => app.tracked_down(),
+        KeyCode::Char('s') | KeyCode::Enter => app.sync_tracked(false),
+        KeyCode::Char('S') => app.sync_tracked(true),
+        KeyCode::Char('n') => app.start_track_playlist(),
+        KeyCode::Char('d') => app.untrack_selected(),
+        KeyCode::Char('c') => app.clean_tracked(),
+        KeyCode::Char('r') => app.show_tracked(),
+        _ => {}
+    }
+}
+
+fn handle_smart_mode(app: &mut App, key: KeyCode, modifiers: KeyModifiers) {
+    match key {
+        KeyCode::Char('q') => app.quit(),
+        KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => app.quit(),
+        KeyCode::Esc => {
+            app.view = View::Main;
+            app.status_message = "Returned to main view".to_string();
+        }
+        KeyCode::Up | KeyCode::Char('k') => app.smart_up(),
+        KeyCode::Down | KeyCode::Char('j') => app.smart_down(),
+        KeyCode::Char('n') => app.start_add_smart(false),
+        KeyCode::Char('e') => app.start_add_smart(true),
+        KeyCode::Char('g') | KeyCode::Enter => app.regenerate_smart(false),
+        KeyCode::Char('G') => app.regenerate_smart(true),
+        KeyCode::Char('d') => app.delete_selected_smart(),
+        _ => {}
+    }
+}
@@ -6,7 +6,12 @@ use ratatui::{
     Frame,
 };
 
-use super::app::{App, CleanupPreview, ErrorTab, JobStatus, SettingsField, View, FORMAT_OPTIONS, QUALITY_OPTIONS};
+use super::app::{
+    App, CleanupPreview, ErrorTab, JobStatus, LinkType, SettingsField, View, DOWNLOAD_FORMAT_OPTIONS,
+    ENCODER_FIELDS, FORMAT_OPTIONS,
+    QUALITY_OPTIONS,
+};
+use crate::config::PlaylistFormat;
 
 pub fn draw(frame: &mut Frame, app: &App) {
     let chunks = Layout::default()
@@ -37,9 +42,9 @@ fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
     ];
 
     let selected = match app.view {
-        View::Main | View::AddLink | View::LinkSettings | View::GenerateM3U | View::M3UConfirm | View::ErrorLog => 0,
+        View::Main | View::AddLink | View::LinkSettings | View::GenerateM3U | View::M3UConfirm | View::ErrorLog | View::Tracked | View::Smart | View::RegenerateConfirm => 0,
         View::Queue => 1,
-        View::Library | View::ConvertSettings | View::ConvertConfirm | View::ConvertBatchConfirm | View::CleanupConfirm => 2,
+        View::Library | View::ConvertSettings | View::ConvertConfirm | View::ConvertBatchConfirm | View::CleanupConfirm | View::NormalizeConfirm | View::Trash => 2,
         View::Logs => 3,
     };
 
@@ -74,7 +79,12 @@ fn draw_main(frame: &mut Frame, app: &App, area: Rect) {
         View::ConvertConfirm => draw_convert_confirm_view(frame, app, area),
         View::ConvertBatchConfirm => draw_convert_batch_confirm_view(frame, app, area),
         View::CleanupConfirm => draw_cleanup_confirm_view(frame, app, area),
+        View::NormalizeConfirm => draw_normalize_confirm_view(frame, app, area),
         View::ErrorLog => draw_error_log_view(frame, app, area),
+        View::Trash => draw_trash_view(frame, app, area),
+        View::Tracked => draw_tracked_view(frame, app, area),
+        View::Smart => draw_smart_view(frame, app, area),
+        View::RegenerateConfirm => draw_regenerate_confirm_view(frame, app, area),
     }
 }
 
@@ -121,10 +131,26 @@ fn draw_main_view(frame: &mut Frame, app: &App, area: Rect) {
             Span::styled("    e", Style::default().fg(Color::Yellow)),
             Span::raw("  View error logs"),
         ]),
+        Line::from(vec![
+            Span::styled("    t", Style::default().fg(Color::Yellow)),
+            Span::raw("  View trash (restore deleted files)"),
+        ]),
+        Line::from(vec![
+            Span::styled("    T", Style::default().fg(Color::Yellow)),
+            Span::raw("  Tracked playlists (sync with Spotify)"),
+        ]),
+        Line::from(vec![
+            Span::styled("    S", Style::default().fg(Color::Yellow)),
+            Span::raw("  Smart playlists (rules over the library)"),
+        ]),
         Line::from(vec![
             Span::styled("    m", Style::default().fg(Color::Yellow)),
             Span::raw("  Generate M3U from Spotify link"),
         ]),
+        Line::from(vec![
+            Span::styled("    R", Style::default().fg(Color::Yellow)),
+            Span::raw("  Regenerate all album/playlist playlists"),
+        ]),
         Line::from(vec![
             Span::styled("Space", Style::default().fg(Color::Yellow)),
             Span::raw("  Pause/resume downloads"),
@@ -159,6 +185,7 @@ fn draw_add_link_view(frame: &mut Frame, app: &App, area: Rect) {
     let title = match app.link_type {
         crate::tui::app::LinkType::Album => " Spotify Album Link ",
         crate::tui::app::LinkType::Playlist => " Spotify Playlist Link ",
+        crate::tui::app::LinkType::TrackedPlaylist => " Spotify Playlist Link (track) ",
         crate::tui::app::LinkType::YouTubePlaylist => " YouTube Playlist Link ",
     };
     let input = Paragraph::new(app.input.as_str())
@@ -193,7 +220,9 @@ fn draw_link_settings_view(frame: &mut Frame, app: &App, area: Rect) {
             Constraint::Length(2), // Spacing
             Constraint::Length(2), // Format row
             Constraint::Length(2), // Quality row
-            Constraint::Length(2), // Spacing
+            Constraint::Length(2), // Lyrics row
+            Constraint::Length(2), // Playlist files row
+            Constraint::Length(1), // Spacing
             Constraint::Min(0),    // Help text
         ])
         .margin(1)
@@ -211,7 +240,7 @@ fn draw_link_settings_view(frame: &mut Frame, app: &App, area: Rect) {
 
     let mut format_spans = vec![Span::styled("  Format:   ", format_label_style)];
 
-    for (i, fmt) in FORMAT_OPTIONS.iter().enumerate() {
+    for (i, fmt) in DOWNLOAD_FORMAT_OPTIONS.iter().enumerate() {
         let is_selected = i == app.selected_format;
         let style = if is_selected {
             Style::default()
@@ -269,6 +298,71 @@ fn draw_link_settings_view(frame: &mut Frame, app: &App, area: Rect) {
     let quality_line = Paragraph::new(Line::from(quality_spans));
     frame.render_widget(quality_line, chunks[2]);
 
+    // Lyrics toggle
+    let lyrics_active = app.settings_field == SettingsField::Lyrics;
+    let lyrics_label_style = if lyrics_active {
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(Color::White)
+    };
+
+    let mut lyrics_spans = vec![Span::styled("  Lyrics:   ", lyrics_label_style)];
+    for (value, label) in [(false, "off"), (true, "on")] {
+        let style = if value == app.fetch_lyrics {
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Cyan)
+                .add_modifier(Modifier::BOLD)
+        } else if lyrics_active {
+            Style::default().fg(Color::White)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        lyrics_spans.push(Span::styled(format!(" {} ", label), style));
+    }
+
+    let lyrics_line = Paragraph::new(Line::from(lyrics_spans));
+    frame.render_widget(lyrics_line, chunks[3]);
+
+    // Playlist file formats (several can be on)
+    let playlists_active = app.settings_field == SettingsField::Playlists;
+    let playlists_label_style = if playlists_active {
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(Color::White)
+    };
+
+    let mut playlist_spans = vec![Span::styled("  Playlist: ", playlists_label_style)];
+    for (i, fmt) in PlaylistFormat::ALL.iter().enumerate() {
+        let mut style = if app.playlist_formats.contains(fmt) {
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Cyan)
+                .add_modifier(Modifier::BOLD)
+        } else if playlists_active {
+            Style::default().fg(Color::White)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        if playlists_active && i == app.playlist_format_cursor {
+            style = style.add_modifier(Modifier::UNDERLINED);
+        }
+        playlist_spans.push(Span::styled(format!(" {} ", fmt.extension()), style));
+    }
+    if app.link_type == LinkType::Album {
+        playlist_spans.push(Span::styled(
+            "  (playlists only)",
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+
+    let playlist_line = Paragraph::new(Line::from(playlist_spans));
+    frame.render_widget(playlist_line, chunks[4]);
+
     // Help text
     let help_text = vec![
         Line::from(""),
@@ -276,7 +370,9 @@ fn draw_link_settings_view(frame: &mut Frame, app: &App, area: Rect) {
             Span::styled("  ↑/↓", Style::default().fg(Color::Yellow)),
             Span::raw("  Select option    "),
             Span::styled("←/→", Style::default().fg(Color::Yellow)),
-            Span::raw("  Change value"),
+            Span::raw("  Change value    "),
+            Span::styled("Space", Style::default().fg(Color::Yellow)),
+            Span::raw("  Toggle playlist format"),
         ]),
         Line::from(vec![
             Span::styled("  Enter", Style::default().fg(Color::Yellow)),
@@ -287,7 +383,7 @@ fn draw_link_settings_view(frame: &mut Frame, app: &App, area: Rect) {
     ];
 
     let help = Paragraph::new(help_text).style(Style::default().fg(Color::DarkGray));
-    frame.render_widget(help, chunks[4]);
+    frame.render_widget(help, chunks[6]);
 }
 
 fn draw_queue_view(frame: &mut Frame, app: &App, area: Rect) {
@@ -323,15 +419,26 @@ fn draw_queue_view(frame: &mut Frame, app: &App, area: Rect) {
                 JobStatus::Pending => ("○", Color::DarkGray),
                 JobStatus::Fetching => ("◐", Color::Yellow),
                 JobStatus::Downloading => ("●", Color::Cyan),
+                JobStatus::Converting => ("◑", Color::Magenta),
                 JobStatus::Complete => ("✓", Color::Green),
                 JobStatus::Failed(_) => ("✗", Color::Red),
             };
 
-            let progress_str = if item.progress.1 > 0 {
+            let mut progress_str = if item.progress.1 > 0 {
                 format!(" [{}/{}]", item.progress.0, item.progress.1)
             } else {
                 String::new()
             };
+            if let (JobStatus::Converting, Some(throughput)) = (&item.status, &item.throughput) {
+                progress_str.push_str(&format!(
+                    " {:.0}% · {:.1}x realtime",
+                    throughput.fraction * 100.0,
+                    throughput.speed()
+                ));
+                if let Some(eta) = throughput.eta_secs() {
+                    progress_str.push_str(&format!(" · ETA {}", format_eta(eta)));
+                }
+            }
 
             let style = if i == app.queue_selected {
                 Style::default().bg(Color::DarkGray).fg(Color::White)
@@ -364,9 +471,11 @@ fn draw_queue_view(frame: &mut Frame, app: &App, area: Rect) {
     if let Some(current) = app
         .queue
         .iter()
-        .find(|q| q.status == JobStatus::Downloading)
+        .find(|q| matches!(q.status, JobStatus::Downloading | JobStatus::Converting))
     {
-        let progress = if current.progress.1 > 0 {
+        let progress = if let Some(throughput) = &current.throughput {
+            throughput.fraction.min(1.0)
+        } else if current.progress.1 > 0 {
             (current.progress.0 as f64 / current.progress.1 as f64).min(1.0)
         } else {
             0.0
@@ -440,7 +549,7 @@ fn draw_library_view(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(list, chunks[0]);
 
     // Help hint at bottom
-    let help = Paragraph::new(" ↑/↓ Navigate  |  c/C Convert  |  x/X Refresh Metadata  |  z Cleanup DB  |  r Refresh  |  Tab Switch")
+    let help = Paragraph::new(" ↑/↓ Navigate  |  c/C Convert  |  x/X Refresh Metadata  |  L Lyrics  |  v Cover  |  g/G Gain  |  z Cleanup DB  |  t Trash  |  r Refresh  |  Tab Switch")
         .style(Style::default().fg(Color::DarkGray));
     frame.render_widget(help, chunks[1]);
 }
@@ -602,6 +711,11 @@ fn draw_m3u_confirm_view(frame: &mut Frame, app: &App, area: Rect) {
                 Span::styled("y", Style::default().fg(Color::Green)),
                 Span::raw(" to generate anyway"),
             ]),
+            Line::from(vec![
+                Span::raw("  Press "),
+                Span::styled("d", Style::default().fg(Color::Green)),
+                Span::raw(" to generate and download the missing tracks"),
+            ]),
             Line::from(vec![
                 Span::raw("  Press "),
                 Span::styled("Esc", Style::default().fg(Color::Red)),
@@ -616,6 +730,50 @@ fn draw_m3u_confirm_view(frame: &mut Frame, app: &App, area: Rect) {
     }
 }
 
+fn draw_regenerate_confirm_view(frame: &mut Frame, app: &App, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Regenerate Playlists - Missing Tracks ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let mut text = vec![
+        Line::from(""),
+        Line::from(Span::styled(
+            "  These albums and playlists have tracks that are not downloaded:",
+            Style::default().fg(Color::Yellow),
+        )),
+        Line::from(""),
+    ];
+    for (source, missing) in &app.regen_missing {
+        text.push(Line::from(vec![
+            Span::raw(format!("  {} ", source.name)),
+            Span::styled(
+                format!("({} missing)", missing),
+                Style::default().fg(Color::Yellow),
+            ),
+        ]));
+    }
+    text.push(Line::from(""));
+    text.push(Line::from(vec![
+        Span::raw("  Press "),
+        Span::styled("Enter", Style::default().fg(Color::Green)),
+        Span::raw(" or "),
+        Span::styled("y", Style::default().fg(Color::Green)),
+        Span::raw(" to queue their downloads"),
+    ]));
+    text.push(Line::from(vec![
+        Span::raw("  Press "),
+        Span::styled("Esc", Style::default().fg(Color::Red)),
+        Span::raw(" or "),
+        Span::styled("n", Style::default().fg(Color::Red)),
+        Span::raw(" to skip"),
+    ]));
+
+    let paragraph = Paragraph::new(text);
+    frame.render_widget(paragraph, inner);
+}
+
 fn draw_convert_settings_view(frame: &mut Frame, app: &App, area: Rect) {
     let block = Block::default()
         .borders(Borders::ALL)
@@ -629,7 +787,8 @@ fn draw_convert_settings_view(frame: &mut Frame, app: &App, area: Rect) {
             Constraint::Length(2), // Track info
             Constraint::Length(2), // Format row
             Constraint::Length(2), // Quality row
-            Constraint::Length(2), // Refresh metadata toggle
+            Constraint::Length(4), // Refresh metadata, keep both and output folder
+            Constraint::Length(ENCODER_FIELDS.len() as u16 + 1), // Encoder options
             Constraint::Min(0),    // Help text
         ])
         .margin(1)
@@ -695,8 +854,57 @@ fn draw_convert_settings_view(frame: &mut Frame, app: &App, area: Rect) {
     } else {
         Span::styled("[ ] Refresh metadata from Spotify", Style::default().fg(Color::DarkGray))
     };
-    let refresh_line = Paragraph::new(Line::from(vec![Span::raw("  "), refresh_status]));
-    frame.render_widget(refresh_line, chunks[3]);
+    let keep_both_status = if app.convert_keep_both {
+        Span::styled("[x] Keep originals as format variants", Style::default().fg(Color::Green))
+    } else {
+        Span::styled("[ ] Keep originals as format variants", Style::default().fg(Color::DarkGray))
+    };
+    let output_dir = match &app.convert_output_dir {
+        Some(dir) => Span::styled(dir.as_str(), Style::default().fg(Color::Yellow)),
+        None => Span::styled("beside the originals", Style::default().fg(Color::DarkGray)),
+    };
+    let toggle_lines = Paragraph::new(vec![
+        Line::from(vec![Span::raw("  "), refresh_status]),
+        Line::from(vec![Span::raw("  "), keep_both_status]),
+        Line::from(vec![Span::raw("  Output: "), output_dir]),
+    ]);
+    frame.render_widget(toggle_lines, chunks[3]);
+
+    // Advanced encoder options; those that don't apply to the format are greyed out
+    let format = FORMAT_OPTIONS[app.convert_target_format];
+    let encoder_lines: Vec<Line> = ENCODER_FIELDS
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let selected = i == app.convert_field;
+            let applies = field.applies(format);
+            let marker = if selected { "▸ " } else { "  " };
+            let label_style = if selected {
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            let value_style = if applies {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+            let mut spans = vec![
+                Span::styled(format!("  {}{:<12}", marker, field.label()), label_style),
+                Span::styled(field.value(&app.convert_encoder), value_style),
+            ];
+            if !applies {
+                spans.push(Span::styled(
+                    format!("  (n/a for {})", format),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            Line::from(spans)
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(encoder_lines), chunks[4]);
 
     // Help text
     let help_text = vec![
@@ -707,9 +915,17 @@ fn draw_convert_settings_view(frame: &mut Frame, app: &App, area: Rect) {
             Span::styled("h/l", Style::default().fg(Color::Yellow)),
             Span::raw("  Change quality"),
         ]),
+        Line::from(vec![
+            Span::styled("  ↑/↓", Style::default().fg(Color::Yellow)),
+            Span::raw("  Select option    "),
+            Span::styled("-/+", Style::default().fg(Color::Yellow)),
+            Span::raw("  Change option"),
+        ]),
         Line::from(vec![
             Span::styled("  Space", Style::default().fg(Color::Yellow)),
-            Span::raw("  Toggle metadata refresh"),
+            Span::raw("  Toggle metadata refresh   "),
+            Span::styled("k", Style::default().fg(Color::Yellow)),
+            Span::raw("  Toggle keep both"),
         ]),
         Line::from(vec![
             Span::styled("  Enter", Style::default().fg(Color::Yellow)),
@@ -720,7 +936,7 @@ fn draw_convert_settings_view(frame: &mut Frame, app: &App, area: Rect) {
     ];
 
     let help = Paragraph::new(help_text).style(Style::default().fg(Color::DarkGray));
-    frame.render_widget(help, chunks[4]);
+    frame.render_widget(help, chunks[5]);
 }
 
 fn draw_convert_confirm_view(frame: &mut Frame, app: &App, area: Rect) {
@@ -820,6 +1036,44 @@ fn draw_convert_batch_confirm_view(frame: &mut Frame, app: &App, area: Rect) {
     }
 }
 
+fn draw_normalize_confirm_view(frame: &mut Frame, app: &App, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Normalize Loudness ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let count = app.normalize_pending.as_ref().map(|t| t.len()).unwrap_or(0);
+    let text = vec![
+        Line::from(""),
+        Line::from(vec![
+            Span::raw("  "),
+            Span::styled(
+                format!("{}", count),
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" tracks will be re-encoded in place to the normalization target."),
+        ]),
+        Line::from(Span::styled(
+            "  The original audio is not kept.",
+            Style::default().fg(Color::Yellow),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::raw("  Press "),
+            Span::styled("y", Style::default().fg(Color::Green)),
+            Span::raw(" to normalize, "),
+            Span::styled("n", Style::default().fg(Color::Red)),
+            Span::raw(" or "),
+            Span::styled("Esc", Style::default().fg(Color::Red)),
+            Span::raw(" to cancel"),
+        ]),
+    ];
+
+    let paragraph = Paragraph::new(text);
+    frame.render_widget(paragraph, inner);
+}
+
 fn draw_cleanup_confirm_view(frame: &mut Frame, app: &App, area: Rect) {
     let block = Block::default()
         .borders(Borders::ALL)
@@ -902,6 +1156,171 @@ fn draw_cleanup_confirm_view(frame: &mut Frame, app: &App, area: Rect) {
     }
 }
 
+fn draw_trash_view(frame: &mut Frame, app: &App, area: Rect) {
+    let items = app.trash.items();
+    if items.is_empty() {
+        let empty = Paragraph::new("  Trash is empty.\n\n  Deleted originals and cleaned up entries show up here.")
+            .style(Style::default().fg(Color::DarkGray))
+            .block(Block::default().borders(Borders::ALL).title(" Trash (0 items) "));
+        frame.render_widget(empty, area);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(area);
+
+    let list_items: Vec<ListItem> = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let style = if i == app.trash_selected {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
+            } else {
+                Style::default()
+            };
+            let deleted_at = item.deleted_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M");
+            let kind = if item.trashed_path.is_some() { "  🗑 " } else { "  ∅ " };
+            let content = vec![
+                Line::from(vec![
+                    Span::styled(kind, Style::default().fg(Color::Red)),
+                    Span::styled(item.display_name(), Style::default().fg(Color::Yellow)),
+                    Span::styled(format!("  ({})", item.reason), Style::default().fg(Color::DarkGray)),
+                ]),
+                Line::from(vec![
+                    Span::styled(format!("     {}  ", deleted_at), Style::default().fg(Color::Cyan)),
+                    Span::styled(&item.original_path, Style::default().fg(Color::DarkGray)),
+                ]),
+            ];
+            ListItem::new(content).style(style)
+        })
+        .collect();
+
+    let title = format!(" Trash ({} items) ", items.len());
+    let list = List::new(list_items).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new(" ↑/↓ Navigate  |  r/Enter Restore  |  D Empty trash  |  Esc Back")
+        .style(Style::default().fg(Color::DarkGray));
+    frame.render_widget(help, chunks[1]);
+}
+
+fn draw_tracked_view(frame: &mut Frame, app: &App, area: Rect) {
+    let playlists = app.tracked.playlists();
+    if playlists.is_empty() {
+        let empty = Paragraph::new("  No tracked playlists.\n\n  Press 'n' to track a Spotify playlist, Esc to go back.")
+            .style(Style::default().fg(Color::DarkGray))
+            .block(Block::default().borders(Borders::ALL).title(" Tracked Playlists (0) "));
+        frame.render_widget(empty, area);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(area);
+
+    let list_items: Vec<ListItem> = playlists
+        .iter()
+        .enumerate()
+        .map(|(i, playlist)| {
+            let style = if i == app.tracked_selected {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
+            } else {
+                Style::default()
+            };
+            let synced_at = playlist.synced_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M");
+            let missing = match playlist.missing() {
+                0 => String::new(),
+                n => format!(", {} missing", n),
+            };
+            let portable = if playlist.portable { " [P]" } else { "" };
+            let content = vec![
+                Line::from(vec![
+                    Span::styled("  ⟳ ", Style::default().fg(Color::Green)),
+                    Span::styled(&playlist.name, Style::default().fg(Color::Yellow)),
+                    Span::styled(
+                        format!("  ({} tracks{}, {} {}{})", playlist.tracks.len(), missing, playlist.format, playlist.quality, portable),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ]),
+                Line::from(vec![
+                    Span::styled(format!("     synced {}  ", synced_at), Style::default().fg(Color::Cyan)),
+                    Span::styled(&playlist.link, Style::default().fg(Color::DarkGray)),
+                ]),
+            ];
+            ListItem::new(content).style(style)
+        })
+        .collect();
+
+    let title = format!(" Tracked Playlists ({}) ", playlists.len());
+    let list = List::new(list_items).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new(" ↑/↓ Navigate  |  s/Enter Sync  |  S Sync all  |  n Track new  |  d Untrack  |  c Clean  |  Esc Back")
+        .style(Style::default().fg(Color::DarkGray));
+    frame.render_widget(help, chunks[1]);
+}
+
+fn draw_smart_view(frame: &mut Frame, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(if app.input_mode { 3 } else { 0 }),
+            Constraint::Length(1),
+        ])
+        .split(area);
+
+    let playlists = app.smart.playlists();
+    let title = format!(" Smart Playlists ({}) ", playlists.len());
+    if playlists.is_empty() {
+        let empty = Paragraph::new("  No smart playlists.\n\n  Press 'n' and type a name and rules, e.g.\n  Chill 90s: genre=ambient,chill; year=1990-1999; limit=50; sort=-added")
+            .style(Style::default().fg(Color::DarkGray))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(empty, chunks[0]);
+    } else {
+        let list_items: Vec<ListItem> = playlists
+            .iter()
+            .enumerate()
+            .map(|(i, smart)| {
+                let style = if i == app.smart_selected {
+                    Style::default().bg(Color::DarkGray).fg(Color::White)
+                } else {
+                    Style::default()
+                };
+                let rules = smart.rules();
+                let content = vec![
+                    Line::from(vec![
+                        Span::styled("  ✦ ", Style::default().fg(Color::Magenta)),
+                        Span::styled(&smart.name, Style::default().fg(Color::Yellow)),
+                    ]),
+                    Line::from(Span::styled(
+                        format!("     {}", if rules.is_empty() { "whole library" } else { &rules }),
+                        Style::default().fg(Color::DarkGray),
+                    )),
+                ];
+                ListItem::new(content).style(style)
+            })
+            .collect();
+        let list = List::new(list_items).block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(list, chunks[0]);
+    }
+
+    if app.input_mode {
+        let input = Paragraph::new(app.input.as_str())
+            .style(Style::default().fg(Color::Yellow))
+            .block(Block::default().borders(Borders::ALL).title(" Name: rules "));
+        frame.render_widget(input, chunks[1]);
+        frame.set_cursor_position((chunks[1].x + app.input.len() as u16 + 1, chunks[1].y + 1));
+    }
+
+    let help = Paragraph::new(" ↑/↓ Navigate  |  g/Enter Write  |  G Write all  |  n New  |  e Edit  |  d Delete  |  Esc Back")
+        .style(Style::default().fg(Color::DarkGray));
+    frame.render_widget(help, chunks[2]);
+}
+
 fn draw_error_log_view(frame: &mut Frame, app: &App, area: Rect) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -1064,6 +1483,18 @@ fn draw_error_log_view(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(keybindings, chunks[3]);
 }
 
+/// Format a remaining time as `1h02m`, `3m05s` or `42s`
+fn format_eta(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
 fn truncate_str(s: &str, max_len: usize) -> String {
     if s.len() <= max_len {
         s.to_string()
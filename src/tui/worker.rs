@@ -1,14 +1,34 @@
-use std::path::PathBuf;
-use tokio::sync::{mpsc, watch};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::{mpsc, watch, Semaphore};
+use tokio::task::JoinSet;
 
 use crate::{
+    artwork::{self, ArtworkQuery},
     cli::PortableConfig,
+    config::{Config, EncoderSettings, PlaylistFormat, SourceQualityConfig},
     converter,
     db::{DownloadDB, TrackEntry},
-    downloader,
-    error_log::{ConvertErrorEntry, DownloadErrorEntry, ErrorLogManager, RefreshErrorEntry},
-    file_utils, metadata,
-    sources::{spotify, youtube},
+    downloader::{self, HeldBack, TrackOrigin},
+    error_log::{
+        ConvertErrorEntry, ConvertJob, DownloadErrorEntry, DownloadJob, ErrorLogManager,
+        RefreshErrorEntry,
+    },
+    file_utils,
+    loudness,
+    lyrics::{self, LyricsQuery},
+    metadata,
+    playlist_index,
+    regenerate::{PlaylistSource, PlaylistSources, SourceKind},
+    smart::SmartPlaylists,
+    sources::{
+        models::TrackMetadata,
+        providers::{self, MetadataProvider, Operation},
+        spotify, youtube,
+    },
+    sync::{SyncedTrack, TrackedPlaylist, TrackedPlaylists},
+    trim::{self, Trim},
 };
 
 #[derive(Debug, Clone)]
@@ -26,6 +46,7 @@ pub enum DownloadRequest {
         portable: bool,
         format: String,
         quality: String,
+        lyrics: bool,
     },
     Playlist {
         id: usize,
@@ -33,6 +54,10 @@ pub enum DownloadRequest {
         portable: bool,
         format: String,
         quality: String,
+        lyrics: bool,
+        playlist_formats: Vec<PlaylistFormat>,
+        /// Track the playlist and reconcile it with its last sync
+        sync: bool,
     },
     YouTubePlaylist {
         id: usize,
@@ -40,12 +65,19 @@ pub enum DownloadRequest {
         portable: bool,
         format: String,
         quality: String,
+        lyrics: bool,
+        playlist_formats: Vec<PlaylistFormat>,
     },
     Convert {
         id: usize,
         input_path: String,
         target_format: String,
         quality: String,
+        encoder: EncoderSettings,
+        /// Folder to write under instead of beside the input
+        output_dir: Option<String>,
+        /// Keep the input and record the output as a format variant
+        keep_both: bool,
         refresh_metadata: bool,
         artist: String,
         title: String,
@@ -55,6 +87,9 @@ pub enum DownloadRequest {
         tracks: Vec<ConvertTrackInfo>,
         target_format: String,
         quality: String,
+        encoder: EncoderSettings,
+        output_dir: Option<String>,
+        keep_both: bool,
         refresh_metadata: bool,
     },
     RefreshMetadata {
@@ -67,6 +102,22 @@ pub enum DownloadRequest {
         id: usize,
         tracks: Vec<ConvertTrackInfo>,
     },
+    FetchLyrics {
+        id: usize,
+        tracks: Vec<ConvertTrackInfo>,
+    },
+    /// Re-embed the album folder's cover.jpg/folder.jpg and audit the result
+    ReembedCover {
+        id: usize,
+        tracks: Vec<ConvertTrackInfo>,
+        portable: bool,
+    },
+    /// Write ReplayGain tags (album gain per folder), normalizing first if requested
+    AnalyzeLoudness {
+        id: usize,
+        tracks: Vec<ConvertTrackInfo>,
+        normalize: bool,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -81,7 +132,6 @@ pub enum DownloadEvent {
         name: String,
         total_tracks: usize,
     },
-    #[allow(dead_code)]
     TrackStarted {
         id: usize,
         artist: String,
@@ -98,6 +148,7 @@ pub enum DownloadEvent {
         id: usize,
         artist: String,
         title: String,
+        reason: String,
     },
     TrackFailed {
         id: usize,
@@ -123,11 +174,19 @@ pub enum DownloadEvent {
     },
     /// M3U confirmation needed (some tracks missing)
     M3UConfirm {
+        link: String,
         name: String,
         found: usize,
         missing: usize,
         paths: Vec<std::path::PathBuf>,
     },
+    /// Playlists of all stored sources were rewritten
+    PlaylistsRegenerated {
+        log: Vec<String>,
+        written: usize,
+        /// Sources with tracks not in the library, and how many
+        missing: Vec<(PlaylistSource, usize)>,
+    },
     /// Conversion started
     ConvertStarted {
         id: usize,
@@ -140,6 +199,14 @@ pub enum DownloadEvent {
         old_path: String,
         new_path: String,
     },
+    /// Overall progress of a batch conversion
+    ConvertProgress {
+        id: usize,
+        /// Completed fraction of the whole batch
+        fraction: f64,
+        /// Seconds of audio converted so far
+        audio_secs: f64,
+    },
     /// Conversion failed
     ConvertFailed {
         id: usize,
@@ -187,6 +254,84 @@ pub enum DownloadEvent {
         total: usize,
         successful: usize,
     },
+    /// Lyrics lookup finished (`found` is false if the provider had no match)
+    LyricsComplete {
+        id: usize,
+        artist: String,
+        title: String,
+        found: bool,
+    },
+    /// Lyrics lookup or embedding failed
+    LyricsFailed {
+        id: usize,
+        artist: String,
+        title: String,
+        error: String,
+    },
+    /// Folder cover re-embedding finished
+    CoverComplete {
+        id: usize,
+        embedded: usize,
+        issues: usize,
+        total: usize,
+    },
+    /// Loudness analysis finished
+    LoudnessComplete {
+        id: usize,
+        tagged: usize,
+        failed: usize,
+        total: usize,
+    },
+}
+
+/// Lock a mutex, carrying on if another holder panicked
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Per-file progress of a parallel batch conversion
+struct BatchProgress {
+    fractions: Vec<f64>,
+    audio_secs: Vec<f64>,
+    last_sent: f64,
+}
+
+impl BatchProgress {
+    fn new(total: usize) -> Self {
+        Self {
+            fractions: vec![0.0; total],
+            audio_secs: vec![0.0; total],
+            last_sent: 0.0,
+        }
+    }
+
+    /// Record one file's progress. Returns the batch fraction and converted
+    /// audio seconds once they moved enough to be worth reporting.
+    fn update(&mut self, index: usize, fraction: f64, audio_secs: f64) -> Option<(f64, f64)> {
+        self.fractions[index] = fraction.clamp(0.0, 1.0);
+        self.audio_secs[index] = audio_secs;
+        let overall = self.fractions.iter().sum::<f64>() / self.fractions.len() as f64;
+        if overall - self.last_sent < 0.005 && overall < 1.0 {
+            return None;
+        }
+        self.last_sent = overall;
+        Some((overall, self.audio_secs.iter().sum()))
+    }
+
+    /// Mark a file as done, whether it succeeded or not
+    fn finish(&mut self, index: usize) -> Option<(f64, f64)> {
+        let audio_secs = self.audio_secs[index];
+        self.update(index, 1.0, audio_secs)
+    }
+}
+
+/// Settings shared by album and playlist download jobs
+#[derive(Debug, Clone, Copy)]
+struct DownloadSettings<'a> {
+    portable: bool,
+    format: &'a str,
+    quality: &'a str,
+    lyrics: bool,
 }
 
 pub struct DownloadWorker {
@@ -195,8 +340,10 @@ pub struct DownloadWorker {
     pause_rx: watch::Receiver<bool>,
     music_path: PathBuf,
     playlist_path: PathBuf,
-    db: DownloadDB,
-    error_log: ErrorLogManager,
+    /// Shared with the other worker lane
+    db: Arc<Mutex<DownloadDB>>,
+    /// Shared with the other worker lane and the app
+    error_log: Arc<Mutex<ErrorLogManager>>,
 }
 
 impl DownloadWorker {
@@ -204,6 +351,8 @@ impl DownloadWorker {
         rx: mpsc::Receiver<DownloadRequest>,
         tx: mpsc::Sender<DownloadEvent>,
         pause_rx: watch::Receiver<bool>,
+        db: Arc<Mutex<DownloadDB>>,
+        error_log: Arc<Mutex<ErrorLogManager>>,
     ) -> Self {
         let music_path = PathBuf::from("data/music");
         let playlist_path = PathBuf::from("data/playlists");
@@ -217,8 +366,8 @@ impl DownloadWorker {
             pause_rx,
             music_path,
             playlist_path,
-            db: DownloadDB::new("data/cache/downloaded_songs.json"),
-            error_log: ErrorLogManager::new("data/errors"),
+            db,
+            error_log,
         }
     }
 
@@ -231,9 +380,15 @@ impl DownloadWorker {
                     portable,
                     format,
                     quality,
+                    lyrics,
                 } => {
-                    self.process_album(id, &link, portable, &format, &quality)
-                        .await;
+                    let settings = DownloadSettings {
+                        portable,
+                        format: &format,
+                        quality: &quality,
+                        lyrics,
+                    };
+                    self.process_album(id, &link, &settings).await;
                 }
                 DownloadRequest::Playlist {
                     id,
@@ -241,8 +396,17 @@ impl DownloadWorker {
                     portable,
                     format,
                     quality,
+                    lyrics,
+                    playlist_formats,
+                    sync,
                 } => {
-                    self.process_playlist(id, &link, portable, &format, &quality)
+                    let settings = DownloadSettings {
+                        portable,
+                        format: &format,
+                        quality: &quality,
+                        lyrics,
+                    };
+                    self.process_playlist(id, &link, &settings, &playlist_formats, sync)
                         .await;
                 }
                 DownloadRequest::YouTubePlaylist {
@@ -251,8 +415,16 @@ impl DownloadWorker {
                     portable,
                     format,
                     quality,
+                    lyrics,
+                    playlist_formats,
                 } => {
-                    self.process_youtube_playlist(id, &link, portable, &format, &quality)
+                    let settings = DownloadSettings {
+                        portable,
+                        format: &format,
+                        quality: &quality,
+                        lyrics,
+                    };
+                    self.process_youtube_playlist(id, &link, &settings, &playlist_formats)
                         .await;
                 }
                 DownloadRequest::Convert {
@@ -260,36 +432,43 @@ impl DownloadWorker {
                     input_path,
                     target_format,
                     quality,
+                    encoder,
+                    output_dir,
+                    keep_both,
                     refresh_metadata,
                     artist,
                     title,
                 } => {
-                    self.process_convert(
-                        id,
-                        &input_path,
-                        &target_format,
-                        &quality,
+                    let job = ConvertJob {
+                        target_format: &target_format,
+                        quality: &quality,
+                        encoder: &encoder,
+                        output_dir: output_dir.as_deref(),
+                        keep_both,
                         refresh_metadata,
-                        &artist,
-                        &title,
-                    )
-                    .await;
+                    };
+                    self.process_convert(id, &input_path, &job, &artist, &title)
+                        .await;
                 }
                 DownloadRequest::ConvertBatch {
                     id,
                     tracks,
                     target_format,
                     quality,
+                    encoder,
+                    output_dir,
+                    keep_both,
                     refresh_metadata,
                 } => {
-                    self.process_convert_batch(
-                        id,
-                        tracks,
-                        &target_format,
-                        &quality,
+                    let job = ConvertJob {
+                        target_format: &target_format,
+                        quality: &quality,
+                        encoder: &encoder,
+                        output_dir: output_dir.as_deref(),
+                        keep_both,
                         refresh_metadata,
-                    )
-                    .await;
+                    };
+                    self.process_convert_batch(id, tracks, &job).await;
                 }
                 DownloadRequest::RefreshMetadata {
                     id,
@@ -303,10 +482,33 @@ impl DownloadWorker {
                 DownloadRequest::RefreshMetadataBatch { id, tracks } => {
                     self.process_refresh_metadata_batch(id, tracks).await;
                 }
+                DownloadRequest::FetchLyrics { id, tracks } => {
+                    self.process_fetch_lyrics(id, tracks).await;
+                }
+                DownloadRequest::ReembedCover { id, tracks, portable } => {
+                    self.process_reembed_cover(id, tracks, portable).await;
+                }
+                DownloadRequest::AnalyzeLoudness { id, tracks, normalize } => {
+                    self.process_analyze_loudness(id, tracks, normalize).await;
+                }
             }
         }
     }
 
+    fn db(&self) -> MutexGuard<'_, DownloadDB> {
+        lock(&self.db)
+    }
+
+    /// Where a converted copy of a library file goes
+    fn convert_output(&self, input: &Path, target_format: &str, output_dir: Option<&str>) -> PathBuf {
+        converter::output_path(
+            input,
+            target_format,
+            &[self.music_path.clone(), self.playlist_path.clone()],
+            output_dir.map(Path::new),
+        )
+    }
+
     async fn send_log(&self, id: usize, line: String) {
         let _ = self.tx.send(DownloadEvent::LogLine { id, line }).await;
     }
@@ -322,46 +524,231 @@ impl DownloadWorker {
         }
     }
 
-    /// Download cover art from a URL to a file path, with proper error logging
-    async fn download_cover_art(&self, id: usize, url: &str, dest: &std::path::Path) -> Option<PathBuf> {
-        match reqwest::get(url).await {
-            Ok(response) => {
-                if !response.status().is_success() {
+    /// Metadata providers in the order configured for refreshes. Built once
+    /// per job so MusicBrainz's rate limit holds across a whole batch.
+    fn refresh_providers() -> Vec<Box<dyn MetadataProvider>> {
+        providers::providers_for(Operation::Refresh, &Config::load().metadata)
+    }
+
+    /// Look up track metadata with the given providers, in order
+    async fn lookup_metadata(
+        providers: &[Box<dyn MetadataProvider>],
+        artist: &str,
+        title: &str,
+    ) -> anyhow::Result<Option<TrackMetadata>> {
+        providers::search_track(providers, artist, title, None).await
+    }
+
+    /// Screen a track's source against the required quality, reporting
+    /// skipped and deferred tracks. `None` if the track isn't downloaded now,
+    /// otherwise the warning to tag the file with.
+    async fn screen_track(
+        &self,
+        id: usize,
+        query: &str,
+        requirement: &SourceQualityConfig,
+        origin: &TrackOrigin<'_>,
+    ) -> Option<Option<String>> {
+        let mut lines = Vec::new();
+        let result = downloader::screen_track(query, requirement, origin, &self.error_log, |line| lines.push(line)).await;
+        for line in lines {
+            self.send_log(id, line).await;
+        }
+        let (artist, title) = (origin.artist.to_string(), origin.title.to_string());
+        match result {
+            Ok(warning) => Some(warning),
+            Err(HeldBack::Skipped(reason)) => {
+                let _ = self
+                    .tx
+                    .send(DownloadEvent::TrackSkipped { id, artist, title, reason })
+                    .await;
+                None
+            }
+            Err(HeldBack::Deferred(error)) => {
+                let _ = self
+                    .tx
+                    .send(DownloadEvent::TrackFailed { id, artist, title, error })
+                    .await;
+                None
+            }
+        }
+    }
+
+    /// Measure loudness and write ReplayGain tags, as one album or per track.
+    /// Returns the number of files tagged and failed.
+    async fn apply_replaygain(&self, id: usize, paths: Vec<PathBuf>, album: bool) -> (usize, usize) {
+        if paths.is_empty() {
+            return (0, 0);
+        }
+        self.send_log(id, format!("Analyzing loudness of {} file(s)...", paths.len()))
+            .await;
+        let loudness_config = Config::load().loudness;
+        let tx = self.tx.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            loudness::apply_replaygain(&paths, album, &loudness_config, |line| {
+                let _ = tx.blocking_send(DownloadEvent::LogLine { id, line: line.to_string() });
+            })
+        })
+        .await;
+        match result {
+            Ok(counts) => counts,
+            Err(e) => {
+                self.send_log(id, format!("ReplayGain failed: {}", e)).await;
+                (0, 0)
+            }
+        }
+    }
+
+    /// Bring smart playlists up to date after tracks were added to the library
+    async fn regenerate_smart_playlists(&self, id: usize) {
+        // Reading the tags of the whole library takes a while, so it runs on
+        // a copy of the download cache instead of keeping it locked
+        let db = Arc::clone(&self.db);
+        let playlist_path = self.playlist_path.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let snapshot = lock(&db).clone();
+            let regenerated = SmartPlaylists::open().regenerate(None, &snapshot, &playlist_path)?;
+            lock(&db).set_durations(&regenerated.durations);
+            Ok::<_, anyhow::Error>(regenerated.written)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result);
+        match result {
+            Ok(written) => {
+                for (name, count) in written {
+                    self.send_log(id, format!("Smart playlist updated: {} ({} tracks)", name, count)).await;
+                }
+            }
+            Err(e) => self.send_log(id, format!("Failed to update smart playlists: {}", e)).await,
+        }
+    }
+
+    /// Remember an album or playlist job's link, so its playlist can be
+    /// regenerated later
+    async fn record_source(&self, id: usize, source: PlaylistSource) {
+        if let Err(e) = PlaylistSources::open().record(source) {
+            self.send_log(id, format!("Source link not recorded: {}", e)).await;
+        }
+    }
+
+    /// Rewrite playlists whose tracks were converted, moved or removed
+    async fn update_playlists(&self, id: usize) {
+        let result = playlist_index::update_playlists(&self.db());
+        match result {
+            Ok(changes) => {
+                for change in changes {
                     self.send_log(
                         id,
-                        format!("Cover art download failed: HTTP {}", response.status()),
+                        format!(
+                            "Playlist updated: {} ({} moved, {} removed)",
+                            change.name,
+                            change.moved,
+                            change.removed.len()
+                        ),
                     )
                     .await;
-                    return None;
-                }
-                match response.bytes().await {
-                    Ok(bytes) => {
-                        if let Err(e) = std::fs::write(dest, &bytes) {
-                            self.send_log(
-                                id,
-                                format!("Failed to save cover art: {}", e),
-                            )
-                            .await;
-                            return None;
-                        }
-                        Some(dest.to_path_buf())
-                    }
-                    Err(e) => {
-                        self.send_log(
-                            id,
-                            format!("Failed to read cover art response: {}", e),
-                        )
-                        .await;
-                        None
-                    }
                 }
             }
+            Err(e) => self.send_log(id, format!("Failed to update playlists: {}", e)).await,
+        }
+    }
+
+    /// Normalize a file's loudness in place. Failures are logged but never
+    /// fail the download.
+    async fn normalize_loudness(&self, id: usize, file_path: &std::path::Path) -> bool {
+        let loudness_config = Config::load().loudness;
+        let path = file_path.to_path_buf();
+        let tx = self.tx.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            loudness::normalize(&path, &loudness_config, |line| {
+                let _ = tx.blocking_send(DownloadEvent::LogLine { id, line: line.to_string() });
+            })
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => true,
+            Ok(Err(e)) => {
+                self.send_log(id, format!("Normalization failed: {}", e)).await;
+                false
+            }
+            Err(e) => {
+                self.send_log(id, format!("Normalization failed: {}", e)).await;
+                false
+            }
+        }
+    }
+
+    /// Cut leading and trailing silence from a file. Failures are logged but
+    /// never fail the download.
+    async fn trim_silence(&self, id: usize, file_path: &std::path::Path) -> Option<Trim> {
+        let trim_config = Config::load().trim;
+        let path = file_path.to_path_buf();
+        match tokio::task::spawn_blocking(move || trim::trim_silence(&path, &trim_config)).await {
+            Ok(Ok(Some(trim))) => {
+                self.send_log(id, format!("Trimmed {:.1}s of silence", trim.removed_secs))
+                    .await;
+                Some(trim)
+            }
+            Ok(Ok(None)) => None,
+            Ok(Err(e)) => {
+                self.send_log(id, format!("Silence trimming failed: {}", e)).await;
+                None
+            }
+            Err(e) => {
+                self.send_log(id, format!("Silence trimming failed: {}", e)).await;
+                None
+            }
+        }
+    }
+
+    /// Tag a file downloaded from a source below the required quality
+    async fn apply_quality_warning(&self, id: usize, file_path: &std::path::Path, warning: Option<&str>) {
+        if let Some(warning) = warning {
+            if let Err(e) = metadata::tag_quality_warning(file_path, warning) {
+                self.send_log(id, format!("Failed to tag quality warning: {}", e)).await;
+            }
+        }
+    }
+
+    /// Fetch and embed lyrics for a downloaded track. Failures are logged but
+    /// never fail the download.
+    async fn apply_lyrics(&self, id: usize, file_path: &std::path::Path, query: &LyricsQuery) {
+        let lyrics_config = Config::load().lyrics;
+        let provider = lyrics::provider_from_config(&lyrics_config);
+        match lyrics::fetch_and_embed(provider.as_ref(), file_path, query, lyrics_config.write_lrc).await {
+            Ok(Some(found)) => {
+                let kind = if found.synced.is_some() { "synced" } else { "plain" };
+                self.send_log(id, format!("Embedded {} lyrics", kind)).await;
+            }
+            Ok(None) => {
+                self.send_log(id, format!("No lyrics found for {} - {}", query.artist, query.title))
+                    .await;
+            }
             Err(e) => {
+                self.send_log(id, format!("Warning: Lyrics failed: {}", e)).await;
+            }
+        }
+    }
+
+    /// Run the artwork provider chain and save the best cover to `dest`
+    async fn fetch_artwork(&self, id: usize, query: &ArtworkQuery, dest: &std::path::Path) -> Option<PathBuf> {
+        match artwork::fetch_cover(query, dest).await {
+            Ok(Some(info)) => {
                 self.send_log(
                     id,
-                    format!("Cover art download failed: {}", e),
+                    format!("Cover art: {} ({}x{})", info.provider, info.width, info.height),
                 )
                 .await;
+                Some(dest.to_path_buf())
+            }
+            Ok(None) => {
+                self.send_log(id, "No cover art found".to_string()).await;
+                None
+            }
+            Err(e) => {
+                self.send_log(id, format!("Failed to save cover art: {}", e))
+                    .await;
                 None
             }
         }
@@ -371,10 +758,9 @@ impl DownloadWorker {
         &mut self,
         id: usize,
         link: &str,
-        portable: bool,
-        format: &str,
-        quality: &str,
+        settings: &DownloadSettings<'_>,
     ) {
+        let DownloadSettings { portable, format, quality, lyrics } = *settings;
         let config = if portable {
             PortableConfig {
                 enabled: true,
@@ -393,6 +779,11 @@ impl DownloadWorker {
 
         // Use mp3 for portable mode, otherwise use selected format
         let actual_format = if portable { "mp3" } else { format };
+        let source_quality = Config::load().source_quality;
+        let loudness_config = Config::load().loudness;
+        let normalize = portable && loudness_config.normalize_portable;
+        let trim_config = Config::load().trim;
+        let tagging = Config::load().tagging;
 
         self.send_log(id, "Fetching album info from Spotify...".to_string())
             .await;
@@ -402,12 +793,14 @@ impl DownloadWorker {
             Err(e) => {
                 let error_msg = Self::format_error_with_hint(&e, "album");
                 // Log error for retry
-                self.error_log.add_download_error(DownloadErrorEntry::new(
-                    link.to_string(),
-                    "album".to_string(),
-                    format.to_string(),
-                    quality.to_string(),
-                    portable,
+                lock(&self.error_log).add_download_error(DownloadErrorEntry::new(
+                    &DownloadJob {
+                        link,
+                        link_type: "album",
+                        format,
+                        quality,
+                        portable,
+                    },
                     None,
                     None,
                     format!("Failed to fetch album: {}", error_msg),
@@ -431,9 +824,12 @@ impl DownloadWorker {
         let album_name = album.name.clone();
         let total_tracks = album.tracks.items.len();
         let display_name = format!("{} - {}", main_artist, album_name);
+        self.record_source(id, PlaylistSource::new(link, SourceKind::Album, &display_name, format, quality, portable))
+            .await;
 
         // Fetch genre for the album
-        let album_genre = spotify::fetch_album_genres(&album).await;
+        let genre_providers = providers::providers_for(Operation::Genre, &Config::load().metadata);
+        let album_genre = providers::spotify_album_genre(&genre_providers, &album).await;
 
         // Update queue with album name while still processing
         let _ = self
@@ -468,20 +864,23 @@ impl DownloadWorker {
             file_utils::create_album_folder(&self.music_path, &main_artist, &album_name)
         };
 
-        // Download cover
-        let cover_path: Option<PathBuf> = if let Some(image) = album.images.first() {
+        // Download cover (full-size cover.jpg; embedded copies are downscaled from it)
+        let cover_path: Option<PathBuf> = {
             let p = album_folder.join("cover.jpg");
             if p.exists() {
                 Some(p)
             } else {
                 self.send_log(id, "Downloading cover art...".to_string())
                     .await;
-                self.download_cover_art(id, &image.url, &p).await
+                let mut query = ArtworkQuery::new(&main_artist, &album_name);
+                query.image_urls = album.images.iter().map(|img| img.url.clone()).collect();
+                self.fetch_artwork(id, &query, &p).await
             }
-        } else {
-            None
         };
 
+        let mut album_paths: Vec<PathBuf> = Vec::new();
+        let mut downloaded_any = false;
+
         for (i, track) in album.tracks.items.iter().enumerate() {
             // Check for pause before starting each track
             while *self.pause_rx.borrow() {
@@ -498,22 +897,30 @@ impl DownloadWorker {
                 .unwrap_or_else(|| main_artist.clone());
 
             let safe_file_name =
-                file_utils::build_filename(&track_artist, &track_title, actual_format, &config);
+                file_utils::build_filename(&track_artist, &track_title, converter::format_extension(actual_format), &config);
             let file_path = album_folder.join(&safe_file_name);
 
             let entry = TrackEntry {
+                id: String::new(),
                 artist: track_artist.clone(),
                 title: track_title.clone(),
                 path: file_path.display().to_string(),
+                source: None,
+                trims: Vec::new(),
+                variants: Vec::new(),
+                duration_secs: None,
             };
 
-            if self.db.contains(&entry) {
+            let existing = self.db().find_download(&entry).map(|e| e.path.clone());
+            if let Some(existing) = existing {
+                album_paths.push(PathBuf::from(existing));
                 let _ = self
                     .tx
                     .send(DownloadEvent::TrackSkipped {
                         id,
                         artist: track_artist,
                         title: track_title,
+                        reason: "exists".to_string(),
                     })
                     .await;
                 continue;
@@ -530,10 +937,23 @@ impl DownloadWorker {
                 .await;
 
             let query = format!("{} {}", track_artist, track_title);
+            let origin = TrackOrigin {
+                link,
+                link_type: "album",
+                format: actual_format,
+                quality,
+                portable,
+                artist: &track_artist,
+                title: &track_title,
+            };
+            let Some(quality_warning) = self.screen_track(id, &query, &source_quality, &origin).await else {
+                continue;
+            };
             let file_path_clone = file_path.clone();
             let format_clone = actual_format.to_string();
             let quality_clone = quality.to_string();
             let tx_clone = self.tx.clone();
+            let sponsorblock = trim_config.sponsorblock;
 
             match tokio::task::spawn_blocking(move || {
                 downloader::download_track_with_output(
@@ -541,6 +961,7 @@ impl DownloadWorker {
                     &file_path_clone,
                     &format_clone,
                     &quality_clone,
+                    sponsorblock,
                     move |line| {
                         // Send log lines from the blocking context
                         let tx = tx_clone.clone();
@@ -552,25 +973,44 @@ impl DownloadWorker {
             })
             .await
             {
-                Ok(Ok(_)) => {
-                    if let Err(e) = metadata::tag_audio(
-                        &file_path,
-                        &track_artist,
-                        &album_name,
-                        &track_title,
-                        (i + 1) as u32,
-                        album_genre.as_deref(),
-                        cover_path.as_deref(),
-                        &config,
-                    ) {
+                Ok(Ok(downloaded)) => {
+                    let file_path = downloaded.path;
+                    let mut trims = downloaded.trims;
+                    if trim_config.silence {
+                        trims.extend(self.trim_silence(id, &file_path).await);
+                    }
+                    let entry = TrackEntry {
+                        path: file_path.display().to_string(),
+                        source: downloaded.source,
+                        trims,
+                        variants: Vec::new(),
+                        ..entry
+                    };
+                    let tag_result = if metadata::holds_tags(&file_path) {
+                        let tags = metadata::TrackTags {
+                            artist: &track_artist,
+                            album: &album_name,
+                            title: &track_title,
+                            track: (i + 1) as u32,
+                            genre: album_genre.as_deref(),
+                        };
+                        metadata::tag_audio(&file_path, &tags, cover_path.as_deref(), &config, &tagging)
+                    } else {
+                        self.send_log(id, format!("{}: {}", metadata::UNTAGGED_WARNING, track_title))
+                            .await;
+                        Ok(())
+                    };
+                    if let Err(e) = tag_result {
                         let error_msg = format!("Tagging failed: {}", e);
                         // Log error for retry
-                        self.error_log.add_download_error(DownloadErrorEntry::new(
-                            link.to_string(),
-                            "album".to_string(),
-                            actual_format.to_string(),
-                            quality.to_string(),
-                            portable,
+                        lock(&self.error_log).add_download_error(DownloadErrorEntry::new(
+                            &DownloadJob {
+                                link,
+                                link_type: "album",
+                                format: actual_format,
+                                quality,
+                                portable,
+                            },
                             Some(track_artist.clone()),
                             Some(track_title.clone()),
                             error_msg.clone(),
@@ -587,7 +1027,23 @@ impl DownloadWorker {
                         continue;
                     }
 
-                    self.db.add(entry);
+                    self.apply_quality_warning(id, &file_path, quality_warning.as_deref()).await;
+
+                    if lyrics {
+                        let query = LyricsQuery {
+                            artist: track_artist.clone(),
+                            title: track_title.clone(),
+                            album: Some(album_name.clone()),
+                            duration_secs: Some(track.duration.num_seconds() as u64),
+                        };
+                        self.apply_lyrics(id, &file_path, &query).await;
+                    }
+                    if normalize {
+                        self.normalize_loudness(id, &file_path).await;
+                    }
+                    self.db().add(entry);
+                    album_paths.push(file_path.clone());
+                    downloaded_any = true;
                     let _ = self
                         .tx
                         .send(DownloadEvent::TrackComplete {
@@ -601,12 +1057,14 @@ impl DownloadWorker {
                 Ok(Err(e)) => {
                     let error_msg = e.to_string();
                     // Log error for retry
-                    self.error_log.add_download_error(DownloadErrorEntry::new(
-                        link.to_string(),
-                        "album".to_string(),
-                        actual_format.to_string(),
-                        quality.to_string(),
-                        portable,
+                    lock(&self.error_log).add_download_error(DownloadErrorEntry::new(
+                        &DownloadJob {
+                            link,
+                            link_type: "album",
+                            format: actual_format,
+                            quality,
+                            portable,
+                        },
                         Some(track_artist.clone()),
                         Some(track_title.clone()),
                         error_msg.clone(),
@@ -624,12 +1082,14 @@ impl DownloadWorker {
                 Err(e) => {
                     let error_msg = e.to_string();
                     // Log error for retry
-                    self.error_log.add_download_error(DownloadErrorEntry::new(
-                        link.to_string(),
-                        "album".to_string(),
-                        actual_format.to_string(),
-                        quality.to_string(),
-                        portable,
+                    lock(&self.error_log).add_download_error(DownloadErrorEntry::new(
+                        &DownloadJob {
+                            link,
+                            link_type: "album",
+                            format: actual_format,
+                            quality,
+                            portable,
+                        },
                         Some(track_artist.clone()),
                         Some(track_title.clone()),
                         error_msg.clone(),
@@ -647,6 +1107,16 @@ impl DownloadWorker {
             }
         }
 
+        if let Err(e) = PlaylistSources::open().record_paths(link, &album_paths) {
+            self.send_log(id, format!("Album files not recorded: {}", e)).await;
+        }
+        if loudness_config.replaygain && downloaded_any {
+            self.apply_replaygain(id, album_paths, true).await;
+        }
+        if downloaded_any {
+            self.regenerate_smart_playlists(id).await;
+        }
+
         let _ = self
             .tx
             .send(DownloadEvent::Complete {
@@ -660,10 +1130,11 @@ impl DownloadWorker {
         &mut self,
         id: usize,
         link: &str,
-        portable: bool,
-        format: &str,
-        quality: &str,
+        settings: &DownloadSettings<'_>,
+        playlist_formats: &[PlaylistFormat],
+        sync: bool,
     ) {
+        let DownloadSettings { portable, format, quality, lyrics } = *settings;
         let config = if portable {
             PortableConfig {
                 enabled: true,
@@ -682,6 +1153,12 @@ impl DownloadWorker {
 
         // Use mp3 for portable mode, otherwise use selected format
         let actual_format = if portable { "mp3" } else { format };
+        let source_quality = Config::load().source_quality;
+        let loudness_config = Config::load().loudness;
+        let normalize = portable && loudness_config.normalize_portable;
+        let trim_config = Config::load().trim;
+        let tagging = Config::load().tagging;
+        let no_requirement = SourceQualityConfig::default();
 
         self.send_log(id, "Fetching playlist info from Spotify...".to_string())
             .await;
@@ -692,12 +1169,14 @@ impl DownloadWorker {
             Err(e) => {
                 let error_msg = Self::format_error_with_hint(&e, "playlist");
                 // Log error for retry
-                self.error_log.add_download_error(DownloadErrorEntry::new(
-                    link.to_string(),
-                    "playlist".to_string(),
-                    format.to_string(),
-                    quality.to_string(),
-                    portable,
+                lock(&self.error_log).add_download_error(DownloadErrorEntry::new(
+                    &DownloadJob {
+                        link,
+                        link_type: "playlist",
+                        format,
+                        quality,
+                        portable,
+                    },
                     None,
                     None,
                     format!("Failed to fetch playlist: {}", error_msg),
@@ -724,6 +1203,29 @@ impl DownloadWorker {
             })
             .await;
 
+        self.record_source(
+            id,
+            PlaylistSource::new(link, SourceKind::Playlist, &playlist_name, format, quality, portable),
+        )
+        .await;
+
+        // Nothing to do when Spotify's copy hasn't changed since the last sync
+        if sync
+            && TrackedPlaylists::open()
+                .find(link)
+                .is_some_and(|tracked| tracked.is_current(&playlist.snapshot_id))
+        {
+            self.send_log(id, format!("'{}' is up to date", playlist_name)).await;
+            let _ = self
+                .tx
+                .send(DownloadEvent::Complete {
+                    id,
+                    name: playlist_name,
+                })
+                .await;
+            return;
+        }
+
         // Fetch ALL playlist items with pagination (no 100 track limit)
         self.send_log(id, format!("Fetching tracks for '{}'...", playlist_name))
             .await;
@@ -732,12 +1234,14 @@ impl DownloadWorker {
             Ok(items) => items,
             Err(e) => {
                 // Log error for retry
-                self.error_log.add_download_error(DownloadErrorEntry::new(
-                    link.to_string(),
-                    "playlist".to_string(),
-                    format.to_string(),
-                    quality.to_string(),
-                    portable,
+                lock(&self.error_log).add_download_error(DownloadErrorEntry::new(
+                    &DownloadJob {
+                        link,
+                        link_type: "playlist",
+                        format,
+                        quality,
+                        portable,
+                    },
                     None,
                     Some(playlist_name.clone()),
                     format!("Failed to fetch playlist tracks: {}", e),
@@ -774,6 +1278,10 @@ impl DownloadWorker {
             .await;
 
         let mut downloaded_paths: Vec<PathBuf> = Vec::new();
+        // Tracks downloaded in this run, for ReplayGain
+        let mut new_paths: Vec<PathBuf> = Vec::new();
+        // Every entry in playlist order, for syncing
+        let mut wanted: Vec<TrackEntry> = Vec::new();
 
         for (i, item) in all_items.iter().enumerate() {
             // Check for pause before starting each track
@@ -783,48 +1291,65 @@ impl DownloadWorker {
                 }
             }
 
-            let track = match &item.track {
-                Some(rspotify::model::PlayableItem::Track(t)) => t,
-                _ => continue,
+            // Podcast episodes are stored by show under Podcasts/ and located
+            // through the configured podcast source instead of a YouTube search
+            let (track_artist, track_title, album_name, track_number, duration_secs, episode) = match &item.track {
+                Some(rspotify::model::PlayableItem::Track(t)) => (
+                    t.artists
+                        .first()
+                        .map(|a| a.name.clone())
+                        .unwrap_or_else(|| "Unknown Artist".to_string()),
+                    t.name.clone(),
+                    t.album.name.clone(),
+                    t.track_number,
+                    Some(t.duration.num_seconds() as u64),
+                    None,
+                ),
+                Some(rspotify::model::PlayableItem::Episode(e)) => {
+                    let meta = spotify::episode_metadata(e);
+                    let duration_secs = Some(meta.duration_ms / 1000);
+                    (meta.show.clone(), meta.title.clone(), meta.show.clone(), 0, duration_secs, Some(meta))
+                }
+                None => continue,
             };
 
-            let track_title = track.name.clone();
-            let track_artist = track
-                .artists
-                .first()
-                .map(|a| a.name.clone())
-                .unwrap_or_else(|| "Unknown Artist".to_string());
-
-            // Get album name from track metadata
-            let album_name = track.album.name.clone();
-
             // Use music path (like albums) and organize by artist/album
             let output_folder = if config.enabled {
                 file_utils::create_portable_folder(&self.music_path, &config)
+            } else if episode.is_some() {
+                file_utils::create_podcast_folder(&self.music_path, &album_name)
             } else {
                 file_utils::create_album_folder(&self.music_path, &track_artist, &album_name)
             };
 
             let safe_file_name =
-                file_utils::build_filename(&track_artist, &track_title, actual_format, &config);
+                file_utils::build_filename(&track_artist, &track_title, converter::format_extension(actual_format), &config);
             let file_path = output_folder.join(&safe_file_name);
 
             let entry = TrackEntry {
+                id: String::new(),
                 artist: track_artist.clone(),
                 title: track_title.clone(),
                 path: file_path.display().to_string(),
+                source: None,
+                trims: Vec::new(),
+                variants: Vec::new(),
+                duration_secs: None,
             };
+            wanted.push(entry.clone());
 
-            if self.db.contains(&entry) {
+            let existing = self.db().find_download(&entry).map(|e| e.path.clone());
+            if let Some(existing) = existing {
+                downloaded_paths.push(PathBuf::from(existing));
                 let _ = self
                     .tx
                     .send(DownloadEvent::TrackSkipped {
                         id,
                         artist: track_artist,
                         title: track_title,
+                        reason: "exists".to_string(),
                     })
                     .await;
-                downloaded_paths.push(file_path);
                 continue;
             }
 
@@ -838,11 +1363,30 @@ impl DownloadWorker {
                 })
                 .await;
 
-            let query = format!("{} {}", track_artist, track_title);
+            let query = match &episode {
+                Some(_) => Config::load().podcast.source_for(&track_artist, &track_title),
+                None => format!("{} {}", track_artist, track_title),
+            };
+            // Podcasts aren't held to the music quality bar
+            let requirement = if episode.is_some() { &no_requirement } else { &source_quality };
+            let origin = TrackOrigin {
+                link,
+                link_type: "playlist",
+                format: actual_format,
+                quality,
+                portable,
+                artist: &track_artist,
+                title: &track_title,
+            };
+            let Some(quality_warning) = self.screen_track(id, &query, requirement, &origin).await else {
+                continue;
+            };
             let file_path_clone = file_path.clone();
             let format_clone = actual_format.to_string();
             let quality_clone = quality.to_string();
             let tx_clone = self.tx.clone();
+            // Podcast episodes are left untouched
+            let sponsorblock = trim_config.sponsorblock && episode.is_none();
 
             match tokio::task::spawn_blocking(move || {
                 downloader::download_track_with_output(
@@ -850,6 +1394,7 @@ impl DownloadWorker {
                     &file_path_clone,
                     &format_clone,
                     &quality_clone,
+                    sponsorblock,
                     move |line| {
                         let tx = tx_clone.clone();
                         let line = line.to_string();
@@ -859,26 +1404,59 @@ impl DownloadWorker {
             })
             .await
             {
-                Ok(Ok(_)) => {
-                    // For playlists, we don't have album-level genre info
-                    if let Err(e) = metadata::tag_audio(
-                        &file_path,
-                        &track_artist,
-                        &album_name,
-                        &track_title,
-                        track.track_number,
-                        None, // genre - can be added via retag command
-                        None,
-                        &config,
-                    ) {
+                Ok(Ok(downloaded)) => {
+                    let file_path = downloaded.path;
+                    let mut trims = downloaded.trims;
+                    if trim_config.silence && episode.is_none() {
+                        trims.extend(self.trim_silence(id, &file_path).await);
+                    }
+                    let entry = TrackEntry {
+                        path: file_path.display().to_string(),
+                        source: downloaded.source,
+                        trims,
+                        variants: Vec::new(),
+                        ..entry
+                    };
+                    let tag_result = match &episode {
+                        _ if !metadata::holds_tags(&file_path) => {
+                            self.send_log(id, format!("{}: {}", metadata::UNTAGGED_WARNING, track_title))
+                                .await;
+                            Ok(())
+                        }
+                        Some(meta) => {
+                            let cover_file = file_path.with_extension("jpg");
+                            let cover_path = if meta.cover_url.is_none() {
+                                None
+                            } else if cover_file.exists() {
+                                Some(cover_file)
+                            } else {
+                                self.fetch_artwork(id, &ArtworkQuery::from_episode(meta), &cover_file).await
+                            };
+                            metadata::tag_episode(&file_path, meta, cover_path.as_deref(), &config)
+                        }
+                        // For playlists, we don't have album-level genre info
+                        None => {
+                            let tags = metadata::TrackTags {
+                                artist: &track_artist,
+                                album: &album_name,
+                                title: &track_title,
+                                track: track_number,
+                                genre: None, // can be added via retag command
+                            };
+                            metadata::tag_audio(&file_path, &tags, None, &config, &tagging)
+                        }
+                    };
+                    if let Err(e) = tag_result {
                         let error_msg = format!("Tagging failed: {}", e);
                         // Log error for retry
-                        self.error_log.add_download_error(DownloadErrorEntry::new(
-                            link.to_string(),
-                            "playlist".to_string(),
-                            actual_format.to_string(),
-                            quality.to_string(),
-                            portable,
+                        lock(&self.error_log).add_download_error(DownloadErrorEntry::new(
+                            &DownloadJob {
+                                link,
+                                link_type: "playlist",
+                                format: actual_format,
+                                quality,
+                                portable,
+                            },
                             Some(track_artist.clone()),
                             Some(track_title.clone()),
                             error_msg.clone(),
@@ -895,8 +1473,24 @@ impl DownloadWorker {
                         continue;
                     }
 
-                    self.db.add(entry);
+                    self.apply_quality_warning(id, &file_path, quality_warning.as_deref()).await;
+
+                    // Lyrics don't apply to podcast episodes
+                    if lyrics && episode.is_none() {
+                        let query = LyricsQuery {
+                            artist: track_artist.clone(),
+                            title: track_title.clone(),
+                            album: Some(album_name.clone()),
+                            duration_secs,
+                        };
+                        self.apply_lyrics(id, &file_path, &query).await;
+                    }
+                    if normalize {
+                        self.normalize_loudness(id, &file_path).await;
+                    }
+                    self.db().add(entry);
                     downloaded_paths.push(file_path.clone());
+                    new_paths.push(file_path.clone());
                     let _ = self
                         .tx
                         .send(DownloadEvent::TrackComplete {
@@ -910,12 +1504,14 @@ impl DownloadWorker {
                 Ok(Err(e)) => {
                     let error_msg = e.to_string();
                     // Log error for retry
-                    self.error_log.add_download_error(DownloadErrorEntry::new(
-                        link.to_string(),
-                        "playlist".to_string(),
-                        actual_format.to_string(),
-                        quality.to_string(),
-                        portable,
+                    lock(&self.error_log).add_download_error(DownloadErrorEntry::new(
+                        &DownloadJob {
+                            link,
+                            link_type: "playlist",
+                            format: actual_format,
+                            quality,
+                            portable,
+                        },
                         Some(track_artist.clone()),
                         Some(track_title.clone()),
                         error_msg.clone(),
@@ -933,12 +1529,14 @@ impl DownloadWorker {
                 Err(e) => {
                     let error_msg = e.to_string();
                     // Log error for retry
-                    self.error_log.add_download_error(DownloadErrorEntry::new(
-                        link.to_string(),
-                        "playlist".to_string(),
-                        actual_format.to_string(),
-                        quality.to_string(),
-                        portable,
+                    lock(&self.error_log).add_download_error(DownloadErrorEntry::new(
+                        &DownloadJob {
+                            link,
+                            link_type: "playlist",
+                            format: actual_format,
+                            quality,
+                            portable,
+                        },
                         Some(track_artist.clone()),
                         Some(track_title.clone()),
                         error_msg.clone(),
@@ -956,7 +1554,47 @@ impl DownloadWorker {
             }
         }
 
-        let _ = file_utils::create_m3u(&playlist_name, &downloaded_paths, &self.playlist_path);
+        if loudness_config.replaygain {
+            self.apply_replaygain(id, new_paths.clone(), false).await;
+        }
+
+        let _ = file_utils::create_playlists(
+            &playlist_name,
+            &downloaded_paths,
+            &self.playlist_path,
+            Some(&self.db()),
+            playlist_formats,
+        );
+        if !new_paths.is_empty() {
+            self.regenerate_smart_playlists(id).await;
+        }
+
+        if sync {
+            let tracks = SyncedTrack::resolve(&wanted, &self.db());
+            let recorded = TrackedPlaylists::open().record(TrackedPlaylist {
+                name: playlist_name.clone(),
+                link: link.to_string(),
+                snapshot_id: playlist.snapshot_id.clone(),
+                synced_at: chrono::Utc::now(),
+                format: actual_format.to_string(),
+                quality: quality.to_string(),
+                portable,
+                tracks,
+                downloaded: new_paths.iter().map(|p| p.display().to_string()).collect(),
+            });
+            match recorded {
+                Ok(diff) => {
+                    for track in &diff.added {
+                        self.send_log(id, format!("+ {}", track)).await;
+                    }
+                    for track in &diff.removed {
+                        self.send_log(id, format!("- {}", track)).await;
+                    }
+                    self.send_log(id, format!("'{}' synced: {}", playlist_name, diff.summary())).await;
+                }
+                Err(e) => self.send_log(id, format!("Failed to record sync: {}", e)).await,
+            }
+        }
 
         let _ = self
             .tx
@@ -971,10 +1609,10 @@ impl DownloadWorker {
         &mut self,
         id: usize,
         link: &str,
-        portable: bool,
-        format: &str,
-        quality: &str,
+        settings: &DownloadSettings<'_>,
+        playlist_formats: &[PlaylistFormat],
     ) {
+        let DownloadSettings { portable, format, quality, lyrics } = *settings;
         let config = if portable {
             PortableConfig {
                 enabled: true,
@@ -992,6 +1630,11 @@ impl DownloadWorker {
         };
 
         let actual_format = if portable { "mp3" } else { format };
+        let source_quality = Config::load().source_quality;
+        let loudness_config = Config::load().loudness;
+        let normalize = portable && loudness_config.normalize_portable;
+        let trim_config = Config::load().trim;
+        let tagging = Config::load().tagging;
 
         self.send_log(id, format!("Fetching YouTube playlist: {}", link))
             .await;
@@ -1007,12 +1650,14 @@ impl DownloadWorker {
             Ok(Ok(p)) => p,
             Ok(Err(e)) => {
                 let error_msg = format!("Failed to fetch YouTube playlist: {}", e);
-                self.error_log.add_download_error(DownloadErrorEntry::new(
-                    link.to_string(),
-                    "youtube_playlist".to_string(),
-                    actual_format.to_string(),
-                    quality.to_string(),
-                    portable,
+                lock(&self.error_log).add_download_error(DownloadErrorEntry::new(
+                    &DownloadJob {
+                        link,
+                        link_type: "youtube_playlist",
+                        format: actual_format,
+                        quality,
+                        portable,
+                    },
                     None,
                     None,
                     error_msg.clone(),
@@ -1055,6 +1700,8 @@ impl DownloadWorker {
             .await;
 
         let mut downloaded_paths: Vec<PathBuf> = Vec::new();
+        // Tracks downloaded in this run, for ReplayGain
+        let mut new_paths: Vec<PathBuf> = Vec::new();
 
         for (i, track) in playlist.tracks.iter().enumerate() {
             // Check for pause before starting each track
@@ -1075,22 +1722,29 @@ impl DownloadWorker {
             };
 
             let safe_file_name =
-                file_utils::build_filename(&track_artist, &track_title, actual_format, &config);
+                file_utils::build_filename(&track_artist, &track_title, converter::format_extension(actual_format), &config);
             let file_path = output_folder.join(&safe_file_name);
 
             let entry = TrackEntry {
+                id: String::new(),
                 artist: track_artist.clone(),
                 title: track_title.clone(),
                 path: file_path.display().to_string(),
+                source: None,
+                trims: Vec::new(),
+                variants: Vec::new(),
+                duration_secs: None,
             };
 
-            if self.db.contains(&entry) {
+            let exists = self.db().find_download(&entry).is_some();
+            if exists {
                 let _ = self
                     .tx
                     .send(DownloadEvent::TrackSkipped {
                         id,
                         artist: track_artist,
                         title: track_title,
+                        reason: "exists".to_string(),
                     })
                     .await;
                 continue;
@@ -1118,12 +1772,26 @@ impl DownloadWorker {
                 })
                 .await;
 
+            let origin = TrackOrigin {
+                link,
+                link_type: "youtube_playlist",
+                format: actual_format,
+                quality,
+                portable,
+                artist: &track_artist,
+                title: &track_title,
+            };
+            let Some(quality_warning) = self.screen_track(id, &track.url, &source_quality, &origin).await else {
+                continue;
+            };
+
             // Download directly from YouTube URL instead of searching
             let file_path_clone = file_path.clone();
             let format_clone = actual_format.to_string();
             let quality_clone = quality.to_string();
             let video_url = track.url.clone();
             let tx_clone = self.tx.clone();
+            let sponsorblock = trim_config.sponsorblock;
 
             match tokio::task::spawn_blocking(move || {
                 // Use the direct URL instead of search query
@@ -1132,6 +1800,7 @@ impl DownloadWorker {
                     &file_path_clone,
                     &format_clone,
                     &quality_clone,
+                    sponsorblock,
                     move |line| {
                         let tx = tx_clone.clone();
                         let line = line.to_string();
@@ -1141,24 +1810,59 @@ impl DownloadWorker {
             })
             .await
             {
-                Ok(Ok(_)) => {
-                    // Tag with basic metadata (no cover art for YouTube)
-                    if let Err(e) = metadata::tag_audio(
-                        &file_path,
-                        &track_artist,
-                        &playlist_name, // Use playlist name as album
-                        &track_title,
-                        (i + 1) as u32,
-                        None, // No genre
-                        None, // No cover art
-                        &config,
-                    ) {
+                Ok(Ok(downloaded)) => {
+                    let file_path = downloaded.path;
+                    let mut trims = downloaded.trims;
+                    if trim_config.silence {
+                        trims.extend(self.trim_silence(id, &file_path).await);
+                    }
+                    let entry = TrackEntry {
+                        path: file_path.display().to_string(),
+                        source: downloaded.source,
+                        trims,
+                        variants: Vec::new(),
+                        ..entry
+                    };
+                    // Use the video thumbnail as cover art (no album to search for)
+                    let mut artwork_query = ArtworkQuery::new(&track_artist, "");
+                    artwork_query.video_url = Some(track.url.clone());
+                    let cover_file = file_path.with_file_name("temp_cover.jpg");
+                    let cover_path = self.fetch_artwork(id, &artwork_query, &cover_file).await;
+
+                    // Tag with basic metadata
+                    let tags = metadata::TrackTags {
+                        artist: &track_artist,
+                        album: &playlist_name, // Use playlist name as album
+                        title: &track_title,
+                        track: (i + 1) as u32,
+                        genre: None,
+                    };
+                    if let Err(e) = metadata::tag_audio(&file_path, &tags, cover_path.as_deref(), &config, &tagging) {
                         self.send_log(id, format!("Warning: Tagging failed: {}", e))
                             .await;
                     }
 
-                    self.db.add(entry);
+                    if let Some(cover) = cover_path {
+                        let _ = std::fs::remove_file(cover);
+                    }
+
+                    self.apply_quality_warning(id, &file_path, quality_warning.as_deref()).await;
+
+                    if lyrics {
+                        let query = LyricsQuery {
+                            artist: track_artist.clone(),
+                            title: track_title.clone(),
+                            album: None,
+                            duration_secs: track.duration,
+                        };
+                        self.apply_lyrics(id, &file_path, &query).await;
+                    }
+                    if normalize {
+                        self.normalize_loudness(id, &file_path).await;
+                    }
+                    self.db().add(entry);
                     downloaded_paths.push(file_path.clone());
+                    new_paths.push(file_path.clone());
 
                     let _ = self
                         .tx
@@ -1172,12 +1876,14 @@ impl DownloadWorker {
                 }
                 Ok(Err(e)) => {
                     let error_msg = e.to_string();
-                    self.error_log.add_download_error(DownloadErrorEntry::new(
-                        link.to_string(),
-                        "youtube_playlist".to_string(),
-                        actual_format.to_string(),
-                        quality.to_string(),
-                        portable,
+                    lock(&self.error_log).add_download_error(DownloadErrorEntry::new(
+                        &DownloadJob {
+                            link,
+                            link_type: "youtube_playlist",
+                            format: actual_format,
+                            quality,
+                            portable,
+                        },
                         Some(track_artist.clone()),
                         Some(track_title.clone()),
                         error_msg.clone(),
@@ -1194,12 +1900,14 @@ impl DownloadWorker {
                 }
                 Err(e) => {
                     let error_msg = e.to_string();
-                    self.error_log.add_download_error(DownloadErrorEntry::new(
-                        link.to_string(),
-                        "youtube_playlist".to_string(),
-                        actual_format.to_string(),
-                        quality.to_string(),
-                        portable,
+                    lock(&self.error_log).add_download_error(DownloadErrorEntry::new(
+                        &DownloadJob {
+                            link,
+                            link_type: "youtube_playlist",
+                            format: actual_format,
+                            quality,
+                            portable,
+                        },
                         Some(track_artist.clone()),
                         Some(track_title.clone()),
                         error_msg.clone(),
@@ -1217,7 +1925,21 @@ impl DownloadWorker {
             }
         }
 
-        let _ = file_utils::create_m3u(&playlist_name, &downloaded_paths, &self.playlist_path);
+        let downloaded_any = !new_paths.is_empty();
+        if loudness_config.replaygain {
+            self.apply_replaygain(id, new_paths, false).await;
+        }
+
+        let _ = file_utils::create_playlists(
+            &playlist_name,
+            &downloaded_paths,
+            &self.playlist_path,
+            Some(&self.db()),
+            playlist_formats,
+        );
+        if downloaded_any {
+            self.regenerate_smart_playlists(id).await;
+        }
 
         let _ = self
             .tx
@@ -1232,13 +1954,31 @@ impl DownloadWorker {
         &mut self,
         id: usize,
         input_path: &str,
-        target_format: &str,
-        quality: &str,
-        refresh_metadata: bool,
+        job: &ConvertJob<'_>,
         artist: &str,
         title: &str,
     ) {
+        let ConvertJob { target_format, quality, encoder, output_dir, keep_both, refresh_metadata } = *job;
         let input = std::path::Path::new(input_path);
+        let output_path = self.convert_output(input, target_format, output_dir);
+        // A codec change within a container (AAC .m4a to ALAC) replaces the file
+        let in_place = output_path == input;
+        if in_place && keep_both {
+            let error = format!(
+                "Can't keep both: {} is written to the same file name; set an output folder",
+                target_format
+            );
+            self.send_log(id, error.clone()).await;
+            let _ = self
+                .tx
+                .send(DownloadEvent::ConvertFailed {
+                    id,
+                    path: input_path.to_string(),
+                    error,
+                })
+                .await;
+            return;
+        }
 
         // Send started event
         let _ = self
@@ -1260,19 +2000,30 @@ impl DownloadWorker {
         let input_clone = input.to_path_buf();
         let format_clone = target_format.to_string();
         let quality_clone = quality.to_string();
+        let encoder_clone = encoder.clone();
         let tx_clone = self.tx.clone();
 
+        let new_path = output_path.clone();
+
         let result = tokio::task::spawn_blocking(move || {
-            converter::convert_audio(&input_clone, &format_clone, &quality_clone, move |line| {
-                let tx = tx_clone.clone();
-                let line = line.to_string();
-                let _ = tx.blocking_send(DownloadEvent::LogLine { id, line });
-            })
+            converter::convert_audio_to(
+                &input_clone,
+                &output_path,
+                &format_clone,
+                &quality_clone,
+                &encoder_clone,
+                move |line| {
+                    let tx = tx_clone.clone();
+                    let line = line.to_string();
+                    let _ = tx.blocking_send(DownloadEvent::LogLine { id, line });
+                },
+                |_| {},
+            )
         })
         .await;
 
         match result {
-            Ok(Ok(new_path)) => {
+            Ok(Ok(())) => {
                 let new_path_str = new_path.display().to_string();
                 self.send_log(id, format!("Conversion complete: {}", new_path_str))
                     .await;
@@ -1281,15 +2032,15 @@ impl DownloadWorker {
                 if refresh_metadata {
                     self.send_log(id, format!("Refreshing metadata for: {} - {}", artist, title))
                         .await;
+                    let tagging = Config::load().tagging;
 
-                    match spotify::search_track(artist, title).await {
+                    match Self::lookup_metadata(&Self::refresh_providers(), artist, title).await {
                         Ok(Some(meta)) => {
                             // Download cover art if available
-                            let cover_path = if let Some(url) = &meta.cover_url {
+                            let cover_path = {
                                 let cover_file = new_path.with_file_name("temp_cover.jpg");
-                                self.download_cover_art(id, url, &cover_file).await
-                            } else {
-                                None
+                                self.fetch_artwork(id, &ArtworkQuery::from_metadata(&meta), &cover_file)
+                                    .await
                             };
 
                             // Apply metadata
@@ -1300,16 +2051,7 @@ impl DownloadWorker {
                                 max_filename_len: 100,
                             };
 
-                            if let Err(e) = metadata::tag_audio(
-                                &new_path,
-                                &meta.artist,
-                                &meta.album,
-                                &meta.title,
-                                meta.track_number,
-                                meta.genre.as_deref(),
-                                cover_path.as_deref(),
-                                &config,
-                            ) {
+                            if let Err(e) = metadata::tag_from_metadata(&new_path, &meta, cover_path.as_deref(), &config, &tagging) {
                                 self.send_log(id, format!("Warning: Failed to apply metadata: {}", e))
                                     .await;
                             } else {
@@ -1325,30 +2067,40 @@ impl DownloadWorker {
                         Ok(None) => {
                             self.send_log(
                                 id,
-                                "Could not find track on Spotify, keeping existing metadata"
+                                "Could not find track metadata, keeping existing metadata"
                                     .to_string(),
                             )
                             .await;
                         }
                         Err(e) => {
-                            self.send_log(id, format!("Spotify search failed: {}", e))
+                            self.send_log(id, format!("Metadata lookup failed: {}", e))
                                 .await;
                         }
                     }
                 }
 
-                // Update database with new path
-                self.db.update_path(input_path, &new_path_str);
+                if in_place {
+                    self.send_log(id, "Converted in place; the original is in the trash".to_string())
+                        .await;
+                } else if keep_both {
+                    self.db().add_variant(input_path, &new_path_str);
+                    self.send_log(id, format!("Original kept; recorded as a {} variant", target_format))
+                        .await;
+                } else {
+                    // Update database with new path
+                    self.db().update_path(input_path, &new_path_str);
+                    self.update_playlists(id).await;
 
-                // Ask for deletion confirmation
-                let _ = self
-                    .tx
-                    .send(DownloadEvent::ConvertDeleteConfirm {
-                        id,
-                        old_path: input_path.to_string(),
-                        new_path: new_path_str.clone(),
-                    })
-                    .await;
+                    // Ask for deletion confirmation
+                    let _ = self
+                        .tx
+                        .send(DownloadEvent::ConvertDeleteConfirm {
+                            id,
+                            old_path: input_path.to_string(),
+                            new_path: new_path_str.clone(),
+                        })
+                        .await;
+                }
 
                 let _ = self
                     .tx
@@ -1363,11 +2115,9 @@ impl DownloadWorker {
                 let error_msg = e.to_string();
                 self.send_log(id, format!("Conversion failed: {}", error_msg)).await;
                 // Log error for retry
-                self.error_log.add_convert_error(ConvertErrorEntry::new(
+                lock(&self.error_log).add_convert_error(ConvertErrorEntry::new(
                     input_path.to_string(),
-                    target_format.to_string(),
-                    quality.to_string(),
-                    refresh_metadata,
+                    job,
                     artist.to_string(),
                     title.to_string(),
                     error_msg.clone(),
@@ -1386,11 +2136,9 @@ impl DownloadWorker {
                 self.send_log(id, format!("Conversion task failed: {}", error_msg))
                     .await;
                 // Log error for retry
-                self.error_log.add_convert_error(ConvertErrorEntry::new(
+                lock(&self.error_log).add_convert_error(ConvertErrorEntry::new(
                     input_path.to_string(),
-                    target_format.to_string(),
-                    quality.to_string(),
-                    refresh_metadata,
+                    job,
                     artist.to_string(),
                     title.to_string(),
                     error_msg.clone(),
@@ -1407,108 +2155,174 @@ impl DownloadWorker {
         }
     }
 
+    /// Convert many files at once, one FFmpeg per CPU core. Results are
+    /// handled (metadata refresh, database update) as each file finishes.
     async fn process_convert_batch(
         &mut self,
         id: usize,
         tracks: Vec<ConvertTrackInfo>,
-        target_format: &str,
-        quality: &str,
-        refresh_metadata: bool,
+        job: &ConvertJob<'_>,
     ) {
+        let ConvertJob { target_format, quality, encoder, output_dir, keep_both, refresh_metadata } = *job;
         let total = tracks.len();
         let mut successful = 0;
+        let mut failed = 0;
         let mut converted_files: Vec<(String, String)> = Vec::new();
+        let jobs = converter::parallel_jobs().min(total.max(1));
+        let metadata_providers = Self::refresh_providers();
+        let tagging = Config::load().tagging;
 
         self.send_log(
             id,
-            format!("Starting batch conversion of {} tracks to {}", total, target_format),
+            format!(
+                "Starting batch conversion of {} tracks to {} ({} at a time)",
+                total, target_format, jobs
+            ),
         )
         .await;
 
-        for (i, track) in tracks.iter().enumerate() {
-            let input = std::path::Path::new(&track.input_path);
+        let semaphore = Arc::new(Semaphore::new(jobs));
+        let progress = Arc::new(Mutex::new(BatchProgress::new(total)));
+        let mut tasks = JoinSet::new();
+        // Which track each task converts, for tasks that panic or are cancelled
+        let mut spawned = HashMap::new();
+
+        for (i, track) in tracks.into_iter().enumerate() {
+            let semaphore = semaphore.clone();
+            let progress = progress.clone();
+            let tx = self.tx.clone();
+            let format = target_format.to_string();
+            let quality = quality.to_string();
+            let encoder = encoder.clone();
+            let output_path = self.convert_output(Path::new(&track.input_path), target_format, output_dir);
+            if keep_both && output_path == Path::new(&track.input_path) {
+                let _ = self
+                    .tx
+                    .send(DownloadEvent::ConvertFailed {
+                        id,
+                        path: track.input_path.clone(),
+                        error: format!(
+                            "Can't keep both: {} is written to the same file name; set an output folder",
+                            target_format
+                        ),
+                    })
+                    .await;
+                lock(&progress).finish(i);
+                failed += 1;
+                continue;
+            }
 
-            self.send_log(
-                id,
-                format!(
-                    "[{}/{}] Converting: {} - {}",
-                    i + 1,
-                    total,
-                    track.artist,
-                    track.title
-                ),
-            )
-            .await;
+            let info = track.clone();
+            let handle = tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let _ = tx
+                    .send(DownloadEvent::LogLine {
+                        id,
+                        line: format!("[{}/{}] Converting: {} - {}", i + 1, total, track.artist, track.title),
+                    })
+                    .await;
+                let _ = tx
+                    .send(DownloadEvent::ConvertStarted {
+                        id,
+                        path: track.input_path.clone(),
+                        target_format: format.clone(),
+                    })
+                    .await;
 
-            let _ = self
-                .tx
-                .send(DownloadEvent::ConvertStarted {
-                    id,
-                    path: track.input_path.clone(),
-                    target_format: target_format.to_string(),
+                let input = PathBuf::from(&track.input_path);
+                let new_path = output_path.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    let log_tx = tx.clone();
+                    converter::convert_audio_to(
+                        &input,
+                        &output_path,
+                        &format,
+                        &quality,
+                        &encoder,
+                        move |line| {
+                            // Percentages of parallel files would drown the log
+                            if !line.starts_with("Progress:") {
+                                let line = line.to_string();
+                                let _ = log_tx.blocking_send(DownloadEvent::LogLine { id, line });
+                            }
+                        },
+                        |p| {
+                            let update = lock(&progress).update(i, p.fraction().unwrap_or(0.0), p.out_secs);
+                            if let Some((fraction, audio_secs)) = update {
+                                let _ = tx.blocking_send(DownloadEvent::ConvertProgress {
+                                    id,
+                                    fraction,
+                                    audio_secs,
+                                });
+                            }
+                        },
+                    )
                 })
                 .await;
+                (i, track, new_path, result)
+            });
+            spawned.insert(handle.id(), (i, info));
+        }
 
-            // Perform conversion
-            let input_clone = input.to_path_buf();
-            let format_clone = target_format.to_string();
-            let quality_clone = quality.to_string();
-            let tx_clone = self.tx.clone();
-
-            let result = tokio::task::spawn_blocking(move || {
-                converter::convert_audio(&input_clone, &format_clone, &quality_clone, move |line| {
-                    let tx = tx_clone.clone();
-                    let line = line.to_string();
-                    let _ = tx.blocking_send(DownloadEvent::LogLine { id, line });
-                })
-            })
-            .await;
+        while let Some(joined) = tasks.join_next_with_id().await {
+            let (i, track, new_path, result) = match joined {
+                Ok((_, done)) => done,
+                // Reported below like a failed conversion
+                Err(e) => match spawned.remove(&e.id()) {
+                    Some((i, track)) => (i, track, PathBuf::new(), Err(e)),
+                    None => continue,
+                },
+            };
+            let update = lock(&progress).finish(i);
+            if let Some((fraction, audio_secs)) = update {
+                let _ = self
+                    .tx
+                    .send(DownloadEvent::ConvertProgress {
+                        id,
+                        fraction,
+                        audio_secs,
+                    })
+                    .await;
+            }
 
             match result {
-                Ok(Ok(new_path)) => {
+                Ok(Ok(())) => {
                     let new_path_str = new_path.display().to_string();
 
                     // Refresh metadata if requested
                     if refresh_metadata {
-                        match spotify::search_track(&track.artist, &track.title).await {
-                            Ok(Some(meta)) => {
-                                let cover_path = if let Some(url) = &meta.cover_url {
-                                    let cover_file = new_path.with_file_name("temp_cover.jpg");
-                                    self.download_cover_art(id, url, &cover_file).await
-                                } else {
-                                    None
-                                };
-
-                                let config = PortableConfig {
-                                    enabled: false,
-                                    max_cover_dim: 500,
-                                    max_cover_bytes: 300 * 1024,
-                                    max_filename_len: 100,
-                                };
-
-                                let _ = metadata::tag_audio(
-                                    &new_path,
-                                    &meta.artist,
-                                    &meta.album,
-                                    &meta.title,
-                                    meta.track_number,
-                                    meta.genre.as_deref(),
-                                    cover_path.as_deref(),
-                                    &config,
-                                );
-
-                                if let Some(cover) = cover_path {
-                                    let _ = std::fs::remove_file(cover);
-                                }
+                        if let Ok(Some(meta)) =
+                            Self::lookup_metadata(&metadata_providers, &track.artist, &track.title).await
+                        {
+                            let cover_path = {
+                                let cover_file = new_path.with_file_name("temp_cover.jpg");
+                                self.fetch_artwork(id, &ArtworkQuery::from_metadata(&meta), &cover_file)
+                                    .await
+                            };
+
+                            let config = PortableConfig {
+                                enabled: false,
+                                max_cover_dim: 500,
+                                max_cover_bytes: 300 * 1024,
+                                max_filename_len: 100,
+                            };
+
+                            let _ = metadata::tag_from_metadata(&new_path, &meta, cover_path.as_deref(), &config, &tagging);
+
+                            if let Some(cover) = cover_path {
+                                let _ = std::fs::remove_file(cover);
                             }
-                            _ => {}
                         }
                     }
 
-                    // Update database with new path
-                    self.db.update_path(&track.input_path, &new_path_str);
-
-                    converted_files.push((track.input_path.clone(), new_path_str.clone()));
+                    if keep_both {
+                        self.db().add_variant(&track.input_path, &new_path_str);
+                    } else if new_path_str != track.input_path {
+                        // Update database with new path; a file converted in
+                        // place has no original left to delete
+                        self.db().update_path(&track.input_path, &new_path_str);
+                        converted_files.push((track.input_path.clone(), new_path_str.clone()));
+                    }
                     successful += 1;
 
                     let _ = self
@@ -1521,6 +2335,7 @@ impl DownloadWorker {
                         .await;
                 }
                 Ok(Err(e)) => {
+                    failed += 1;
                     let error_msg = e.to_string();
                     self.send_log(
                         id,
@@ -1528,11 +2343,9 @@ impl DownloadWorker {
                     )
                     .await;
                     // Log error for retry
-                    self.error_log.add_convert_error(ConvertErrorEntry::new(
+                    lock(&self.error_log).add_convert_error(ConvertErrorEntry::new(
                         track.input_path.clone(),
-                        target_format.to_string(),
-                        quality.to_string(),
-                        refresh_metadata,
+                        job,
                         track.artist.clone(),
                         track.title.clone(),
                         error_msg.clone(),
@@ -1547,6 +2360,7 @@ impl DownloadWorker {
                         .await;
                 }
                 Err(e) => {
+                    failed += 1;
                     let error_msg = e.to_string();
                     self.send_log(
                         id,
@@ -1554,11 +2368,9 @@ impl DownloadWorker {
                     )
                     .await;
                     // Log error for retry
-                    self.error_log.add_convert_error(ConvertErrorEntry::new(
+                    lock(&self.error_log).add_convert_error(ConvertErrorEntry::new(
                         track.input_path.clone(),
-                        target_format.to_string(),
-                        quality.to_string(),
-                        refresh_metadata,
+                        job,
                         track.artist.clone(),
                         track.title.clone(),
                         error_msg.clone(),
@@ -1578,12 +2390,16 @@ impl DownloadWorker {
         self.send_log(
             id,
             format!(
-                "Batch conversion complete: {}/{} successful",
-                successful, total
+                "Batch conversion complete: {}/{} successful, {} failed",
+                successful, total, failed
             ),
         )
         .await;
 
+        if !converted_files.is_empty() {
+            self.update_playlists(id).await;
+        }
+
         // Send batch complete event
         let _ = self
             .tx
@@ -1611,6 +2427,7 @@ impl DownloadWorker {
         title: &str,
     ) {
         let input = std::path::Path::new(input_path);
+        let tagging = Config::load().tagging;
 
         let _ = self
             .tx
@@ -1627,14 +2444,13 @@ impl DownloadWorker {
         )
         .await;
 
-        match spotify::search_track(artist, title).await {
+        match Self::lookup_metadata(&Self::refresh_providers(), artist, title).await {
             Ok(Some(meta)) => {
                 // Download cover art if available
-                let cover_path = if let Some(url) = &meta.cover_url {
+                let cover_path = {
                     let cover_file = input.with_file_name("temp_cover.jpg");
-                    self.download_cover_art(id, url, &cover_file).await
-                } else {
-                    None
+                    self.fetch_artwork(id, &ArtworkQuery::from_metadata(&meta), &cover_file)
+                        .await
                 };
 
                 // Apply metadata
@@ -1645,21 +2461,12 @@ impl DownloadWorker {
                     max_filename_len: 100,
                 };
 
-                if let Err(e) = metadata::tag_audio(
-                    input,
-                    &meta.artist,
-                    &meta.album,
-                    &meta.title,
-                    meta.track_number,
-                    meta.genre.as_deref(),
-                    cover_path.as_deref(),
-                    &config,
-                ) {
+                if let Err(e) = metadata::tag_from_metadata(input, &meta, cover_path.as_deref(), &config, &tagging) {
                     let error_msg = e.to_string();
                     self.send_log(id, format!("Failed to apply metadata: {}", error_msg))
                         .await;
                     // Log error for retry
-                    self.error_log.add_refresh_error(RefreshErrorEntry::new(
+                    lock(&self.error_log).add_refresh_error(RefreshErrorEntry::new(
                         input_path.to_string(),
                         artist.to_string(),
                         title.to_string(),
@@ -1693,14 +2500,14 @@ impl DownloadWorker {
                 }
             }
             Ok(None) => {
-                let error_msg = "Track not found on Spotify".to_string();
+                let error_msg = "Track not found by any metadata provider".to_string();
                 self.send_log(
                     id,
-                    format!("Could not find {} - {} on Spotify", artist, title),
+                    format!("Could not find metadata for {} - {}", artist, title),
                 )
                 .await;
                 // Log error for retry
-                self.error_log.add_refresh_error(RefreshErrorEntry::new(
+                lock(&self.error_log).add_refresh_error(RefreshErrorEntry::new(
                     input_path.to_string(),
                     artist.to_string(),
                     title.to_string(),
@@ -1718,14 +2525,14 @@ impl DownloadWorker {
             }
             Err(e) => {
                 let error_msg = e.to_string();
-                self.send_log(id, format!("Spotify search failed: {}", error_msg))
+                self.send_log(id, format!("Metadata lookup failed: {}", error_msg))
                     .await;
                 // Log error for retry
-                self.error_log.add_refresh_error(RefreshErrorEntry::new(
+                lock(&self.error_log).add_refresh_error(RefreshErrorEntry::new(
                     input_path.to_string(),
                     artist.to_string(),
                     title.to_string(),
-                    format!("Spotify search failed: {}", error_msg),
+                    format!("Metadata lookup failed: {}", error_msg),
                 ));
                 let _ = self
                     .tx
@@ -1743,6 +2550,8 @@ impl DownloadWorker {
     async fn process_refresh_metadata_batch(&mut self, id: usize, tracks: Vec<ConvertTrackInfo>) {
         let total = tracks.len();
         let mut successful = 0;
+        let metadata_providers = Self::refresh_providers();
+        let tagging = Config::load().tagging;
 
         self.send_log(
             id,
@@ -1774,13 +2583,12 @@ impl DownloadWorker {
                 })
                 .await;
 
-            match spotify::search_track(&track.artist, &track.title).await {
+            match Self::lookup_metadata(&metadata_providers, &track.artist, &track.title).await {
                 Ok(Some(meta)) => {
-                    let cover_path = if let Some(url) = &meta.cover_url {
+                    let cover_path = {
                         let cover_file = input.with_file_name("temp_cover.jpg");
-                        self.download_cover_art(id, url, &cover_file).await
-                    } else {
-                        None
+                        self.fetch_artwork(id, &ArtworkQuery::from_metadata(&meta), &cover_file)
+                            .await
                     };
 
                     let config = PortableConfig {
@@ -1790,16 +2598,7 @@ impl DownloadWorker {
                         max_filename_len: 100,
                     };
 
-                    if metadata::tag_audio(
-                        input,
-                        &meta.artist,
-                        &meta.album,
-                        &meta.title,
-                        meta.track_number,
-                        meta.genre.as_deref(),
-                        cover_path.as_deref(),
-                        &config,
-                    )
+                    if metadata::tag_from_metadata(input, &meta, cover_path.as_deref(), &config, &tagging)
                     .is_ok()
                     {
                         successful += 1;
@@ -1814,7 +2613,7 @@ impl DownloadWorker {
                     } else {
                         let error_msg = "Failed to apply metadata".to_string();
                         // Log error for retry
-                        self.error_log.add_refresh_error(RefreshErrorEntry::new(
+                        lock(&self.error_log).add_refresh_error(RefreshErrorEntry::new(
                             track.input_path.clone(),
                             track.artist.clone(),
                             track.title.clone(),
@@ -1836,9 +2635,9 @@ impl DownloadWorker {
                     }
                 }
                 Ok(None) => {
-                    let error_msg = "Track not found on Spotify".to_string();
+                    let error_msg = "Track not found by any metadata provider".to_string();
                     // Log error for retry
-                    self.error_log.add_refresh_error(RefreshErrorEntry::new(
+                    lock(&self.error_log).add_refresh_error(RefreshErrorEntry::new(
                         track.input_path.clone(),
                         track.artist.clone(),
                         track.title.clone(),
@@ -1857,11 +2656,11 @@ impl DownloadWorker {
                 Err(e) => {
                     let error_msg = e.to_string();
                     // Log error for retry
-                    self.error_log.add_refresh_error(RefreshErrorEntry::new(
+                    lock(&self.error_log).add_refresh_error(RefreshErrorEntry::new(
                         track.input_path.clone(),
                         track.artist.clone(),
                         track.title.clone(),
-                        format!("Spotify search failed: {}", error_msg),
+                        format!("Metadata lookup failed: {}", error_msg),
                     ));
                     let _ = self
                         .tx
@@ -1894,4 +2693,152 @@ impl DownloadWorker {
             })
             .await;
     }
+
+    /// Fetch and embed lyrics for library tracks
+    async fn process_fetch_lyrics(&mut self, id: usize, tracks: Vec<ConvertTrackInfo>) {
+        let lyrics_config = Config::load().lyrics;
+        let provider = lyrics::provider_from_config(&lyrics_config);
+
+        for track in tracks {
+            let input = std::path::Path::new(&track.input_path);
+            self.send_log(
+                id,
+                format!("Fetching lyrics: {} - {}", track.artist, track.title),
+            )
+            .await;
+
+            let album = metadata::read_tags(input).ok().and_then(|t| t.album);
+            let query = LyricsQuery {
+                artist: track.artist.clone(),
+                title: track.title.clone(),
+                album,
+                duration_secs: None,
+            };
+
+            let event = match lyrics::fetch_and_embed(
+                provider.as_ref(),
+                input,
+                &query,
+                lyrics_config.write_lrc,
+            )
+            .await
+            {
+                Ok(found) => DownloadEvent::LyricsComplete {
+                    id,
+                    artist: track.artist,
+                    title: track.title,
+                    found: found.is_some(),
+                },
+                Err(e) => DownloadEvent::LyricsFailed {
+                    id,
+                    artist: track.artist,
+                    title: track.title,
+                    error: e.to_string(),
+                },
+            };
+            let _ = self.tx.send(event).await;
+        }
+    }
+
+    async fn process_reembed_cover(&mut self, id: usize, tracks: Vec<ConvertTrackInfo>, portable: bool) {
+        let config = if portable {
+            PortableConfig {
+                enabled: true,
+                max_cover_dim: 128,
+                max_cover_bytes: 64 * 1024,
+                max_filename_len: 64,
+            }
+        } else {
+            PortableConfig {
+                enabled: false,
+                max_cover_dim: 500,
+                max_cover_bytes: 300 * 1024,
+                max_filename_len: 100,
+            }
+        };
+        let total = tracks.len();
+        let mut embedded = 0;
+        let mut issues = 0;
+
+        for track in tracks {
+            let input = std::path::Path::new(&track.input_path);
+            let label = format!("{} - {}", track.artist, track.title);
+
+            match input.parent().and_then(file_utils::find_folder_cover) {
+                Some(cover) => match metadata::embed_cover(input, &cover, &config) {
+                    Ok(()) => {
+                        embedded += 1;
+                        self.send_log(id, format!("Embedded {}: {}", cover.display(), label))
+                            .await;
+                    }
+                    Err(e) => {
+                        self.send_log(id, format!("Cover embed failed: {} - {}", label, e))
+                            .await;
+                    }
+                },
+                None => {
+                    self.send_log(id, format!("No cover.jpg or folder.jpg for {}", label))
+                        .await;
+                }
+            }
+
+            // Covers are embedded no larger than max_cover_dim, so a portable
+            // cover is only tiny if it's below that
+            match metadata::audit_cover(input, config.max_cover_dim.min(300)) {
+                Ok(Some(issue)) => {
+                    issues += 1;
+                    self.send_log(id, format!("Cover issue: {} - {}", label, issue))
+                        .await;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    issues += 1;
+                    self.send_log(id, format!("Cover unreadable: {} - {}", label, e))
+                        .await;
+                }
+            }
+        }
+
+        let _ = self
+            .tx
+            .send(DownloadEvent::CoverComplete {
+                id,
+                embedded,
+                issues,
+                total,
+            })
+            .await;
+    }
+
+    /// Normalize (optionally) and ReplayGain-tag library tracks, one album per folder
+    async fn process_analyze_loudness(&mut self, id: usize, tracks: Vec<ConvertTrackInfo>, normalize: bool) {
+        let total = tracks.len();
+        let paths: Vec<PathBuf> = tracks.iter().map(|t| PathBuf::from(&t.input_path)).collect();
+        let mut failed = 0;
+
+        if normalize {
+            for path in &paths {
+                if !self.normalize_loudness(id, path).await {
+                    failed += 1;
+                }
+            }
+        }
+
+        let mut tagged = 0;
+        for album in loudness::group_by_folder(&paths) {
+            let (t, f) = self.apply_replaygain(id, album, true).await;
+            tagged += t;
+            failed += f;
+        }
+
+        let _ = self
+            .tx
+            .send(DownloadEvent::LoudnessComplete {
+                id,
+                tagged,
+                failed,
+                total,
+            })
+            .await;
+    }
 }